@@ -3,5 +3,34 @@ export M1CALIBRATION=/fsx/m1calibration/
 export GMT_MODES_PATH=/fsx/ceo
 export FEM_REPO=/fsx/20220308_1335_MT_mount_zen_30_m1HFN_FSM/
 export SH48_N_STEP=5
+export SH48_N_PROBE=1
+export SH48_FLUX_THRESHOLD=0.5
+export SH48_N_MODE=27
+export ACO_M1_RBM=
+export ACO_M2_RBM=
+export ACO_EXCLUDE=
 export LOM=/fsx
-export DATA_REPO=/fsx/grim
\ No newline at end of file
+export DATA_REPO=/fsx/grim
+export CALIBRATION_REPO=/fsx/grim/calibrations
+export CALIBRATION_MAX_CLOSURE_ERROR=1e-6
+export CALIBRATION_CONDITION_TOLERANCE=1e-6
+export CALIBRATION_STROKE_M1_TXYZ=1e-6
+export CALIBRATION_STROKE_M1_RXYZ=1e-6
+export CALIBRATION_STROKE_M2_TXYZ=1e-6
+export CALIBRATION_STROKE_M2_RXYZ=1e-6
+export CALIBRATION_STROKE_M1_MODES=1e-6
+export CALIBRATION_LINEARITY_FACTOR=2
+export CALIBRATION_MAX_NONLINEARITY=0.05
+export ATMOSPHERE=on
+export ATMOSPHERE_FILE=/fsx/atmosphere/free_atm_15mn.bin
+export ATMOSPHERE_REMOVE_LAYERS=0
+export DOME_SEEING=on
+export DOME_SEEING_CFD_CASE=/fsx/CASES/zen30az000_OS7
+export STATIC_ABERRATIONS=raw-polishing_print-through_soak1deg_769.bin
+export WIND_LOADS=TopEnd,M2Baffle,Trusses,M1Baffle,MirrorCovers,LaserGuideStars,CRings,GIR,Platforms,M1Segments,M2Segments
+export WIND_SPEED=7
+export WIND_LOADS_CROSS_FADE=1
+export WIND_LOADS_SOURCE=cfd
+export WIND_LOADS_SEED=0
+export WIND_LOADS_CACHE=/fsx/grim/cfd-loads
+export FIGURES=svg
\ No newline at end of file
@@ -0,0 +1,266 @@
+//! Active optics
+//!
+//! The active optics (AcO) reconstructor estimates, from the AGWS SH48 measurements,
+//! the M1 and M2 segments rigid body motions and the M1 segments bending modes
+//! selected with [Aco](crate::config::Aco).
+//! The estimates are split by [Split] into [M1RbmEstimate], [M2RbmEstimate] and
+//! [M1ModesEstimate] that are fed to the integrators of the M1 hardpoints, of the
+//! M2 positioners and of the M1 force actuators, respectively.
+
+use dos_actors::{
+    io::{Data, Read, Write},
+    UniqueIdentifier, Update,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr, sync::Arc};
+
+/// Number of rigid body motions per segment
+pub const N_RBM: usize = 6;
+/// Number of segments
+pub const N_SEGMENT: usize = 7;
+
+/// Segmented mirror
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mirror {
+    M1,
+    M2,
+}
+
+/// Rigid body motion axes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Axis {
+    Tx,
+    Ty,
+    Tz,
+    Rx,
+    Ry,
+    Rz,
+}
+impl Axis {
+    /// All the axes, translations first
+    pub const ALL: [Axis; N_RBM] = [Axis::Tx, Axis::Ty, Axis::Tz, Axis::Rx, Axis::Ry, Axis::Rz];
+    /// Returns the index of the axis in the segment rigid body motions vector
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+impl FromStr for Axis {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "tx" => Ok(Axis::Tx),
+            "ty" => Ok(Axis::Ty),
+            "tz" => Ok(Axis::Tz),
+            "rx" => Ok(Axis::Rx),
+            "ry" => Ok(Axis::Ry),
+            "rz" => Ok(Axis::Rz),
+            _ => Err(format!(
+                "unknown axis {s:?}, expected one of Tx,Ty,Tz,Rx,Ry,Rz"
+            )),
+        }
+    }
+}
+
+/// Active optics degree of freedom
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Dof {
+    /// Rigid body motion of segment `sid` (1 to 7)
+    Rbm {
+        mirror: Mirror,
+        sid: usize,
+        axis: Axis,
+    },
+    /// Bending mode of M1 segment `sid` (1 to 7)
+    Mode { sid: usize, mode: usize },
+}
+impl Display for Dof {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dof::Rbm { mirror, sid, axis } => write!(f, "{mirror:?}S{sid}{axis:?}"),
+            Dof::Mode { sid, mode } => write!(f, "M1S{sid}B{mode}"),
+        }
+    }
+}
+
+impl FromStr for Dof {
+    type Err = String;
+    /// Parses a segment rigid body motion, e.g. `M1S7Rz`, or a M1 segment bending mode, e.g. `M1S7B0`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid degree of freedom {s:?}, expected e.g. M1S7Rz or M1S7B0");
+        let s = s.trim().to_uppercase();
+        let mirror = match s.get(..2) {
+            Some("M1") => Mirror::M1,
+            Some("M2") => Mirror::M2,
+            _ => return Err(err()),
+        };
+        let sid = s
+            .get(2..4)
+            .and_then(|s| s.strip_prefix('S'))
+            .and_then(|sid| sid.parse::<usize>().ok())
+            .filter(|sid| (1..=N_SEGMENT).contains(sid))
+            .ok_or_else(err)?;
+        match s.get(4..).ok_or_else(err)? {
+            mode if mode.starts_with('B') && mirror == Mirror::M1 => Ok(Dof::Mode {
+                sid,
+                mode: mode[1..].parse().map_err(|_| err())?,
+            }),
+            axis => Ok(Dof::Rbm {
+                mirror,
+                sid,
+                axis: axis.parse()?,
+            }),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub enum M1RbmEstimate {}
+impl UniqueIdentifier for M1RbmEstimate {
+    type Data = Vec<f64>;
+}
+#[doc(hidden)]
+pub enum M2RbmEstimate {}
+impl UniqueIdentifier for M2RbmEstimate {
+    type Data = Vec<f64>;
+}
+#[doc(hidden)]
+pub enum M1ModesEstimate {}
+impl UniqueIdentifier for M1ModesEstimate {
+    type Data = Vec<f64>;
+}
+
+/// Splits the estimates of the controlled degrees of freedom per mirror
+///
+/// The degrees of freedom that are not controlled are set to zero:
+///  - [M1RbmEstimate]: `[7x6]` M1 segments rigid body motions
+///  - [M2RbmEstimate]: `[7x6]` M2 segments rigid body motions
+///  - [M1ModesEstimate]: `[7xn_mode]` M1 segments bending modes
+#[derive(Debug)]
+pub struct Split {
+    dofs: Vec<Dof>,
+    n_mode: usize,
+    m1_rbm: Vec<f64>,
+    m2_rbm: Vec<f64>,
+    m1_modes: Vec<f64>,
+}
+impl Split {
+    /// Creates a new splitter for the estimates of `dofs`
+    pub fn new(dofs: Vec<Dof>, n_mode: usize) -> Self {
+        Self {
+            dofs,
+            n_mode,
+            m1_rbm: vec![0f64; N_RBM * N_SEGMENT],
+            m2_rbm: vec![0f64; N_RBM * N_SEGMENT],
+            m1_modes: vec![0f64; n_mode * N_SEGMENT],
+        }
+    }
+}
+impl Update for Split {}
+impl<U: UniqueIdentifier<Data = Vec<f64>>> Read<Vec<f64>, U> for Split {
+    fn read(&mut self, data: Arc<Data<U>>) {
+        if data.len() != self.dofs.len() {
+            log::warn!(
+                "AcO split: expected {} estimates, found {}",
+                self.dofs.len(),
+                data.len()
+            );
+        }
+        for (dof, &value) in self.dofs.iter().zip(data.iter()) {
+            match *dof {
+                Dof::Rbm {
+                    mirror: Mirror::M1,
+                    sid,
+                    axis,
+                } => self.m1_rbm[(sid - 1) * N_RBM + axis.index()] = value,
+                Dof::Rbm {
+                    mirror: Mirror::M2,
+                    sid,
+                    axis,
+                } => self.m2_rbm[(sid - 1) * N_RBM + axis.index()] = value,
+                Dof::Mode { sid, mode } => self.m1_modes[(sid - 1) * self.n_mode + mode] = value,
+            }
+        }
+    }
+}
+impl Write<Vec<f64>, M1RbmEstimate> for Split {
+    fn write(&mut self) -> Option<Arc<Data<M1RbmEstimate>>> {
+        Some(Arc::new(Data::new(self.m1_rbm.clone())))
+    }
+}
+impl Write<Vec<f64>, M2RbmEstimate> for Split {
+    fn write(&mut self) -> Option<Arc<Data<M2RbmEstimate>>> {
+        Some(Arc::new(Data::new(self.m2_rbm.clone())))
+    }
+}
+impl Write<Vec<f64>, M1ModesEstimate> for Split {
+    fn write(&mut self) -> Option<Arc<Data<M1ModesEstimate>>> {
+        Some(Arc::new(Data::new(self.m1_modes.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dos_actors::prelude::*;
+
+    #[derive(UID)]
+    #[uid(data = "Vec<f64>")]
+    enum Estimates {}
+
+    #[test]
+    fn parse() {
+        assert_eq!(" rZ ".parse::<Axis>(), Ok(Axis::Rz));
+        assert!("Rw".parse::<Axis>().is_err());
+        assert_eq!(
+            "m2s3tx".parse::<Dof>(),
+            Ok(Dof::Rbm {
+                mirror: Mirror::M2,
+                sid: 3,
+                axis: Axis::Tx
+            })
+        );
+        assert_eq!("M1S7B26".parse::<Dof>(), Ok(Dof::Mode { sid: 7, mode: 26 }));
+        for dof in [
+            "M3S1Tx", "M1S0Tx", "M1S8Tx", "M1STx", "M2S1B0", "M1S1B", "M1S1",
+        ] {
+            assert!(dof.parse::<Dof>().is_err(), "{dof}");
+        }
+        for sid in 1..=N_SEGMENT {
+            for dof in Axis::ALL
+                .into_iter()
+                .flat_map(|axis| {
+                    [Mirror::M1, Mirror::M2].map(|mirror| Dof::Rbm { mirror, sid, axis })
+                })
+                .chain([Dof::Mode { sid, mode: 10 }])
+            {
+                assert_eq!(dof.to_string().parse::<Dof>(), Ok(dof));
+            }
+        }
+    }
+
+    #[test]
+    fn split() {
+        let dofs: Vec<Dof> = ["M1S1Tx", "M2S7Rz", "M1S2B1", "M1S7B0"]
+            .into_iter()
+            .map(|dof| dof.parse().unwrap())
+            .collect();
+        let mut split = Split::new(dofs, 2);
+        <Split as Read<Vec<f64>, Estimates>>::read(
+            &mut split,
+            Arc::new(Data::new(vec![1., 2., 3., 4.])),
+        );
+        let m1_rbm = <Split as Write<Vec<f64>, M1RbmEstimate>>::write(&mut split).unwrap();
+        let m2_rbm = <Split as Write<Vec<f64>, M2RbmEstimate>>::write(&mut split).unwrap();
+        let m1_modes = <Split as Write<Vec<f64>, M1ModesEstimate>>::write(&mut split).unwrap();
+        let mut expected = vec![0f64; N_RBM * N_SEGMENT];
+        expected[0] = 1.;
+        assert_eq!(**m1_rbm, expected);
+        let mut expected = vec![0f64; N_RBM * N_SEGMENT];
+        expected[6 * N_RBM + 5] = 2.;
+        assert_eq!(**m2_rbm, expected);
+        let mut expected = vec![0f64; 2 * N_SEGMENT];
+        expected[3] = 3.;
+        expected[12] = 4.;
+        assert_eq!(**m1_modes, expected);
+    }
+}
@@ -0,0 +1,311 @@
+//! Spectral analysis of the simulation results
+//!
+//! [Analysis] estimates the power spectral density of each channel of a stream of the parquet
+//! result files with the [Welch] method, the samples of the warm-up being discarded.
+//! Each stream is analyzed at its own sampling rate: 1kHz for `grim.parquet`, 200Hz for
+//! `sh24.parquet` or 1/30Hz for `sh48.parquet`.
+//!
+//! [Spectra] gives the PSD, the reverse cumulative RMS and the RMS in frequency bands
+//! of each channel, the quantities used in the error budgets.
+//!
+//! [Analysis::transfer_function] estimates the transfer function in between 2 channels,
+//! the [FrequencyResponse] compares the estimate to the frequency response of a linear model.
+
+use crate::{
+    results::{read_time_series, write_columns},
+    spectrum::{Detrend, Psd, Tfe, TransferFunction, Welch, Window},
+    GrimError, Result,
+};
+use rustfft::num_complex::Complex;
+use std::{fmt::Display, path::Path};
+
+/// Welch power spectral density analysis
+#[derive(Debug, Clone, Copy)]
+pub struct Analysis {
+    n_fft: Option<usize>,
+    overlap: f64,
+    window: Window,
+    detrend: Detrend,
+}
+impl Default for Analysis {
+    fn default() -> Self {
+        Self {
+            n_fft: None,
+            overlap: 0.5,
+            window: Window::Hann,
+            detrend: Detrend::Constant,
+        }
+    }
+}
+impl Analysis {
+    /// Sets the segment length
+    ///
+    /// If not set, the segment length is the largest power of 2 giving at least 8 overlapping
+    /// segments, up to 4096 samples
+    pub fn n_fft(self, n_fft: usize) -> Self {
+        Self {
+            n_fft: Some(n_fft),
+            ..self
+        }
+    }
+    /// Sets the overlap of consecutive segments as a fraction of the segment length
+    pub fn overlap(self, overlap: f64) -> Self {
+        Self { overlap, ..self }
+    }
+    /// Sets the segment window
+    pub fn window(self, window: Window) -> Self {
+        Self { window, ..self }
+    }
+    /// Sets the trend removed from each segment
+    pub fn detrend(self, detrend: Detrend) -> Self {
+        Self { detrend, ..self }
+    }
+    /// Returns the segment length for `n` samples
+    pub fn segment_length(&self, n: usize) -> Result<usize> {
+        let n_fft = match self.n_fft {
+            Some(n_fft) => n_fft,
+            None if n >= 16 => (1usize << (n / 4).ilog2()).min(4096),
+            None => n,
+        };
+        if n_fft < 2 || n_fft > n {
+            return Err(GrimError::Config(format!(
+                "{n} samples for segments of {n_fft} samples"
+            )));
+        }
+        Ok(n_fft)
+    }
+    /// Estimates the power spectral density of each channel of the time series `samples`
+    /// sampled at `sampling_frequency`
+    pub fn psds(&self, samples: &[Vec<f64>], sampling_frequency: f64) -> Result<Vec<Psd>> {
+        let n_fft = self.segment_length(samples.len())?;
+        let n_channel = samples.first().map_or(0, |sample| sample.len());
+        Ok((0..n_channel)
+            .map(|i| {
+                let mut welch = Welch::new(n_fft, sampling_frequency)
+                    .window(self.window)
+                    .overlap(self.overlap)
+                    .detrend(self.detrend);
+                samples.iter().for_each(|sample| welch.push(&[sample[i]]));
+                welch.psd()
+            })
+            .collect())
+    }
+    /// Estimates the transfer function from `input` to `output` sampled at `sampling_frequency`
+    /// with the excitation `reference` or, without reference, with the H1 estimator
+    pub fn transfer_function(
+        &self,
+        reference: Option<&[f64]>,
+        input: &[f64],
+        output: &[f64],
+        sampling_frequency: f64,
+    ) -> Result<TransferFunction> {
+        let n = reference
+            .map_or(input.len(), |reference| reference.len())
+            .min(input.len())
+            .min(output.len());
+        let mut tfe = Tfe::new(self.segment_length(n)?, sampling_frequency)
+            .window(self.window)
+            .overlap(self.overlap)
+            .detrend(self.detrend);
+        for i in 0..n {
+            tfe.push(reference.map_or(input[i], |r| r[i]), input[i], output[i]);
+        }
+        Ok(tfe.transfer_function())
+    }
+    /// Analyzes the stream `name` of the parquet file `path` with rows sampled at
+    /// `sampling_frequency`, discarding the samples before `warm_up` [s]
+    pub fn from_parquet<P: AsRef<Path>>(
+        &self,
+        path: P,
+        name: &str,
+        sampling_frequency: f64,
+        warm_up: f64,
+    ) -> Result<Spectra> {
+        let (time, samples) = read_time_series(path, name, sampling_frequency)?;
+        let start = time
+            .iter()
+            .position(|t| *t >= warm_up)
+            .unwrap_or(time.len());
+        let samples = &samples[start..];
+        // the stream sampling rate, decimated streams have null rows in between the samples
+        let stream_sampling_frequency = match (time.get(start), time.last()) {
+            (Some(t0), Some(t1)) if t1 > t0 => (samples.len() - 1) as f64 / (t1 - t0),
+            _ => sampling_frequency,
+        };
+        Ok(Spectra {
+            name: name.to_string(),
+            psds: self.psds(samples, stream_sampling_frequency)?,
+        })
+    }
+}
+
+/// Power spectral densities of the channels of a stream
+#[derive(Debug, Clone, Default)]
+pub struct Spectra {
+    pub name: String,
+    pub psds: Vec<Psd>,
+}
+impl Spectra {
+    /// Multiplies the power spectral densities by `scale`²
+    pub fn scale(mut self, scale: f64) -> Self {
+        self.psds
+            .iter_mut()
+            .for_each(|psd| psd.values.iter_mut().for_each(|p| *p *= scale * scale));
+        self
+    }
+    /// Returns the RMS of each channel in the frequency `bands`
+    pub fn band_rms(&self, bands: &[(f64, f64)]) -> BandRms {
+        BandRms {
+            name: self.name.clone(),
+            bands: bands.to_vec(),
+            rms: self
+                .psds
+                .iter()
+                .map(|psd| {
+                    bands
+                        .iter()
+                        .map(|(f_min, f_max)| psd.band_rms(*f_min, *f_max))
+                        .collect()
+                })
+                .collect(),
+            total: self.psds.iter().map(|psd| psd.rms()).collect(),
+        }
+    }
+    /// Saves the frequencies and, for each channel, the PSD and the reverse cumulative RMS
+    /// in the parquet file `path`, one row per frequency
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let Some(first) = self.psds.first() else {
+            return Err(GrimError::Config(format!("{}: no channel", self.name)));
+        };
+        let cumulative_rms: Vec<_> = self.psds.iter().map(|psd| psd.cumulative_rms()).collect();
+        let (psd, rms): (Vec<Vec<f64>>, Vec<Vec<f64>>) = (0..first.frequencies.len())
+            .map(|i| {
+                (
+                    self.psds.iter().map(|psd| psd.values[i]).collect(),
+                    cumulative_rms.iter().map(|rms| rms[i]).collect(),
+                )
+            })
+            .unzip();
+        write_columns(
+            path,
+            &[
+                (
+                    "Frequency".to_string(),
+                    first.frequencies.iter().map(|f| vec![*f]).collect(),
+                ),
+                ("Psd".to_string(), psd),
+                ("CumulativeRms".to_string(), rms),
+            ],
+        )
+    }
+}
+
+/// Band-limited RMS of the channels of a stream
+#[derive(Debug, Clone, Default)]
+pub struct BandRms {
+    pub name: String,
+    /// Frequency bands [Hz]
+    pub bands: Vec<(f64, f64)>,
+    /// RMS of each channel in each band
+    pub rms: Vec<Vec<f64>>,
+    /// RMS of each channel over all the frequencies
+    pub total: Vec<f64>,
+}
+impl Display for BandRms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<20}", self.name)?;
+        for (f_min, f_max) in &self.bands {
+            write!(f, " {:>20}", format!("{f_min:.3}-{f_max:.3}Hz"))?;
+        }
+        writeln!(f, " {:>20}", "TOTAL")?;
+        for (i, (rms, total)) in self.rms.iter().zip(&self.total).enumerate() {
+            write!(f, "{:<20}", format!("[{i}]"))?;
+            for rms in rms {
+                write!(f, " {rms:>20.3e}")?;
+            }
+            writeln!(f, " {total:>20.3e}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimated and modeled frequency response in between 2 channels
+#[derive(Debug, Clone, Default)]
+pub struct FrequencyResponse {
+    pub name: String,
+    pub estimate: TransferFunction,
+    /// Frequency response of the linear model at the frequencies of the estimate
+    pub model: Option<Vec<Complex<f64>>>,
+}
+impl FrequencyResponse {
+    /// Returns the largest magnitude [dB] and phase [deg] differences in between the estimate and
+    /// the model, at the frequencies where the coherence is larger than `min_coherence`
+    pub fn model_error(&self, min_coherence: f64) -> Option<(f64, f64)> {
+        let model = self.model.as_ref()?;
+        self.estimate
+            .values
+            .iter()
+            .zip(model)
+            .zip(&self.estimate.coherence)
+            .skip(1)
+            .filter(|(_, c)| **c >= min_coherence)
+            .map(|((h, m), _)| {
+                let ratio = h / m;
+                (20f64 * ratio.norm().log10(), ratio.arg().to_degrees())
+            })
+            .fold(None, |error: Option<(f64, f64)>, (db, deg)| {
+                let (max_db, max_deg) = error.unwrap_or_default();
+                Some((max_db.max(db.abs()), max_deg.max(deg.abs())))
+            })
+    }
+    /// Saves the frequencies, the magnitude, the phase [deg] and the coherence of the estimate
+    /// and the magnitude and the phase of the model in the parquet file `path`, one row per frequency
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let column =
+            |values: Vec<f64>| -> Vec<Vec<f64>> { values.into_iter().map(|x| vec![x]).collect() };
+        let mut columns = vec![
+            (
+                "Frequency".to_string(),
+                column(self.estimate.frequencies.clone()),
+            ),
+            ("Magnitude".to_string(), column(self.estimate.magnitude())),
+            ("Phase".to_string(), column(self.estimate.phase())),
+            (
+                "Coherence".to_string(),
+                column(self.estimate.coherence.clone()),
+            ),
+        ];
+        if let Some(model) = &self.model {
+            columns.push((
+                "ModelMagnitude".to_string(),
+                column(model.iter().map(|h| h.norm()).collect()),
+            ));
+            columns.push((
+                "ModelPhase".to_string(),
+                column(model.iter().map(|h| h.arg().to_degrees()).collect()),
+            ));
+        }
+        write_columns(path, &columns)
+    }
+}
+impl Display for FrequencyResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let n_coherent = self
+            .estimate
+            .coherence
+            .iter()
+            .filter(|c| **c >= 0.8)
+            .count();
+        write!(
+            f,
+            "{}: {} frequencies, {} with a coherence larger than 0.8",
+            self.name,
+            self.estimate.frequencies.len(),
+            n_coherent
+        )?;
+        if let Some((db, deg)) = self.model_error(0.8) {
+            write!(f, ", model error: {db:.2}dB, {deg:.1}deg")?;
+        }
+        Ok(())
+    }
+}
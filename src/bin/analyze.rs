@@ -0,0 +1,159 @@
+use fem::fem_io::{MCM2Lcl6D, OSSM1Lcl};
+use grim::{
+    logging::StreamMetadata,
+    results::{write_time_series, Run, Stats},
+};
+use lom::{Loader, LoaderTrait, OpticalSensitivities, OpticalSensitivity};
+use nalgebra as na;
+use skyangle::Conversion;
+
+// M1 segment diameter [m]
+const SEGMENT_DIAMETER: f64 = 8.4;
+// Source of the wavefront errors that are not derived from a LOM sensitivity
+const WFE_APPROXIMATION: &str = "LOM first order approximation: differential segment piston, \
+segment tip-tilt over the 8.4m segments and M1 bending modes assuming unit RMS surface modes";
+
+// Returns the matrix of a linear optical model sensitivity
+fn sensitivity(
+    senses: &OpticalSensitivities,
+    sensitivity: OpticalSensitivity,
+) -> anyhow::Result<na::DMatrix<f64>> {
+    let data = match &senses[sensitivity] {
+        OpticalSensitivity::TipTilt(data)
+        | OpticalSensitivity::SegmentTipTilt(data)
+        | OpticalSensitivity::SegmentPiston(data) => data,
+        _ => anyhow::bail!("unsupported optical sensitivity"),
+    };
+    // 42 M1 and 42 M2 rigid body motions
+    Ok(na::DMatrix::from_column_slice(data.len() / 84, 84, data))
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let run = Run::open(grim::data_repo())?;
+    let sampling_frequency = run.sampling_frequency();
+    let warm_up = grim::config::env_or("ANALYZE_WARM_UP", run.warm_up())?;
+
+    let path = run.dir().join("grim.parquet");
+    let m1_rbm = run.get_from::<OSSM1Lcl>("grim.parquet")?;
+    let time = m1_rbm.time;
+    let m1_rbm = m1_rbm.samples;
+    let m2_rbm = run.get_from::<MCM2Lcl6D>("grim.parquet")?.samples;
+    let m1_modes = run
+        .stream("grim.parquet", "M1modes")
+        .map(|signal| signal.samples)
+        .unwrap_or_else(|e| {
+            log::warn!("M1 modes not found in {path:?} ({e}), the WFE is rigid body only");
+            vec![]
+        });
+    let n_sample = m1_rbm.len().min(m2_rbm.len());
+    println!(
+        "{n_sample} samples ({:.3}s) in {path:?}",
+        n_sample as f64 / sampling_frequency
+    );
+
+    let senses: OpticalSensitivities = Loader::<OpticalSensitivities>::default().load()?;
+    let tiptilt = sensitivity(&senses, OpticalSensitivity::TipTilt(Vec::new()))?;
+    let segment_tiptilt = sensitivity(&senses, OpticalSensitivity::SegmentTipTilt(Vec::new()))?;
+    let segment_piston = sensitivity(&senses, OpticalSensitivity::SegmentPiston(Vec::new()))?;
+
+    let metadata = StreamMetadata {
+        sampling_frequency,
+        decimation: 1,
+        start_time: run.warm_up(),
+        source: "LOM".to_string(),
+        ..Default::default()
+    };
+    let mut columns: Vec<(String, StreamMetadata, Vec<Vec<f64>>)> = [
+        ("TipTilt", "mas"),
+        ("SegmentTipTilt", "mas"),
+        ("SegmentPiston", "nm"),
+        ("SegmentWfeRms", "nm"),
+        ("WfeRms", "nm"),
+    ]
+    .into_iter()
+    .map(|(name, unit)| {
+        let source = if name.contains("Wfe") {
+            WFE_APPROXIMATION.to_string()
+        } else {
+            metadata.source.clone()
+        };
+        (
+            name.to_string(),
+            StreamMetadata {
+                unit: unit.to_string(),
+                source,
+                ..metadata.clone()
+            },
+            Vec::with_capacity(n_sample),
+        )
+    })
+    .collect();
+    for i in 0..n_sample {
+        let rbm = na::DVector::from_iterator(84, m1_rbm[i].iter().chain(m2_rbm[i].iter()).cloned());
+        let tt = &tiptilt * &rbm;
+        let stt = &segment_tiptilt * &rbm;
+        let sp = &segment_piston * &rbm;
+        // first order segment WFE RMS: differential piston, tip-tilt over the segment
+        // and M1 bending modes (unit RMS surface modes)
+        let mean_piston = sp.mean();
+        let modes = m1_modes.get(i);
+        let segment_wfe: Vec<f64> = (0..7)
+            .map(|s| {
+                let piston = sp[s] - mean_piston;
+                let tilt = stt[s].hypot(stt[s + 7]) * SEGMENT_DIAMETER / 4f64;
+                let bending = modes.map_or(0f64, |modes| {
+                    let n = modes.len() / 7;
+                    2f64 * modes[s * n..(s + 1) * n]
+                        .iter()
+                        .map(|x| x * x)
+                        .sum::<f64>()
+                        .sqrt()
+                });
+                (piston * piston + tilt * tilt + bending * bending).sqrt()
+            })
+            .collect();
+        let wfe = (segment_wfe.iter().map(|x| x * x).sum::<f64>() / 7f64).sqrt();
+        for ((_, _, column), sample) in columns.iter_mut().zip([
+            tt.iter().map(|x| x.to_mas()).collect(),
+            stt.iter().map(|x| x.to_mas()).collect(),
+            sp.iter().map(|x| x * 1e9).collect(),
+            segment_wfe.iter().map(|x| x * 1e9).collect::<Vec<f64>>(),
+            vec![wfe * 1e9],
+        ]) {
+            column.push(sample);
+        }
+    }
+
+    let lom_path = run.dir().join("lom.parquet");
+    write_time_series(&lom_path, &time[..n_sample], &columns)?;
+    println!("LOM time series saved to {lom_path:?}");
+
+    let n_warm_up = ((warm_up * sampling_frequency) as usize).min(n_sample);
+    println!(
+        "Statistics after {warm_up}s warm-up ({} samples):",
+        n_sample - n_warm_up
+    );
+    println!(
+        "{:<20} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "", "MEAN", "STD", "RMS", "MIN", "MAX"
+    );
+    for (name, StreamMetadata { unit, source, .. }, column) in columns.iter() {
+        let n = column.first().map_or(0, |sample| sample.len());
+        // approximated streams are marked with a `~`
+        let mark = if source == WFE_APPROXIMATION { "~" } else { "" };
+        for j in 0..n {
+            let data: Vec<f64> = column[n_warm_up..].iter().map(|x| x[j]).collect();
+            let label = if n > 1 {
+                format!("{mark}{name}[{j}] ({unit})")
+            } else {
+                format!("{mark}{name} ({unit})")
+            };
+            println!("{label:<20} {}", Stats::new(&data));
+        }
+    }
+    println!("~ {WFE_APPROXIMATION}");
+
+    Ok(())
+}
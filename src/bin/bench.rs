@@ -4,25 +4,26 @@ use dos_actors::{
     clients::{
         arrow_client::{Arrow, Get},
         ceo,
-        gmt_state::GmtState,
     },
     prelude::*,
     Update,
 };
-use linya::{Bar, Progress};
+use grim::{
+    config::env_opt, fits::FrameLogger, probe::Probes, progress::Progress, results::Run,
+    shutdown::Shutdown,
+};
 use skyangle::Conversion;
-use std::{sync::Arc, time::Duration};
-use tokio::sync::Mutex;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::init();
     let sim_sampling_frequency = 1_000_usize;
-    const CFD_DELAY: usize = 10; // seconds
     const EXPOSURE_RATE: usize = 30_000;
     let sim_duration = (EXPOSURE_RATE / sim_sampling_frequency) as f64;
     log::info!("Simulation duration: {:6.3}s", sim_duration);
 
+    let probes = Probes::default();
     let gmt_builder = Gmt::builder().m1_n_mode(162);
     let bench = ceo::OpticalModel::builder()
         .gmt(gmt_builder)
@@ -53,8 +54,8 @@ async fn main() -> anyhow::Result<()> {
                 PSSn::<TelescopeError>::builder(),
             )),
         ])
-        .build()?
-        .into_arcx();
+        .build()?;
+    let bench = probes.probe("ON-AXIS GMT", bench).into_arcx();
 
     (*bench.lock().await).update();
     let pssn_e = (*bench.lock().await).pssn.as_mut().unwrap().estimates();
@@ -63,12 +64,9 @@ async fn main() -> anyhow::Result<()> {
     let mut on_axis = Actor::<_, 1, EXPOSURE_RATE>::new(bench.clone()).name("ON-AXIS GMT");
 
     let n_step = (sim_duration * sim_sampling_frequency as f64) as usize;
-    let mut gmt_state: Initiator<_> = Into::<GmtState>::into((
-        Arrow::from_parquet("grim.parquet")?,
-        CFD_DELAY * sim_sampling_frequency,
-        Some(n_step),
-    ))
-    .into();
+    let shutdown = Shutdown::listen();
+    let run = Run::open(grim::data_repo())?;
+    let mut gmt_state: Initiator<_> = shutdown.interruptible(run.gmt_state(Some(n_step))?).into();
 
     gmt_state
         .add_output()
@@ -89,6 +87,18 @@ async fn main() -> anyhow::Result<()> {
         .into_arcx();
 
     let mut logger = Terminator::<_, EXPOSURE_RATE>::new(logs.clone()).name("Logs");
+    let mut frame_logger = FrameLogger::builder()
+        .dirname("bench-frames")
+        .frame_size(512, 512)
+        .sampling_frequency(sim_sampling_frequency as f64)
+        .rate(EXPOSURE_RATE)
+        .source("ON-AXIS GMT")
+        .unit("photon");
+    if let Some(pixel_scale) = env_opt("BENCH_PIXEL_SCALE")? {
+        frame_logger = frame_logger.pixel_scale(pixel_scale);
+    }
+    let mut frames =
+        Terminator::<_, EXPOSURE_RATE>::new(frame_logger.build().into_arcx()).name("Frames");
     on_axis
         .add_output()
         .build::<ceo::WfeRms>()
@@ -121,37 +131,30 @@ async fn main() -> anyhow::Result<()> {
         .await
         .confirm()?
         .add_output()
+        .multiplex(2)
         .build::<ceo::DetectorFrame>()
         .logn(&mut logger, 512 * 512)
-        .await;
+        .await
+        .into_input(&mut frames);
 
     let model = Model::new(vec![
         Box::new(gmt_state),
         Box::new(on_axis),
         Box::new(logger),
+        Box::new(frames),
     ])
     .name("bench")
     .check()?
     .flowchart()
     .run();
 
-    let progress = Arc::new(Mutex::new(Progress::new()));
-    let onaxis_progress = progress.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(Duration::from_secs(3));
-        let bar: Bar = onaxis_progress
-            .lock()
-            .await
-            .bar(EXPOSURE_RATE, "Bench integration");
-        loop {
-            interval.tick().await;
-            let mut progress = onaxis_progress.lock().await;
-            let n = (*bench.lock().await).sensor.as_ref().unwrap().n_frame();
-            progress.set_and_draw(&bar, n);
-        }
-    });
+    let on_axis_stats = (*bench.lock().await).stats();
+    let integration = Progress::new()
+        .phase("Bench integration", Some(on_axis_stats.step() + n_step))
+        .track(Duration::from_secs(3), move || on_axis_stats.step());
 
     model.wait().await?;
+    integration.finish().await;
 
     let pssn: Vec<Vec<f64>> = (*logs.lock().await).get("PSSn")?;
     println!("PPSn: {pssn:?}");
@@ -0,0 +1,258 @@
+use crseo::{calibrations, Builder, Calibration, FromBuilder, Gmt, Source, SH24 as TT7, SH48};
+use dos_actors::clients::ceo;
+use grim::{
+    aco::{self, Dof},
+    calibration::{self, Key, Metadata, Store, Subset},
+    config::{Aco, Sh48, Strokes},
+    diagnostics::{self, Diagnostics, Linearity, Thresholds},
+    progress::Progress,
+};
+use nalgebra as na;
+
+// Calibration specifications of the degrees of freedom of `key` with their strokes scaled by `scale`
+//
+// Each degree of freedom is calibrated separately with its own stroke,
+// the order of the degrees of freedom is preserved
+fn specs(
+    key: &Key,
+    scale: f64,
+) -> Vec<Option<Vec<(calibrations::Mirror, Vec<calibrations::Segment>)>>> {
+    use calibrations::{Mirror, Segment::*};
+    (1..=aco::N_SEGMENT)
+        .map(|sid| {
+            let mut spec: Vec<(Mirror, Vec<calibrations::Segment>)> = vec![];
+            for (dof, stroke) in key.dofs.iter().zip(&key.strokes) {
+                let stroke = stroke * scale;
+                let (mirror, segment) = match *dof {
+                    Dof::Rbm {
+                        mirror,
+                        sid: s,
+                        axis,
+                    } if s == sid => {
+                        let i = axis.index();
+                        let mirror = match mirror {
+                            aco::Mirror::M1 => Mirror::M1,
+                            aco::Mirror::M2 => Mirror::M2,
+                        };
+                        if i < 3 {
+                            (mirror, Txyz(stroke, Some(i..i + 1)))
+                        } else {
+                            (mirror, Rxyz(stroke, Some(i - 3..i - 2)))
+                        }
+                    }
+                    Dof::Mode { sid: s, mode } if s == sid => {
+                        (Mirror::M1MODES, Modes(stroke, mode..mode + 1))
+                    }
+                    _ => continue,
+                };
+                match spec.last_mut() {
+                    Some((last, segments)) if *last == mirror => segments.push(segment),
+                    _ => spec.push((mirror, vec![segment])),
+                }
+            }
+            (!spec.is_empty()).then_some(spec)
+        })
+        .collect()
+}
+
+// Interaction matrix of a calibration
+fn poke_matrix(calibration: Calibration) -> na::DMatrix<f64> {
+    let n_mode = calibration.n_mode;
+    let poke: Vec<f64> = calibration.poke.into();
+    na::DMatrix::<f64>::from_column_slice(poke.len() / n_mode, n_mode, &poke)
+}
+
+// Push and pull interaction matrices of `key` with the strokes scaled by `scale`
+fn push_pull<F>(
+    model: &mut ceo::OpticalModel,
+    new_calibration: F,
+    key: &Key,
+    scale: f64,
+) -> anyhow::Result<[na::DMatrix<f64>; 2]>
+where
+    F: Fn(&ceo::OpticalModel) -> Calibration,
+{
+    let mut poke = |s: f64| {
+        let mut calibration = new_calibration(model);
+        calibration.calibrate(
+            specs(key, s),
+            calibrations::ValidLensletCriteria::OtherSensor(&mut model.sensor.as_mut().unwrap()),
+        );
+        poke_matrix(calibration)
+    };
+    let (push, pull) = (poke(scale), poke(-scale));
+    anyhow::ensure!(
+        push.shape() == pull.shape(),
+        "{} push {:?} and pull {:?} interaction matrices do not match",
+        key.sensor,
+        push.shape(),
+        pull.shape()
+    );
+    // the pokes are normalized by the signed strokes
+    Ok([push, pull])
+}
+
+// Push-pull calibration of `key` with the linearity check, returns the calibration with
+// its push and pull interaction matrices
+fn calibrate<F>(
+    model: &mut ceo::OpticalModel,
+    new_calibration: F,
+    key: Key,
+    thresholds: &Thresholds,
+    progress: &Progress,
+) -> anyhow::Result<(calibration::Calibration, [na::DMatrix<f64>; 2])>
+where
+    F: Fn(&ceo::OpticalModel) -> Calibration,
+{
+    let phase = progress.phase(format!("{} calibration", key.sensor), None);
+    let [push, pull] = push_pull(model, &new_calibration, &key, 1f64)?;
+    phase.finish();
+    let phase = progress.phase(format!("{} linearity check", key.sensor), None);
+    let [scaled_push, scaled_pull] =
+        push_pull(model, &new_calibration, &key, thresholds.linearity_factor)?;
+    phase.finish();
+    let mut calibration = calibration::Calibration::new(key, &push, &pull, thresholds)?;
+    let linearity = Linearity::new(
+        &calibration.metadata.key.dofs,
+        &calibration.poke,
+        &((scaled_push + scaled_pull) * 0.5),
+        thresholds,
+    )?;
+    println!(
+        "{} calibration [{}x{}]: {}",
+        calibration.metadata.key.sensor,
+        calibration.metadata.n_data,
+        calibration.metadata.n_dof,
+        calibration.diagnostics
+    );
+    println!(" . {linearity}");
+    if !(linearity.nonlinear.is_empty() && linearity.saturated.is_empty()) {
+        log::warn!(
+            "{} calibration: the non-linear and saturated degrees of freedom bias the reconstructor, consider reducing their strokes",
+            calibration.metadata.key.sensor
+        );
+    }
+    calibration.diagnostics.linearity = Some(linearity);
+    Ok((calibration, [push, pull]))
+}
+
+// Latest stored calibration of `key`, the new calibration must not be worse conditioned
+fn previous(store: &Store, key: &Key) -> Option<Metadata> {
+    match store.latest(key) {
+        Ok(previous) => previous,
+        Err(e) => {
+            log::warn!("{e}: the conditioning of the new calibration is not checked");
+            None
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let sh48 = Sh48::from_env()?;
+    let aco = Aco::from_env(&sh48)?;
+    let strokes = Strokes::from_env()?;
+    let thresholds = Thresholds::from_env()?;
+    let store = Store::from_env();
+    println!("Calibration store: {:?}", store.root());
+    let progress = Progress::new();
+
+    let gmt_builder = Gmt::builder().m1_n_mode(162);
+
+    // SH24
+    let key = Key::sh24(&strokes);
+    let sh24_previous = previous(&store, &key);
+    let mut agws_sh24 = ceo::OpticalModel::builder()
+        .gmt(gmt_builder.clone())
+        .source(Source::builder())
+        .options(vec![ceo::OpticalModelOptions::ShackHartmann {
+            options: ceo::ShackHartmannOptions::Diffractive(*TT7::<crseo::Diffractive>::new()),
+            flux_threshold: key.flux_threshold,
+        }])
+        .build()?;
+    let (mut sh24, _) = calibrate(
+        &mut agws_sh24,
+        |model| Calibration::new(&model.gmt, &model.src, TT7::<crseo::Geometric>::new()),
+        key,
+        &thresholds,
+        &progress,
+    )?;
+    sh24.diagnostics.check(
+        &thresholds,
+        sh24_previous.as_ref().map(|previous| {
+            (
+                format!("SH24 calibration v{}", previous.version),
+                previous.condition_number,
+            )
+        }),
+    )?;
+    let path = store.save(&mut sh24)?;
+    println!(" . saved to {path:?}");
+
+    // SH48
+    let key = Key::sh48(&sh48, &aco, &strokes);
+    let sh48_previous = previous(&store, &key);
+    let mut agws_sh48 = ceo::OpticalModel::builder()
+        .gmt(gmt_builder)
+        .source(Source::builder().zenith_azimuth(sh48.zenith(), sh48.azimuth()))
+        .options(vec![ceo::OpticalModelOptions::ShackHartmann {
+            options: ceo::ShackHartmannOptions::Diffractive(
+                *SH48::<crseo::Diffractive>::new().n_sensor(sh48.n_probe()),
+            ),
+            flux_threshold: key.flux_threshold,
+        }])
+        .build()?;
+    let n_probe = sh48.n_probe();
+    let (mut sh48, [push, pull]) = calibrate(
+        &mut agws_sh48,
+        |model| {
+            Calibration::new(
+                &model.gmt,
+                &model.src,
+                SH48::<crseo::Geometric>::new().n_sensor(n_probe),
+            )
+        },
+        key,
+        &thresholds,
+        &progress,
+    )?;
+    // the active optics may not control all the calibrated degrees of freedom,
+    // the closure test is done on the controlled ones only
+    let controlled = aco.controlled();
+    let columns = aco.controlled_columns();
+    let poke = sh48.poke.select_columns(&columns);
+    let reconstructor = diagnostics::reconstructor(&poke, &thresholds)?;
+    let diagnostics = Diagnostics::new(
+        &poke,
+        &reconstructor,
+        &[
+            &push.select_columns(&columns),
+            &pull.select_columns(&columns),
+        ],
+    )?;
+    println!(" . AcO controlled degrees of freedom: {diagnostics}");
+    // the conditioning is compared to the one of the previous calibration of the same subset
+    let reference = sh48_previous.as_ref().and_then(|previous| {
+        previous
+            .controlled
+            .as_ref()
+            .filter(|subset| subset.dofs == controlled)
+            .map(|subset| {
+                (
+                    format!("SH48 calibration v{} AcO", previous.version),
+                    subset.condition_number,
+                )
+            })
+    });
+    diagnostics.check(&thresholds, reference)?;
+    sh48.metadata.controlled = Some(Subset {
+        dofs: controlled,
+        condition_number: diagnostics.condition_number,
+        max_closure_error: diagnostics.max_closure_error,
+    });
+    let path = store.save(&mut sh48)?;
+    println!(" . saved to {path:?}");
+
+    Ok(())
+}
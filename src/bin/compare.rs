@@ -0,0 +1,35 @@
+use grim::{compare::compare_file, config::Tolerances};
+use std::{env, path::PathBuf, process::ExitCode};
+
+fn main() -> anyhow::Result<ExitCode> {
+    env_logger::init();
+
+    let args: Vec<PathBuf> = env::args().skip(1).map(PathBuf::from).collect();
+    let [reference, run] = args.as_slice() else {
+        anyhow::bail!("usage: compare <reference run directory> <run directory>");
+    };
+    let tolerances = Tolerances::from_env()?;
+    let files = env::var("COMPARE_FILES")
+        .unwrap_or_else(|_| "grim.parquet,sh24.parquet,sh48.parquet".to_string());
+
+    println!("Comparing {run:?} to {reference:?}");
+    let mut n_failed = 0;
+    let mut n_stream = 0;
+    for file in files.split(',').map(|file| file.trim()) {
+        for comparison in compare_file(reference, run, file, &tolerances)? {
+            println!(" . {comparison}");
+            n_stream += 1;
+            if !comparison.is_ok() {
+                n_failed += 1;
+            }
+        }
+    }
+    anyhow::ensure!(n_stream > 0, "no result file to compare");
+    if n_failed > 0 {
+        println!("{n_failed}/{n_stream} streams outside the tolerances");
+        Ok(ExitCode::FAILURE)
+    } else {
+        println!("All {n_stream} streams within the tolerances");
+        Ok(ExitCode::SUCCESS)
+    }
+}
@@ -0,0 +1,40 @@
+use grim::{
+    export::{export_run, Format},
+    results::{Run, DECIMATIONS},
+};
+use std::{env, path::PathBuf};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let run_dir = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(grim::data_repo);
+    let run = Run::open(&run_dir)?;
+    let formats = env::var("EXPORT_FORMATS")
+        .unwrap_or_else(|_| "mat".to_string())
+        .split(',')
+        .map(|format| format.parse::<Format>())
+        .collect::<grim::Result<Vec<_>>>()?;
+    let files = env::var("EXPORT_FILES").unwrap_or_else(|_| {
+        DECIMATIONS
+            .iter()
+            .map(|(file, _)| *file)
+            .collect::<Vec<_>>()
+            .join(",")
+    });
+    let files: Vec<&str> = files.split(',').map(|file| file.trim()).collect();
+    let export_dir = env::var("EXPORT_DIR").map_or_else(|_| run_dir.clone(), PathBuf::from);
+
+    println!("Exporting {run_dir:?} to {export_dir:?}");
+    for format in formats {
+        let exported = export_run(&run, &files, format, &export_dir)?;
+        anyhow::ensure!(!exported.is_empty(), "no result file to export");
+        for (path, n_stream) in exported {
+            println!(" . {path:?} ({format}): {n_stream} streams");
+        }
+    }
+
+    Ok(())
+}
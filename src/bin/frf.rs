@@ -0,0 +1,214 @@
+use fem::FEM;
+use grim::{
+    analysis::{Analysis, FrequencyResponse},
+    config::{env_opt, env_or, Excitation},
+    lti::IntegratedModel,
+    manifest::Manifest,
+    results::read_time_series,
+    spectrum::{Detrend, Window},
+};
+use nalgebra::Complex;
+use std::{env, path::PathBuf};
+
+// Parses a signal channel `Name[channel]`, the channel is 0 if omitted
+fn channel(value: &str) -> anyhow::Result<(String, usize)> {
+    let value = value.trim();
+    match value.split_once('[') {
+        Some((name, channel)) => {
+            let channel = channel.strip_suffix(']').ok_or_else(|| {
+                anyhow::anyhow!("invalid signal channel {value:?} (Name[channel])")
+            })?;
+            Ok((name.trim().to_string(), channel.trim().parse()?))
+        }
+        None => Ok((value.to_string(), 0)),
+    }
+}
+
+// Parses a frequency response `In[i]>Out[j]`
+fn response(value: &str) -> anyhow::Result<((String, usize), (String, usize))> {
+    let (input, output) = value
+        .split_once('>')
+        .ok_or_else(|| anyhow::anyhow!("invalid response {value:?} (In[i]>Out[j])"))?;
+    Ok((channel(input)?, channel(output)?))
+}
+
+// Returns the name of the file of the frequency response from `input` to `output`
+fn file_name(input: &(String, usize), output: &(String, usize)) -> String {
+    format!(
+        "frf_{}_{}_to_{}_{}.parquet",
+        input.0, input.1, output.0, output.1
+    )
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let run_dir = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(grim::data_repo);
+    let manifest = Manifest::load(&run_dir)?;
+    let sampling_frequency = manifest.sampling_frequency as f64;
+    let injection = manifest.injection.as_ref();
+    if injection.is_none() {
+        log::warn!("no injection in the run manifest, the transfer functions are H1 estimates");
+    }
+    let file = env::var("FRF_FILE").unwrap_or_else(|_| "injection.parquet".to_string());
+    let responses =
+        env::var("FRF_RESPONSES").map_err(|_| anyhow::anyhow!("FRF_RESPONSES is not set"))?;
+    let min_coherence = env_or("FRF_MIN_COHERENCE", 0.8)?;
+    let mut analysis = Analysis::default()
+        .overlap(env_or("FRF_OVERLAP", 0.5)?)
+        .window(env_or("FRF_WINDOW", Window::Hann)?)
+        .detrend(env_or("FRF_DETREND", Detrend::Constant)?);
+    // one segment per period of the excitation
+    let n_fft = env_opt("FRF_N_FFT")?.or_else(|| match injection.map(|i| &i.excitation) {
+        Some(Excitation::Chirp { duration: t, .. })
+        | Some(Excitation::Multisine { period: t, .. }) => {
+            Some((t * sampling_frequency).round() as usize)
+        }
+        _ => None,
+    });
+    if let Some(n_fft) = n_fft {
+        analysis = analysis.n_fft(n_fft);
+    }
+
+    // linear model of the simulation
+    let closed_loop = if env::var("FRF_MODEL").map_or(true, |value| value != "off") {
+        match FEM::from_env()
+            .map_err(anyhow::Error::from)
+            .and_then(|fem| {
+                Ok(IntegratedModel::new(fem)
+                    .sampling(sampling_frequency)
+                    .proportional_damping(2. / 100.)
+                    .n_block(env_or("LTI_N_BLOCK", 20)?)
+                    .tolerance(env_or("LTI_TOLERANCE", 1e-9)?)
+                    .build()?)
+            }) {
+            Ok(closed_loop) => Some(closed_loop),
+            Err(e) => {
+                log::warn!("the linear model could not be built: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let path = run_dir.join(&file);
+    for value in responses.split(',') {
+        let (input, output) = response(value)?;
+        let (time, u) = read_time_series(&path, &input.0, sampling_frequency)?;
+        let (_, y) = read_time_series(&path, &output.0, sampling_frequency)?;
+        // the samples after the start of the excitation
+        let start = time
+            .iter()
+            .position(|t| *t >= manifest.warm_up)
+            .unwrap_or(time.len());
+        let samples = |x: &[Vec<f64>], channel: usize| -> anyhow::Result<Vec<f64>> {
+            x[start..]
+                .iter()
+                .map(|x| {
+                    x.get(channel)
+                        .cloned()
+                        .ok_or_else(|| anyhow::anyhow!("no channel {channel}"))
+                })
+                .collect()
+        };
+        let (u, y) = (samples(&u, input.1)?, samples(&y, output.1)?);
+        let reference = injection.map(|injection| {
+            injection.samples(
+                time[start..]
+                    .iter()
+                    .map(|t| (t * sampling_frequency).round() as usize),
+            )
+        });
+        let estimate =
+            analysis.transfer_function(reference.as_deref(), &u, &y, sampling_frequency)?;
+
+        let name = format!("{}[{}]>{}[{}]", input.0, input.1, output.0, output.1);
+        let model = closed_loop.as_ref().and_then(|closed_loop| {
+            // the loop of the input is opened at the input
+            let model = closed_loop.open_at(&input.0).and_then(|model| {
+                model.frequency_response(
+                    (&input.0, input.1),
+                    (&output.0, output.1),
+                    &estimate.frequencies,
+                )
+            });
+            match model {
+                Ok(mut model) => {
+                    // the delayed signals are logged one sample late, so the estimate is delayed
+                    // by one sample if the output is delayed and advanced by one sample if the
+                    // input is delayed, the model being opened at the input of the delay
+                    let delay = closed_loop.is_delayed(&output.0) as i32
+                        - closed_loop.is_delayed(&input.0) as i32;
+                    if delay != 0 {
+                        model
+                            .iter_mut()
+                            .zip(&estimate.frequencies)
+                            .for_each(|(h, f)| {
+                                *h *= Complex::new(
+                                    0f64,
+                                    -2f64 * std::f64::consts::PI * f * delay as f64
+                                        / sampling_frequency,
+                                )
+                                .exp()
+                            });
+                    }
+                    Some(model)
+                }
+                Err(e) => {
+                    log::warn!("{name}: no model frequency response: {e}");
+                    None
+                }
+            }
+        });
+        let frf = FrequencyResponse {
+            name,
+            estimate,
+            model,
+        };
+        println!("{frf}");
+        if let Some((db, deg)) = frf.model_error(min_coherence) {
+            println!(
+                " . largest model error where the coherence is larger than {min_coherence}: {db:.2}dB, {deg:.1}deg"
+            );
+        }
+        let path = run_dir.join(file_name(&input, &output));
+        frf.to_parquet(&path)?;
+        println!(" . saved in {path:?}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels() {
+        assert_eq!(
+            channel("MountTorques[2]").unwrap(),
+            ("MountTorques".to_string(), 2)
+        );
+        assert_eq!(channel(" PZTcmd ").unwrap(), ("PZTcmd".to_string(), 0));
+        assert_eq!(
+            channel(" MCM2PZTD [ 13 ] ").unwrap(),
+            ("MCM2PZTD".to_string(), 13)
+        );
+        assert!(channel("MountTorques[2").is_err());
+        assert!(channel("MountTorques[x]").is_err());
+        assert!(channel("MountTorques[-1]").is_err());
+        let (input, output) = response("MountTorques[0]>MountEncoders[1]").unwrap();
+        assert_eq!(input, ("MountTorques".to_string(), 0));
+        assert_eq!(output, ("MountEncoders".to_string(), 1));
+        assert_eq!(
+            file_name(&input, &output),
+            "frf_MountTorques_0_to_MountEncoders_1.parquet"
+        );
+        assert!(response("MountTorques[0]").is_err());
+        assert!(response("MountTorques[0]>MountEncoders[").is_err());
+    }
+}
@@ -0,0 +1,520 @@
+use chrono::prelude::*;
+use dos_actors::{
+    clients::{
+        fsm::*,
+        m1::*,
+        mount::{Mount, MountEncoders, MountSetPoint, MountTorques},
+    },
+    prelude::*,
+};
+use fem::{
+    dos::{DiscreteModalSolver, ExponentialMatrix},
+    fem_io::*,
+    FEM,
+};
+use grim::{
+    config::{env_or, Excitation, Injection},
+    injection::Injector,
+    logging::Logger,
+    manifest::Manifest,
+    shutdown::Shutdown,
+};
+use std::{env, fs::create_dir_all};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let sim_sampling_frequency = 1000_usize;
+    const M1_RATE: usize = 10;
+    assert_eq!(sim_sampling_frequency / M1_RATE, 100); // Hz
+
+    let injection = Injection::from_env(sim_sampling_frequency as f64, 0f64)?
+        .ok_or_else(|| anyhow::anyhow!("INJECTION_SIGNAL is not set"))?;
+    let period = match injection.excitation {
+        Excitation::Chirp { duration, .. } => duration,
+        Excitation::Multisine { period, .. } => period,
+        Excitation::Step => 10f64,
+    };
+    let sim_duration: f64 = env_or("INJECTION_SIM_DURATION", injection.start + 10f64 * period)?;
+    let n_step = (sim_duration * sim_sampling_frequency as f64) as usize;
+    log::info!("Simulation duration: {:6.3}s", sim_duration);
+
+    let local: DateTime<Local> = Local::now();
+    let data_path = grim::data_repo().join(format!("injection-{}", local.to_rfc3339()));
+    create_dir_all(&data_path)?;
+    println!("Data repository: {:?}", &data_path);
+    env::set_var("DATA_REPO", data_path);
+
+    let shutdown = Shutdown::listen();
+    let mut manifest = Manifest::new(sim_sampling_frequency, sim_duration);
+    manifest.warm_up = injection.start;
+    manifest.injection = Some(injection.clone());
+    manifest.save()?;
+    println!(
+        "{:?} injected into {}{:?} from {}s",
+        injection.excitation, injection.signal, injection.channels, injection.start
+    );
+
+    let fem = FEM::from_env()?.static_from_env()?;
+    let n_io = (fem.n_inputs(), fem.n_outputs());
+    let state_space = DiscreteModalSolver::<ExponentialMatrix>::from_fem(fem)
+        .sampling(sim_sampling_frequency as f64)
+        .proportional_damping(2. / 100.)
+        .use_static_gain_compensation(n_io)
+        .ins::<OSSElDriveTorque>()
+        .ins::<OSSAzDriveTorque>()
+        .ins::<OSSRotDriveTorque>()
+        .ins::<OSSHarpointDeltaF>()
+        .ins::<M1ActuatorsSegment1>()
+        .ins::<M1ActuatorsSegment2>()
+        .ins::<M1ActuatorsSegment3>()
+        .ins::<M1ActuatorsSegment4>()
+        .ins::<M1ActuatorsSegment5>()
+        .ins::<M1ActuatorsSegment6>()
+        .ins::<M1ActuatorsSegment7>()
+        .ins::<MCM2SmHexF>()
+        .ins::<MCM2PZTF>()
+        .outs::<OSSAzEncoderAngle>()
+        .outs::<OSSElEncoderAngle>()
+        .outs::<OSSRotEncoderAngle>()
+        .outs::<OSSHardpointD>()
+        .outs::<OSSM1Lcl>()
+        .outs::<MCM2Lcl6D>()
+        .outs::<MCM2SmHexD>()
+        .outs::<MCM2PZTD>()
+        .build()?;
+    println!("{state_space}");
+
+    let logging = Logger::builder()
+        .filename("injection.parquet")
+        .row_group_size(sim_sampling_frequency)
+        .sampling_frequency(sim_sampling_frequency as f64)
+        .start_time(injection.start)
+        .source("GMT Finite Element Model")
+        .stream_source::<MountSetPoint>("Injection")
+        .stream_source::<MountTorques>("Injection")
+        .stream_source::<M1RBMcmd>("Injection")
+        .stream_source::<OSSHarpointDeltaF>("Injection")
+        .stream_source::<M2poscmd>("Injection")
+        .stream_source::<MCM2SmHexF>("Injection")
+        .stream_source::<PZTcmd>("Injection")
+        .stream_source::<MCM2PZTF>("Injection")
+        .stream_unit::<MountEncoders>("rad")
+        .stream_unit::<OSSM1Lcl>("m, rad")
+        .stream_unit::<MCM2Lcl6D>("m, rad")
+        .shutdown(&shutdown)
+        .build()
+        .into_arcx();
+    let mut sink = Terminator::<_>::new(logging.clone()).name("Injection Logs");
+
+    let mut fem: Actor<_> = (state_space, "GMT Finite Element Model").into();
+    let mut mount: Actor<_> = (Mount::new(), "Mount Control").into();
+    let mut m1_hardpoints: Actor<_> =
+        (m1_ctrl::hp_dynamics::Controller::new(), "M1 Hardpoints").into();
+    let mut m1_hp_loadcells: Actor<_, 1, M1_RATE> =
+        (m1_ctrl::hp_load_cells::Controller::new(), "M1 LoadCells").into();
+    let mut m2_positionner: Actor<_> =
+        (fsm::positionner::Controller::new(), "M2 Positionners").into();
+    let mut m2_piezostack: Actor<_> =
+        (fsm::piezostack::Controller::new(), "M2 PZT Actuators").into();
+
+    // the excitation is injected into one of the signals, the other injectors pass their signal through
+    let injection = Some(&injection);
+    let mut mount_set_point_injector: Actor<_> = (
+        Injector::<MountSetPoint>::from_config(injection),
+        "MountSetPoint Injection",
+    )
+        .into();
+    let mut mount_torques_injector: Actor<_> = (
+        Injector::<MountTorques>::from_config(injection),
+        "MountTorques Injection",
+    )
+        .into();
+    let mut m1_rbm_cmd_injector: Actor<_> = (
+        Injector::<M1RBMcmd>::from_config(injection),
+        "M1RBMcmd Injection",
+    )
+        .into();
+    let mut m1_hardpoints_injector: Actor<_> = (
+        Injector::<OSSHarpointDeltaF>::from_config(injection),
+        "OSSHarpointDeltaF Injection",
+    )
+        .into();
+    let mut m2_pos_cmd_injector: Actor<_> = (
+        Injector::<M2poscmd>::from_config(injection),
+        "M2poscmd Injection",
+    )
+        .into();
+    let mut m2_positionner_injector: Actor<_> = (
+        Injector::<MCM2SmHexF>::from_config(injection),
+        "MCM2SmHexF Injection",
+    )
+        .into();
+    let mut pzt_cmd_injector: Actor<_> = (
+        Injector::<PZTcmd>::from_config(injection),
+        "PZTcmd Injection",
+    )
+        .into();
+    let mut m2_piezostack_injector: Actor<_> = (
+        Injector::<MCM2PZTF>::from_config(injection),
+        "MCM2PZTF Injection",
+    )
+        .into();
+
+    // MOUNT
+    let mut mount_set_point: Initiator<_> =
+        (shutdown.interruptible(Signals::new(3, n_step)), "Mount 0pt").into();
+    mount_set_point
+        .add_output()
+        .build::<MountSetPoint>()
+        .into_input(&mut mount_set_point_injector);
+    mount_set_point_injector
+        .add_output()
+        .multiplex(2)
+        .build::<MountSetPoint>()
+        .into_input(&mut mount)
+        .into_input(&mut sink);
+    mount
+        .add_output()
+        .build::<MountTorques>()
+        .into_input(&mut mount_torques_injector);
+    mount_torques_injector
+        .add_output()
+        .multiplex(2)
+        .build::<MountTorques>()
+        .into_input(&mut fem)
+        .into_input(&mut sink);
+    fem.add_output()
+        .bootstrap()
+        .multiplex(2)
+        .build::<MountEncoders>()
+        .into_input(&mut mount)
+        .into_input(&mut sink);
+
+    // M1 HARDPOINTS
+    let mut m1_rbm_cmd: Initiator<_> = (
+        shutdown.interruptible(Signals::new(42, n_step)),
+        "M1 RBM 0pt",
+    )
+        .into();
+    m1_rbm_cmd
+        .add_output()
+        .build::<M1RBMcmd>()
+        .into_input(&mut m1_rbm_cmd_injector);
+    m1_rbm_cmd_injector
+        .add_output()
+        .multiplex(2)
+        .build::<M1RBMcmd>()
+        .into_input(&mut m1_hardpoints)
+        .into_input(&mut sink);
+    m1_hardpoints
+        .add_output()
+        .build::<OSSHarpointDeltaF>()
+        .into_input(&mut m1_hardpoints_injector);
+    m1_hardpoints_injector
+        .add_output()
+        .multiplex(3)
+        .build::<OSSHarpointDeltaF>()
+        .into_input(&mut fem)
+        .into_input(&mut m1_hp_loadcells)
+        .into_input(&mut sink);
+    fem.add_output()
+        .bootstrap()
+        .multiplex(2)
+        .build::<OSSHardpointD>()
+        .into_input(&mut m1_hp_loadcells)
+        .into_input(&mut sink);
+
+    // M1 ACTUATORS
+    let mut m1_segment1: Actor<_, M1_RATE, 1> = (
+        m1_ctrl::actuators::segment1::Controller::new(),
+        "M1S1 Actuators",
+    )
+        .into();
+    let mut m1_segment2: Actor<_, M1_RATE, 1> = (
+        m1_ctrl::actuators::segment2::Controller::new(),
+        "M1S2 Actuators",
+    )
+        .into();
+    let mut m1_segment3: Actor<_, M1_RATE, 1> = (
+        m1_ctrl::actuators::segment3::Controller::new(),
+        "M1S3 Actuators",
+    )
+        .into();
+    let mut m1_segment4: Actor<_, M1_RATE, 1> = (
+        m1_ctrl::actuators::segment4::Controller::new(),
+        "M1S4 Actuators",
+    )
+        .into();
+    let mut m1_segment5: Actor<_, M1_RATE, 1> = (
+        m1_ctrl::actuators::segment5::Controller::new(),
+        "M1S5 Actuators",
+    )
+        .into();
+    let mut m1_segment6: Actor<_, M1_RATE, 1> = (
+        m1_ctrl::actuators::segment6::Controller::new(),
+        "M1S6 Actuators",
+    )
+        .into();
+    let mut m1_segment7: Actor<_, M1_RATE, 1> = (
+        m1_ctrl::actuators::segment7::Controller::new(),
+        "M1S7 Actuators",
+    )
+        .into();
+    let mut m1s1f: Initiator<_, M1_RATE> = (
+        shutdown.interruptible(Signals::new(335, n_step)),
+        "M1S1 0pt",
+    )
+        .into();
+    let mut m1s2f: Initiator<_, M1_RATE> = (
+        shutdown.interruptible(Signals::new(335, n_step)),
+        "M1S2 0pt",
+    )
+        .into();
+    let mut m1s3f: Initiator<_, M1_RATE> = (
+        shutdown.interruptible(Signals::new(335, n_step)),
+        "M1S3 0pt",
+    )
+        .into();
+    let mut m1s4f: Initiator<_, M1_RATE> = (
+        shutdown.interruptible(Signals::new(335, n_step)),
+        "M1S4 0pt",
+    )
+        .into();
+    let mut m1s5f: Initiator<_, M1_RATE> = (
+        shutdown.interruptible(Signals::new(335, n_step)),
+        "M1S5 0pt",
+    )
+        .into();
+    let mut m1s6f: Initiator<_, M1_RATE> = (
+        shutdown.interruptible(Signals::new(335, n_step)),
+        "M1S6 0pt",
+    )
+        .into();
+    let mut m1s7f: Initiator<_, M1_RATE> = (
+        shutdown.interruptible(Signals::new(306, n_step)),
+        "M1S7 0pt",
+    )
+        .into();
+    m1s1f
+        .add_output()
+        .build::<S1SAoffsetFcmd>()
+        .into_input(&mut m1_segment1);
+    m1s2f
+        .add_output()
+        .build::<S2SAoffsetFcmd>()
+        .into_input(&mut m1_segment2);
+    m1s3f
+        .add_output()
+        .build::<S3SAoffsetFcmd>()
+        .into_input(&mut m1_segment3);
+    m1s4f
+        .add_output()
+        .build::<S4SAoffsetFcmd>()
+        .into_input(&mut m1_segment4);
+    m1s5f
+        .add_output()
+        .build::<S5SAoffsetFcmd>()
+        .into_input(&mut m1_segment5);
+    m1s6f
+        .add_output()
+        .build::<S6SAoffsetFcmd>()
+        .into_input(&mut m1_segment6);
+    m1s7f
+        .add_output()
+        .build::<S7SAoffsetFcmd>()
+        .into_input(&mut m1_segment7);
+    m1_hp_loadcells
+        .add_output()
+        .bootstrap()
+        .build::<S1HPLC>()
+        .into_input(&mut m1_segment1);
+    m1_hp_loadcells
+        .add_output()
+        .bootstrap()
+        .build::<S2HPLC>()
+        .into_input(&mut m1_segment2);
+    m1_hp_loadcells
+        .add_output()
+        .bootstrap()
+        .build::<S3HPLC>()
+        .into_input(&mut m1_segment3);
+    m1_hp_loadcells
+        .add_output()
+        .bootstrap()
+        .build::<S4HPLC>()
+        .into_input(&mut m1_segment4);
+    m1_hp_loadcells
+        .add_output()
+        .bootstrap()
+        .build::<S5HPLC>()
+        .into_input(&mut m1_segment5);
+    m1_hp_loadcells
+        .add_output()
+        .bootstrap()
+        .build::<S6HPLC>()
+        .into_input(&mut m1_segment6);
+    m1_hp_loadcells
+        .add_output()
+        .bootstrap()
+        .build::<S7HPLC>()
+        .into_input(&mut m1_segment7);
+    m1_segment1
+        .add_output()
+        .build::<M1ActuatorsSegment1>()
+        .into_input(&mut fem);
+    m1_segment2
+        .add_output()
+        .build::<M1ActuatorsSegment2>()
+        .into_input(&mut fem);
+    m1_segment3
+        .add_output()
+        .build::<M1ActuatorsSegment3>()
+        .into_input(&mut fem);
+    m1_segment4
+        .add_output()
+        .build::<M1ActuatorsSegment4>()
+        .into_input(&mut fem);
+    m1_segment5
+        .add_output()
+        .build::<M1ActuatorsSegment5>()
+        .into_input(&mut fem);
+    m1_segment6
+        .add_output()
+        .build::<M1ActuatorsSegment6>()
+        .into_input(&mut fem);
+    m1_segment7
+        .add_output()
+        .build::<M1ActuatorsSegment7>()
+        .into_input(&mut fem);
+
+    // M2 POSITIONNERS
+    let mut m2_pos_cmd: Initiator<_> = (
+        shutdown.interruptible(Signals::new(42, n_step)),
+        "M2 RBM 0pt",
+    )
+        .into();
+    m2_pos_cmd
+        .add_output()
+        .build::<M2poscmd>()
+        .into_input(&mut m2_pos_cmd_injector);
+    m2_pos_cmd_injector
+        .add_output()
+        .multiplex(2)
+        .build::<M2poscmd>()
+        .into_input(&mut m2_positionner)
+        .into_input(&mut sink);
+    m2_positionner
+        .add_output()
+        .build::<MCM2SmHexF>()
+        .into_input(&mut m2_positionner_injector);
+    m2_positionner_injector
+        .add_output()
+        .multiplex(2)
+        .build::<MCM2SmHexF>()
+        .into_input(&mut fem)
+        .into_input(&mut sink);
+    fem.add_output()
+        .bootstrap()
+        .multiplex(2)
+        .build::<MCM2SmHexD>()
+        .into_input(&mut m2_positionner)
+        .into_input(&mut sink);
+
+    // M2 PIEZOSTACKS
+    let mut pzt_cmd: Initiator<_> = (
+        shutdown.interruptible(Signals::new(21, n_step)),
+        "M2 PZT 0pt",
+    )
+        .into();
+    pzt_cmd
+        .add_output()
+        .build::<PZTcmd>()
+        .into_input(&mut pzt_cmd_injector);
+    pzt_cmd_injector
+        .add_output()
+        .multiplex(2)
+        .build::<PZTcmd>()
+        .into_input(&mut m2_piezostack)
+        .into_input(&mut sink);
+    m2_piezostack
+        .add_output()
+        .build::<MCM2PZTF>()
+        .into_input(&mut m2_piezostack_injector);
+    m2_piezostack_injector
+        .add_output()
+        .multiplex(2)
+        .build::<MCM2PZTF>()
+        .into_input(&mut fem)
+        .into_input(&mut sink);
+    fem.add_output()
+        .bootstrap()
+        .multiplex(2)
+        .build::<MCM2PZTD>()
+        .into_input(&mut m2_piezostack)
+        .into_input(&mut sink);
+
+    fem.add_output()
+        .bootstrap()
+        .build::<OSSM1Lcl>()
+        .into_input(&mut sink);
+    fem.add_output()
+        .bootstrap()
+        .build::<MCM2Lcl6D>()
+        .into_input(&mut sink);
+
+    Model::new(vec![
+        Box::new(mount_set_point),
+        Box::new(mount_set_point_injector),
+        Box::new(mount),
+        Box::new(mount_torques_injector),
+        Box::new(m1_rbm_cmd),
+        Box::new(m1_rbm_cmd_injector),
+        Box::new(m1_hardpoints),
+        Box::new(m1_hardpoints_injector),
+        Box::new(m1_hp_loadcells),
+        Box::new(m1s1f),
+        Box::new(m1s2f),
+        Box::new(m1s3f),
+        Box::new(m1s4f),
+        Box::new(m1s5f),
+        Box::new(m1s6f),
+        Box::new(m1s7f),
+        Box::new(m1_segment1),
+        Box::new(m1_segment2),
+        Box::new(m1_segment3),
+        Box::new(m1_segment4),
+        Box::new(m1_segment5),
+        Box::new(m1_segment6),
+        Box::new(m1_segment7),
+        Box::new(m2_pos_cmd),
+        Box::new(m2_pos_cmd_injector),
+        Box::new(m2_positionner),
+        Box::new(m2_positionner_injector),
+        Box::new(pzt_cmd),
+        Box::new(pzt_cmd_injector),
+        Box::new(m2_piezostack),
+        Box::new(m2_piezostack_injector),
+        Box::new(fem),
+        Box::new(sink),
+    ])
+    .name("injection")
+    .flowchart()
+    .check()?
+    .run()
+    .wait()
+    .await?;
+
+    let last_time = {
+        let mut logging = logging.lock().await;
+        (*logging).finish()?;
+        (*logging).size() as f64 / sim_sampling_frequency as f64
+    };
+    manifest.end(last_time, shutdown.is_requested()).save()?;
+    if shutdown.is_requested() {
+        log::warn!("Simulation truncated at {:.3}s", last_time);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use fem::FEM;
+use grim::{
+    config::{env_opt, env_or},
+    export::Format,
+    lti::{log_frequencies, IntegratedModel},
+    results::write_columns,
+};
+use std::{env, fs::create_dir_all};
+
+// Parses a signal channel `Name[channel]`, the channel is 0 if omitted
+fn channel(value: &str) -> anyhow::Result<(String, usize)> {
+    let value = value.trim();
+    match value.split_once('[') {
+        Some((name, channel)) => {
+            let channel = channel.strip_suffix(']').ok_or_else(|| {
+                anyhow::anyhow!("invalid signal channel {value:?} (Name[channel])")
+            })?;
+            Ok((name.trim().to_string(), channel.trim().parse()?))
+        }
+        None => Ok((value.to_string(), 0)),
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let sampling_frequency = 1000f64;
+    let mut model = IntegratedModel::new(FEM::from_env()?)
+        .sampling(sampling_frequency)
+        .proportional_damping(2. / 100.)
+        .n_block(env_or("LTI_N_BLOCK", 20)?)
+        .tolerance(env_or("LTI_TOLERANCE", 1e-9)?);
+    if let Some(max_eigen_frequency) = env_opt("LTI_MAX_EIGEN_FREQUENCY")? {
+        model = model.max_eigen_frequency(max_eigen_frequency);
+    }
+    let closed_loop = model.build()?;
+    let state_space = closed_loop.build()?;
+    println!("Closed-loop model: {state_space}");
+
+    let mut poles: Vec<_> = state_space
+        .poles()
+        .into_iter()
+        .filter(|z| z.im >= 0f64)
+        .map(|z| {
+            // equivalent continuous pole
+            let s = z.ln() * sampling_frequency;
+            (
+                s.norm() / (2. * std::f64::consts::PI),
+                -s.re / s.norm(),
+                z.norm(),
+            )
+        })
+        .collect();
+    poles.sort_by(|a, b| b.2.total_cmp(&a.2));
+    println!(
+        "Spectral radius: {:.6} ({})",
+        state_space.spectral_radius(),
+        if state_space.is_stable() {
+            "stable"
+        } else {
+            "UNSTABLE"
+        }
+    );
+    println!("Slowest poles:");
+    for (frequency, damping, magnitude) in poles.iter().take(10) {
+        println!(" . {frequency:9.3}Hz, damping: {damping:9.6}, |z|: {magnitude:.6}");
+    }
+
+    let data_repo = grim::data_repo();
+    create_dir_all(&data_repo)?;
+    let format = env_or("LTI_FORMAT", Format::Mat)?;
+    let path = data_repo.join("lti").with_extension(format.extension());
+    state_space.to_mat(&path, format)?;
+    println!("Closed-loop model saved in {path:?}");
+
+    let frequencies = log_frequencies(
+        env_or("LTI_F_MIN", 0.1)?,
+        env_or("LTI_F_MAX", sampling_frequency / 2.)?,
+        env_or("LTI_N_FREQUENCY", 1000)?,
+    );
+    let loops = env::var("LTI_LOOPS")
+        .unwrap_or_else(|_| "MountTorques[0],MCM2SmHexF[0],MCM2PZTF[0]".to_string());
+    for signal in loops.split(',').filter(|signal| !signal.trim().is_empty()) {
+        let (name, channel) = channel(signal)?;
+        let margins = closed_loop.margins(&name, channel, &frequencies)?;
+        println!("{name}[{channel}] loop: {margins}");
+    }
+
+    if let Ok(responses) = env::var("LTI_RESPONSES") {
+        let mut columns = vec![(
+            "Frequency".to_string(),
+            frequencies.iter().map(|f| vec![*f]).collect::<Vec<_>>(),
+        )];
+        for response in responses.split(',') {
+            let (input, output) = response
+                .split_once('>')
+                .ok_or_else(|| anyhow::anyhow!("invalid response {response:?} (In[i]>Out[j])"))?;
+            let (input, output) = (channel(input)?, channel(output)?);
+            let h = state_space.frequency_response(
+                (&input.0, input.1),
+                (&output.0, output.1),
+                &frequencies,
+            )?;
+            let label = format!("{}[{}]>{}[{}]", input.0, input.1, output.0, output.1);
+            columns.push((
+                format!("{label} magnitude"),
+                h.iter().map(|h| vec![h.norm()]).collect(),
+            ));
+            columns.push((
+                format!("{label} phase"),
+                h.iter().map(|h| vec![h.arg().to_degrees()]).collect(),
+            ));
+        }
+        let path = data_repo.join("lti-responses.parquet");
+        write_columns(&path, &columns)?;
+        println!("Frequency responses saved in {path:?}");
+    }
+
+    Ok(())
+}
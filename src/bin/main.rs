@@ -1,7 +1,6 @@
 use chrono::prelude::*;
 use dos_actors::{
     clients::{
-        arrow_client::Arrow,
         ceo::M1modes,
         mount::{Mount, MountEncoders, MountSetPoint, MountTorques},
         windloads,
@@ -13,12 +12,17 @@ use fem::{
     fem_io::*,
     FEM,
 };
+use grim::{
+    logging::Logger, manifest::Manifest, probe::Probes, progress::Progress, shutdown::Shutdown,
+    telemetry::Telemetry,
+};
 use nalgebra as na;
 use parse_monitors::cfd;
 use std::{
     env,
     fs::{create_dir, File},
     path::Path,
+    time::Duration,
 };
 
 fn fig_2_mode(sid: u32) -> na::DMatrix<f64> {
@@ -34,6 +38,14 @@ fn fig_2_mode(sid: u32) -> na::DMatrix<f64> {
     }
 }
 
+// Locations of the FEM inputs of the CFD loads
+fn cfd_locations(fem: &FEM) -> Vec<Vec<f64>> {
+    fem.inputs[0]
+        .as_ref()
+        .map(|input| input.get_by(|x| x.properties.location.clone()))
+        .unwrap_or_default()
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::init();
@@ -62,20 +74,154 @@ async fn main() -> anyhow::Result<()> {
     let n_sh48_exposure = env::var("SH48_N_STEP")?.parse::<usize>()?;
     let sim_duration = (CFD_DELAY + n_sh48_exposure * SH48_RATE / sim_sampling_frequency) as f64;
     log::info!("Simulation duration: {:6.3}s", sim_duration);
+    // the wind speed of the CFD case is 7m/s
+    let wind_loads = grim::config::WindLoads::from_env(7f64)?;
+    let figures = grim::figures::Format::from_env()?;
+    #[cfg(feature = "full")]
+    let sh48 = grim::config::Sh48::from_env()?;
+    #[cfg(feature = "full")]
+    let aco = grim::config::Aco::from_env(&sh48)?;
+    #[cfg(feature = "full")]
+    let optical_disturbances = grim::config::Disturbances::from_env(sim_sampling_frequency)?;
+    // pixel scales of the SH24 and SH48 detector frames [arcsec]
+    #[cfg(feature = "full")]
+    let (sh24_pixel_scale, sh48_pixel_scale) = (
+        grim::config::env_or("SH24_PIXEL_SCALE", 0.31)?,
+        grim::config::env_or("SH48_PIXEL_SCALE", 0.37)?,
+    );
+    #[cfg(feature = "full")]
+    let (sh24_reconstructor, sh48_reconstructor) = {
+        use grim::{
+            calibration::{Key, Store},
+            config::Strokes,
+            diagnostics::{self, Diagnostics, Thresholds},
+        };
+        let store = Store::from_env();
+        let strokes = Strokes::from_env()?;
+        let thresholds = Thresholds::from_env()?;
+        let sh24_calibration = store.load(&Key::sh24(&strokes))?;
+        println!(
+            "SH24 calibration v{} [{}x{}]",
+            sh24_calibration.metadata.version,
+            sh24_calibration.metadata.n_data,
+            sh24_calibration.metadata.n_dof
+        );
+        // closure test of the calibration
+        sh24_calibration.diagnostics.check(&thresholds, None)?;
+        let sh48_calibration = store.load(&Key::sh48(&sh48, &aco, &strokes))?;
+        println!(
+            "SH48 calibration v{} [{}x{}]",
+            sh48_calibration.metadata.version,
+            sh48_calibration.metadata.n_data,
+            sh48_calibration.metadata.n_dof
+        );
+        // Reconstructor of the controlled degrees of freedom
+        let dof_2_wfs = sh48_calibration
+            .poke
+            .select_columns(&aco.controlled_columns());
+        println!(
+            " . AcO controlled degrees of freedom: {} ({} rigid body motions)",
+            dof_2_wfs.ncols(),
+            dof_2_wfs.ncols() - sh48.n_dof()
+        );
+        let wfs_2_dof = diagnostics::reconstructor(&dof_2_wfs, &thresholds)?;
+        // the closure test of the controlled degrees of freedom is done by `calibrate`
+        let diagnostics = Diagnostics::new(&dof_2_wfs, &wfs_2_dof, &[])?;
+        println!(" . {diagnostics}");
+        diagnostics.to_json(grim::data_repo().join("sh48-diagnostics.json"))?;
+        // the conditioning is compared to the one of the controlled degrees of freedom
+        // checked by `calibrate`, controlling more degrees of freedom may worsen it
+        let Some(reference) = sh48_calibration.metadata.controlled.as_ref() else {
+            anyhow::bail!(
+                "SH48 calibration v{} has no AcO controlled degrees of freedom (run `calibrate` again)",
+                sh48_calibration.metadata.version
+            );
+        };
+        if reference.dofs != aco.controlled() {
+            log::warn!(
+                "the AcO controlled degrees of freedom differ from the ones of the SH48 calibration v{}, their closure is not tested",
+                sh48_calibration.metadata.version
+            );
+        } else if reference.max_closure_error.is_nan()
+            || reference.max_closure_error > thresholds.max_closure_error
+        {
+            anyhow::bail!(
+                "SH48 calibration v{} AcO closure error {:e} is larger than {:e}",
+                sh48_calibration.metadata.version,
+                reference.max_closure_error,
+                thresholds.max_closure_error
+            );
+        }
+        diagnostics.check(
+            &thresholds,
+            Some((
+                format!(
+                    "SH48 calibration v{} AcO",
+                    sh48_calibration.metadata.version
+                ),
+                reference.condition_number,
+            )),
+        )?;
+        (sh24_calibration.reconstructor, wfs_2_dof)
+    };
 
+    let shutdown = Shutdown::listen();
+    let mut manifest = Manifest::new(sim_sampling_frequency, sim_duration);
+    manifest.warm_up = CFD_DELAY as f64;
+    manifest.wind_loads = Some(wind_loads.clone());
+    #[cfg(feature = "full")]
+    {
+        manifest.disturbances = Some(optical_disturbances.clone());
+    }
+    manifest.save()?;
+
+    let probes = if env::var("GRIM_PROFILE").is_ok() {
+        Probes::with_profiling()
+    } else {
+        Probes::default()
+    };
+    let telemetry = Telemetry::new(
+        probes.clone(),
+        "GMT Finite Element Model",
+        sim_sampling_frequency,
+        sim_duration,
+    );
+    if let Ok(addr) = env::var("GRIM_TELEMETRY") {
+        telemetry.spawn_server(addr);
+    }
+    let progress = Progress::new();
+
+    let fem_build = progress.phase("FEM build", None);
     let (cfd_loads, state_space) = {
         use dos_actors::clients::windloads::WindLoads::*;
-        let loads = vec![
-            TopEnd,
-            M2Baffle,
-            Trusses,
-            M1Baffle,
-            MirrorCovers,
-            LaserGuideStars,
-            CRings,
-            GIR,
-            Platforms,
-        ];
+        use grim::{
+            cache::{CacheKey, CacheWriter, CachedLoads},
+            config::WindLoadsSource,
+            stitch::Stitched,
+            synthetic::{Generator, Spectra, WindSource},
+            uid_name,
+            windloads::{Group, ScaledLoads},
+        };
+        let loads: Vec<_> = [
+            (TopEnd, "TopEnd"),
+            (M2Baffle, "M2Baffle"),
+            (Trusses, "Trusses"),
+            (M1Baffle, "M1Baffle"),
+            (MirrorCovers, "MirrorCovers"),
+            (LaserGuideStars, "LaserGuideStars"),
+            (CRings, "CRings"),
+            (GIR, "GIR"),
+            (Platforms, "Platforms"),
+        ]
+        .into_iter()
+        .filter_map(|(load, name)| wind_loads.scale(name).map(|scale| (load, name, scale)))
+        .collect();
+        // the CFD2021106F nodes are ordered by groups
+        let groups: Vec<_> = loads
+            .iter()
+            .map(|(load, name, scale)| Group::new(*name, load.keys().len(), *scale))
+            .collect();
+        let loads: Vec<_> = loads.into_iter().map(|(load, ..)| load).collect();
         let mut fem = FEM::from_env()?.static_from_env()?;
         let n_io = (fem.n_inputs(), fem.n_outputs());
         //println!("{}", fem);
@@ -84,17 +230,185 @@ async fn main() -> anyhow::Result<()> {
         println!("CFD CASE ({}Hz): {}", cfd_sampling_frequency, cfd_case);
         let cfd_path = cfd::Baseline::<2021>::path().join(cfd_case.to_string());
 
-        let cfd_loads =
+        // the CFD record is repeated if the simulation is longer than the record
+        let record_duration = wind_loads
+            .time_range
+            .map_or(sim_duration, |(start, end)| end - start);
+        let mut cfd_loads =
             windloads::CfdLoads::foh(cfd_path.to_str().unwrap(), sim_sampling_frequency)
-                .duration(sim_duration as f64)
-                //.time_range((200f64, 340f64))
+                .duration(record_duration)
                 //.nodes(loads.iter().flat_map(|x| x.keys()).collect(), locations)
-                .loads(loads, &mut fem, 0)
-                .m1_segments()
-                .m2_segments()
-                .build()
-                .unwrap()
-                .into_arcx();
+                .loads(loads, &mut fem, 0);
+        if let Some(time_range) = wind_loads.time_range {
+            cfd_loads = cfd_loads.time_range(time_range);
+        }
+        let mut m1_scaled_loads = vec![];
+        if let Some(scale) = wind_loads.scale("M1Segments") {
+            cfd_loads = cfd_loads.m1_segments();
+            m1_scaled_loads.push(Group::new("M1Segments", 7, scale));
+        }
+        let mut m2_scaled_loads = vec![];
+        if let Some(scale) = wind_loads.scale("M2Segments") {
+            cfd_loads = cfd_loads.m2_segments();
+            m2_scaled_loads.push(Group::new("M2Segments", 7, scale));
+        }
+        let n_step = (sim_duration * sim_sampling_frequency as f64).round() as usize;
+        let n_fade = (wind_loads.cross_fade * sim_sampling_frequency as f64).round() as usize;
+        let n_node: usize = groups.iter().map(|group| group.n_node).sum();
+        // the synthetic loads are not repeated and the CFD loads builder is only used
+        // to select the FEM inputs of the wind loads
+        let (cfd_loads, n_record) = match &wind_loads.source {
+            WindLoadsSource::Cfd => {
+                let n_record = (record_duration * sim_sampling_frequency as f64).round() as usize;
+                let source = match &wind_loads.cache {
+                    Some(dir) => {
+                        let key = CacheKey {
+                            cfd_case: cfd_case.to_string(),
+                            loads: wind_loads
+                                .groups
+                                .iter()
+                                .map(|(name, _)| name.clone())
+                                .collect(),
+                            sampling_frequency: sim_sampling_frequency,
+                            duration: record_duration,
+                            time_range: wind_loads.time_range,
+                        };
+                        let (m1, m2) = (
+                            wind_loads.is_enabled("M1Segments"),
+                            wind_loads.is_enabled("M2Segments"),
+                        );
+                        let mut outputs = vec![(uid_name::<CFD2021106F>(), 6 * n_node)];
+                        if m1 {
+                            outputs.push((uid_name::<OSSM1Lcl6F>(), 42));
+                        }
+                        if m2 {
+                            outputs.push((uid_name::<MCM2LclForce6F>(), 42));
+                        }
+                        let locations = cfd_locations(&fem);
+                        let cached = match CachedLoads::open(dir, &key, &outputs, &locations)? {
+                            Some(cached) => cached,
+                            None => {
+                                let mut phase = progress.phase("CFD loads cache", Some(n_record));
+                                let mut client = cfd_loads.build().unwrap();
+                                let mut writer = CacheWriter::new(
+                                    dir,
+                                    key.clone(),
+                                    n_record,
+                                    outputs.clone(),
+                                    locations.clone(),
+                                )?;
+                                while writer.step() < n_record {
+                                    client.update();
+                                    let more = writer.pull::<CFD2021106F, _>(&mut client)?
+                                        && (!m1 || writer.pull::<OSSM1Lcl6F, _>(&mut client)?)
+                                        && (!m2
+                                            || writer.pull::<MCM2LclForce6F, _>(&mut client)?);
+                                    if !more {
+                                        break;
+                                    }
+                                    if writer.step() % sim_sampling_frequency == 0 {
+                                        phase.set(writer.step());
+                                    }
+                                }
+                                phase.finish();
+                                println!("CFD loads cached in {:?}", writer.finish()?);
+                                CachedLoads::open(dir, &key, &outputs, &locations)?
+                                    .ok_or_else(|| anyhow::anyhow!("CFD loads cache not found"))?
+                            }
+                        };
+                        println!(
+                            "CFD loads read from the cache {:?} ({} steps)",
+                            Path::new(dir).join(key.file_name()),
+                            cached.n_step()
+                        );
+                        WindSource::Cached(cached)
+                    }
+                    None => WindSource::Cfd(cfd_loads.build().unwrap()),
+                };
+                (source, n_record)
+            }
+            WindLoadsSource::Psd { file } => {
+                let spectra = Spectra::load(file)?;
+                anyhow::ensure!(
+                    spectra.sampling_frequency == sim_sampling_frequency as f64,
+                    "wind loads spectra sampled at {}Hz, expected {}Hz",
+                    spectra.sampling_frequency,
+                    sim_sampling_frequency
+                );
+                spectra.check::<CFD2021106F>(6 * n_node)?;
+                if wind_loads.is_enabled("M1Segments") {
+                    spectra.check::<OSSM1Lcl6F>(42)?;
+                }
+                if wind_loads.is_enabled("M2Segments") {
+                    spectra.check::<MCM2LclForce6F>(42)?;
+                }
+                println!("Wind loads synthesized from {file}");
+                (
+                    WindSource::Synthetic(Generator::new(&spectra, wind_loads.seed, n_fade)),
+                    n_step,
+                )
+            }
+            WindLoadsSource::VonKarman {
+                force_std,
+                moment_std,
+                length_scale,
+            } => {
+                let spectra = Spectra::new(
+                    sim_sampling_frequency as f64,
+                    grim::config::env_or("WIND_LOADS_PSD_N_FFT", 8192)?,
+                )
+                .von_karman::<CFD2021106F>(
+                    n_node,
+                    *force_std,
+                    *moment_std,
+                    wind_loads.wind_speed,
+                    *length_scale,
+                )
+                .von_karman::<OSSM1Lcl6F>(
+                    7,
+                    *force_std,
+                    *moment_std,
+                    wind_loads.wind_speed,
+                    *length_scale,
+                )
+                .von_karman::<MCM2LclForce6F>(
+                    7,
+                    *force_std,
+                    *moment_std,
+                    wind_loads.wind_speed,
+                    *length_scale,
+                );
+                println!("Wind loads synthesized from von Karman spectra");
+                (
+                    WindSource::Synthetic(Generator::new(&spectra, wind_loads.seed, n_fade)),
+                    n_step,
+                )
+            }
+        };
+        let cfd_loads = Stitched::new(cfd_loads, n_record, n_fade, n_step)
+            .sampling_frequency(sim_sampling_frequency as f64);
+        if let WindSource::Cfd(_) = &*cfd_loads {
+            // the record of the CFD loads that are not cached is repeated from memory
+            let n_segment_loads = ["M1Segments", "M2Segments"]
+                .into_iter()
+                .filter(|group| wind_loads.is_enabled(group))
+                .count();
+            cfd_loads.check_memory(6 * n_node + 42 * n_segment_loads)?;
+        }
+        if cfd_loads.is_stitched() {
+            println!(
+                "CFD loads: {:.3}s record repeated every {:.3}s",
+                record_duration,
+                cfd_loads.period() as f64 / sim_sampling_frequency as f64
+            );
+        }
+        let cfd_loads = ScaledLoads::new(cfd_loads)
+            .output::<CFD2021106F>(groups)
+            .output::<OSSM1Lcl6F>(m1_scaled_loads)
+            .output::<MCM2LclForce6F>(m2_scaled_loads);
+        let cfd_loads = probes
+            .probe("CFD Loads", shutdown.interruptible(cfd_loads))
+            .into_arcx();
 
         (cfd_loads, {
             /*
@@ -137,7 +451,7 @@ async fn main() -> anyhow::Result<()> {
                         Default::default(),
                     )?;
             */
-            DiscreteModalSolver::<ExponentialMatrix>::from_fem(fem)
+            let state_space = DiscreteModalSolver::<ExponentialMatrix>::from_fem(fem)
                 .sampling(sim_sampling_frequency as f64)
                 .proportional_damping(2. / 100.)
                 //.truncate_hankel_singular_values(1e-4)
@@ -172,21 +486,39 @@ async fn main() -> anyhow::Result<()> {
                 .outs_with::<M1Segment7AxialD>(fig_2_mode(7))
                 .outs::<MCM2SmHexD>()
                 .outs::<MCM2PZTD>()
-                .build()?
+                .build()?;
+            probes
+                .probe("GMT Finite Element Model", state_space)
                 .into_arcx()
         })
     };
-    println!("{}", *state_space.lock().await);
+    fem_build.finish();
+    println!("{}", **state_space.lock().await);
     //println!("Y sizes: {:?}", state_space.y_sizes);
+    let fem_stats = (*state_space.lock().await).stats();
 
     let n_step = (sim_duration * sim_sampling_frequency as f64) as usize;
-    let logging = Arrow::builder(n_step)
-        .filename("grim.parquet")
-        .build()
+    let logging = probes
+        .probe(
+            "GMT State",
+            Logger::builder()
+                .filename("grim.parquet")
+                .row_group_size(sim_sampling_frequency)
+                .sampling_frequency(sim_sampling_frequency as f64)
+                .start_time(CFD_DELAY as f64)
+                .source("GMT Finite Element Model")
+                .stream_unit::<OSSM1Lcl>("m, rad")
+                .stream_unit::<MCM2Lcl6D>("m, rad")
+                .stream_unit::<M1modes>("m")
+                .stream_unit::<MountEncoders>("rad")
+                .shutdown(&shutdown)
+                .build(),
+        )
         .into_arcx();
-    let mnt_ctrl = Mount::new().into_arcx();
+    let mnt_ctrl = probes.probe("Mount Control", Mount::new()).into_arcx();
 
-    (*cfd_loads.lock().await).stop_after(CFD_DELAY * sim_sampling_frequency);
+    let n_warm_up = CFD_DELAY * sim_sampling_frequency;
+    (*cfd_loads.lock().await).stop_after(n_warm_up);
 
     let model_1 = {
         let mut source: Initiator<_> = Actor::new(cfd_loads.clone());
@@ -200,16 +532,22 @@ async fn main() -> anyhow::Result<()> {
             .add_output()
             .build::<CFD2021106F>()
             .into_input(&mut fem);
-        source
-            .add_output()
-            .build::<OSSM1Lcl6F>()
-            .into_input(&mut fem);
-        source
-            .add_output()
-            .build::<MCM2LclForce6F>()
-            .into_input(&mut fem);
+        if wind_loads.is_enabled("M1Segments") {
+            source
+                .add_output()
+                .build::<OSSM1Lcl6F>()
+                .into_input(&mut fem);
+        }
+        if wind_loads.is_enabled("M2Segments") {
+            source
+                .add_output()
+                .build::<MCM2LclForce6F>()
+                .into_input(&mut fem);
+        }
 
-        let mut mount_set_point: Initiator<_> = Signals::new(3, n_step).into();
+        let mut mount_set_point: Initiator<_> = probes
+            .probe("Mount 0pt", shutdown.interruptible(Signals::new(3, n_step)))
+            .into();
         mount_set_point
             .add_output()
             .build::<MountSetPoint>()
@@ -221,23 +559,22 @@ async fn main() -> anyhow::Result<()> {
 
         fem.add_output()
             .bootstrap()
+            .multiplex(2)
             .build::<MountEncoders>()
-            .into_input(&mut mount);
+            .into_input(&mut mount)
+            .into_input(&mut sink);
         fem.add_output()
             .bootstrap()
             .build::<OSSM1Lcl>()
-            .logn(&mut sink, 42)
-            .await;
+            .into_input(&mut sink);
         fem.add_output()
             .bootstrap()
             .build::<MCM2Lcl6D>()
-            .logn(&mut sink, 42)
-            .await;
+            .into_input(&mut sink);
         fem.add_output()
             .bootstrap()
             .build::<M1modes>()
-            .logn(&mut sink, 162 * 7)
-            .await;
+            .into_input(&mut sink);
 
         Model::new(vec![
             Box::new(source),
@@ -250,19 +587,24 @@ async fn main() -> anyhow::Result<()> {
         .check()?
         .run()
     };
+    let warm_up = progress
+        .phase("Warm-up", Some(n_warm_up))
+        .track(Duration::from_secs(1), {
+            let fem_stats = fem_stats.clone();
+            move || fem_stats.step()
+        });
 
     #[cfg(not(feature = "full"))]
-    model_1.wait().await?;
+    {
+        model_1.wait().await?;
+        warm_up.finish().await;
+    }
 
     #[cfg(feature = "full")]
     {
-        use crseo::{
-            calibrations, Atmosphere, Builder, Calibration, FromBuilder, Gmt, Source, SH24 as TT7,
-            SH48,
-        };
+        use crseo::{Atmosphere, Builder, FromBuilder, Gmt, Source, SH24 as TT7, SH48};
         use dos_actors::{
             clients::{
-                arrow_client::Arrow,
                 ceo,
                 ceo::M1modes,
                 fsm::*,
@@ -272,16 +614,13 @@ async fn main() -> anyhow::Result<()> {
             },
             prelude::*,
         };
-        use linya::{Bar, Progress};
+        use grim::{
+            aco::{M1ModesEstimate, M1RbmEstimate, M2RbmEstimate, Split},
+            fits::FrameLogger,
+        };
         use lom::{Loader, LoaderTrait, OpticalSensitivities, OpticalSensitivity};
         use skyangle::Conversion;
-        use std::{
-            fs::File,
-            path::Path,
-            sync::Arc,
-            time::{Duration, Instant},
-        };
-        use tokio::sync::Mutex;
+        use std::{fs::File, path::Path};
 
         let mut source: Initiator<_> = Actor::new(cfd_loads.clone()).name("CFD Loads");
         let mut sink = Terminator::<_>::new(logging.clone()).name("GMT State");
@@ -294,41 +633,83 @@ async fn main() -> anyhow::Result<()> {
             .add_output()
             .build::<CFD2021106F>()
             .into_input(&mut fem);
-        source
-            .add_output()
-            .build::<OSSM1Lcl6F>()
-            .into_input(&mut fem);
-        source
-            .add_output()
-            .build::<MCM2LclForce6F>()
-            .into_input(&mut fem);
+        if wind_loads.is_enabled("M1Segments") {
+            source
+                .add_output()
+                .build::<OSSM1Lcl6F>()
+                .into_input(&mut fem);
+        }
+        if wind_loads.is_enabled("M2Segments") {
+            source
+                .add_output()
+                .build::<MCM2LclForce6F>()
+                .into_input(&mut fem);
+        }
 
         // HARDPOINTS
-        let mut m1_hardpoints: Actor<_> =
-            (m1_ctrl::hp_dynamics::Controller::new(), "M1 Hardpoints").into();
+        let mut m1_hardpoints: Actor<_> = (
+            probes.probe("M1 Hardpoints", m1_ctrl::hp_dynamics::Controller::new()),
+            "M1 Hardpoints",
+        )
+            .into();
         // LOADCELLS
-        let mut m1_hp_loadcells: Actor<_, 1, M1_RATE> =
-            (m1_ctrl::hp_load_cells::Controller::new(), "M1 LoadCells").into();
+        let mut m1_hp_loadcells: Actor<_, 1, M1_RATE> = (
+            probes.probe("M1 LoadCells", m1_ctrl::hp_load_cells::Controller::new()),
+            "M1 LoadCells",
+        )
+            .into();
         // M1 SEGMENTS ACTUATORS
-        let mut m1_segment1: Actor<_, M1_RATE, 1> =
-            m1_ctrl::actuators::segment1::Controller::new().into();
-        let mut m1_segment2: Actor<_, M1_RATE, 1> =
-            m1_ctrl::actuators::segment2::Controller::new().into();
-        let mut m1_segment3: Actor<_, M1_RATE, 1> =
-            m1_ctrl::actuators::segment3::Controller::new().into();
-        let mut m1_segment4: Actor<_, M1_RATE, 1> =
-            m1_ctrl::actuators::segment4::Controller::new().into();
-        let mut m1_segment5: Actor<_, M1_RATE, 1> =
-            m1_ctrl::actuators::segment5::Controller::new().into();
-        let mut m1_segment6: Actor<_, M1_RATE, 1> =
-            m1_ctrl::actuators::segment6::Controller::new().into();
-        let mut m1_segment7: Actor<_, M1_RATE, 1> =
-            m1_ctrl::actuators::segment7::Controller::new().into();
+        let mut m1_segment1: Actor<_, M1_RATE, 1> = probes
+            .probe(
+                "M1S1 Actuators",
+                m1_ctrl::actuators::segment1::Controller::new(),
+            )
+            .into();
+        let mut m1_segment2: Actor<_, M1_RATE, 1> = probes
+            .probe(
+                "M1S2 Actuators",
+                m1_ctrl::actuators::segment2::Controller::new(),
+            )
+            .into();
+        let mut m1_segment3: Actor<_, M1_RATE, 1> = probes
+            .probe(
+                "M1S3 Actuators",
+                m1_ctrl::actuators::segment3::Controller::new(),
+            )
+            .into();
+        let mut m1_segment4: Actor<_, M1_RATE, 1> = probes
+            .probe(
+                "M1S4 Actuators",
+                m1_ctrl::actuators::segment4::Controller::new(),
+            )
+            .into();
+        let mut m1_segment5: Actor<_, M1_RATE, 1> = probes
+            .probe(
+                "M1S5 Actuators",
+                m1_ctrl::actuators::segment5::Controller::new(),
+            )
+            .into();
+        let mut m1_segment6: Actor<_, M1_RATE, 1> = probes
+            .probe(
+                "M1S6 Actuators",
+                m1_ctrl::actuators::segment6::Controller::new(),
+            )
+            .into();
+        let mut m1_segment7: Actor<_, M1_RATE, 1> = probes
+            .probe(
+                "M1S7 Actuators",
+                m1_ctrl::actuators::segment7::Controller::new(),
+            )
+            .into();
 
         //let logging = Logging::default().n_entry(2).into_arcx();
         //let mut sink = Terminator::<_>::new(logging.clone());
 
-        let mut mount_set_point: Initiator<_> = (Signals::new(3, n_step), "Mount 0pt").into();
+        let mut mount_set_point: Initiator<_> = (
+            probes.probe("Mount 0pt", shutdown.interruptible(Signals::new(3, n_step))),
+            "Mount 0pt",
+        )
+            .into();
         mount_set_point
             .add_output()
             .build::<MountSetPoint>()
@@ -352,7 +733,10 @@ async fn main() -> anyhow::Result<()> {
         .into();*/
         // M1S1 -------------------------------------------------------------------------------
         let mut m1s1f: Actor<_, SH48_RATE, M1_RATE> = (
-            Mode2Force::<1>::new(335, 162, "m1s1mode2forces.bin")?.n_input_mode(27),
+            probes.probe(
+                "M1S1_M2F",
+                Mode2Force::<1>::new(335, 162, "m1s1mode2forces.bin")?.n_input_mode(sh48.n_mode),
+            ),
             "M1S1_M2F",
         )
             .into();
@@ -362,7 +746,10 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut m1_segment1);
         // M1S2 -------------------------------------------------------------------------------
         let mut m1s2f: Actor<_, SH48_RATE, M1_RATE> = (
-            Mode2Force::<2>::new(335, 162, "m1s2mode2forces.bin")?.n_input_mode(27),
+            probes.probe(
+                "M1S2_M2F",
+                Mode2Force::<2>::new(335, 162, "m1s2mode2forces.bin")?.n_input_mode(sh48.n_mode),
+            ),
             "M1S2_M2F",
         )
             .into();
@@ -372,7 +759,10 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut m1_segment2);
         // M1S3 -------------------------------------------------------------------------------
         let mut m1s3f: Actor<_, SH48_RATE, M1_RATE> = (
-            Mode2Force::<3>::new(335, 162, "m1s3mode2forces.bin")?.n_input_mode(27),
+            probes.probe(
+                "M1S3_M2F",
+                Mode2Force::<3>::new(335, 162, "m1s3mode2forces.bin")?.n_input_mode(sh48.n_mode),
+            ),
             "M1S3_M2F",
         )
             .into();
@@ -382,7 +772,10 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut m1_segment3);
         // M1S4 -------------------------------------------------------------------------------
         let mut m1s4f: Actor<_, SH48_RATE, M1_RATE> = (
-            Mode2Force::<4>::new(335, 162, "m1s4mode2forces.bin")?.n_input_mode(27),
+            probes.probe(
+                "M1S4_M2F",
+                Mode2Force::<4>::new(335, 162, "m1s4mode2forces.bin")?.n_input_mode(sh48.n_mode),
+            ),
             "M1S4_M2F",
         )
             .into();
@@ -392,7 +785,10 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut m1_segment4);
         // M1S5 -------------------------------------------------------------------------------
         let mut m1s5f: Actor<_, SH48_RATE, M1_RATE> = (
-            Mode2Force::<5>::new(335, 162, "m1s5mode2forces.bin")?.n_input_mode(27),
+            probes.probe(
+                "M1S5_M2F",
+                Mode2Force::<5>::new(335, 162, "m1s5mode2forces.bin")?.n_input_mode(sh48.n_mode),
+            ),
             "M1S5_M2F",
         )
             .into();
@@ -402,7 +798,10 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut m1_segment5);
         // M1S6 -------------------------------------------------------------------------------
         let mut m1s6f: Actor<_, SH48_RATE, M1_RATE> = (
-            Mode2Force::<6>::new(335, 162, "m1s6mode2forces.bin")?.n_input_mode(27),
+            probes.probe(
+                "M1S6_M2F",
+                Mode2Force::<6>::new(335, 162, "m1s6mode2forces.bin")?.n_input_mode(sh48.n_mode),
+            ),
             "M1S6_M2F",
         )
             .into();
@@ -412,7 +811,10 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut m1_segment6);
         // M1S7 -------------------------------------------------------------------------------
         let mut m1s7f: Actor<_, SH48_RATE, M1_RATE> = (
-            Mode2Force::<7>::new(306, 151, "m1s7mode2forces.bin")?.n_input_mode(27),
+            probes.probe(
+                "M1S7_M2F",
+                Mode2Force::<7>::new(306, 151, "m1s7mode2forces.bin")?.n_input_mode(sh48.n_mode),
+            ),
             "M1S7_M2F",
         )
             .into();
@@ -421,11 +823,6 @@ async fn main() -> anyhow::Result<()> {
             .build::<S7SAoffsetFcmd>()
             .into_input(&mut m1_segment7);
 
-        let mut m1rbm_set_point: Initiator<_> = (Signals::new(42, n_step), "M1 RBM 0pt").into();
-        m1rbm_set_point
-            .add_output()
-            .build::<M1RBMcmd>()
-            .into_input(&mut m1_hardpoints);
         m1_hardpoints
             .add_output()
             .multiplex(2)
@@ -433,41 +830,73 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut fem)
             .into_input(&mut m1_hp_loadcells);
 
+        let m1_logger = Logger::builder()
+            .filename("m1-loadcells.parquet")
+            .row_group_size(sim_sampling_frequency / M1_RATE)
+            .sampling_frequency(sim_sampling_frequency as f64)
+            .rate(M1_RATE)
+            .start_time(CFD_DELAY as f64)
+            .source("M1 LoadCells")
+            .stream_unit::<S1HPLC>("N")
+            .stream_unit::<S2HPLC>("N")
+            .stream_unit::<S3HPLC>("N")
+            .stream_unit::<S4HPLC>("N")
+            .stream_unit::<S5HPLC>("N")
+            .stream_unit::<S6HPLC>("N")
+            .stream_unit::<S7HPLC>("N")
+            .shutdown(&shutdown)
+            .build();
+        let mut m1_log: Terminator<_, M1_RATE> =
+            (probes.probe("M1_Log", m1_logger), "M1_Log").into();
         m1_hp_loadcells
             .add_output()
             .bootstrap()
+            .multiplex(2)
             .build::<S1HPLC>()
-            .into_input(&mut m1_segment1);
+            .into_input(&mut m1_segment1)
+            .into_input(&mut m1_log);
         m1_hp_loadcells
             .add_output()
             .bootstrap()
+            .multiplex(2)
             .build::<S2HPLC>()
-            .into_input(&mut m1_segment2);
+            .into_input(&mut m1_segment2)
+            .into_input(&mut m1_log);
         m1_hp_loadcells
             .add_output()
             .bootstrap()
+            .multiplex(2)
             .build::<S3HPLC>()
-            .into_input(&mut m1_segment3);
+            .into_input(&mut m1_segment3)
+            .into_input(&mut m1_log);
         m1_hp_loadcells
             .add_output()
             .bootstrap()
+            .multiplex(2)
             .build::<S4HPLC>()
-            .into_input(&mut m1_segment4);
+            .into_input(&mut m1_segment4)
+            .into_input(&mut m1_log);
         m1_hp_loadcells
             .add_output()
             .bootstrap()
+            .multiplex(2)
             .build::<S5HPLC>()
-            .into_input(&mut m1_segment5);
+            .into_input(&mut m1_segment5)
+            .into_input(&mut m1_log);
         m1_hp_loadcells
             .add_output()
             .bootstrap()
+            .multiplex(2)
             .build::<S6HPLC>()
-            .into_input(&mut m1_segment6);
+            .into_input(&mut m1_segment6)
+            .into_input(&mut m1_log);
         m1_hp_loadcells
             .add_output()
             .bootstrap()
+            .multiplex(2)
             .build::<S7HPLC>()
-            .into_input(&mut m1_segment7);
+            .into_input(&mut m1_segment7)
+            .into_input(&mut m1_log);
 
         m1_segment1
             .add_output()
@@ -498,24 +927,29 @@ async fn main() -> anyhow::Result<()> {
             .build::<M1ActuatorsSegment7>()
             .into_input(&mut fem);
 
+        let mut fem_monitor: Terminator<_> = (
+            probes.probe("FEM Telemetry", telemetry.monitor()),
+            "FEM Telemetry",
+        )
+            .into();
         fem.add_output()
             .bootstrap()
+            .multiplex(3)
             .build::<MountEncoders>()
-            .into_input(&mut mount);
+            .into_input(&mut mount)
+            .into_input(&mut fem_monitor)
+            .into_input(&mut sink);
         fem.add_output()
             .bootstrap()
             .build::<OSSHardpointD>()
             .into_input(&mut m1_hp_loadcells);
 
-        // M2 POSITIONER COMMAND
-        let mut m2_pos_cmd: Initiator<_> = (Signals::new(42, n_step), "M2 Positionners 0pt").into();
         // FSM POSITIONNER
-        let mut m2_positionner: Actor<_> =
-            (fsm::positionner::Controller::new(), "M2 Positionners").into();
-        m2_pos_cmd
-            .add_output()
-            .build::<M2poscmd>()
-            .into_input(&mut m2_positionner);
+        let mut m2_positionner: Actor<_> = (
+            probes.probe("M2 Positionners", fsm::positionner::Controller::new()),
+            "M2 Positionners",
+        )
+            .into();
         m2_positionner
             .add_output()
             .build::<MCM2SmHexF>()
@@ -523,8 +957,11 @@ async fn main() -> anyhow::Result<()> {
         // FSM PIEZOSTACK COMMAND
         //let mut m2_pzt_cmd: Initiator<_> = (Signals::new(21, n_step), "M2_PZT_setpoint").into();
         // FSM PIEZOSTACK
-        let mut m2_piezostack: Actor<_> =
-            (fsm::piezostack::Controller::new(), "M2 PZT Actuators").into();
+        let mut m2_piezostack: Actor<_> = (
+            probes.probe("M2 PZT Actuators", fsm::piezostack::Controller::new()),
+            "M2 PZT Actuators",
+        )
+            .into();
         /*m2_pzt_cmd
         .add_output()
         .build::< PZTcmd>()
@@ -544,12 +981,18 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut m2_piezostack);
         // FSM TIP-TILT CONTROL
         let mut tiptilt_set_point: Initiator<_, FSM_RATE> = (
-            Into::<Signals>::into((vec![0f64; 14], n_step)),
+            probes.probe(
+                "TipTilt_setpoint",
+                shutdown.interruptible(Into::<Signals>::into((vec![0f64; 14], n_step))),
+            ),
             "TipTilt_setpoint",
         )
             .into();
-        let mut m2_tiptilt: Actor<_, FSM_RATE, 1> =
-            (fsm::tiptilt::Controller::new(), "M2 TipTilt Control").into();
+        let mut m2_tiptilt: Actor<_, FSM_RATE, 1> = (
+            probes.probe("M2 TipTilt Control", fsm::tiptilt::Controller::new()),
+            "M2 TipTilt Control",
+        )
+            .into();
         tiptilt_set_point
             .add_output()
             .build::<TTSP>()
@@ -561,85 +1004,91 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut m2_piezostack);
         // OPTICAL MODEL (SH24)
         println!("SH24");
-        let atm_duration = 20f32;
-        let atm_n_duration = Some((sim_duration / atm_duration as f64).ceil() as i32);
-        let atm_sampling = 48 * 16 + 1;
-        let atm = Atmosphere::builder().ray_tracing(
-            25.5,
-            atm_sampling,
-            20f32.from_arcmin(),
-            atm_duration,
-            Some("/fsx/atmosphere/free_atm_15mn.bin".to_owned()),
-            atm_n_duration,
-        );
-        let tau = (sim_sampling_frequency as f64).recip();
-        let free_atm = ceo::OpticalModelOptions::Atmosphere {
-            builder: atm.remove_turbulence_layer(0),
-            time_step: tau,
-        };
-        let dome_seeing = ceo::OpticalModelOptions::DomeSeeing {
-            cfd_case: "/fsx/CASES/zen30az000_OS7".to_string(),
-            upsampling_rate: (sim_sampling_frequency / 5) as usize,
-        };
-        let static_aberration = {
+        let mut disturbances = vec![];
+        if let Some(atmosphere) = &optical_disturbances.atmosphere {
+            let atm_n_duration = Some((sim_duration / atmosphere.duration as f64).ceil() as i32);
+            let mut atm = Atmosphere::builder().ray_tracing(
+                atmosphere.width,
+                atmosphere.sampling as i32,
+                atmosphere.field.from_arcmin(),
+                atmosphere.duration,
+                Some(atmosphere.file.clone()),
+                atm_n_duration,
+            );
+            if let Some(r0) = atmosphere.r0 {
+                atm = atm.r0_at_zenith(r0);
+            }
+            if let Some(l0) = atmosphere.l0 {
+                atm = atm.oscale(l0);
+            }
+            // removing the layers from the last one preserves the indices of the others
+            let mut removed_layers = atmosphere.removed_layers.clone();
+            removed_layers.sort_unstable();
+            removed_layers.dedup();
+            for layer in removed_layers.into_iter().rev() {
+                atm = atm.remove_turbulence_layer(layer);
+            }
+            let tau = (sim_sampling_frequency as f64).recip();
+            disturbances.push(ceo::OpticalModelOptions::Atmosphere {
+                builder: atm,
+                time_step: tau,
+            });
+        }
+        if let Some(dome_seeing) = &optical_disturbances.dome_seeing {
+            disturbances.push(ceo::OpticalModelOptions::DomeSeeing {
+                cfd_case: dome_seeing.cfd_case.clone(),
+                upsampling_rate: sim_sampling_frequency / dome_seeing.sampling_frequency,
+            });
+        }
+        if !optical_disturbances.static_aberrations.is_empty() {
             let gmt_modes_path = std::env::var("GMT_MODES_PATH")?;
             let path_to_static = Path::new(&gmt_modes_path);
-            let static_phase: Vec<f32> = bincode::deserialize_from(File::open(
-                path_to_static.join("raw-polishing_print-through_soak1deg_769.bin"),
-            )?)?;
-            ceo::OpticalModelOptions::StaticAberration(static_phase.into())
-        };
+            let mut static_phase: Vec<f32> = vec![];
+            for static_aberration in &optical_disturbances.static_aberrations {
+                let phase: Vec<f32> =
+                    bincode::deserialize_from(File::open(path_to_static.join(static_aberration))?)?;
+                if static_phase.is_empty() {
+                    static_phase = phase;
+                } else {
+                    anyhow::ensure!(
+                        phase.len() == static_phase.len(),
+                        "static aberration {static_aberration} has {} samples, expected {}",
+                        phase.len(),
+                        static_phase.len()
+                    );
+                    static_phase
+                        .iter_mut()
+                        .zip(phase)
+                        .for_each(|(s, p)| *s += p);
+                }
+            }
+            disturbances.push(ceo::OpticalModelOptions::StaticAberration(
+                static_phase.into(),
+            ));
+        }
         let gmt_builder = Gmt::builder().m1_n_mode(162);
         let mut agws_tt7: Actor<_, 1, FSM_RATE> = {
             let mut agws_sh24 = ceo::OpticalModel::builder()
                 .gmt(gmt_builder.clone())
                 .source(Source::builder())
-                .options(vec![
-                    ceo::OpticalModelOptions::ShackHartmann {
-                        options: ceo::ShackHartmannOptions::Diffractive(
-                            *TT7::<crseo::Diffractive>::new(),
-                        ),
-                        flux_threshold: 0.5,
-                    },
-                    free_atm.clone(),
-                    dome_seeing.clone(),
-                    static_aberration.clone(),
-                ])
+                .options(
+                    [
+                        vec![ceo::OpticalModelOptions::ShackHartmann {
+                            options: ceo::ShackHartmannOptions::Diffractive(*TT7::<
+                                crseo::Diffractive,
+                            >::new(
+                            )),
+                            flux_threshold: grim::calibration::SH24_FLUX_THRESHOLD,
+                        }],
+                        disturbances.clone(),
+                    ]
+                    .concat(),
+                )
                 .build()?;
-            use calibrations::Mirror;
-            use calibrations::Segment::*;
-            // GMT 2 WFS
-            println!(" - calibration ...");
-            let mut gmt2wfs = Calibration::new(
-                &agws_sh24.gmt,
-                &agws_sh24.src,
-                TT7::<crseo::Geometric>::new(),
-            );
-            let specs = vec![Some(vec![(Mirror::M2, vec![Rxyz(1e-6, Some(0..2))])]); 7];
-            let now = Instant::now();
-            gmt2wfs.calibrate(
-                specs,
-                calibrations::ValidLensletCriteria::OtherSensor(
-                    &mut agws_sh24.sensor.as_mut().unwrap(),
-                ),
-            );
-            println!(
-                "GMT 2 WFS calibration [{}x{}] in {}s",
-                gmt2wfs.n_data,
-                gmt2wfs.n_mode,
-                now.elapsed().as_secs()
-            );
-            let dof_2_wfs: Vec<f64> = gmt2wfs.poke.into();
-            let dof_2_wfs = na::DMatrix::<f64>::from_column_slice(
-                dof_2_wfs.len() / gmt2wfs.n_mode,
-                gmt2wfs.n_mode,
-                &dof_2_wfs,
-            );
-            let wfs_2_rxy = dof_2_wfs.clone().pseudo_inverse(1e-12).unwrap();
             let senses: OpticalSensitivities = Loader::<OpticalSensitivities>::default().load()?;
             let rxy_2_stt = senses[OpticalSensitivity::SegmentTipTilt(Vec::new())].m2_rxy()?;
-            agws_sh24.sensor_matrix_transform(rxy_2_stt * wfs_2_rxy);
-            (agws_sh24, "AGWS SH24").into()
+            agws_sh24.sensor_matrix_transform(rxy_2_stt * sh24_reconstructor);
+            (probes.probe("AGWS SH24", agws_sh24), "AGWS SH24").into()
         };
         agws_tt7
             .add_output()
@@ -648,76 +1097,29 @@ async fn main() -> anyhow::Result<()> {
 
         // OPTICAL MODEL (SH48)
         println!("SH48");
-        let n_sh48 = 1;
         let gmt_agws_sh48 = {
             let mut agws_sh48 = ceo::OpticalModel::builder()
                 .gmt(gmt_builder)
-                .source(Source::builder().on_ring(6f32.from_arcmin()))
-                .options(vec![
-                    ceo::OpticalModelOptions::ShackHartmann {
-                        options: ceo::ShackHartmannOptions::Diffractive(
-                            *SH48::<crseo::Diffractive>::new().n_sensor(n_sh48),
-                        ),
-                        flux_threshold: 0.5,
-                    },
-                    free_atm,
-                    dome_seeing,
-                    static_aberration,
-                ])
+                .source(Source::builder().zenith_azimuth(sh48.zenith(), sh48.azimuth()))
+                .options(
+                    [
+                        vec![ceo::OpticalModelOptions::ShackHartmann {
+                            options: ceo::ShackHartmannOptions::Diffractive(
+                                *SH48::<crseo::Diffractive>::new().n_sensor(sh48.n_probe()),
+                            ),
+                            flux_threshold: sh48.flux_threshold,
+                        }],
+                        disturbances,
+                    ]
+                    .concat(),
+                )
                 .build()?;
-            let data_repo = env::var("DATA_REPO").unwrap_or_else(|_| ".".to_string());
-            let filename = format!("sh48x{}-diff_2_m1-modes.bin", n_sh48);
-            let poke_mat_file = Path::new(&data_repo).join(&filename);
-            let wfs_2_dof: na::DMatrix<f64> = if poke_mat_file.is_file() {
-                println!(" . Poke matrix loaded from {poke_mat_file:?}");
-                let file = File::open(poke_mat_file)?;
-                bincode::deserialize_from(file)?
-            } else {
-                println!(" - calibration ...");
-                use calibrations::Mirror;
-                use calibrations::Segment::*;
-                // GMT 2 WFS
-                let mut gmt2sh48 = Calibration::new(
-                    &agws_sh48.gmt,
-                    &agws_sh48.src,
-                    SH48::<crseo::Geometric>::new().n_sensor(n_sh48),
-                );
-                let specs = vec![Some(vec![(Mirror::M1MODES, vec![Modes(1e-6, 0..27)])]); 7];
-                let now = Instant::now();
-                gmt2sh48.calibrate(
-                    specs,
-                    calibrations::ValidLensletCriteria::OtherSensor(
-                        &mut agws_sh48.sensor.as_mut().unwrap(),
-                    ),
-                );
-                println!(
-                    "GMT 2 SH48 calibration [{}x{}] in {}s",
-                    gmt2sh48.n_data,
-                    gmt2sh48.n_mode,
-                    now.elapsed().as_secs()
-                );
-                let dof_2_wfs: Vec<f64> = gmt2sh48.poke.into();
-                let dof_2_wfs = na::DMatrix::<f64>::from_column_slice(
-                    dof_2_wfs.len() / gmt2sh48.n_mode,
-                    gmt2sh48.n_mode,
-                    &dof_2_wfs,
-                );
-                let singular_values = dof_2_wfs.singular_values();
-                let max_sv: f64 = singular_values[0];
-                let min_sv: f64 = singular_values.as_slice().iter().last().unwrap().clone();
-                let condition_number = max_sv / min_sv;
-                println!("SH48 poke matrix condition number: {condition_number:e}");
-                let wfs_2_dof = dof_2_wfs.clone().pseudo_inverse(1e-12).unwrap();
-                let mut file = File::create(&poke_mat_file)?;
-                bincode::serialize_into(&mut file, &wfs_2_dof)?;
-                println!(" . Poke matrix saved to {poke_mat_file:?}");
-                wfs_2_dof
-            };
-            agws_sh48.sensor_matrix_transform(wfs_2_dof);
-            agws_sh48.into_arcx()
+            agws_sh48.sensor_matrix_transform(sh48_reconstructor);
+            agws_sh48
         };
-        let name = format!("AGWS SH48 (x{})", n_sh48);
-        let mut agws_sh48: Actor<_, 1, SH48_RATE> = Actor::new(gmt_agws_sh48.clone()).name(name);
+        let name = format!("AGWS SH48 (x{})", sh48.n_probe());
+        let gmt_agws_sh48 = probes.probe(name.as_str(), gmt_agws_sh48).into_arcx();
+        let mut agws_sh48: Actor<_, 1, SH48_RATE> = Actor::new(gmt_agws_sh48).name(name);
 
         fem.add_output()
             .bootstrap()
@@ -764,7 +1166,7 @@ async fn main() -> anyhow::Result<()> {
             .flat_map(|x| x.as_slice().to_vec())
             .collect();
          */
-        let zero_point = vec![0f64; 27 * 7];
+        let zero_point = vec![0f64; sh48.n_dof()];
         /*
         zero_point.chunks_mut(27).for_each(|x| {
                 x[0] = 1e-6;
@@ -776,72 +1178,159 @@ async fn main() -> anyhow::Result<()> {
             let mut m1_modes: Initiator<_, SH48_RATE> = Into::<Signals>::into((zero_point, n_step)).into();
                 //dbg!(&zero_point);
         */
-        let mut gain = vec![0.; 7 * 27];
-        gain.iter_mut().skip(26).step_by(27).for_each(|g| *g = 0.5);
-        let mut integrator: Actor<_, SH48_RATE, SH48_RATE> =
-            Integrator::<f64, ceo::SensorData>::new(27 * 7)
-                //.gain_vector(gain)
-                .gain(0.5)
-                .zero(zero_point)
-                .into();
-        let sh48_arrow = Arrow::builder(n_step).filename("sh48.parquet").build();
-        let mut sh48_log: Terminator<_, SH48_RATE> = (sh48_arrow, "SH48_Log").into();
+        let mut integrator: Actor<_, SH48_RATE, SH48_RATE> = probes
+            .probe(
+                "SH48 Integrator",
+                Integrator::<f64, M1ModesEstimate>::new(sh48.n_dof())
+                    .gain(aco.m1_modes_gain)
+                    .zero(zero_point),
+            )
+            .into();
+        // AcO rigid body motions integrators
+        let mut m1_rbm_integrator: Actor<_, SH48_RATE, 1> = probes
+            .probe(
+                "M1 RBM Integrator",
+                Integrator::<f64, M1RbmEstimate>::new(42).gain(aco.m1_rbm_gain),
+            )
+            .into();
+        let mut m2_rbm_integrator: Actor<_, SH48_RATE, 1> = probes
+            .probe(
+                "M2 RBM Integrator",
+                Integrator::<f64, M2RbmEstimate>::new(42).gain(aco.m2_rbm_gain),
+            )
+            .into();
+        let mut aco_split: Actor<_, SH48_RATE, SH48_RATE> = (
+            probes.probe("AcO Split", Split::new(aco.controlled(), sh48.n_mode)),
+            "AcO Split",
+        )
+            .into();
+        aco_split
+            .add_output()
+            .build::<M1ModesEstimate>()
+            .into_input(&mut integrator);
+        aco_split
+            .add_output()
+            .build::<M1RbmEstimate>()
+            .into_input(&mut m1_rbm_integrator);
+        aco_split
+            .add_output()
+            .build::<M2RbmEstimate>()
+            .into_input(&mut m2_rbm_integrator);
+        m1_rbm_integrator
+            .add_output()
+            .bootstrap()
+            .build::<M1RBMcmd>()
+            .into_input(&mut m1_hardpoints);
+        m2_rbm_integrator
+            .add_output()
+            .bootstrap()
+            .build::<M2poscmd>()
+            .into_input(&mut m2_positionner);
+        let sh48_logger = Logger::builder()
+            .filename("sh48.parquet")
+            .row_group_size(1)
+            .sampling_frequency(sim_sampling_frequency as f64)
+            .rate(SH48_RATE)
+            .start_time(CFD_DELAY as f64)
+            .source("AGWS SH48")
+            .stream_source::<M1ModalCmd>("SH48 Integrator")
+            .stream_unit::<ceo::WfeRms>("m")
+            .stream_unit::<ceo::DetectorFrame>("photon")
+            .stream_unit::<M1ModalCmd>("m")
+            .shutdown(&shutdown)
+            .build();
+        let mut sh48_log: Terminator<_, SH48_RATE> =
+            (probes.probe("SH48_Log", sh48_logger), "SH48_Log").into();
 
         agws_sh48
             .add_output()
             .multiplex(2)
             .build::<ceo::SensorData>()
-            .into_input(&mut integrator)
-            .logn(&mut sh48_log, 27 * 7)
-            .await;
+            .into_input(&mut aco_split)
+            .into_input(&mut sh48_log);
         agws_sh48
             .add_output()
             .build::<ceo::WfeRms>()
-            .log(&mut sh48_log)
-            .await;
+            .into_input(&mut sh48_log);
+        let mut sh48_frames: Terminator<_, SH48_RATE> = (
+            probes.probe(
+                "SH48 Frames",
+                FrameLogger::builder()
+                    .dirname("sh48-frames")
+                    .frame_size(48 * 8, 48 * 8)
+                    .pixel_scale(sh48_pixel_scale)
+                    .sampling_frequency(sim_sampling_frequency as f64)
+                    .rate(SH48_RATE)
+                    .source("AGWS SH48")
+                    .unit("photon")
+                    .build(),
+            ),
+            "SH48 Frames",
+        )
+            .into();
         agws_sh48
             .add_output()
+            .multiplex(2)
             .build::<ceo::DetectorFrame>()
-            .logn(&mut sh48_log, 48 * 48 * 8 * 8 * n_sh48)
-            .await;
+            .into_input(&mut sh48_log)
+            .into_input(&mut sh48_frames);
 
-        let sh24_arrow = Arrow::builder(n_step)
+        let sh24_logger = Logger::builder()
             .filename("sh24.parquet")
+            .row_group_size(sim_sampling_frequency / FSM_RATE)
             //.decimation(10)
+            .sampling_frequency(sim_sampling_frequency as f64)
+            .rate(FSM_RATE)
+            .start_time(CFD_DELAY as f64)
+            .source("AGWS SH24")
+            .stream_unit::<ceo::WfeRms>("m")
+            .stream_unit::<ceo::TipTilt>("rad")
+            .stream_unit::<ceo::SegmentWfeRms>("m")
+            .stream_unit::<ceo::SegmentPiston>("m")
+            .stream_unit::<ceo::SegmentTipTilt>("rad")
+            .shutdown(&shutdown)
             .build();
-        let mut sh24_log: Terminator<_, FSM_RATE> = (sh24_arrow, "SH24_Log").into();
+        let mut sh24_log: Terminator<_, FSM_RATE> =
+            (probes.probe("SH24_Log", sh24_logger), "SH24_Log").into();
+        let mut sh24_monitor: Terminator<_, FSM_RATE> = (
+            probes.probe("SH24 Telemetry", telemetry.monitor()),
+            "SH24 Telemetry",
+        )
+            .into();
 
         agws_tt7
             .add_output()
+            .multiplex(2)
             .build::<ceo::WfeRms>()
-            .log(&mut sh24_log)
-            .await;
+            .into_input(&mut sh24_log)
+            .into_input(&mut sh24_monitor);
         agws_tt7
             .add_output()
             .build::<ceo::TipTilt>()
-            .log(&mut sh24_log)
-            .await;
+            .into_input(&mut sh24_log);
         agws_tt7
             .add_output()
             .build::<ceo::SegmentWfeRms>()
-            .log(&mut sh24_log)
-            .await;
+            .into_input(&mut sh24_log);
         agws_tt7
             .add_output()
             .build::<ceo::SegmentPiston>()
-            .log(&mut sh24_log)
-            .await;
+            .into_input(&mut sh24_log);
         agws_tt7
             .add_output()
+            .multiplex(2)
             .build::<ceo::SegmentTipTilt>()
-            .log(&mut sh24_log)
-            .await;
+            .into_input(&mut sh24_log)
+            .into_input(&mut sh24_monitor);
 
         #[derive(UID)]
         #[uid(data = "Vec<f32>")]
         enum SH24Frame {}
         let mut sh24_frame_sampler: Actor<_, FSM_RATE, { FSM_RATE * 200 }> = (
-            Sampler::<Vec<f32>, ceo::DetectorFrame, SH24Frame>::default(),
+            probes.probe(
+                "SH24 Frame",
+                Sampler::<Vec<f32>, ceo::DetectorFrame, SH24Frame>::default(),
+            ),
             "SH24 Frame",
         )
             .into();
@@ -850,22 +1339,50 @@ async fn main() -> anyhow::Result<()> {
             .build::<ceo::DetectorFrame>()
             .into_input(&mut &mut sh24_frame_sampler);
         let mut sh24_frame_logger: Terminator<_, { FSM_RATE * 200 }> = (
-            Arrow::builder(n_step)
-                .filename("sh24-frame.parquet")
-                .build(),
+            probes.probe(
+                "SH24 Frame Logs",
+                Logger::builder()
+                    .filename("sh24-frame.parquet")
+                    .row_group_size(1)
+                    .sampling_frequency(sim_sampling_frequency as f64)
+                    .rate(FSM_RATE * 200)
+                    .start_time(CFD_DELAY as f64)
+                    .source("SH24 Frame")
+                    .stream_unit::<SH24Frame>("photon")
+                    .shutdown(&shutdown)
+                    .build(),
+            ),
             "SH24 Frame Logs",
         )
             .into();
+        let mut sh24_frames: Terminator<_, { FSM_RATE * 200 }> = (
+            probes.probe(
+                "SH24 Frames",
+                FrameLogger::builder()
+                    .dirname("sh24-frames")
+                    .frame_size(24 * 12, 24 * 12)
+                    .pixel_scale(sh24_pixel_scale)
+                    .exposure_time(FSM_RATE as f64 / sim_sampling_frequency as f64)
+                    .sampling_frequency(sim_sampling_frequency as f64)
+                    .rate(FSM_RATE * 200)
+                    .source("SH24 Frame")
+                    .unit("photon")
+                    .build(),
+            ),
+            "SH24 Frames",
+        )
+            .into();
         sh24_frame_sampler
             .add_output()
+            .multiplex(2)
             .build::<SH24Frame>()
-            .logn(&mut sh24_frame_logger, 24 * 24 * 12 * 12)
-            .await;
+            .into_input(&mut sh24_frame_logger)
+            .into_input(&mut sh24_frames);
 
         integrator
             .add_output()
             .bootstrap()
-            .multiplex(7)
+            .multiplex(8)
             .build::<M1ModalCmd>()
             .into_input(&mut m1s1f)
             .into_input(&mut m1s2f)
@@ -873,12 +1390,14 @@ async fn main() -> anyhow::Result<()> {
             .into_input(&mut m1s4f)
             .into_input(&mut m1s5f)
             .into_input(&mut m1s6f)
-            .into_input(&mut m1s7f);
+            .into_input(&mut m1s7f)
+            .into_input(&mut sh48_log);
         //println!("{integrator}");
 
         model_1.wait().await?;
+        warm_up.finish().await;
 
-        (*cfd_loads.lock().await).start_from(CFD_DELAY * sim_sampling_frequency);
+        (*cfd_loads.lock().await).start_from(n_warm_up);
 
         let model = Model::new(vec![
             Box::new(source),
@@ -891,7 +1410,6 @@ async fn main() -> anyhow::Result<()> {
             Box::new(m1s5f),
             Box::new(m1s6f),
             Box::new(m1s7f),
-            Box::new(m1rbm_set_point),
             Box::new(m1_hardpoints),
             Box::new(m1_hp_loadcells),
             Box::new(m1_segment1),
@@ -901,19 +1419,26 @@ async fn main() -> anyhow::Result<()> {
             Box::new(m1_segment5),
             Box::new(m1_segment6),
             Box::new(m1_segment7),
-            Box::new(m2_pos_cmd),
             Box::new(m2_positionner),
             Box::new(m2_piezostack),
             Box::new(tiptilt_set_point),
             Box::new(m2_tiptilt),
             Box::new(agws_tt7),
             Box::new(agws_sh48),
+            Box::new(aco_split),
             Box::new(integrator),
+            Box::new(m1_rbm_integrator),
+            Box::new(m2_rbm_integrator),
+            Box::new(m1_log),
             Box::new(sh48_log),
+            Box::new(sh48_frames),
             Box::new(sh24_log),
+            Box::new(sh24_monitor),
             Box::new(sh24_frame_sampler),
             Box::new(sh24_frame_logger),
+            Box::new(sh24_frames),
             Box::new(fem),
+            Box::new(fem_monitor),
             Box::new(sink),
         ])
         .name("im-fsm")
@@ -921,61 +1446,59 @@ async fn main() -> anyhow::Result<()> {
         .check()?
         .run();
 
-        let logs = logging.clone();
-        let progress = Arc::new(Mutex::new(Progress::new()));
-        let logging_progress = progress.clone();
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_secs(5));
-            let bar: Bar = logging_progress.lock().await.bar(n_step, "Logging");
-            loop {
-                interval.tick().await;
-                let mut progress = logging_progress.lock().await;
-                progress.set_and_draw(&bar, (*logs.lock().await).size());
-                if progress.is_done(&bar) {
-                    break;
-                }
-            }
-        });
+        let closed_loop = progress
+            .phase("Closed loop", Some(n_step - n_warm_up))
+            .track(Duration::from_secs(1), move || {
+                fem_stats.step().saturating_sub(n_warm_up)
+            });
 
-        let sh48_progress = progress.clone();
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_secs(1));
-            let bar: Bar = sh48_progress
-                .lock()
-                .await
-                .bar(SH48_RATE, "SH48 integration");
-            loop {
-                interval.tick().await;
-                let mut progress = sh48_progress.lock().await;
-                progress.set_and_draw(
-                    &bar,
-                    (*gmt_agws_sh48.lock().await)
-                        .sensor
-                        .as_ref()
-                        .unwrap()
-                        .n_frame(),
+        model.wait().await?;
+        closed_loop.finish().await;
+    }
+
+    let last_time = {
+        let mut logging = logging.lock().await;
+        (*logging).finish()?;
+        (*logging).size() as f64 / sim_sampling_frequency as f64
+    };
+    manifest.end(last_time, shutdown.is_requested()).save()?;
+    if shutdown.is_requested() {
+        log::warn!("Simulation truncated at {:.3}s", last_time);
+    }
+    let spectral_errors: Vec<String> = {
+        let cfd_loads = cfd_loads.lock().await;
+        println!("{}", cfd_loads.report());
+        let mut errors = vec![];
+        for check in cfd_loads.spectral_check() {
+            println!(" . {check}");
+            if check.max_error.is_nan() || check.max_error > wind_loads.max_spectral_error {
+                log::warn!(
+                    "{}: the repeated CFD loads spectrum differs from the record by more than {}dB",
+                    check.name,
+                    wind_loads.max_spectral_error
                 );
+                errors.push(check.name);
             }
-        });
-
-        model.wait().await?;
+        }
+        errors
+    };
+    if let Some(report) = probes.report() {
+        println!("{report}");
+        report.to_json(grim::data_repo().join("profile.json"))?;
     }
 
-    /*
-    let lom = LOM::builder()
-        .rigid_body_motions_record((*logging.lock().await).record()?)?
-        .build()?;
-    let segment_tiptilt = lom.segment_tiptilt();
-
-    let tau = (sim_sampling_frequency as f64).recip();
-    let _: complot::Plot = ((
-        segment_tiptilt
-            .items()
-            .enumerate()
-            .map(|(i, data)| (i as f64 * tau, data.to_owned().to_mas())),
-        None,
-    ))
-        .into();
-    */
+    if let Some(format) = figures {
+        match grim::figures::plot_run(grim::data_repo(), format) {
+            Ok(figures) => println!("{} figures saved", figures.len()),
+            Err(e) => log::warn!("the figures could not be plotted: {e}"),
+        }
+    }
+    if wind_loads.spectral_check && !spectral_errors.is_empty() {
+        anyhow::bail!(
+            "the repeated CFD loads spectrum of {} differs from the record by more than {}dB (WIND_LOADS_SPECTRAL_CHECK=off to only warn)",
+            spectral_errors.join(", "),
+            wind_loads.max_spectral_error
+        );
+    }
     Ok(())
 }
@@ -3,7 +3,9 @@ use dos_actors::{
     clients::{arrow_client::Arrow, ceo},
     prelude::*,
 };
+use grim::{probe::Probes, progress::Progress, shutdown::Shutdown};
 use skyangle::Conversion;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -36,7 +38,10 @@ async fn main() -> anyhow::Result<()> {
         .into();
 
     let n_step = (sim_duration * sim_sampling_frequency as f64) as usize;
-    let mut timer: Initiator<_> = Timer::new(n_step).progress().into();
+    let shutdown = Shutdown::listen();
+    let timer = Probes::default().probe("Timer", shutdown.interruptible(Timer::new(n_step)));
+    let timer_stats = timer.stats();
+    let mut timer: Initiator<_> = timer.into();
 
     timer.add_output().build::<Tick>().into_input(&mut on_axis);
 
@@ -73,13 +78,17 @@ async fn main() -> anyhow::Result<()> {
         .await
         .confirm()?;
 
-    Model::new(vec![Box::new(timer), Box::new(on_axis), Box::new(logger)])
+    let model = Model::new(vec![Box::new(timer), Box::new(on_axis), Box::new(logger)])
         .name("onaxis")
         .check()?
         .flowchart()
-        .run()
-        .wait()
-        .await?;
+        .run();
+
+    let integration = Progress::new()
+        .phase("On-axis integration", Some(n_step))
+        .track(Duration::from_secs(1), move || timer_stats.step());
+    model.wait().await?;
+    integration.finish().await;
 
     Ok(())
 }
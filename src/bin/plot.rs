@@ -0,0 +1,19 @@
+use grim::figures::{plot_run, Format};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let data_repo = grim::data_repo();
+    let format = Format::from_env()?.unwrap_or_default();
+    let figures = plot_run(&data_repo, format)?;
+    for figure in &figures {
+        println!(" . {}", figure.display());
+    }
+    println!(
+        "{} figures saved in {:?}",
+        figures.len(),
+        data_repo.join("figures")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use grim::{
+    analysis::Analysis,
+    config::{env_opt, env_or},
+    manifest::Manifest,
+    results::decimation,
+    spectrum::{Detrend, Window},
+};
+use std::env;
+
+// Parses the frequency bands `f_min:f_max,...`
+fn bands(value: &str) -> anyhow::Result<Vec<(f64, f64)>> {
+    value
+        .split(',')
+        .map(|band| {
+            let (f_min, f_max) = band
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("invalid frequency band {band:?} (f_min:f_max)"))?;
+            Ok((f_min.trim().parse()?, f_max.trim().parse()?))
+        })
+        .collect()
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let data_repo = grim::data_repo();
+    let manifest = Manifest::load(&data_repo)?;
+    let file = env::var("SPECTRA_FILE").unwrap_or_else(|_| "grim.parquet".to_string());
+    let streams =
+        env::var("SPECTRA_STREAMS").map_err(|_| anyhow::anyhow!("SPECTRA_STREAMS is not set"))?;
+    let sampling_frequency = env_or(
+        "SPECTRA_SAMPLING_FREQUENCY",
+        manifest.sampling_frequency as f64 / decimation(&file) as f64,
+    )?;
+    let warm_up = env_or("ANALYZE_WARM_UP", manifest.warm_up)?;
+    let scale = env_or("SPECTRA_SCALE", 1f64)?;
+    let mut analysis = Analysis::default()
+        .overlap(env_or("SPECTRA_OVERLAP", 0.5)?)
+        .window(env_or("SPECTRA_WINDOW", Window::Hann)?)
+        .detrend(env_or("SPECTRA_DETREND", Detrend::Constant)?);
+    if let Some(n_fft) = env_opt("SPECTRA_N_FFT")? {
+        analysis = analysis.n_fft(n_fft);
+    }
+    let bands = env::var("SPECTRA_BANDS")
+        .ok()
+        .map(|value| bands(&value))
+        .transpose()?;
+
+    let path = data_repo.join(&file);
+    for stream in streams.split(',').map(|stream| stream.trim()) {
+        let spectra = analysis
+            .from_parquet(&path, stream, sampling_frequency, warm_up)?
+            .scale(scale);
+        let bands = bands.clone().unwrap_or_else(|| {
+            spectra
+                .psds
+                .first()
+                .map(|psd| psd.decade_bands())
+                .unwrap_or_default()
+        });
+        println!("{}", spectra.band_rms(&bands));
+        let spectra_path = data_repo.join(format!(
+            "{}-{stream}-spectra.parquet",
+            file.trim_end_matches(".parquet")
+        ));
+        spectra.to_parquet(&spectra_path)?;
+        println!("{stream} spectra saved to {spectra_path:?}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use dos_actors::{clients::windloads, io::Write, UniqueIdentifier, Update};
+use fem::{fem_io::*, FEM};
+use grim::{
+    config::{env_or, WindLoads},
+    progress::Progress,
+    synthetic::{Fit, Spectra},
+    uid_name,
+};
+use parse_monitors::cfd;
+use std::{collections::BTreeMap, env};
+
+// Pushes the next sample of the output `U` of `client` to the output fit
+fn pull<U, C>(client: &mut C, fits: &mut BTreeMap<String, Fit>) -> Option<()>
+where
+    U: UniqueIdentifier<Data = Vec<f64>>,
+    C: Write<Vec<f64>, U>,
+{
+    let data = client.write()?;
+    if let Some(fit) = fits.get_mut(&uid_name::<U>()) {
+        fit.push(&data);
+    }
+    Some(())
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let sampling_frequency = 1000_usize;
+    // the wind speed of the CFD case is 7m/s
+    let wind_loads = WindLoads::from_env(7f64)?;
+    let n_fft: usize = env_or("WIND_LOADS_PSD_N_FFT", 8192)?;
+    let file = env::var("WIND_LOADS_PSD_FILE").unwrap_or_else(|_| "wind-loads-psd.bin".to_string());
+
+    let loads: Vec<_> = {
+        use windloads::WindLoads::*;
+        [
+            (TopEnd, "TopEnd"),
+            (M2Baffle, "M2Baffle"),
+            (Trusses, "Trusses"),
+            (M1Baffle, "M1Baffle"),
+            (MirrorCovers, "MirrorCovers"),
+            (LaserGuideStars, "LaserGuideStars"),
+            (CRings, "CRings"),
+            (GIR, "GIR"),
+            (Platforms, "Platforms"),
+        ]
+        .into_iter()
+        .filter_map(|(load, name)| wind_loads.is_enabled(name).then_some(load))
+        .collect()
+    };
+    let mut fem = FEM::from_env()?;
+    let cfd_case = cfd::CfdCase::<2021>::colloquial(30, 0, "os", 7)?;
+    println!("CFD CASE ({}Hz): {}", sampling_frequency, cfd_case);
+    let cfd_path = cfd::Baseline::<2021>::path().join(cfd_case.to_string());
+    let mut cfd_loads = windloads::CfdLoads::foh(cfd_path.to_str().unwrap(), sampling_frequency)
+        .loads(loads, &mut fem, 0);
+    if let Some((start, end)) = wind_loads.time_range {
+        cfd_loads = cfd_loads.duration(end - start).time_range((start, end));
+    }
+    // the forces and moments of a node are correlated
+    let mut fits = BTreeMap::new();
+    fits.insert(
+        uid_name::<CFD2021106F>(),
+        Fit::new(n_fft, sampling_frequency as f64, 6),
+    );
+    if wind_loads.is_enabled("M1Segments") {
+        cfd_loads = cfd_loads.m1_segments();
+        fits.insert(
+            uid_name::<OSSM1Lcl6F>(),
+            Fit::new(n_fft, sampling_frequency as f64, 6),
+        );
+    }
+    if wind_loads.is_enabled("M2Segments") {
+        cfd_loads = cfd_loads.m2_segments();
+        fits.insert(
+            uid_name::<MCM2LclForce6F>(),
+            Fit::new(n_fft, sampling_frequency as f64, 6),
+        );
+    }
+    let mut cfd_loads = cfd_loads.build().unwrap();
+
+    let progress = Progress::new();
+    let mut phase = progress.phase("Wind loads spectra fit", None);
+    let mut step = 0;
+    loop {
+        cfd_loads.update();
+        if pull::<CFD2021106F, _>(&mut cfd_loads, &mut fits).is_none()
+            || (wind_loads.is_enabled("M1Segments")
+                && pull::<OSSM1Lcl6F, _>(&mut cfd_loads, &mut fits).is_none())
+            || (wind_loads.is_enabled("M2Segments")
+                && pull::<MCM2LclForce6F, _>(&mut cfd_loads, &mut fits).is_none())
+        {
+            break;
+        }
+        step += 1;
+        if step % sampling_frequency == 0 {
+            phase.set(step);
+        }
+    }
+    phase.finish();
+    anyhow::ensure!(
+        step >= n_fft,
+        "the CFD record ({step} samples) is shorter than WIND_LOADS_PSD_N_FFT ({n_fft})"
+    );
+
+    let mut spectra = Spectra::new(sampling_frequency as f64, n_fft);
+    for (name, fit) in fits {
+        let output = fit.spectra();
+        println!(
+            "{name}: {} channels in {} blocks",
+            output.n_channel,
+            output.blocks.len()
+        );
+        spectra.outputs.insert(name, output);
+    }
+    spectra.save(&file)?;
+    println!(
+        "Wind loads spectra fitted to {:.3}s of CFD loads saved to {file}",
+        step as f64 / sampling_frequency as f64
+    );
+
+    Ok(())
+}
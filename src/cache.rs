@@ -0,0 +1,453 @@
+//! CFD loads cache
+//!
+//! The CFD loads resampled at the simulation sampling rate and mapped onto the FEM nodes
+//! are saved in the directory given by the `WIND_LOADS_CACHE` environment variable,
+//! one file per [CacheKey]: the CFD case, the wind loads groups, the sampling rate and the
+//! duration or time window of the record.
+//!
+//! A cache file is a header followed by the loads:
+//!  - the 8 bytes magic number `GRIMCFD1`,
+//!  - the length of the JSON [Header] as a little-endian `u64`,
+//!  - the JSON [Header] padded with spaces to a multiple of 8 bytes,
+//!  - the loads as little-endian `f64`, time step after time step, and for each time step
+//!    the loads of each output in the order of the header outputs.
+//!
+//! The file is memory-mapped by [CachedLoads] and the header is checked against the key of the
+//! simulation and against the locations of the FEM inputs of the CFD loads, a stale file is
+//! rebuilt as if it did not exist.
+//! The loads of any time step are read back from the file with [CachedLoads::sample], so the
+//! record is repeated by [Stitched](crate::stitch::Stitched) without a copy in memory.
+
+use crate::{stitch::Replay, uid_name, GrimError, Result};
+use dos_actors::{
+    io::{Data, Write},
+    UniqueIdentifier, Update,
+};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write as IoWrite},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// CFD loads cache format version
+pub const FORMAT_VERSION: u32 = 1;
+const MAGIC: &[u8; 8] = b"GRIMCFD1";
+
+/// CFD loads configuration of a cache file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub cfd_case: String,
+    /// Wind loads groups
+    pub loads: Vec<String>,
+    /// Sampling frequency [Hz]
+    pub sampling_frequency: usize,
+    /// Record duration [s]
+    pub duration: f64,
+    /// CFD record time window [s]
+    pub time_range: Option<(f64, f64)>,
+}
+impl CacheKey {
+    /// Returns the key hash
+    ///
+    /// The hash is the 64 bits FNV-1a hash of the key JSON representation
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        let hash = json.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("{hash:016x}")
+    }
+    /// Returns the name of the cache file
+    pub fn file_name(&self) -> String {
+        format!("cfd-loads-{}.bin", self.hash())
+    }
+}
+
+/// Cache file header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub format: u32,
+    pub key: CacheKey,
+    /// Number of time steps
+    pub n_step: usize,
+    /// Outputs names and number of loads
+    pub outputs: Vec<(String, usize)>,
+    /// Locations of the FEM inputs of the CFD loads
+    pub locations: Vec<Vec<f64>>,
+}
+impl Header {
+    // Number of loads per time step
+    fn stride(&self) -> usize {
+        self.outputs.iter().map(|(_, n)| n).sum()
+    }
+}
+
+/// Writer of a CFD loads cache file
+///
+/// The loads are written to a temporary file that is renamed once all the time steps are written,
+/// the temporary file is removed if the writer is dropped before
+pub struct CacheWriter {
+    header: Header,
+    path: PathBuf,
+    tmp: PathBuf,
+    file: BufWriter<File>,
+    output: usize,
+    step: usize,
+    finished: bool,
+}
+impl CacheWriter {
+    /// Creates the cache file `key` in the directory `dir` for `n_step` time steps of the outputs
+    /// `outputs` (name and number of loads) and the FEM inputs `locations`
+    pub fn new<P: AsRef<Path>>(
+        dir: P,
+        key: CacheKey,
+        n_step: usize,
+        outputs: Vec<(String, usize)>,
+        locations: Vec<Vec<f64>>,
+    ) -> Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(|e| GrimError::Io(e, dir.to_path_buf()))?;
+        let path = dir.join(key.file_name());
+        let tmp = path.with_extension(format!("tmp-{}", std::process::id()));
+        let header = Header {
+            format: FORMAT_VERSION,
+            key,
+            n_step,
+            outputs,
+            locations,
+        };
+        let mut json = serde_json::to_vec(&header)?;
+        json.resize(json.len().next_multiple_of(8), b' ');
+        let mut file =
+            BufWriter::new(File::create(&tmp).map_err(|e| GrimError::Io(e, tmp.clone()))?);
+        file.write_all(MAGIC)
+            .and_then(|_| file.write_all(&(json.len() as u64).to_le_bytes()))
+            .and_then(|_| file.write_all(&json))
+            .map_err(|e| GrimError::Io(e, tmp.clone()))?;
+        Ok(Self {
+            header,
+            path,
+            tmp,
+            file,
+            output: 0,
+            step: 0,
+            finished: false,
+        })
+    }
+    /// Writes the next sample of the output `U` read from `client`
+    ///
+    /// The outputs must be written in the order of the header outputs,
+    /// returns `false` if the client has no more data
+    pub fn pull<U, C>(&mut self, client: &mut C) -> Result<bool>
+    where
+        U: UniqueIdentifier<Data = Vec<f64>>,
+        C: Write<Vec<f64>, U>,
+    {
+        let (name, n) = &self.header.outputs[self.output];
+        if *name != uid_name::<U>() {
+            return Err(GrimError::Config(format!(
+                "CFD loads cache: expected {name}, found {}",
+                uid_name::<U>()
+            )));
+        }
+        let Some(data) = client.write() else {
+            return Ok(false);
+        };
+        if data.len() != *n {
+            return Err(GrimError::Config(format!(
+                "CFD loads cache: {name} has {} loads, expected {n}",
+                data.len()
+            )));
+        }
+        for x in data.iter() {
+            self.file
+                .write_all(&x.to_le_bytes())
+                .map_err(|e| GrimError::Io(e, self.tmp.clone()))?;
+        }
+        self.output = (self.output + 1) % self.header.outputs.len();
+        if self.output == 0 {
+            self.step += 1;
+        }
+        Ok(true)
+    }
+    /// Returns the number of written time steps
+    pub fn step(&self) -> usize {
+        self.step
+    }
+    /// Checks that all the time steps are written and moves the file to the cache
+    pub fn finish(mut self) -> Result<PathBuf> {
+        if self.step != self.header.n_step || self.output != 0 {
+            return Err(GrimError::Config(format!(
+                "CFD loads cache: {} time steps written, expected {}",
+                self.step, self.header.n_step
+            )));
+        }
+        self.file
+            .flush()
+            .map_err(|e| GrimError::Io(e, self.tmp.clone()))?;
+        fs::rename(&self.tmp, &self.path).map_err(|e| GrimError::Io(e, self.path.clone()))?;
+        self.finished = true;
+        Ok(self.path.clone())
+    }
+}
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Memory-mapped CFD loads cache client
+pub struct CachedLoads {
+    header: Header,
+    data_start: usize,
+    mmap: Mmap,
+    // offset in the time step and current step of each output
+    outputs: Vec<(String, usize, usize)>,
+}
+impl CachedLoads {
+    /// Opens the cache file of `key` in the directory `dir` if it exists
+    ///
+    /// Returns `None` if the file does not exist or if it is stale, i.e. it does not match the
+    /// `key`, the FEM inputs `locations` or the outputs `outputs` (name and number of loads):
+    /// a stale file is rebuilt by writing a new [CacheWriter] over it
+    pub fn open<P: AsRef<Path>>(
+        dir: P,
+        key: &CacheKey,
+        outputs: &[(String, usize)],
+        locations: &[Vec<f64>],
+    ) -> Result<Option<Self>> {
+        let path = dir.as_ref().join(key.file_name());
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(&path).map_err(|e| GrimError::Io(e, path.clone()))?;
+        // SAFETY: the cache files are only created by renaming a complete file
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| GrimError::Io(e, path.clone()))?;
+        match Self::check(mmap, key, outputs, locations) {
+            Ok(cached) => Ok(Some(cached)),
+            Err(msg) => {
+                log::warn!("stale CFD loads cache {path:?}: {msg}");
+                Ok(None)
+            }
+        }
+    }
+    // Checks the header of the cache file against the simulation
+    fn check(
+        mmap: Mmap,
+        key: &CacheKey,
+        outputs: &[(String, usize)],
+        locations: &[Vec<f64>],
+    ) -> std::result::Result<Self, String> {
+        if mmap.len() < 16 || &mmap[..8] != MAGIC {
+            return Err("not a CFD loads cache file".to_string());
+        }
+        let header_len = u64::from_le_bytes(mmap[8..16].try_into().unwrap()) as usize;
+        let data_start = 16 + header_len;
+        let header: Header = serde_json::from_slice(
+            mmap.get(16..data_start)
+                .ok_or_else(|| "truncated header".to_string())?,
+        )
+        .map_err(|e| format!("invalid header: {e}"))?;
+        if header.format != FORMAT_VERSION {
+            return Err(format!(
+                "format {}, expected {FORMAT_VERSION}",
+                header.format
+            ));
+        }
+        if header.key != *key {
+            return Err(format!(
+                "configuration does not match:\n{:#?}\nexpected:\n{:#?}",
+                header.key, key
+            ));
+        }
+        if header.outputs != outputs {
+            return Err(format!(
+                "outputs {:?} do not match {:?}",
+                header.outputs, outputs
+            ));
+        }
+        let matching_locations = header.locations.len() == locations.len()
+            && header.locations.iter().zip(locations).all(|(a, b)| {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6)
+            });
+        if !matching_locations {
+            return Err(
+                "the FEM locations of the CFD loads do not match the locations of the current FEM"
+                    .to_string(),
+            );
+        }
+        if mmap.len() != data_start + 8 * header.n_step * header.stride() {
+            return Err(format!(
+                "{} bytes, expected {}",
+                mmap.len(),
+                data_start + 8 * header.n_step * header.stride()
+            ));
+        }
+        let outputs = header
+            .outputs
+            .iter()
+            .scan(0, |offset, (name, n)| {
+                let output = (name.clone(), *offset, 0);
+                *offset += n;
+                Some(output)
+            })
+            .collect();
+        Ok(Self {
+            header,
+            data_start,
+            mmap,
+            outputs,
+        })
+    }
+    /// Returns the number of time steps
+    pub fn n_step(&self) -> usize {
+        self.header.n_step
+    }
+    /// Returns the loads of the output `U` at the time step `step`
+    pub fn sample<U: UniqueIdentifier>(&self, step: usize) -> Option<Vec<f64>> {
+        let name = uid_name::<U>();
+        let (i, (_, offset, _)) = self
+            .outputs
+            .iter()
+            .enumerate()
+            .find(|(_, (output, ..))| *output == name)?;
+        if step >= self.header.n_step {
+            return None;
+        }
+        let n = self.header.outputs[i].1;
+        let start = self.data_start + 8 * (step * self.header.stride() + offset);
+        Some(
+            self.mmap[start..start + 8 * n]
+                .chunks_exact(8)
+                .map(|x| f64::from_le_bytes(x.try_into().unwrap()))
+                .collect(),
+        )
+    }
+}
+impl Update for CachedLoads {}
+impl<U: UniqueIdentifier<Data = Vec<f64>>> Write<Vec<f64>, U> for CachedLoads {
+    fn write(&mut self) -> Option<Arc<Data<U>>> {
+        let name = uid_name::<U>();
+        let i = self
+            .outputs
+            .iter()
+            .position(|(output, ..)| *output == name)?;
+        let loads = self.sample::<U>(self.outputs[i].2)?;
+        self.outputs[i].2 += 1;
+        Some(Arc::new(Data::new(loads)))
+    }
+}
+impl<U: UniqueIdentifier> Replay<U> for CachedLoads {
+    fn replay(&self, step: usize) -> Option<Vec<f64>> {
+        self.sample::<U>(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempPath;
+    use dos_actors::prelude::*;
+
+    #[derive(UID)]
+    #[uid(data = "Vec<f64>")]
+    enum Loads {}
+
+    // loads client writing `[step, step+1]`
+    struct Client {
+        step: usize,
+    }
+    impl Write<Vec<f64>, Loads> for Client {
+        fn write(&mut self) -> Option<Arc<Data<Loads>>> {
+            self.step += 1;
+            let x = self.step as f64;
+            Some(Arc::new(Data::new(vec![x, x + 1f64])))
+        }
+    }
+
+    fn cache(dir: &Path, key: &CacheKey, locations: &[Vec<f64>]) -> PathBuf {
+        let mut writer = CacheWriter::new(
+            dir,
+            key.clone(),
+            3,
+            vec![("Loads".to_string(), 2)],
+            locations.to_vec(),
+        )
+        .unwrap();
+        let mut client = Client { step: 0 };
+        while writer.step() < 3 {
+            assert!(writer.pull::<Loads, _>(&mut client).unwrap());
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn stale() {
+        let dir = TempPath::new("cache");
+        let key = CacheKey {
+            cfd_case: "zen30az000_OS7".to_string(),
+            loads: vec!["TopEnd".to_string()],
+            sampling_frequency: 1000,
+            duration: 0.003,
+            time_range: None,
+        };
+        let outputs = vec![("Loads".to_string(), 2)];
+        let locations = vec![vec![0f64, 1f64, 2f64]];
+        assert!(CachedLoads::open(&dir, &key, &outputs, &locations)
+            .unwrap()
+            .is_none());
+        let path = cache(&dir, &key, &locations);
+        assert_eq!(path, dir.join(key.file_name()));
+        let mut cached = CachedLoads::open(&dir, &key, &outputs, &locations)
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached.n_step(), 3);
+        let loads: Vec<Vec<f64>> = (0..4)
+            .filter_map(|_| <CachedLoads as Write<Vec<f64>, Loads>>::write(&mut cached))
+            .map(|data| data.to_vec())
+            .collect();
+        assert_eq!(loads, vec![vec![1., 2.], vec![2., 3.], vec![3., 4.]]);
+        assert_eq!(cached.sample::<Loads>(1), Some(vec![2., 3.]));
+        assert_eq!(cached.sample::<Loads>(3), None);
+        drop(cached);
+        // the FEM has changed
+        let moved = vec![vec![0f64, 1f64, 2.5]];
+        assert!(CachedLoads::open(&dir, &key, &outputs, &moved)
+            .unwrap()
+            .is_none());
+        assert!(
+            CachedLoads::open(&dir, &key, &[("Loads".to_string(), 3)], &locations)
+                .unwrap()
+                .is_none()
+        );
+        cache(&dir, &key, &moved);
+        assert!(CachedLoads::open(&dir, &key, &outputs, &moved)
+            .unwrap()
+            .is_some());
+        assert!(CachedLoads::open(&dir, &key, &outputs, &locations)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn dropped_writer() {
+        let dir = TempPath::new("cache");
+        let key = CacheKey {
+            cfd_case: "zen30az000_OS7".to_string(),
+            loads: vec!["TopEnd".to_string()],
+            sampling_frequency: 1000,
+            duration: 0.003,
+            time_range: None,
+        };
+        let mut writer =
+            CacheWriter::new(&dir, key, 3, vec![("Loads".to_string(), 2)], vec![]).unwrap();
+        assert!(writer.pull::<Loads, _>(&mut Client { step: 0 }).unwrap());
+        drop(writer);
+        assert_eq!(fs::read_dir(&*dir).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,434 @@
+//! Calibration store
+//!
+//! The interaction matrices of the wavefront sensors and their reconstructors are computed
+//! with the `calibrate` binary and saved in the calibration store, in the directory given by
+//! the `CALIBRATION_REPO` environment variable.
+//!
+//! A calibration is identified by a [Key] that describes the optics configuration:
+//! the wavefront sensor, the guide stars positions, the lenslet flux threshold and
+//! the calibrated degrees of freedom with their calibration strokes.
+//! Each calibration of the same configuration is saved as a new version:
+//! ```text
+//! CALIBRATION_REPO/
+//!  └── SH48-<key hash>/
+//!       ├── v1/
+//!       └── v2/
+//!            ├── metadata.json
+//!            ├── diagnostics.json
+//!            ├── poke.bin
+//!            └── reconstructor.bin
+//! ```
+//! The simulation loads the latest version of the calibration that matches its optics configuration
+//! and refuses to start if there is none.
+
+use crate::{
+    aco::{Axis, Dof, Mirror, N_SEGMENT},
+    config::{Aco, Sh48, Strokes},
+    diagnostics::{self, Diagnostics, Thresholds},
+    GrimError, Result,
+};
+use chrono::{DateTime, Local};
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+/// Calibration store format version
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Lenslet flux threshold of the AGWS SH24
+pub const SH24_FLUX_THRESHOLD: f64 = 0.5;
+
+/// Optics configuration of a calibration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Key {
+    /// Wavefront sensor
+    pub sensor: String,
+    /// Guide stars field positions (zenith [arcmin], azimuth [deg])
+    pub probes: Vec<(f32, f32)>,
+    pub flux_threshold: f64,
+    /// Calibrated degrees of freedom
+    pub dofs: Vec<Dof>,
+    /// Push-pull calibration stroke of each degree of freedom [m or rd]
+    pub strokes: Vec<f64>,
+}
+impl Key {
+    /// AGWS SH24 calibration of M2 segments Rx and Ry
+    pub fn sh24(strokes: &Strokes) -> Self {
+        let dofs: Vec<Dof> = (1..=N_SEGMENT)
+            .flat_map(|sid| {
+                [Axis::Rx, Axis::Ry].map(|axis| Dof::Rbm {
+                    mirror: Mirror::M2,
+                    sid,
+                    axis,
+                })
+            })
+            .collect();
+        Self {
+            sensor: "SH24".to_string(),
+            probes: vec![(0f32, 0f32)],
+            flux_threshold: SH24_FLUX_THRESHOLD,
+            strokes: dofs.iter().map(|dof| strokes.stroke(dof)).collect(),
+            dofs,
+        }
+    }
+    /// AGWS SH48 calibration of the active optics degrees of freedom
+    pub fn sh48(sh48: &Sh48, aco: &Aco, strokes: &Strokes) -> Self {
+        let dofs = aco.calibrated();
+        Self {
+            sensor: "SH48".to_string(),
+            probes: sh48.probes.clone(),
+            flux_threshold: sh48.flux_threshold,
+            strokes: dofs.iter().map(|dof| strokes.stroke(dof)).collect(),
+            dofs,
+        }
+    }
+    /// Returns the key hash
+    ///
+    /// The hash is the 64 bits FNV-1a hash of the key JSON representation
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        let hash = json.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("{hash:016x}")
+    }
+    /// Returns the name of the store directory of the calibration
+    pub fn dir_name(&self) -> String {
+        format!("{}-{}", self.sensor, self.hash())
+    }
+}
+
+/// Calibration metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metadata {
+    pub format: u32,
+    pub version: usize,
+    pub key: Key,
+    pub created: DateTime<Local>,
+    /// Number of wavefront sensor measurements
+    pub n_data: usize,
+    /// Number of degrees of freedom
+    pub n_dof: usize,
+    /// Singular values of the interaction matrix
+    pub singular_values: Vec<f64>,
+    pub condition_number: f64,
+    /// Degrees of freedom controlled by the active optics when the calibration was checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controlled: Option<Subset>,
+}
+
+/// Subset of the calibrated degrees of freedom
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subset {
+    pub dofs: Vec<Dof>,
+    /// Condition number of the interaction matrix of the subset
+    pub condition_number: f64,
+    /// Closure error of the reconstructor of the subset
+    pub max_closure_error: f64,
+}
+
+/// Interaction matrix and reconstructor
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    pub metadata: Metadata,
+    /// Interaction matrix `[n_data x n_dof]`
+    pub poke: na::DMatrix<f64>,
+    /// Reconstructor `[n_dof x n_data]`
+    pub reconstructor: na::DMatrix<f64>,
+    pub diagnostics: Diagnostics,
+}
+impl Calibration {
+    /// Creates a new calibration from the push and pull interaction matrices `push` and `pull`
+    /// of the configuration `key`
+    ///
+    /// The interaction matrix is the mean of the push and pull matrices and the reconstructor is
+    /// its truncated pseudo-inverse (see [diagnostics::reconstructor]), the closure test
+    /// reconstructs the push and the pull matrices
+    pub fn new(
+        key: Key,
+        push: &na::DMatrix<f64>,
+        pull: &na::DMatrix<f64>,
+        thresholds: &Thresholds,
+    ) -> Result<Self> {
+        if push.shape() != pull.shape() {
+            return Err(GrimError::Calibration(format!(
+                "{} push {:?} and pull {:?} interaction matrices do not match",
+                key.sensor,
+                push.shape(),
+                pull.shape()
+            )));
+        }
+        let poke = (push + pull) * 0.5;
+        if poke.ncols() != key.dofs.len() {
+            return Err(GrimError::Calibration(format!(
+                "{} interaction matrix has {} columns for {} degrees of freedom",
+                key.sensor,
+                poke.ncols(),
+                key.dofs.len()
+            )));
+        }
+        let reconstructor = diagnostics::reconstructor(&poke, thresholds)?;
+        let diagnostics = Diagnostics::new(&poke, &reconstructor, &[push, pull])?;
+        Ok(Self {
+            metadata: Metadata {
+                format: FORMAT_VERSION,
+                version: 0,
+                n_data: poke.nrows(),
+                n_dof: poke.ncols(),
+                key,
+                created: Local::now(),
+                singular_values: diagnostics.singular_values.clone(),
+                condition_number: diagnostics.condition_number,
+                controlled: None,
+            },
+            poke,
+            reconstructor,
+            diagnostics,
+        })
+    }
+}
+
+/// Calibration store
+#[derive(Debug, Clone)]
+pub struct Store {
+    root: PathBuf,
+}
+impl Store {
+    /// Creates a store in the directory `root`
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+    /// Creates a store in the directory given by the `CALIBRATION_REPO` environment variable
+    pub fn from_env() -> Self {
+        Self::new(
+            env::var("CALIBRATION_REPO").unwrap_or_else(|_| "/fsx/grim/calibrations".to_string()),
+        )
+    }
+    /// Returns the store directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    fn versions(&self, key: &Key) -> Vec<usize> {
+        let mut versions: Vec<usize> = fs::read_dir(self.root.join(key.dir_name()))
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| {
+                        entry
+                            .ok()?
+                            .file_name()
+                            .to_str()?
+                            .strip_prefix('v')?
+                            .parse()
+                            .ok()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        versions.sort_unstable();
+        versions
+    }
+    /// Saves a new version of `calibration` and returns its directory
+    pub fn save(&self, calibration: &mut Calibration) -> Result<PathBuf> {
+        let key = &calibration.metadata.key;
+        let version = self.versions(key).last().map_or(1, |v| v + 1);
+        let path = self.root.join(key.dir_name()).join(format!("v{version}"));
+        fs::create_dir_all(&path).map_err(|e| GrimError::Io(e, path.clone()))?;
+        calibration.metadata.version = version;
+        let file = path.join("metadata.json");
+        serde_json::to_writer_pretty(
+            File::create(&file).map_err(|e| GrimError::Io(e, file))?,
+            &calibration.metadata,
+        )?;
+        calibration
+            .diagnostics
+            .to_json(path.join("diagnostics.json"))?;
+        let file = path.join("poke.bin");
+        bincode::serialize_into(
+            File::create(&file).map_err(|e| GrimError::Io(e, file))?,
+            &calibration.poke,
+        )?;
+        let file = path.join("reconstructor.bin");
+        bincode::serialize_into(
+            File::create(&file).map_err(|e| GrimError::Io(e, file))?,
+            &calibration.reconstructor,
+        )?;
+        Ok(path)
+    }
+    /// Loads the metadata of the latest calibration matching `key`
+    pub fn metadata(&self, key: &Key) -> Result<Metadata> {
+        self.latest(key)?.ok_or_else(|| {
+            GrimError::Calibration(format!(
+                "no {} calibration in {:?} matches the optics configuration (run `calibrate` first)",
+                key.sensor, self.root
+            ))
+        })
+    }
+    /// Loads the metadata of the latest calibration matching `key` if there is one
+    pub fn latest(&self, key: &Key) -> Result<Option<Metadata>> {
+        let Some(version) = self.versions(key).last().copied() else {
+            return Ok(None);
+        };
+        let file = self
+            .root
+            .join(key.dir_name())
+            .join(format!("v{version}"))
+            .join("metadata.json");
+        let metadata: Metadata =
+            serde_json::from_reader(File::open(&file).map_err(|e| GrimError::Io(e, file))?)?;
+        if metadata.format != FORMAT_VERSION {
+            return Err(GrimError::Calibration(format!(
+                "{} calibration v{version} has format {}, expected {FORMAT_VERSION} (run `calibrate` again)",
+                key.sensor, metadata.format
+            )));
+        }
+        if metadata.key != *key {
+            return Err(GrimError::Calibration(format!(
+                "{} calibration v{version} optics configuration does not match:\n{:#?}\nexpected:\n{:#?}",
+                key.sensor, metadata.key, key
+            )));
+        }
+        Ok(Some(metadata))
+    }
+    /// Loads the latest calibration matching `key`
+    pub fn load(&self, key: &Key) -> Result<Calibration> {
+        let metadata = self.metadata(key)?;
+        let path = self
+            .root
+            .join(key.dir_name())
+            .join(format!("v{}", metadata.version));
+        let file = path.join("poke.bin");
+        let poke: na::DMatrix<f64> =
+            bincode::deserialize_from(File::open(&file).map_err(|e| GrimError::Io(e, file))?)?;
+        let file = path.join("reconstructor.bin");
+        let reconstructor: na::DMatrix<f64> =
+            bincode::deserialize_from(File::open(&file).map_err(|e| GrimError::Io(e, file))?)?;
+        if poke.shape() != (metadata.n_data, metadata.n_dof)
+            || reconstructor.shape() != (metadata.n_dof, metadata.n_data)
+        {
+            return Err(GrimError::Calibration(format!(
+                "{} calibration v{} matrices do not match the metadata",
+                key.sensor, metadata.version
+            )));
+        }
+        let diagnostics = Diagnostics::from_json(path.join("diagnostics.json"))?;
+        Ok(Calibration {
+            metadata,
+            poke,
+            reconstructor,
+            diagnostics,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempPath;
+
+    fn thresholds() -> Thresholds {
+        Thresholds {
+            max_closure_error: 1e-2,
+            singular_value_tolerance: 1e-9,
+            condition_tolerance: 1e-6,
+            linearity_factor: 2.,
+            max_nonlinearity: 0.05,
+        }
+    }
+
+    fn sh48_key(stroke: f64) -> Key {
+        let dofs: Vec<Dof> = ["M1S1Tx", "M2S1Rx", "M1S1B0"]
+            .into_iter()
+            .map(|dof| dof.parse().unwrap())
+            .collect();
+        Key {
+            sensor: "SH48".to_string(),
+            probes: vec![(6., 0.)],
+            flux_threshold: 0.5,
+            strokes: vec![stroke; dofs.len()],
+            dofs,
+        }
+    }
+
+    fn calibration(key: Key) -> Calibration {
+        let poke = na::DMatrix::<f64>::from_row_slice(
+            6,
+            3,
+            &[
+                1., 0., 0.5, 0., 1., 0.2, 0.3, 0., 1., 0.5, 0.5, 0., 0., 0.4, 0.3, 0.2, 0., 0.1,
+            ],
+        );
+        Calibration::new(key, &poke, &poke, &thresholds()).unwrap()
+    }
+
+    #[test]
+    fn key_hash() {
+        assert_eq!(sh48_key(1e-6).hash(), sh48_key(1e-6).hash());
+        assert_ne!(sh48_key(1e-6).hash(), sh48_key(5e-7).hash());
+        assert!(sh48_key(1e-6).dir_name().starts_with("SH48-"));
+        let strokes = Strokes {
+            m1_txyz: 1e-6,
+            m1_rxyz: 1e-6,
+            m2_txyz: 1e-6,
+            m2_rxyz: 2e-6,
+            m1_modes: 1e-6,
+            dofs: vec![("M2S7Ry".parse().unwrap(), 5e-7)],
+        };
+        let sh24 = Key::sh24(&strokes);
+        assert_eq!(sh24.dofs.len(), 2 * N_SEGMENT);
+        assert_eq!(sh24.strokes[0], 2e-6);
+        assert_eq!(sh24.strokes[2 * N_SEGMENT - 1], 5e-7);
+    }
+
+    #[test]
+    fn mismatch() {
+        let poke = na::DMatrix::<f64>::identity(6, 3);
+        let pull = na::DMatrix::<f64>::identity(5, 3);
+        assert!(matches!(
+            Calibration::new(sh48_key(1e-6), &poke, &pull, &thresholds()),
+            Err(GrimError::Calibration(_))
+        ));
+        let poke = na::DMatrix::<f64>::identity(6, 2);
+        assert!(matches!(
+            Calibration::new(sh48_key(1e-6), &poke, &poke, &thresholds()),
+            Err(GrimError::Calibration(_))
+        ));
+    }
+
+    #[test]
+    fn store() {
+        let root = TempPath::new("calibrations");
+        let store = Store::new(&root);
+        let key = sh48_key(1e-6);
+        assert!(store.latest(&key).unwrap().is_none());
+        assert!(matches!(store.load(&key), Err(GrimError::Calibration(_))));
+        let mut calibration = calibration(key.clone());
+        assert!(store.save(&mut calibration).unwrap().ends_with("v1"));
+        let path = store.save(&mut calibration).unwrap();
+        assert!(path.ends_with(format!("{}/v2", key.dir_name())));
+        let loaded = store.load(&key).unwrap();
+        assert_eq!(loaded.metadata.version, 2);
+        assert_eq!(loaded.metadata.key, key);
+        assert_eq!(loaded.poke, calibration.poke);
+        assert_eq!(loaded.reconstructor, calibration.reconstructor);
+        assert_eq!(
+            loaded.diagnostics.condition_number,
+            calibration.diagnostics.condition_number
+        );
+        // another optics configuration
+        assert!(store.latest(&sh48_key(5e-7)).unwrap().is_none());
+        // a calibration saved with another format
+        let file = path.join("metadata.json");
+        let mut metadata: serde_json::Value =
+            serde_json::from_reader(File::open(&file).unwrap()).unwrap();
+        metadata["format"] = (FORMAT_VERSION - 1).into();
+        serde_json::to_writer(File::create(&file).unwrap(), &metadata).unwrap();
+        assert!(matches!(store.latest(&key), Err(GrimError::Calibration(_))));
+        assert!(store.load(&key).is_err());
+    }
+}
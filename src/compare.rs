@@ -0,0 +1,248 @@
+//! Run-to-run comparison
+//!
+//! The streams with the same name in the result files of 2 runs are aligned row by row and,
+//! for each channel, the RMS of the difference is compared to the RMS of the channel of the
+//! reference run.
+//! A channel matches if its relative error is less than the [Tolerances] of the stream or if
+//! the RMS of the difference is less than the absolute tolerance.
+
+use crate::{
+    config::Tolerances,
+    results::{read_column, stream_names},
+    Result,
+};
+use std::{fmt::Display, path::Path};
+
+// largest number of mismatched channels listed in the report
+const MAX_LISTED: usize = 10;
+
+/// Comparison of a stream of 2 runs
+#[derive(Debug, Clone, Default)]
+pub struct StreamComparison {
+    pub file: String,
+    pub stream: String,
+    /// Number of compared samples
+    pub n_sample: usize,
+    /// RMS of the channels of the reference run
+    pub reference_rms: Vec<f64>,
+    /// RMS of the difference of the channels
+    pub difference_rms: Vec<f64>,
+    /// Relative tolerance
+    pub tolerance: f64,
+    /// Absolute tolerance
+    pub absolute_tolerance: f64,
+    /// Reason why the stream could not be compared
+    pub error: Option<String>,
+}
+impl StreamComparison {
+    fn failed<S: Into<String>>(file: &str, stream: &str, error: S) -> Self {
+        Self {
+            file: file.to_string(),
+            stream: stream.to_string(),
+            error: Some(error.into()),
+            ..Default::default()
+        }
+    }
+    /// Returns the relative error of each channel
+    pub fn relative_errors(&self) -> Vec<f64> {
+        self.reference_rms
+            .iter()
+            .zip(&self.difference_rms)
+            .map(|(reference, difference)| {
+                if *difference == 0f64 {
+                    0f64
+                } else {
+                    difference / reference
+                }
+            })
+            .collect()
+    }
+    /// Returns the indices of the channels outside the tolerances
+    pub fn mismatches(&self) -> Vec<usize> {
+        self.relative_errors()
+            .iter()
+            .zip(&self.difference_rms)
+            .enumerate()
+            .filter_map(|(i, (relative, absolute))| {
+                (!(*relative <= self.tolerance || *absolute <= self.absolute_tolerance))
+                    .then_some(i)
+            })
+            .collect()
+    }
+    /// Checks if the stream could be compared and all the channels are within the tolerances
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none() && self.mismatches().is_empty()
+    }
+}
+impl Display for StreamComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}: ", self.file, self.stream)?;
+        if let Some(error) = &self.error {
+            return write!(f, "FAILED: {error}");
+        }
+        let max = |x: &[f64]| {
+            x.iter().cloned().fold(0f64, |m, x| {
+                if x.is_nan() || m.is_nan() {
+                    f64::NAN
+                } else {
+                    m.max(x)
+                }
+            })
+        };
+        let relative_errors = self.relative_errors();
+        write!(
+            f,
+            "{} channels, {} samples, max. relative error: {:.3e} (tolerance: {:.1e}), max. RMS difference: {:.3e}",
+            self.reference_rms.len(),
+            self.n_sample,
+            max(&relative_errors),
+            self.tolerance,
+            max(&self.difference_rms)
+        )?;
+        let mismatches = self.mismatches();
+        if mismatches.is_empty() {
+            return write!(f, ": OK");
+        }
+        write!(f, ": FAILED ({} channels)", mismatches.len())?;
+        for &i in mismatches.iter().take(MAX_LISTED) {
+            write!(
+                f,
+                "\n   [{i}] relative error: {:.3e}, RMS difference: {:.3e}, reference RMS: {:.3e}",
+                relative_errors[i], self.difference_rms[i], self.reference_rms[i]
+            )?;
+        }
+        if mismatches.len() > MAX_LISTED {
+            write!(f, "\n   ...")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the stream `stream` of the parquet files `reference` and `run`
+///
+/// The rows are aligned by index and only the rows with a sample in both files are compared
+pub fn compare_stream<P: AsRef<Path>, Q: AsRef<Path>>(
+    reference: P,
+    run: Q,
+    stream: &str,
+    tolerances: &Tolerances,
+) -> Result<StreamComparison> {
+    let file = reference
+        .as_ref()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let reference = read_column(reference, stream)?;
+    let run = read_column(run, stream)?;
+    if reference.len() != run.len() {
+        log::warn!(
+            "{file}/{stream}: {} rows in the reference run and {} rows in the run, comparing the first {} rows",
+            reference.len(),
+            run.len(),
+            reference.len().min(run.len())
+        );
+    }
+    let mut comparison = StreamComparison {
+        file: file.clone(),
+        stream: stream.to_string(),
+        tolerance: tolerances.relative(stream),
+        absolute_tolerance: tolerances.absolute,
+        ..Default::default()
+    };
+    let mut sum_squares: Option<(Vec<f64>, Vec<f64>)> = None;
+    for (reference, run) in reference
+        .iter()
+        .zip(&run)
+        .filter_map(|(reference, run)| reference.as_ref().zip(run.as_ref()))
+    {
+        if reference.len() != run.len() {
+            return Ok(StreamComparison::failed(
+                &file,
+                stream,
+                format!(
+                    "{} channels in the reference run and {} channels in the run",
+                    reference.len(),
+                    run.len()
+                ),
+            ));
+        }
+        let (reference_ss, difference_ss) = sum_squares
+            .get_or_insert_with(|| (vec![0f64; reference.len()], vec![0f64; reference.len()]));
+        if reference_ss.len() != reference.len() {
+            return Ok(StreamComparison::failed(
+                &file,
+                stream,
+                "the number of channels changes from sample to sample",
+            ));
+        }
+        for (i, (x_reference, x_run)) in reference.iter().zip(run).enumerate() {
+            reference_ss[i] += x_reference * x_reference;
+            difference_ss[i] += (x_run - x_reference).powi(2);
+        }
+        comparison.n_sample += 1;
+    }
+    let Some((reference_ss, difference_ss)) = sum_squares else {
+        return Ok(StreamComparison::failed(&file, stream, "no common sample"));
+    };
+    let n = comparison.n_sample as f64;
+    comparison.reference_rms = reference_ss.iter().map(|x| (x / n).sqrt()).collect();
+    comparison.difference_rms = difference_ss.iter().map(|x| (x / n).sqrt()).collect();
+    Ok(comparison)
+}
+
+/// Compares all the streams of the result file `file` of the run directories `reference` and `run`
+///
+/// The streams missing from one of the runs fail the comparison,
+/// the file is skipped if it is missing from both runs
+pub fn compare_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    reference: P,
+    run: Q,
+    file: &str,
+    tolerances: &Tolerances,
+) -> Result<Vec<StreamComparison>> {
+    let reference = reference.as_ref().join(file);
+    let run = run.as_ref().join(file);
+    match (reference.exists(), run.exists()) {
+        (false, false) => return Ok(vec![]),
+        (true, false) => {
+            return Ok(vec![StreamComparison::failed(
+                file,
+                "*",
+                "missing from the run",
+            )])
+        }
+        (false, true) => {
+            return Ok(vec![StreamComparison::failed(
+                file,
+                "*",
+                "missing from the reference run",
+            )])
+        }
+        (true, true) => (),
+    }
+    let reference_streams = stream_names(&reference)?;
+    let run_streams = stream_names(&run)?;
+    let mut comparisons = vec![];
+    for stream in &reference_streams {
+        if run_streams.contains(stream) {
+            comparisons.push(compare_stream(&reference, &run, stream, tolerances)?);
+        } else {
+            comparisons.push(StreamComparison::failed(
+                file,
+                stream,
+                "missing from the run",
+            ));
+        }
+    }
+    for stream in run_streams
+        .iter()
+        .filter(|stream| !reference_streams.contains(stream))
+    {
+        comparisons.push(StreamComparison::failed(
+            file,
+            stream,
+            "missing from the reference run",
+        ));
+    }
+    Ok(comparisons)
+}
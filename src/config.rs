@@ -0,0 +1,1078 @@
+//! Model configuration
+//!
+//! The model is configured with environment variables, see the README for the list of variables.
+
+use crate::{
+    aco::{Axis, Dof, Mirror, N_RBM, N_SEGMENT},
+    windloads::GROUPS,
+    GrimError, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{env, str::FromStr};
+
+/// Parses the environment variable `key` or returns `default` if the variable is not set
+pub fn env_or<T: FromStr>(key: &str, default: T) -> Result<T> {
+    Ok(env_opt(key)?.unwrap_or(default))
+}
+/// Parses the environment variable `key` or returns `None` if the variable is not set
+pub fn env_opt<T: FromStr>(key: &str) -> Result<Option<T>> {
+    match env::var(key) {
+        Ok(value) => value
+            .trim()
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| GrimError::Config(format!("invalid value for {key}: {value:?}"))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// AGWS SH48 configuration
+///
+/// Environment variables:
+///  - `SH48_N_PROBE` [1]: the number of SH48 probes (1 to 4)
+///  - `SH48_PROBES` [evenly spaced on a 6' ring]: the probes field positions as a comma separated
+///    list of `zenith:azimuth` pairs with the zenith angle in arcmin and the azimuth angle in degree,
+///    e.g. `6:0,6:120,6:240`
+///  - `SH48_FLUX_THRESHOLD` [0.5]: the lenslet flux threshold
+///  - `SH48_N_MODE` [27]: the number of M1 modes corrected by the active optics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sh48 {
+    /// Probes field positions (zenith [arcmin], azimuth [deg])
+    pub probes: Vec<(f32, f32)>,
+    pub flux_threshold: f64,
+    pub n_mode: usize,
+}
+impl Sh48 {
+    /// Reads the SH48 configuration from the environment
+    pub fn from_env() -> Result<Self> {
+        let n_probe: usize = env_or("SH48_N_PROBE", 1)?;
+        if !(1..=4).contains(&n_probe) {
+            return Err(GrimError::Config(format!(
+                "SH48_N_PROBE must be in [1,4], found {n_probe}"
+            )));
+        }
+        let probes = match env::var("SH48_PROBES") {
+            Ok(probes) => probes
+                .split(',')
+                .map(|probe| {
+                    probe
+                        .split_once(':')
+                        .and_then(|(z, a)| z.trim().parse().ok().zip(a.trim().parse().ok()))
+                        .ok_or_else(|| {
+                            GrimError::Config(format!("invalid SH48 probe position: {probe:?}"))
+                        })
+                })
+                .collect::<Result<Vec<(f32, f32)>>>()?,
+            Err(_) => (0..n_probe)
+                .map(|i| (6f32, 360f32 * i as f32 / n_probe as f32))
+                .collect(),
+        };
+        if probes.len() != n_probe {
+            return Err(GrimError::Config(format!(
+                "SH48_PROBES has {} positions but SH48_N_PROBE is {n_probe}",
+                probes.len()
+            )));
+        }
+        let flux_threshold = env_or("SH48_FLUX_THRESHOLD", 0.5)?;
+        if !(0f64..=1f64).contains(&flux_threshold) {
+            return Err(GrimError::Config(format!(
+                "SH48_FLUX_THRESHOLD must be in [0,1], found {flux_threshold}"
+            )));
+        }
+        let n_mode = env_or("SH48_N_MODE", 27)?;
+        // M1 segment #7 has only 151 modes
+        if !(1..=151).contains(&n_mode) {
+            return Err(GrimError::Config(format!(
+                "SH48_N_MODE must be in [1,151], found {n_mode}"
+            )));
+        }
+        Ok(Self {
+            probes,
+            flux_threshold,
+            n_mode,
+        })
+    }
+    /// Returns the number of probes
+    pub fn n_probe(&self) -> usize {
+        self.probes.len()
+    }
+    /// Returns the probes zenith angles [rd]
+    pub fn zenith(&self) -> Vec<f32> {
+        self.probes
+            .iter()
+            .map(|(z, _)| (z / 60f32).to_radians())
+            .collect()
+    }
+    /// Returns the probes azimuth angles [rd]
+    pub fn azimuth(&self) -> Vec<f32> {
+        self.probes.iter().map(|(_, a)| a.to_radians()).collect()
+    }
+    /// Returns the number of degrees of freedom of the active optics (`7 x n_mode`)
+    pub fn n_dof(&self) -> usize {
+        7 * self.n_mode
+    }
+    /// Returns a tag that uniquely identifies the configuration
+    pub fn tag(&self) -> String {
+        let probes: Vec<_> = self
+            .probes
+            .iter()
+            .map(|(z, a)| format!("{z}_{a}"))
+            .collect();
+        format!(
+            "x{}-{}-{}-{}",
+            self.n_probe(),
+            probes.join("-"),
+            self.flux_threshold,
+            self.n_mode
+        )
+    }
+}
+
+/// Active optics configuration
+///
+/// Environment variables:
+///  - `ACO_M1_RBM` [none]: the comma separated list of the M1 segments rigid body motions axes
+///    controlled by the active optics, e.g. `Tx,Ty,Tz,Rx,Ry`
+///  - `ACO_M2_RBM` [none]: the comma separated list of the M2 segments rigid body motions axes
+///    controlled by the active optics
+///  - `ACO_EXCLUDE` [none]: the comma separated list of the segments rigid body motions that are
+///    not controlled, e.g. `M1S7Rz,M2S7Rz`
+///  - `ACO_M1_MODES_GAIN` [0.5]: the gain of the integrator of the M1 segments bending modes
+///  - `ACO_M1_RBM_GAIN` [0.5]: the gain of the integrator of the M1 segments rigid body motions
+///  - `ACO_M2_RBM_GAIN` [0.5]: the gain of the integrator of the M2 segments rigid body motions
+///
+/// The M1 segments bending modes are always controlled, their number is given by [Sh48::n_mode].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Aco {
+    /// M1 controlled rigid body motions `[7x6]`
+    pub m1_rbm: Vec<bool>,
+    /// M2 controlled rigid body motions `[7x6]`
+    pub m2_rbm: Vec<bool>,
+    pub n_mode: usize,
+    /// M1 bending modes integrator gain
+    pub m1_modes_gain: f64,
+    /// M1 rigid body motions integrator gain
+    pub m1_rbm_gain: f64,
+    /// M2 rigid body motions integrator gain
+    pub m2_rbm_gain: f64,
+}
+impl Aco {
+    /// Reads the active optics configuration from the environment
+    pub fn from_env(sh48: &Sh48) -> Result<Self> {
+        let axes = |key: &str| -> Result<Vec<bool>> {
+            let mut rbm = [false; N_RBM];
+            if let Ok(value) = env::var(key) {
+                for axis in value.split(',').filter(|axis| !axis.trim().is_empty()) {
+                    let axis = axis
+                        .parse::<Axis>()
+                        .map_err(|e| GrimError::Config(format!("{key}: {e}")))?;
+                    rbm[axis.index()] = true;
+                }
+            }
+            Ok(rbm.repeat(N_SEGMENT))
+        };
+        let mut m1_rbm = axes("ACO_M1_RBM")?;
+        let mut m2_rbm = axes("ACO_M2_RBM")?;
+        if let Ok(value) = env::var("ACO_EXCLUDE") {
+            for dof in value.split(',').filter(|dof| !dof.trim().is_empty()) {
+                match dof.trim().parse::<Dof>() {
+                    Ok(Dof::Rbm { mirror, sid, axis }) => {
+                        let idx = (sid - 1) * N_RBM + axis.index();
+                        match mirror {
+                            Mirror::M1 => m1_rbm[idx] = false,
+                            Mirror::M2 => m2_rbm[idx] = false,
+                        }
+                    }
+                    Ok(Dof::Mode { .. }) => {
+                        return Err(GrimError::Config(format!(
+                            "ACO_EXCLUDE: invalid rigid body motion {dof:?}, expected e.g. M1S7Rz"
+                        )))
+                    }
+                    Err(e) => return Err(GrimError::Config(format!("ACO_EXCLUDE: {e}"))),
+                }
+            }
+        }
+        Ok(Self {
+            m1_rbm,
+            m2_rbm,
+            n_mode: sh48.n_mode,
+            m1_modes_gain: env_or("ACO_M1_MODES_GAIN", 0.5)?,
+            m1_rbm_gain: env_or("ACO_M1_RBM_GAIN", 0.5)?,
+            m2_rbm_gain: env_or("ACO_M2_RBM_GAIN", 0.5)?,
+        })
+    }
+    /// Returns true if some rigid body motions of segment `sid` of `mirror` are controlled
+    pub fn has_rbm(&self, mirror: Mirror, sid: usize) -> bool {
+        let rbm = match mirror {
+            Mirror::M1 => &self.m1_rbm,
+            Mirror::M2 => &self.m2_rbm,
+        };
+        rbm[(sid - 1) * N_RBM..sid * N_RBM].iter().any(|&c| c)
+    }
+    /// Returns the calibrated degrees of freedom
+    ///
+    /// The degrees of freedom are ordered per segment and, for each segment, as:
+    /// M1 rigid body motions, M2 rigid body motions and M1 bending modes.
+    /// All the rigid body motions of a segment are calibrated if any of them is controlled.
+    pub fn calibrated(&self) -> Vec<Dof> {
+        let mut dofs = vec![];
+        for sid in 1..=N_SEGMENT {
+            for mirror in [Mirror::M1, Mirror::M2] {
+                if self.has_rbm(mirror, sid) {
+                    dofs.extend(Axis::ALL.map(|axis| Dof::Rbm { mirror, sid, axis }));
+                }
+            }
+            dofs.extend((0..self.n_mode).map(|mode| Dof::Mode { sid, mode }));
+        }
+        dofs
+    }
+    /// Returns true if `dof` is controlled
+    pub fn is_controlled(&self, dof: &Dof) -> bool {
+        match *dof {
+            Dof::Rbm {
+                mirror: Mirror::M1,
+                sid,
+                axis,
+            } => self.m1_rbm[(sid - 1) * N_RBM + axis.index()],
+            Dof::Rbm {
+                mirror: Mirror::M2,
+                sid,
+                axis,
+            } => self.m2_rbm[(sid - 1) * N_RBM + axis.index()],
+            Dof::Mode { mode, .. } => mode < self.n_mode,
+        }
+    }
+    /// Returns the indices of the controlled degrees of freedom in the [calibrated](Aco::calibrated) ones
+    pub fn controlled_columns(&self) -> Vec<usize> {
+        self.calibrated()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, dof)| self.is_controlled(dof).then_some(i))
+            .collect()
+    }
+    /// Returns the controlled degrees of freedom
+    pub fn controlled(&self) -> Vec<Dof> {
+        self.calibrated()
+            .into_iter()
+            .filter(|dof| self.is_controlled(dof))
+            .collect()
+    }
+    /// Returns a tag that uniquely identifies the calibrated degrees of freedom
+    pub fn tag(&self) -> String {
+        let segments = |mirror| -> String {
+            (1..=N_SEGMENT)
+                .map(|sid| if self.has_rbm(mirror, sid) { '1' } else { '0' })
+                .collect()
+        };
+        format!("rbm{}{}", segments(Mirror::M1), segments(Mirror::M2))
+    }
+}
+
+/// Calibration strokes
+///
+/// Environment variables:
+///  - `CALIBRATION_STROKE_M1_TXYZ` [1e-6]: the M1 segments translations stroke [m]
+///  - `CALIBRATION_STROKE_M1_RXYZ` [1e-6]: the M1 segments rotations stroke [rd]
+///  - `CALIBRATION_STROKE_M2_TXYZ` [1e-6]: the M2 segments translations stroke [m]
+///  - `CALIBRATION_STROKE_M2_RXYZ` [1e-6]: the M2 segments rotations stroke [rd]
+///  - `CALIBRATION_STROKE_M1_MODES` [1e-6]: the M1 segments bending modes stroke
+///  - `CALIBRATION_STROKES` [none]: the comma separated list of the strokes of some degrees of freedom,
+///    e.g. `M1S7Rz=5e-7,M1S1B0=2e-6`, that overrides the strokes above
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Strokes {
+    pub m1_txyz: f64,
+    pub m1_rxyz: f64,
+    pub m2_txyz: f64,
+    pub m2_rxyz: f64,
+    pub m1_modes: f64,
+    pub dofs: Vec<(Dof, f64)>,
+}
+impl Strokes {
+    /// Reads the calibration strokes from the environment
+    pub fn from_env() -> Result<Self> {
+        let dofs = match env::var("CALIBRATION_STROKES") {
+            Ok(value) => value
+                .split(',')
+                .filter(|stroke| !stroke.trim().is_empty())
+                .map(|stroke| {
+                    stroke
+                        .split_once('=')
+                        .and_then(|(dof, s)| dof.parse::<Dof>().ok().zip(s.trim().parse().ok()))
+                        .ok_or_else(|| {
+                            GrimError::Config(format!(
+                                "CALIBRATION_STROKES: invalid stroke {stroke:?}, expected e.g. M1S7Rz=5e-7"
+                            ))
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            Err(_) => vec![],
+        };
+        let strokes = Self {
+            m1_txyz: env_or("CALIBRATION_STROKE_M1_TXYZ", 1e-6)?,
+            m1_rxyz: env_or("CALIBRATION_STROKE_M1_RXYZ", 1e-6)?,
+            m2_txyz: env_or("CALIBRATION_STROKE_M2_TXYZ", 1e-6)?,
+            m2_rxyz: env_or("CALIBRATION_STROKE_M2_RXYZ", 1e-6)?,
+            m1_modes: env_or("CALIBRATION_STROKE_M1_MODES", 1e-6)?,
+            dofs,
+        };
+        if [
+            strokes.m1_txyz,
+            strokes.m1_rxyz,
+            strokes.m2_txyz,
+            strokes.m2_rxyz,
+            strokes.m1_modes,
+        ]
+        .into_iter()
+        .chain(strokes.dofs.iter().map(|(_, s)| *s))
+        .any(|s| !(s.is_finite() && s > 0f64))
+        {
+            return Err(GrimError::Config(
+                "calibration strokes must be positive".to_string(),
+            ));
+        }
+        Ok(strokes)
+    }
+    /// Returns the calibration stroke of `dof`
+    pub fn stroke(&self, dof: &Dof) -> f64 {
+        if let Some((_, stroke)) = self.dofs.iter().find(|(d, _)| d == dof) {
+            return *stroke;
+        }
+        match dof {
+            Dof::Rbm {
+                mirror: Mirror::M1,
+                axis,
+                ..
+            } if axis.index() < 3 => self.m1_txyz,
+            Dof::Rbm {
+                mirror: Mirror::M1, ..
+            } => self.m1_rxyz,
+            Dof::Rbm {
+                mirror: Mirror::M2,
+                axis,
+                ..
+            } if axis.index() < 3 => self.m2_txyz,
+            Dof::Rbm {
+                mirror: Mirror::M2, ..
+            } => self.m2_rxyz,
+            Dof::Mode { .. } => self.m1_modes,
+        }
+    }
+}
+
+/// Atmospheric turbulence configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Atmosphere {
+    /// Phase screens file
+    pub file: String,
+    /// Phase screens width [m]
+    pub width: f32,
+    /// Phase screens sampling [px]
+    pub sampling: usize,
+    /// Field of view [arcmin]
+    pub field: f32,
+    /// Phase screens duration [s]
+    pub duration: f32,
+    /// Fried parameter at zenith [m]
+    pub r0: Option<f64>,
+    /// Outer scale [m]
+    pub l0: Option<f64>,
+    /// Indices of the turbulence profile layers that are removed
+    pub removed_layers: Vec<usize>,
+}
+
+/// Dome seeing configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DomeSeeing {
+    /// CFD case directory
+    pub cfd_case: String,
+    /// Sampling frequency of the CFD dome seeing [Hz]
+    pub sampling_frequency: usize,
+}
+
+/// Optical disturbances configuration
+///
+/// Environment variables:
+///  - `ATMOSPHERE` [on]: the atmospheric turbulence switch (`on` or `off`)
+///  - `ATMOSPHERE_FILE` [/fsx/atmosphere/free_atm_15mn.bin]: the atmosphere phase screens file
+///  - `ATMOSPHERE_WIDTH` [25.5]: the phase screens width in meter
+///  - `ATMOSPHERE_SAMPLING` [769]: the phase screens sampling in pixel
+///  - `ATMOSPHERE_FIELD` [20]: the phase screens field of view in arcmin
+///  - `ATMOSPHERE_DURATION` [20]: the phase screens duration in second
+///  - `ATMOSPHERE_R0` [default profile]: the Fried parameter at zenith in meter
+///  - `ATMOSPHERE_L0` [default profile]: the outer scale in meter
+///  - `ATMOSPHERE_REMOVE_LAYERS` [0]: the comma separated list of the indices of the turbulence
+///    layers removed from the profile, the ground layer (0) is replaced by the dome seeing
+///  - `DOME_SEEING` [on]: the dome seeing switch (`on` or `off`)
+///  - `DOME_SEEING_CFD_CASE` [/fsx/CASES/zen30az000_OS7]: the dome seeing CFD case directory
+///  - `DOME_SEEING_SAMPLING_FREQUENCY` [5]: the sampling frequency of the CFD dome seeing in Hz,
+///    a divisor of the simulation sampling frequency
+///  - `STATIC_ABERRATIONS` [raw-polishing_print-through_soak1deg_769.bin]: the comma separated list of
+///    the static phase maps that are summed, relative to `GMT_MODES_PATH`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Disturbances {
+    pub atmosphere: Option<Atmosphere>,
+    pub dome_seeing: Option<DomeSeeing>,
+    pub static_aberrations: Vec<String>,
+}
+impl Disturbances {
+    /// Reads the optical disturbances configuration from the environment for a simulation
+    /// sampled at `sampling_frequency` [Hz]
+    pub fn from_env(sampling_frequency: usize) -> Result<Self> {
+        let atmosphere = if switch("ATMOSPHERE")? {
+            Some(Atmosphere {
+                file: env::var("ATMOSPHERE_FILE")
+                    .unwrap_or_else(|_| "/fsx/atmosphere/free_atm_15mn.bin".to_string()),
+                width: env_or("ATMOSPHERE_WIDTH", 25.5)?,
+                sampling: env_or("ATMOSPHERE_SAMPLING", 48 * 16 + 1)?,
+                field: env_or("ATMOSPHERE_FIELD", 20f32)?,
+                duration: env_or("ATMOSPHERE_DURATION", 20f32)?,
+                r0: env_opt("ATMOSPHERE_R0")?,
+                l0: env_opt("ATMOSPHERE_L0")?,
+                removed_layers: list("ATMOSPHERE_REMOVE_LAYERS", "0")?,
+            })
+        } else {
+            None
+        };
+        if let Some(atmosphere) = &atmosphere {
+            for (key, value) in [
+                ("ATMOSPHERE_R0", atmosphere.r0),
+                ("ATMOSPHERE_L0", atmosphere.l0),
+            ] {
+                if let Some(value) = value.filter(|value| value.is_nan() || *value <= 0f64) {
+                    return Err(GrimError::Config(format!(
+                        "{key} must be positive, found {value}"
+                    )));
+                }
+            }
+        }
+        let dome_seeing = if switch("DOME_SEEING")? {
+            Some(DomeSeeing {
+                cfd_case: env::var("DOME_SEEING_CFD_CASE")
+                    .unwrap_or_else(|_| "/fsx/CASES/zen30az000_OS7".to_string()),
+                sampling_frequency: env_or("DOME_SEEING_SAMPLING_FREQUENCY", 5)?,
+            })
+        } else {
+            None
+        };
+        if let Some(dome_seeing) = &dome_seeing {
+            if dome_seeing.sampling_frequency == 0
+                || sampling_frequency % dome_seeing.sampling_frequency != 0
+            {
+                return Err(GrimError::Config(format!(
+                    "DOME_SEEING_SAMPLING_FREQUENCY must divide the simulation sampling frequency ({sampling_frequency}Hz), found {}",
+                    dome_seeing.sampling_frequency
+                )));
+            }
+        }
+        let static_aberrations = list(
+            "STATIC_ABERRATIONS",
+            "raw-polishing_print-through_soak1deg_769.bin",
+        )?;
+        Ok(Self {
+            atmosphere,
+            dome_seeing,
+            static_aberrations,
+        })
+    }
+}
+
+/// Wind loads configuration
+///
+/// Environment variables:
+///  - `WIND_LOADS` [all]: the comma separated list of the enabled wind loads groups among
+///    TopEnd, M2Baffle, Trusses, M1Baffle, MirrorCovers, LaserGuideStars, CRings, GIR, Platforms,
+///    M1Segments and M2Segments
+///  - `WIND_LOADS_SCALES` [1]: the comma separated list of the `group=factor` scaling factors
+///    of the wind loads groups, e.g. `TopEnd=1.2,M1Segments=0.5`
+///  - `WIND_SPEED` [CFD case wind speed]: the wind speed in m/s, all the wind loads are scaled by
+///    the dynamic pressure ratio `(v/v₀)²` with `v₀` the wind speed of the CFD case
+///  - `WIND_LOADS_TIME_RANGE` [unset]: the `start:end` time window in second of the CFD record,
+///    the window is repeated if the simulation is longer than the window
+///  - `WIND_LOADS_CROSS_FADE` [1]: the duration in second of the cross-fade between the end and
+///    the start of the repeated window
+///  - `WIND_LOADS_MAX_SPECTRAL_ERROR` [1]: the largest octave band power error in dB between the
+///    repeated and the original wind loads
+///  - `WIND_LOADS_SPECTRAL_CHECK` [on]: the simulation fails if the octave band power error is
+///    larger than `WIND_LOADS_MAX_SPECTRAL_ERROR`, only a warning is logged if `off`
+///  - `WIND_LOADS_SOURCE` [cfd]: the wind loads source, `cfd` for the CFD loads, `psd` for the loads
+///    synthesized from the spectra in `WIND_LOADS_PSD_FILE` or `von-karman` for the loads
+///    synthesized from von Kármán spectra
+///  - `WIND_LOADS_PSD_FILE` [wind-loads-psd.bin]: the wind loads spectra file written by the
+///    `windpsd` binary
+///  - `WIND_LOADS_FORCE_STD` [1], `WIND_LOADS_MOMENT_STD` [1]: the standard deviations in N and N.m
+///    of the von Kármán forces and moments of each node
+///  - `WIND_LOADS_LENGTH_SCALE` [10]: the von Kármán turbulence length scale in meter
+///  - `WIND_LOADS_SEED` [0]: the seed of the random generator of the synthetic loads
+///  - `WIND_LOADS_CACHE` [unset]: the directory of the CFD loads cache, the cache is not used if unset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindLoads {
+    /// Enabled groups with their scaling factors, including the dynamic pressure ratio
+    pub groups: Vec<(String, f64)>,
+    /// CFD case wind speed [m/s]
+    pub cfd_wind_speed: f64,
+    /// Wind speed [m/s]
+    pub wind_speed: f64,
+    /// CFD record time window [s]
+    pub time_range: Option<(f64, f64)>,
+    /// Cross-fade duration of the repeated time window [s]
+    pub cross_fade: f64,
+    /// Largest octave band power error of the repeated time window [dB]
+    pub max_spectral_error: f64,
+    /// Fails the simulation if the octave band power error is larger than `max_spectral_error`
+    pub spectral_check: bool,
+    pub source: WindLoadsSource,
+    /// Seed of the random generator of the synthetic loads
+    pub seed: u64,
+    /// CFD loads cache directory
+    pub cache: Option<String>,
+}
+impl WindLoads {
+    /// Reads the wind loads configuration from the environment for a CFD case with a wind speed of `cfd_wind_speed`
+    pub fn from_env(cfd_wind_speed: f64) -> Result<Self> {
+        let group = |name: &str| {
+            GROUPS
+                .iter()
+                .find(|group| group.eq_ignore_ascii_case(name.trim()))
+                .map(|group| group.to_string())
+                .ok_or_else(|| {
+                    GrimError::Config(format!(
+                        "unknown wind loads group {name:?}, expected one of {}",
+                        GROUPS.join(",")
+                    ))
+                })
+        };
+        let enabled = match env::var("WIND_LOADS") {
+            Ok(groups) => groups
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .map(group)
+                .collect::<Result<Vec<String>>>()?,
+            Err(_) => GROUPS.iter().map(|group| group.to_string()).collect(),
+        };
+        let scales = env::var("WIND_LOADS_SCALES")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| {
+                let (name, factor) = item.split_once('=').ok_or_else(|| {
+                    GrimError::Config(format!("invalid wind loads scale: {item:?}"))
+                })?;
+                let factor: f64 = factor.trim().parse().map_err(|_| {
+                    GrimError::Config(format!("invalid wind loads scale: {item:?}"))
+                })?;
+                Ok((group(name)?, factor))
+            })
+            .collect::<Result<Vec<(String, f64)>>>()?;
+        let wind_speed: f64 = env_or("WIND_SPEED", cfd_wind_speed)?;
+        if wind_speed.is_nan() || wind_speed < 0f64 {
+            return Err(GrimError::Config(format!(
+                "WIND_SPEED must be positive, found {wind_speed}"
+            )));
+        }
+        let dynamic_pressure_ratio = (wind_speed / cfd_wind_speed).powi(2);
+        let groups = GROUPS
+            .iter()
+            .filter(|group| enabled.iter().any(|name| name == *group))
+            .map(|group| {
+                let factor = scales
+                    .iter()
+                    .rev()
+                    .find(|(name, _)| name == group)
+                    .map_or(1f64, |(_, factor)| *factor);
+                (group.to_string(), factor * dynamic_pressure_ratio)
+            })
+            .collect();
+        let time_range = match env::var("WIND_LOADS_TIME_RANGE") {
+            Ok(range) => {
+                let (start, end): (f64, f64) = range
+                    .split_once(':')
+                    .and_then(|(a, b)| a.trim().parse().ok().zip(b.trim().parse().ok()))
+                    .ok_or_else(|| {
+                        GrimError::Config(format!(
+                            "invalid value for WIND_LOADS_TIME_RANGE: {range:?}"
+                        ))
+                    })?;
+                if start.is_nan() || start < 0f64 || end.is_nan() || end <= start {
+                    return Err(GrimError::Config(format!(
+                        "WIND_LOADS_TIME_RANGE must be a positive time window, found {range:?}"
+                    )));
+                }
+                Some((start, end))
+            }
+            Err(_) => None,
+        };
+        let cross_fade: f64 = env_or("WIND_LOADS_CROSS_FADE", 1f64)?;
+        if cross_fade.is_nan() || cross_fade < 0f64 {
+            return Err(GrimError::Config(format!(
+                "WIND_LOADS_CROSS_FADE must be positive, found {cross_fade}"
+            )));
+        }
+        let source = match env::var("WIND_LOADS_SOURCE")
+            .unwrap_or_else(|_| "cfd".to_string())
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "cfd" => WindLoadsSource::Cfd,
+            "psd" => WindLoadsSource::Psd {
+                file: env::var("WIND_LOADS_PSD_FILE")
+                    .unwrap_or_else(|_| "wind-loads-psd.bin".to_string()),
+            },
+            "von-karman" => WindLoadsSource::VonKarman {
+                force_std: env_or("WIND_LOADS_FORCE_STD", 1f64)?,
+                moment_std: env_or("WIND_LOADS_MOMENT_STD", 1f64)?,
+                length_scale: env_or("WIND_LOADS_LENGTH_SCALE", 10f64)?,
+            },
+            value => {
+                return Err(GrimError::Config(format!(
+                "invalid value for WIND_LOADS_SOURCE: {value:?}, expected cfd, psd or von-karman"
+            )))
+            }
+        };
+        Ok(Self {
+            groups,
+            cfd_wind_speed,
+            wind_speed,
+            time_range,
+            cross_fade,
+            max_spectral_error: env_or("WIND_LOADS_MAX_SPECTRAL_ERROR", 1f64)?,
+            spectral_check: switch("WIND_LOADS_SPECTRAL_CHECK")?,
+            source,
+            seed: env_or("WIND_LOADS_SEED", 0)?,
+            cache: env::var("WIND_LOADS_CACHE").ok(),
+        })
+    }
+    /// Returns the scaling factor of the wind loads `group` or `None` if the group is disabled
+    pub fn scale(&self, group: &str) -> Option<f64> {
+        self.groups
+            .iter()
+            .find(|(name, _)| name == group)
+            .map(|(_, factor)| *factor)
+    }
+    /// Checks if the wind loads `group` is enabled
+    pub fn is_enabled(&self, group: &str) -> bool {
+        self.scale(group).is_some()
+    }
+}
+
+/// Wind loads source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WindLoadsSource {
+    /// CFD loads
+    Cfd,
+    /// Loads synthesized from the spectra in `file`
+    Psd { file: String },
+    /// Loads synthesized from von Kármán spectra
+    VonKarman {
+        /// Standard deviation of the forces [N]
+        force_std: f64,
+        /// Standard deviation of the moments [N.m]
+        moment_std: f64,
+        /// Turbulence length scale [m]
+        length_scale: f64,
+    },
+}
+
+/// Run comparison tolerances
+///
+/// Environment variables:
+///  - `COMPARE_RELATIVE_TOLERANCE` [1e-6]: the largest ratio between the RMS of the difference
+///    of a channel and the RMS of the channel of the reference run
+///  - `COMPARE_ABSOLUTE_TOLERANCE` [0]: the RMS of the difference of a channel below which the
+///    channel matches whatever its relative error
+///  - `COMPARE_TOLERANCES` [none]: the comma separated list of the relative tolerances of some
+///    streams, e.g. `WfeRms=1e-3,SegmentPiston=1e-2`, that overrides `COMPARE_RELATIVE_TOLERANCE`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tolerances {
+    pub relative: f64,
+    pub absolute: f64,
+    pub streams: Vec<(String, f64)>,
+}
+impl Tolerances {
+    /// Reads the comparison tolerances from the environment
+    pub fn from_env() -> Result<Self> {
+        let streams = match env::var("COMPARE_TOLERANCES") {
+            Ok(value) => value
+                .split(',')
+                .filter(|tolerance| !tolerance.trim().is_empty())
+                .map(|tolerance| {
+                    tolerance
+                        .split_once('=')
+                        .and_then(|(stream, t)| {
+                            t.trim().parse().ok().map(|t| (stream.trim().to_string(), t))
+                        })
+                        .ok_or_else(|| {
+                            GrimError::Config(format!(
+                                "COMPARE_TOLERANCES: invalid tolerance {tolerance:?}, expected e.g. WfeRms=1e-3"
+                            ))
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            Err(_) => vec![],
+        };
+        let tolerances = Self {
+            relative: env_or("COMPARE_RELATIVE_TOLERANCE", 1e-6)?,
+            absolute: env_or("COMPARE_ABSOLUTE_TOLERANCE", 0f64)?,
+            streams,
+        };
+        if [tolerances.relative, tolerances.absolute]
+            .into_iter()
+            .chain(tolerances.streams.iter().map(|(_, t)| *t))
+            .any(|t| !(t.is_finite() && t >= 0f64))
+        {
+            return Err(GrimError::Config(
+                "comparison tolerances must be positive".to_string(),
+            ));
+        }
+        Ok(tolerances)
+    }
+    /// Returns the relative tolerance of `stream`
+    pub fn relative(&self, stream: &str) -> f64 {
+        self.streams
+            .iter()
+            .find(|(name, _)| name == stream)
+            .map_or(self.relative, |(_, t)| *t)
+    }
+}
+
+/// Excitation of a frequency response measurement
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Excitation {
+    /// Logarithmic sine sweep from `f_min` to `f_max` [Hz] repeated every `duration` [s]
+    Chirp {
+        f_min: f64,
+        f_max: f64,
+        duration: f64,
+    },
+    /// Sum of the sines, with Schroeder phases, at the frequencies multiple of 1/`period` [s]
+    /// in between `f_min` and `f_max` [Hz]
+    Multisine { f_min: f64, f_max: f64, period: f64 },
+    /// Unit step
+    Step,
+}
+
+/// Frequency response measurement configuration
+///
+/// Environment variables:
+///  - `INJECTION_SIGNAL` [unset]: the unique identifier of the signal the excitation is added to,
+///    e.g. `MountTorques` or `PZTcmd`, no excitation is injected if unset
+///  - `INJECTION_EXCITATION` [chirp]: the excitation, `chirp`, `multisine` or `step`
+///  - `INJECTION_AMPLITUDE` [1]: the amplitude of the chirp and of the step, the multisine has
+///    the same RMS as a sine of that amplitude
+///  - `INJECTION_CHANNELS` [0]: the comma separated list of the channels of the signal the
+///    excitation is added to
+///  - `INJECTION_START` [warm-up duration]: the time in second when the excitation starts
+///  - `INJECTION_F_MIN` [0.1], `INJECTION_F_MAX` [Nyquist frequency]: the frequency range in Hz
+///    of the chirp and of the multisine
+///  - `INJECTION_PERIOD` [10]: the duration in second of a chirp sweep or the period of the multisine,
+///    the frequency range must contain at least one harmonic of the multisine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Injection {
+    /// Unique identifier of the signal
+    pub signal: String,
+    pub excitation: Excitation,
+    pub amplitude: f64,
+    pub channels: Vec<usize>,
+    /// Start of the excitation [s]
+    pub start: f64,
+    /// Sampling frequency of the signal [Hz]
+    pub sampling_frequency: f64,
+}
+impl Injection {
+    /// Reads the injection configuration from the environment for a signal sampled at
+    /// `sampling_frequency` and a simulation with a warm-up of `warm_up` seconds
+    pub fn from_env(sampling_frequency: f64, warm_up: f64) -> Result<Option<Self>> {
+        let signal = match env::var("INJECTION_SIGNAL") {
+            Ok(signal) if !signal.trim().is_empty() => signal.trim().to_string(),
+            _ => return Ok(None),
+        };
+        let f_min: f64 = env_or("INJECTION_F_MIN", 0.1)?;
+        let f_max: f64 = env_or("INJECTION_F_MAX", sampling_frequency / 2f64)?;
+        if !(f_min > 0f64 && f_max > f_min && f_max <= sampling_frequency / 2f64) {
+            return Err(GrimError::Config(format!(
+                "the injection frequency range must be in ]0,{}]Hz, found [{f_min},{f_max}]Hz",
+                sampling_frequency / 2f64
+            )));
+        }
+        let period: f64 = env_or("INJECTION_PERIOD", 10f64)?;
+        if !(period.is_finite() && period * f_min >= 1f64) {
+            return Err(GrimError::Config(format!(
+                "INJECTION_PERIOD must be at least 1/INJECTION_F_MIN={}s, found {period}s",
+                f_min.recip()
+            )));
+        }
+        let excitation = match env::var("INJECTION_EXCITATION")
+            .unwrap_or_else(|_| "chirp".to_string())
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "chirp" => Excitation::Chirp {
+                f_min,
+                f_max,
+                duration: period,
+            },
+            "multisine" => Excitation::Multisine {
+                f_min,
+                f_max,
+                period,
+            },
+            "step" => Excitation::Step,
+            value => {
+                return Err(GrimError::Config(format!(
+                    "invalid value for INJECTION_EXCITATION: {value:?}, expected chirp, multisine or step"
+                )))
+            }
+        };
+        let injection = Self {
+            signal,
+            excitation,
+            amplitude: env_or("INJECTION_AMPLITUDE", 1f64)?,
+            channels: list("INJECTION_CHANNELS", "0")?,
+            start: env_or("INJECTION_START", warm_up)?,
+            sampling_frequency,
+        };
+        if matches!(injection.excitation, Excitation::Multisine { .. })
+            && injection.harmonics().is_empty()
+        {
+            return Err(GrimError::Config(format!(
+                "the multisine has no harmonic of 1/INJECTION_PERIOD={}Hz in [{f_min},{f_max}]Hz",
+                period.recip()
+            )));
+        }
+        Ok(Some(injection))
+    }
+}
+
+/// Parses the `on`/`off` environment variable `key` that is `on` by default
+fn switch(key: &str) -> Result<bool> {
+    match env::var(key)
+        .unwrap_or_else(|_| "on".to_string())
+        .trim()
+        .to_lowercase()
+        .as_str()
+    {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        value => Err(GrimError::Config(format!(
+            "invalid value for {key}: {value:?}, expected on or off"
+        ))),
+    }
+}
+
+/// Parses the comma separated list of the environment variable `key`
+fn list<T: FromStr>(key: &str, default: &str) -> Result<Vec<T>> {
+    env::var(key)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| {
+            item.trim()
+                .parse()
+                .map_err(|_| GrimError::Config(format!("invalid value for {key}: {item:?}")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // the tests share the environment of the process
+    static ENV: Mutex<()> = Mutex::new(());
+
+    // Sets the environment variables `vars` while running `f` and removes them afterwards
+    fn with_env<T>(vars: &[(&str, &str)], f: impl FnOnce() -> T) -> T {
+        let _lock = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        vars.iter()
+            .for_each(|(key, value)| env::set_var(key, value));
+        let value = f();
+        vars.iter().for_each(|(key, _)| env::remove_var(key));
+        value
+    }
+
+    #[test]
+    fn sh48() {
+        let sh48 = with_env(&[], Sh48::from_env).unwrap();
+        assert_eq!(sh48.probes, vec![(6., 0.)]);
+        assert_eq!((sh48.flux_threshold, sh48.n_mode), (0.5, 27));
+        let sh48 = with_env(&[("SH48_N_PROBE", "3")], Sh48::from_env).unwrap();
+        assert_eq!(sh48.probes, vec![(6., 0.), (6., 120.), (6., 240.)]);
+        let sh48 = with_env(
+            &[
+                ("SH48_N_PROBE", "2"),
+                ("SH48_PROBES", "6:0, 8 : 90"),
+                ("SH48_FLUX_THRESHOLD", "0.8"),
+                ("SH48_N_MODE", "151"),
+            ],
+            Sh48::from_env,
+        )
+        .unwrap();
+        assert_eq!(sh48.probes, vec![(6., 0.), (8., 90.)]);
+        assert_eq!((sh48.flux_threshold, sh48.n_mode), (0.8, 151));
+        assert_eq!(sh48.n_dof(), 7 * 151);
+        for vars in [
+            &[("SH48_N_PROBE", "5")][..],
+            &[("SH48_N_PROBE", "two")],
+            &[("SH48_PROBES", "6")],
+            &[("SH48_PROBES", "6:0,6:120")],
+            &[("SH48_FLUX_THRESHOLD", "1.5")],
+            &[("SH48_N_MODE", "0")],
+            &[("SH48_N_MODE", "152")],
+        ] {
+            assert!(
+                matches!(with_env(vars, Sh48::from_env), Err(GrimError::Config(_))),
+                "{vars:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn aco() {
+        let sh48 = with_env(&[("SH48_N_MODE", "2")], Sh48::from_env).unwrap();
+        let aco = with_env(
+            &[
+                ("ACO_M1_RBM", "Tx, rz,"),
+                ("ACO_M2_RBM", "Rx"),
+                ("ACO_EXCLUDE", "M1S7Rz"),
+                ("ACO_M2_RBM_GAIN", "0.2"),
+            ],
+            || Aco::from_env(&sh48),
+        )
+        .unwrap();
+        assert_eq!(aco.m1_rbm.iter().filter(|&&c| c).count(), 13);
+        assert_eq!(aco.m2_rbm.iter().filter(|&&c| c).count(), 7);
+        assert!(!aco.m1_rbm[6 * N_RBM + Axis::Rz.index()]);
+        assert_eq!(
+            (aco.m1_modes_gain, aco.m1_rbm_gain, aco.m2_rbm_gain),
+            (0.5, 0.5, 0.2)
+        );
+        // all the rigid body motions of the segments with a controlled one are calibrated
+        let calibrated = aco.calibrated();
+        assert_eq!(calibrated.len(), N_SEGMENT * (2 * N_RBM + 2));
+        let controlled = aco.controlled();
+        assert_eq!(controlled.len(), 13 + 7 + N_SEGMENT * 2);
+        assert_eq!(
+            aco.controlled_columns()
+                .into_iter()
+                .map(|i| calibrated[i])
+                .collect::<Vec<_>>(),
+            controlled
+        );
+        assert_eq!(
+            controlled[..3],
+            ["M1S1Tx", "M1S1Rz", "M2S1Rx"].map(|dof| dof.parse().unwrap())
+        );
+        let aco = with_env(&[], || Aco::from_env(&sh48)).unwrap();
+        assert_eq!(aco.calibrated(), aco.controlled());
+        assert_eq!(aco.calibrated().len(), N_SEGMENT * 2);
+        for vars in [
+            &[("ACO_M1_RBM", "Tw")][..],
+            &[("ACO_EXCLUDE", "M1S1B0")],
+            &[("ACO_EXCLUDE", "M1S8Rz")],
+            &[("ACO_M1_MODES_GAIN", "high")],
+        ] {
+            assert!(
+                matches!(
+                    with_env(vars, || Aco::from_env(&sh48)),
+                    Err(GrimError::Config(_))
+                ),
+                "{vars:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn wind_loads() {
+        let wind_loads = with_env(&[], || WindLoads::from_env(7.)).unwrap();
+        assert_eq!(wind_loads.groups.len(), GROUPS.len());
+        assert!(wind_loads.groups.iter().all(|(_, factor)| *factor == 1.));
+        // the last scale of a group is used and the dynamic pressure ratio is applied
+        let wind_loads = with_env(
+            &[
+                ("WIND_LOADS", "m1segments, topend,"),
+                ("WIND_LOADS_SCALES", "TopEnd=2, topend=3,Trusses=0"),
+                ("WIND_SPEED", "14"),
+            ],
+            || WindLoads::from_env(7.),
+        )
+        .unwrap();
+        assert_eq!(
+            wind_loads.groups,
+            vec![("TopEnd".to_string(), 12.), ("M1Segments".to_string(), 4.)]
+        );
+        assert_eq!(wind_loads.scale("TopEnd"), Some(12.));
+        assert!(!wind_loads.is_enabled("Trusses"));
+        for vars in [
+            &[("WIND_LOADS", "Foo")][..],
+            &[("WIND_LOADS_SCALES", "TopEnd:2")],
+            &[("WIND_LOADS_SCALES", "TopEnd=x")],
+            &[("WIND_LOADS_SCALES", "Foo=2")],
+            &[("WIND_SPEED", "-1")],
+        ] {
+            assert!(
+                matches!(
+                    with_env(vars, || WindLoads::from_env(7.)),
+                    Err(GrimError::Config(_))
+                ),
+                "{vars:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn disturbances() {
+        let disturbances = with_env(&[("ATMOSPHERE_R0", "0.15")], || {
+            Disturbances::from_env(1000)
+        })
+        .unwrap();
+        assert_eq!(disturbances.atmosphere.unwrap().r0, Some(0.15));
+        assert_eq!(disturbances.dome_seeing.unwrap().sampling_frequency, 5);
+        for vars in [
+            [("ATMOSPHERE_R0", "0")],
+            [("ATMOSPHERE_L0", "-25")],
+            [("DOME_SEEING_SAMPLING_FREQUENCY", "0")],
+            [("DOME_SEEING_SAMPLING_FREQUENCY", "3")],
+        ] {
+            assert!(
+                matches!(
+                    with_env(&vars, || Disturbances::from_env(1000)),
+                    Err(GrimError::Config(_))
+                ),
+                "{vars:?}"
+            );
+        }
+        let disturbances = with_env(
+            &[
+                ("DOME_SEEING", "off"),
+                ("ATMOSPHERE", "off"),
+                ("ATMOSPHERE_R0", "0"),
+            ],
+            || Disturbances::from_env(1000),
+        )
+        .unwrap();
+        assert!(disturbances.atmosphere.is_none() && disturbances.dome_seeing.is_none());
+    }
+
+    #[test]
+    fn multisine() {
+        let vars = [
+            ("INJECTION_SIGNAL", "MountTorques"),
+            ("INJECTION_EXCITATION", "multisine"),
+            ("INJECTION_F_MIN", "0.15"),
+            ("INJECTION_F_MAX", "0.18"),
+        ];
+        assert!(matches!(
+            with_env(&vars, || Injection::from_env(1000., 1.)),
+            Err(GrimError::Config(_))
+        ));
+        let injection = with_env(&[vars[0], vars[1], ("INJECTION_F_MAX", "1")], || {
+            Injection::from_env(1000., 1.)
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(injection.harmonics().len(), 10);
+        assert_eq!(injection.start, 1.);
+    }
+}
@@ -0,0 +1,366 @@
+//! Interaction matrix diagnostics
+//!
+//! The [Diagnostics] of a calibration are computed from the interaction matrix `P` and
+//! the reconstructor `R` of the calibration:
+//!  - the singular values of `P` and the corresponding eigenmodes in the degrees of freedom space,
+//!  - the noise propagation factor of each eigenmode, `1/σ²`, and of each degree of freedom,
+//!    the diagonal of `RRᵀ`, for a unit variance measurement noise,
+//!  - the closure test: the responses of the degrees of freedom measured independently of `P`,
+//!    e.g. the push and the pull interaction matrices of which `P` is the mean, are reconstructed
+//!    with `R`, the closure error of a degree of freedom is the norm of the difference between the
+//!    reconstructed and the unit degree of freedom, the largest over the independent measurements.
+//!
+//! `R·P` is the identity but for the truncated eigenmodes, so the closure error of the independent
+//! measurements reveals the measurement noise and the even non-linearities of the sensor.
+//! The [reconstructor] is the pseudo-inverse of `P` truncated to the singular values larger than
+//! [Thresholds::singular_value_tolerance] times the largest one, so the closure error of a degree of
+//! freedom is also large if it is not observable, i.e. if it projects on a truncated eigenmode.
+//!
+//! The [Linearity] check compares the interaction matrices calibrated at two strokes
+//! and reports the degrees of freedom with a non-linear response or that saturate the lenslets.
+//!
+//! The diagnostics are checked against the [Thresholds].
+
+use crate::{aco::Dof, config::env_or, GrimError, Result};
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, fs::File, path::Path};
+
+/// Returns the reconstructor of the interaction matrix `poke`
+///
+/// The reconstructor is the pseudo-inverse of the interaction matrix truncated to the singular values
+/// larger than [Thresholds::singular_value_tolerance] times the largest singular value
+pub fn reconstructor(poke: &na::DMatrix<f64>, thresholds: &Thresholds) -> Result<na::DMatrix<f64>> {
+    let svd = poke.clone().svd(true, true);
+    let max_singular_value = svd.singular_values.max();
+    svd.pseudo_inverse(max_singular_value * thresholds.singular_value_tolerance)
+        .map_err(|e| GrimError::Calibration(e.to_string()))
+}
+
+/// Calibration diagnostics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostics {
+    pub singular_values: Vec<f64>,
+    pub condition_number: f64,
+    /// Eigenmodes in the degrees of freedom space, one per singular value
+    pub eigenmodes: Vec<Vec<f64>>,
+    /// Noise propagation factor of each eigenmode
+    pub mode_noise_propagation: Vec<f64>,
+    /// Noise propagation factor of each degree of freedom
+    pub dof_noise_propagation: Vec<f64>,
+    /// Closure error of each degree of freedom
+    pub closure_errors: Vec<f64>,
+    pub max_closure_error: f64,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub linearity: Option<Linearity>,
+}
+impl Diagnostics {
+    /// Computes the diagnostics of the interaction matrix `poke` and of the reconstructor `reconstructor`
+    ///
+    /// The closure test reconstructs the interaction matrices `pokes` of the same degrees of freedom
+    /// measured independently of `poke`, there is no closure test if `pokes` is empty
+    pub fn new(
+        poke: &na::DMatrix<f64>,
+        reconstructor: &na::DMatrix<f64>,
+        pokes: &[&na::DMatrix<f64>],
+    ) -> Result<Self> {
+        if reconstructor.shape() != (poke.ncols(), poke.nrows()) {
+            return Err(GrimError::Calibration(format!(
+                "reconstructor {:?} does not match interaction matrix {:?}",
+                reconstructor.shape(),
+                poke.shape()
+            )));
+        }
+        if let Some(other) = pokes.iter().find(|other| other.shape() != poke.shape()) {
+            return Err(GrimError::Calibration(format!(
+                "closure test interaction matrix {:?} does not match interaction matrix {:?}",
+                other.shape(),
+                poke.shape()
+            )));
+        }
+        let svd = poke.clone().svd(false, true);
+        let singular_values: Vec<f64> = svd.singular_values.as_slice().to_vec();
+        let condition_number = singular_values.first().copied().unwrap_or_default()
+            / singular_values.last().copied().unwrap_or(f64::NAN);
+        let eigenmodes = svd
+            .v_t
+            .map(|v_t| {
+                v_t.row_iter()
+                    .map(|row| row.iter().copied().collect())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mode_noise_propagation = singular_values.iter().map(|s| s.powi(-2)).collect();
+        let dof_noise_propagation = reconstructor
+            .row_iter()
+            .map(|row| row.norm_squared())
+            .collect();
+        let identity = na::DMatrix::<f64>::identity(poke.ncols(), poke.ncols());
+        let closure_errors: Vec<f64> = pokes
+            .iter()
+            .map(|other| reconstructor * *other - &identity)
+            .fold(vec![], |errors: Vec<f64>, closure| {
+                closure
+                    .column_iter()
+                    .enumerate()
+                    .map(|(i, c)| errors.get(i).map_or(c.norm(), |e| e.max(c.norm())))
+                    .collect()
+            });
+        let max_closure_error = closure_errors.iter().cloned().fold(0f64, f64::max);
+        Ok(Self {
+            singular_values,
+            condition_number,
+            eigenmodes,
+            mode_noise_propagation,
+            dof_noise_propagation,
+            closure_errors,
+            max_closure_error,
+            linearity: None,
+        })
+    }
+    /// Saves the diagnostics in the JSON file `path`
+    pub fn to_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+    /// Loads the diagnostics from the JSON file `path`
+    pub fn from_json<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+    /// Checks the diagnostics against the thresholds
+    ///
+    /// If the condition number of a `reference` interaction matrix of the same degrees of freedom
+    /// is given, the condition number must not be larger than the reference condition number,
+    /// the reference is described by its `name`, e.g. `"SH48 calibration v3"`
+    pub fn check(&self, thresholds: &Thresholds, reference: Option<(String, f64)>) -> Result<()> {
+        if self.max_closure_error.is_nan() || self.max_closure_error > thresholds.max_closure_error
+        {
+            return Err(GrimError::Calibration(format!(
+                "closure error {:e} is larger than {:e}",
+                self.max_closure_error, thresholds.max_closure_error
+            )));
+        }
+        if let Some((name, condition_number)) = reference {
+            let max_condition_number = condition_number * (1f64 + thresholds.condition_tolerance);
+            if self.condition_number.is_nan() || self.condition_number > max_condition_number {
+                return Err(GrimError::Calibration(format!(
+                    "condition number {:e} is worse than the {name} condition number {:e}",
+                    self.condition_number, condition_number
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let max = |x: &[f64]| x.iter().cloned().fold(0f64, f64::max);
+        write!(
+            f,
+            "condition number: {:e}, singular values: [{:e},{:e}], max. noise propagation: {:e} (mode) {:e} (dof), max. closure error: {:e}",
+            self.condition_number,
+            self.singular_values.last().copied().unwrap_or_default(),
+            self.singular_values.first().copied().unwrap_or_default(),
+            max(&self.mode_noise_propagation),
+            max(&self.dof_noise_propagation),
+            self.max_closure_error
+        )
+    }
+}
+
+/// Linearity check
+///
+/// The interaction matrix `P` calibrated with the nominal strokes is compared to the
+/// interaction matrix `Pₖ` calibrated with the nominal strokes scaled by the linearity `factor`.
+/// Both matrices are normalized by their strokes and, for each degree of freedom `i`:
+///  - the non-linearity is `|Pₖᵢ-Pᵢ|/|Pᵢ|`,
+///  - the gain is `Pₖᵢ·Pᵢ/|Pᵢ|²`.
+///
+/// A degree of freedom saturates the lenslets if its gain is less than `1-max_nonlinearity`,
+/// otherwise its response is non-linear if its non-linearity is larger than `max_nonlinearity`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Linearity {
+    pub factor: f64,
+    pub nonlinearity: Vec<f64>,
+    pub gain: Vec<f64>,
+    /// Degrees of freedom with a non-linear response
+    pub nonlinear: Vec<Dof>,
+    /// Degrees of freedom that saturate the lenslets
+    pub saturated: Vec<Dof>,
+}
+impl Linearity {
+    /// Compares the interaction matrices `poke` and `scaled_poke` of the degrees of freedom `dofs`
+    pub fn new(
+        dofs: &[Dof],
+        poke: &na::DMatrix<f64>,
+        scaled_poke: &na::DMatrix<f64>,
+        thresholds: &Thresholds,
+    ) -> Result<Self> {
+        if poke.shape() != scaled_poke.shape() || poke.ncols() != dofs.len() {
+            return Err(GrimError::Calibration(format!(
+                "linearity check: interaction matrices {:?} and {:?} do not match {} degrees of freedom",
+                poke.shape(),
+                scaled_poke.shape(),
+                dofs.len()
+            )));
+        }
+        let (nonlinearity, gain): (Vec<f64>, Vec<f64>) = poke
+            .column_iter()
+            .zip(scaled_poke.column_iter())
+            .map(|(p, pk)| {
+                let norm_squared = p.norm_squared();
+                (
+                    (pk - p).norm() / norm_squared.sqrt(),
+                    pk.dot(&p) / norm_squared,
+                )
+            })
+            .unzip();
+        let mut nonlinear = vec![];
+        let mut saturated = vec![];
+        for ((dof, n), g) in dofs.iter().zip(&nonlinearity).zip(&gain) {
+            if *g < 1f64 - thresholds.max_nonlinearity {
+                saturated.push(*dof);
+            } else if *n > thresholds.max_nonlinearity {
+                nonlinear.push(*dof);
+            }
+        }
+        Ok(Self {
+            factor: thresholds.linearity_factor,
+            nonlinearity,
+            gain,
+            nonlinear,
+            saturated,
+        })
+    }
+}
+impl Display for Linearity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = |dofs: &[Dof]| -> String {
+            dofs.iter()
+                .map(|dof| dof.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        write!(
+            f,
+            "linearity (x{}): max. non-linearity: {:e}, non-linear: [{}], saturated: [{}]",
+            self.factor,
+            self.nonlinearity.iter().cloned().fold(0f64, f64::max),
+            names(&self.nonlinear),
+            names(&self.saturated)
+        )
+    }
+}
+
+/// Diagnostics thresholds
+///
+/// Environment variables:
+///  - `CALIBRATION_MAX_CLOSURE_ERROR` [1e-2]: the largest closure error
+///  - `CALIBRATION_SINGULAR_VALUE_TOLERANCE` [1e-9]: the singular values of an interaction matrix
+///    smaller than this tolerance times the largest singular value are truncated from the reconstructor
+///  - `CALIBRATION_CONDITION_TOLERANCE` [1e-6]: the relative tolerance on the increase of the
+///    condition number with respect to the stored calibration
+///  - `CALIBRATION_LINEARITY_FACTOR` [2]: the ratio of the strokes of the linearity check
+///  - `CALIBRATION_MAX_NONLINEARITY` [0.05]: the largest non-linearity of a degree of freedom
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Thresholds {
+    pub max_closure_error: f64,
+    pub singular_value_tolerance: f64,
+    pub condition_tolerance: f64,
+    pub linearity_factor: f64,
+    pub max_nonlinearity: f64,
+}
+impl Thresholds {
+    /// Reads the thresholds from the environment
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            max_closure_error: env_or("CALIBRATION_MAX_CLOSURE_ERROR", 1e-2)?,
+            singular_value_tolerance: env_or("CALIBRATION_SINGULAR_VALUE_TOLERANCE", 1e-9)?,
+            condition_tolerance: env_or("CALIBRATION_CONDITION_TOLERANCE", 1e-6)?,
+            linearity_factor: env_or("CALIBRATION_LINEARITY_FACTOR", 2f64)?,
+            max_nonlinearity: env_or("CALIBRATION_MAX_NONLINEARITY", 0.05)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thresholds() -> Thresholds {
+        Thresholds {
+            max_closure_error: 1e-2,
+            singular_value_tolerance: 1e-9,
+            condition_tolerance: 1e-6,
+            linearity_factor: 2.,
+            max_nonlinearity: 0.05,
+        }
+    }
+
+    // Push and pull interaction matrices of `poke` with the even non-linearity `delta`
+    fn push_pull(poke: &na::DMatrix<f64>, delta: f64) -> (na::DMatrix<f64>, na::DMatrix<f64>) {
+        let offset = na::DMatrix::<f64>::from_fn(poke.nrows(), poke.ncols(), |i, j| {
+            delta * ((i + 2 * j) as f64).cos()
+        });
+        (poke + &offset, poke - &offset)
+    }
+
+    #[test]
+    fn closure() {
+        let poke = na::DMatrix::<f64>::from_row_slice(
+            6,
+            3,
+            &[
+                1., 0., 0.5, 0., 1., 0.2, 0.3, 0., 1., 0.5, 0.5, 0., 0., 0.4, 0.3, 0.2, 0., 0.1,
+            ],
+        );
+        let reconstructor = reconstructor(&poke, &thresholds()).unwrap();
+        // a linear sensor
+        let (push, pull) = push_pull(&poke, 0.);
+        let diagnostics = Diagnostics::new(&poke, &reconstructor, &[&push, &pull]).unwrap();
+        assert!(diagnostics.max_closure_error < 1e-12);
+        assert!(diagnostics.check(&thresholds(), None).is_ok());
+        // the closure of the mean interaction matrix does not see the non-linearity
+        let (push, pull) = push_pull(&poke, 1e-2);
+        let diagnostics = Diagnostics::new(&poke, &reconstructor, &[&poke]).unwrap();
+        assert!(diagnostics.max_closure_error < 1e-12);
+        let diagnostics = Diagnostics::new(&poke, &reconstructor, &[&push, &pull]).unwrap();
+        assert!(diagnostics.max_closure_error > 1e-3);
+        assert!(diagnostics.check(&thresholds(), None).is_ok());
+        let (push, pull) = push_pull(&poke, 0.1);
+        let diagnostics = Diagnostics::new(&poke, &reconstructor, &[&push, &pull]).unwrap();
+        assert!(diagnostics.check(&thresholds(), None).is_err());
+        assert!(Diagnostics::new(&poke, &reconstructor, &[&poke.transpose()]).is_err());
+    }
+
+    #[test]
+    fn rank_deficient() {
+        // the third degree of freedom is the sum of the first two
+        let poke = na::DMatrix::<f64>::from_row_slice(
+            4,
+            3,
+            &[1., 0., 1., 0., 1., 1., 0.5, 0.2, 0.7, 0.1, 0.3, 0.4],
+        );
+        let reconstructor = reconstructor(&poke, &thresholds()).unwrap();
+        assert!(reconstructor.iter().all(|x| x.is_finite()));
+        let (push, pull) = push_pull(&poke, 0.);
+        let diagnostics = Diagnostics::new(&poke, &reconstructor, &[&push, &pull]).unwrap();
+        assert_eq!(diagnostics.singular_values.len(), 3);
+        assert!(diagnostics.singular_values[2] < 1e-12 * diagnostics.singular_values[0]);
+        assert!(diagnostics.condition_number > 1e12);
+        // the truncated eigenmode is the difference of the third and of the sum of the first two
+        let mode = &diagnostics.eigenmodes[2];
+        assert!((mode[0] - mode[1]).abs() < 1e-9 && (mode[0] + mode[2]).abs() < 1e-9);
+        // no degree of freedom is observable on its own
+        assert!(diagnostics.closure_errors.iter().all(|e| *e > 0.5));
+        assert!(diagnostics
+            .dof_noise_propagation
+            .iter()
+            .all(|x| x.is_finite()));
+        assert!(diagnostics.check(&thresholds(), None).is_err());
+    }
+}
@@ -0,0 +1,783 @@
+//! Export of the simulation results
+//!
+//! The streams of a result file are exported to a MATLAB MAT-file or to a NumPy `.npz` archive
+//! with one variable per stream, named after the stream unique identifier.
+//!
+//! In a MAT-file, a stream is a structure with the fields:
+//!  - `data`: the samples, one sample per row,
+//!  - `time`: the time of the samples since the start of the simulation [s],
+//!  - `sampling_frequency` [Hz], `warm_up` [s], `unit`, `source` and `file`.
+//!
+//! The version 5 MAT-files are written by this module, the variables are limited to 4GB.
+//! The version 7.3 MAT-files are HDF5 files and require the `mat73` feature.
+//!
+//! In a `.npz` archive, the samples of the stream `<uid>` are saved in the array `<uid>`,
+//! the time in `<uid>_time` and the metadata, as a JSON string, in `<uid>_metadata`:
+//! ```python
+//! import json, numpy as np
+//! grim = np.load("grim.npz")
+//! m1_rbm = grim["OSSM1Lcl"]
+//! metadata = json.loads(str(grim["OSSM1Lcl_metadata"]))
+//! ```
+
+use crate::{
+    results::{Run, Signal},
+    GrimError, Result,
+};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Export file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// MATLAB version 5 MAT-file
+    #[default]
+    Mat,
+    /// MATLAB version 7.3 (HDF5) MAT-file
+    Mat73,
+    /// NumPy archive
+    Npz,
+}
+impl Format {
+    /// Returns the file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Mat | Format::Mat73 => "mat",
+            Format::Npz => "npz",
+        }
+    }
+}
+impl FromStr for Format {
+    type Err = GrimError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "mat" | "mat5" | "v5" => Ok(Format::Mat),
+            "mat73" | "v7.3" => Ok(Format::Mat73),
+            "npz" => Ok(Format::Npz),
+            _ => Err(GrimError::Config(format!(
+                "invalid export format {s:?} (mat, mat73 or npz)"
+            ))),
+        }
+    }
+}
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Mat => write!(f, "MAT-file v5"),
+            Format::Mat73 => write!(f, "MAT-file v7.3"),
+            Format::Npz => write!(f, "NumPy npz"),
+        }
+    }
+}
+
+/// Exported variable value
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// Array of the given dimensions with the data in column-major order
+    Double(Vec<usize>, Vec<f64>),
+    Text(String),
+    /// Structure with named fields
+    Struct(Vec<(String, Value)>),
+}
+impl Value {
+    /// Returns a scalar
+    pub fn scalar(value: f64) -> Self {
+        Value::Double(vec![1, 1], vec![value])
+    }
+    /// Returns a column vector
+    pub fn column(data: &[f64]) -> Self {
+        Value::Double(vec![data.len(), 1], data.to_vec())
+    }
+    /// Returns the matrix with one sample per row
+    ///
+    /// Returns an error if the samples are not all of the same size
+    pub fn matrix(samples: &[Vec<f64>]) -> Result<Self> {
+        let n_sample = samples.len();
+        let width = sample_width(samples).map_err(GrimError::Export)?;
+        let data = (0..width)
+            .flat_map(|j| samples.iter().map(move |sample| sample[j]))
+            .collect();
+        Ok(Value::Double(vec![n_sample, width], data))
+    }
+}
+
+impl From<&nalgebra::DMatrix<f64>> for Value {
+    fn from(matrix: &nalgebra::DMatrix<f64>) -> Self {
+        Value::Double(
+            vec![matrix.nrows(), matrix.ncols()],
+            matrix.as_slice().to_vec(),
+        )
+    }
+}
+
+// Returns the size of the samples or an error if they are not all of the same size
+fn sample_width(samples: &[Vec<f64>]) -> std::result::Result<usize, String> {
+    let width = samples.first().map_or(0, |sample| sample.len());
+    match samples.iter().position(|sample| sample.len() != width) {
+        Some(i) => Err(format!(
+            "sample #{i} has {} values, expected {width}",
+            samples[i].len()
+        )),
+        None => Ok(width),
+    }
+}
+
+/// Returns a valid MATLAB and Python variable name from the stream name `name`
+pub fn variable_name(name: &str) -> String {
+    let mut variable: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !variable.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        variable.insert(0, 'x');
+    }
+    variable.truncate(63);
+    variable
+}
+
+// Returns the MAT-file structure of the signal
+fn signal_struct(signal: &Signal) -> Result<Value> {
+    Ok(Value::Struct(vec![
+        ("data".to_string(), Value::matrix(&signal.samples)?),
+        ("time".to_string(), Value::column(&signal.time)),
+        (
+            "sampling_frequency".to_string(),
+            Value::scalar(signal.info.sampling_frequency),
+        ),
+        ("warm_up".to_string(), Value::scalar(signal.warm_up)),
+        ("unit".to_string(), Value::Text(signal.info.unit.clone())),
+        (
+            "source".to_string(),
+            Value::Text(signal.info.source.clone()),
+        ),
+        ("file".to_string(), Value::Text(signal.info.file.clone())),
+    ]))
+}
+
+/// Exports the streams of the result file `file` of the `run` to the file `path`
+///
+/// Returns the number of exported streams
+pub fn export_file<P: AsRef<Path>>(
+    run: &Run,
+    file: &str,
+    format: Format,
+    path: P,
+) -> Result<usize> {
+    let path = path.as_ref();
+    let names = crate::results::stream_names(run.dir().join(file))?;
+    let signals = names
+        .iter()
+        .map(|name| run.stream(file, name))
+        .collect::<Result<Vec<Signal>>>()?;
+    for signal in &signals {
+        sample_width(&signal.samples)
+            .map_err(|e| GrimError::Export(format!("{}: {e}", signal.info.uid)))?;
+    }
+    match format {
+        Format::Mat | Format::Mat73 => {
+            let variables = signals
+                .iter()
+                .map(|signal| Ok((variable_name(&signal.info.uid), signal_struct(signal)?)))
+                .collect::<Result<Vec<_>>>()?;
+            write_mat(path, &variables, format)?;
+        }
+        Format::Npz => npz::write(path, &signals)?,
+    }
+    Ok(signals.len())
+}
+
+/// Writes the `variables` in the MAT-file `path` of version `format`
+pub fn write_mat<P: AsRef<Path>>(
+    path: P,
+    variables: &[(String, Value)],
+    format: Format,
+) -> Result<()> {
+    let path = path.as_ref();
+    match format {
+        Format::Mat => mat5::write(path, variables),
+        #[cfg(feature = "mat73")]
+        Format::Mat73 => mat73::write(path, variables),
+        #[cfg(not(feature = "mat73"))]
+        Format::Mat73 => Err(GrimError::Export(
+            "the MAT-file v7.3 export requires the `mat73` feature".to_string(),
+        )),
+        Format::Npz => Err(GrimError::Export(format!(
+            "{path:?}: {format} is not a MAT-file format"
+        ))),
+    }
+}
+
+/// Exports the result files `files` of the `run` to the directory `dir`
+///
+/// The streams of `<name>.parquet` are exported to `<name>.mat` or `<name>.npz`,
+/// the missing result files are skipped.
+/// Returns the exported files and the number of streams in each
+pub fn export_run<P: AsRef<Path>>(
+    run: &Run,
+    files: &[&str],
+    format: Format,
+    dir: P,
+) -> Result<Vec<(PathBuf, usize)>> {
+    let dir = dir.as_ref();
+    let mut exported = vec![];
+    for file in files {
+        if !run.dir().join(file).exists() {
+            log::warn!("{file} not found in {:?}, skipped", run.dir());
+            continue;
+        }
+        let path = dir
+            .join(file.trim_end_matches(".parquet"))
+            .with_extension(format.extension());
+        let n_stream = export_file(run, file, format, &path)?;
+        exported.push((path, n_stream));
+    }
+    Ok(exported)
+}
+
+// Returns the 116 bytes of the MAT-file description text
+fn mat_description(version: &str) -> Vec<u8> {
+    let mut text = format!(
+        "MATLAB {version} MAT-file, Platform: {}, Created on: {} by GRIM",
+        std::env::consts::OS,
+        chrono::Local::now().format("%a %b %e %H:%M:%S %Y")
+    )
+    .into_bytes();
+    text.resize(116, b' ');
+    text
+}
+
+mod mat5 {
+    //! MATLAB version 5 MAT-file writer
+    use super::{mat_description, Value};
+    use crate::{GrimError, Result};
+    use std::{
+        fs::File,
+        io::{BufWriter, Write},
+        path::Path,
+    };
+
+    const MI_INT8: u32 = 1;
+    const MI_UINT16: u32 = 4;
+    const MI_INT32: u32 = 5;
+    const MI_UINT32: u32 = 6;
+    const MI_DOUBLE: u32 = 9;
+    const MI_MATRIX: u32 = 14;
+    const MX_STRUCT_CLASS: u32 = 2;
+    const MX_CHAR_CLASS: u32 = 4;
+    const MX_DOUBLE_CLASS: u32 = 6;
+
+    /// Writes the `variables` in the MAT-file `path`
+    pub fn write(path: &Path, variables: &[(String, Value)]) -> Result<()> {
+        let file = File::create(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+        let mut writer = BufWriter::new(file);
+        let mut header = mat_description("5.0");
+        // no subsystem data, version 0x0100 and little endian indicator
+        header.extend([0u8; 8]);
+        header.extend(0x0100u16.to_le_bytes());
+        header.extend(b"IM");
+        let write_err = |e| GrimError::Io(e, path.to_path_buf());
+        writer.write_all(&header).map_err(write_err)?;
+        for (name, value) in variables {
+            let element =
+                matrix(name, value).map_err(|e| GrimError::Export(format!("{name}: {e}")))?;
+            writer.write_all(&element).map_err(write_err)?;
+        }
+        writer.flush().map_err(write_err)?;
+        Ok(())
+    }
+
+    // Appends the data element `data` of type `data_type` padded to 8 bytes
+    fn element(
+        buffer: &mut Vec<u8>,
+        data_type: u32,
+        data: &[u8],
+    ) -> std::result::Result<(), String> {
+        let n_byte = u32::try_from(data.len())
+            .map_err(|_| "larger than 4GB, export to MAT-file v7.3 instead".to_string())?;
+        buffer.extend(data_type.to_le_bytes());
+        buffer.extend(n_byte.to_le_bytes());
+        buffer.extend(data);
+        buffer.resize(buffer.len().next_multiple_of(8), 0);
+        Ok(())
+    }
+
+    // Returns the matrix data element of the variable `name`
+    fn matrix(name: &str, value: &Value) -> std::result::Result<Vec<u8>, String> {
+        let (class, dims) = match value {
+            Value::Double(dims, _) => (MX_DOUBLE_CLASS, dims.clone()),
+            Value::Text(text) => (MX_CHAR_CLASS, vec![1, text.encode_utf16().count()]),
+            Value::Struct(_) => (MX_STRUCT_CLASS, vec![1, 1]),
+        };
+        let mut body = vec![];
+        let flags: Vec<u8> = [class, 0u32].iter().flat_map(|x| x.to_le_bytes()).collect();
+        element(&mut body, MI_UINT32, &flags)?;
+        let dims: Vec<u8> = dims
+            .iter()
+            .map(|&dim| i32::try_from(dim).map_err(|_| format!("dimension {dim} too large")))
+            .collect::<std::result::Result<Vec<i32>, String>>()?
+            .iter()
+            .flat_map(|dim| dim.to_le_bytes())
+            .collect();
+        element(&mut body, MI_INT32, &dims)?;
+        element(&mut body, MI_INT8, name.as_bytes())?;
+        match value {
+            Value::Double(_, data) => {
+                let data: Vec<u8> = data.iter().flat_map(|x| x.to_le_bytes()).collect();
+                element(&mut body, MI_DOUBLE, &data)?;
+            }
+            Value::Text(text) => {
+                let data: Vec<u8> = text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+                element(&mut body, MI_UINT16, &data)?;
+            }
+            Value::Struct(fields) => {
+                // field names are NUL terminated and padded to the longest name
+                let length = fields
+                    .iter()
+                    .map(|(name, _)| name.len() + 1)
+                    .max()
+                    .unwrap_or(1);
+                if length > 64 {
+                    return Err(format!("field names longer than 63 characters in {name}"));
+                }
+                element(&mut body, MI_INT32, &(length as i32).to_le_bytes())?;
+                let names: Vec<u8> = fields
+                    .iter()
+                    .flat_map(|(name, _)| {
+                        let mut name = name.clone().into_bytes();
+                        name.resize(length, 0);
+                        name
+                    })
+                    .collect();
+                element(&mut body, MI_INT8, &names)?;
+                for (_, field) in fields {
+                    body.extend(matrix("", field)?);
+                }
+            }
+        }
+        let mut buffer = vec![];
+        element(&mut buffer, MI_MATRIX, &body)?;
+        Ok(buffer)
+    }
+}
+
+#[cfg(feature = "mat73")]
+mod mat73 {
+    //! MATLAB version 7.3 MAT-file writer
+    //!
+    //! A version 7.3 MAT-file is an HDF5 file with a 512 bytes user block holding the MAT-file header.
+    //! MATLAB arrays are column-major so the HDF5 dimensions are the MATLAB dimensions reversed.
+    use super::{mat_description, Value};
+    use crate::{GrimError, Result};
+    use hdf5::{types::FixedAscii, Location};
+    use std::{fs::OpenOptions, io::Write, path::Path};
+
+    /// Writes the `variables` in the MAT-file `path`
+    pub fn write(path: &Path, variables: &[(String, Value)]) -> Result<()> {
+        let hdf5_err = |e: hdf5::Error| GrimError::Export(format!("{path:?}: {e}"));
+        let file = hdf5::File::with_options()
+            .with_fcpl(|p| p.userblock(512))
+            .create(path)
+            .map_err(hdf5_err)?;
+        for (name, value) in variables {
+            write_value(&file, name, value).map_err(hdf5_err)?;
+        }
+        file.close().map_err(hdf5_err)?;
+        let mut header = mat_description("7.3");
+        header.extend([0u8; 8]);
+        header.extend(0x0200u16.to_le_bytes());
+        header.extend(b"IM");
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(&header))
+            .map_err(|e| GrimError::Io(e, path.to_path_buf()))
+    }
+
+    // Sets the MATLAB class attribute of the location
+    fn class(location: &Location, class: &str) -> hdf5::Result<()> {
+        let class = FixedAscii::<8>::from_ascii(class).map_err(|e| e.to_string())?;
+        location
+            .new_attr::<FixedAscii<8>>()
+            .create("MATLAB_class")?
+            .write_scalar(&class)
+    }
+
+    // Writes an empty array of MATLAB dimensions `dims`
+    fn write_empty(
+        group: &hdf5::Group,
+        name: &str,
+        dims: &[usize],
+        matlab_class: &str,
+    ) -> hdf5::Result<()> {
+        let dims: Vec<u64> = dims.iter().map(|&dim| dim as u64).collect();
+        let dataset = group.new_dataset::<u64>().shape(dims.len()).create(name)?;
+        dataset.write_raw(&dims)?;
+        class(&dataset, matlab_class)?;
+        dataset
+            .new_attr::<u8>()
+            .create("MATLAB_empty")?
+            .write_scalar(&1u8)
+    }
+
+    fn write_value(group: &hdf5::Group, name: &str, value: &Value) -> hdf5::Result<()> {
+        match value {
+            Value::Double(dims, data) if data.is_empty() => {
+                write_empty(group, name, dims, "double")
+            }
+            Value::Double(dims, data) => {
+                let shape: Vec<usize> = dims.iter().rev().cloned().collect();
+                let dataset = group.new_dataset::<f64>().shape(shape).create(name)?;
+                dataset.write_raw(data)?;
+                class(&dataset, "double")
+            }
+            Value::Text(text) if text.is_empty() => write_empty(group, name, &[0, 0], "char"),
+            Value::Text(text) => {
+                let data: Vec<u16> = text.encode_utf16().collect();
+                let dataset = group
+                    .new_dataset::<u16>()
+                    .shape([data.len(), 1])
+                    .create(name)?;
+                dataset.write_raw(&data)?;
+                class(&dataset, "char")?;
+                dataset
+                    .new_attr::<u32>()
+                    .create("MATLAB_int_decode")?
+                    .write_scalar(&2u32)
+            }
+            Value::Struct(fields) => {
+                let group = group.create_group(name)?;
+                class(&group, "struct")?;
+                for (name, field) in fields {
+                    write_value(&group, name, field)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+mod npz {
+    //! NumPy `.npz` archive writer
+    //!
+    //! The archive is an uncompressed zip file of `.npy` arrays
+    use super::{sample_width, variable_name};
+    use crate::{results::Signal, GrimError, Result};
+    use std::{
+        fs::File,
+        io::{BufWriter, Write},
+        path::Path,
+    };
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    // Returns the `.npy` file of an array of type `descr` and shape `shape`
+    fn npy(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+        let shape = match shape {
+            [n] => format!("({n},)"),
+            shape => format!(
+                "({})",
+                shape
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let mut header =
+            format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+        // the magic string, the version and the header length are 10 bytes and
+        // the header is padded for the data to be aligned on 64 bytes
+        let n_pad = (64 - (10 + header.len() + 1) % 64) % 64;
+        header.extend(std::iter::repeat_n(' ', n_pad));
+        header.push('\n');
+        let mut buffer = b"\x93NUMPY\x01\x00".to_vec();
+        buffer.extend((header.len() as u16).to_le_bytes());
+        buffer.extend(header.as_bytes());
+        buffer.extend(data);
+        buffer
+    }
+
+    // Returns the `.npy` file of the samples, one sample per row
+    fn npy_samples(samples: &[Vec<f64>]) -> Result<Vec<u8>> {
+        let width = sample_width(samples).map_err(GrimError::Export)?;
+        let data: Vec<u8> = samples
+            .iter()
+            .flatten()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        Ok(npy("<f8", &[samples.len(), width], &data))
+    }
+
+    // Returns the `.npy` file of a unicode scalar
+    fn npy_text(text: &str) -> Vec<u8> {
+        let n_char = text.chars().count().max(1);
+        let mut data: Vec<u8> = text
+            .chars()
+            .flat_map(|c| (c as u32).to_le_bytes())
+            .collect();
+        data.resize(4 * n_char, 0);
+        npy(&format!("<U{n_char}"), &[], &data)
+    }
+
+    /// Writes the `signals` in the NumPy archive `path`
+    pub fn write(path: &Path, signals: &[Signal]) -> Result<()> {
+        let file = File::create(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        for signal in signals {
+            let name = variable_name(&signal.info.uid);
+            let metadata = serde_json::json!({
+                "file": signal.info.file,
+                "sampling_frequency": signal.info.sampling_frequency,
+                "warm_up": signal.warm_up,
+                "unit": signal.info.unit,
+                "source": signal.info.source,
+            });
+            let data: Vec<u8> = signal.time.iter().flat_map(|t| t.to_le_bytes()).collect();
+            for (array, npy) in [
+                (name.clone(), npy_samples(&signal.samples)?),
+                (
+                    format!("{name}_time"),
+                    npy("<f8", &[signal.time.len()], &data),
+                ),
+                (format!("{name}_metadata"), npy_text(&metadata.to_string())),
+            ] {
+                let options = FileOptions::default()
+                    .compression_method(CompressionMethod::Stored)
+                    .large_file(npy.len() as u64 >= u32::MAX as u64);
+                zip.start_file(format!("{array}.npy"), options)?;
+                zip.write_all(&npy)
+                    .map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+            }
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{results::SignalInfo, TempPath};
+    use std::{
+        fs::{self, File},
+        io::Read,
+    };
+
+    // Returns the data type and the data of the element at `offset` and the offset of the next element
+    fn element(buffer: &[u8], offset: usize) -> (u32, &[u8], usize) {
+        let word = |i: usize| u32::from_le_bytes(buffer[i..i + 4].try_into().unwrap());
+        let (data_type, n_byte) = (word(offset), word(offset + 4) as usize);
+        let end = offset + 8 + n_byte;
+        let next = offset + 8 + n_byte.next_multiple_of(8);
+        assert!(
+            buffer[end..next].iter().all(|&b| b == 0),
+            "element at {offset} is not padded with zeros"
+        );
+        (data_type, &buffer[offset + 8..end], next)
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Matrix {
+        Double(Vec<i32>, Vec<f64>),
+        Char(String),
+        Struct(Vec<(String, Matrix)>),
+    }
+
+    // Parses the content of a matrix element into its name and value
+    fn matrix(data: &[u8]) -> (String, Matrix) {
+        let (data_type, flags, offset) = element(data, 0);
+        assert_eq!(data_type, 6);
+        let class = u32::from_le_bytes(flags[..4].try_into().unwrap());
+        let (data_type, dims, offset) = element(data, offset);
+        assert_eq!(data_type, 5);
+        let dims: Vec<i32> = dims
+            .chunks(4)
+            .map(|x| i32::from_le_bytes(x.try_into().unwrap()))
+            .collect();
+        let (data_type, name, mut offset) = element(data, offset);
+        assert_eq!(data_type, 1);
+        let name = String::from_utf8(name.to_vec()).unwrap();
+        let value = match class {
+            6 => {
+                let (data_type, x, next) = element(data, offset);
+                assert_eq!(data_type, 9);
+                offset = next;
+                let x: Vec<f64> = x
+                    .chunks(8)
+                    .map(|x| f64::from_le_bytes(x.try_into().unwrap()))
+                    .collect();
+                assert_eq!(dims.iter().product::<i32>() as usize, x.len());
+                Matrix::Double(dims, x)
+            }
+            4 => {
+                let (data_type, x, next) = element(data, offset);
+                assert_eq!(data_type, 4);
+                offset = next;
+                let x: Vec<u16> = x
+                    .chunks(2)
+                    .map(|x| u16::from_le_bytes(x.try_into().unwrap()))
+                    .collect();
+                assert_eq!(dims, vec![1, x.len() as i32]);
+                Matrix::Char(String::from_utf16(&x).unwrap())
+            }
+            2 => {
+                assert_eq!(dims, vec![1, 1]);
+                let (data_type, length, next) = element(data, offset);
+                assert_eq!(data_type, 5);
+                let length = i32::from_le_bytes(length.try_into().unwrap()) as usize;
+                let (data_type, names, next) = element(data, next);
+                assert_eq!(data_type, 1);
+                offset = next;
+                let fields = names
+                    .chunks(length)
+                    .map(|name| {
+                        let name: Vec<u8> = name.iter().take_while(|&&b| b != 0).cloned().collect();
+                        let (data_type, field, next) = element(data, offset);
+                        assert_eq!(data_type, 14);
+                        offset = next;
+                        let (field_name, field) = matrix(field);
+                        assert!(field_name.is_empty());
+                        (String::from_utf8(name).unwrap(), field)
+                    })
+                    .collect();
+                Matrix::Struct(fields)
+            }
+            _ => panic!("unexpected class {class}"),
+        };
+        assert_eq!(offset, data.len());
+        (name, value)
+    }
+
+    #[test]
+    fn mat5_round_trip() {
+        let path = TempPath::new("export.mat");
+        let samples = vec![vec![1., 2., 3.], vec![4., 5., 6.]];
+        let variables = vec![(
+            "OSSM1Lcl".to_string(),
+            Value::Struct(vec![
+                ("data".to_string(), Value::matrix(&samples).unwrap()),
+                ("unit".to_string(), Value::Text("N.m".to_string())),
+                (
+                    "nested".to_string(),
+                    Value::Struct(vec![("warm_up".to_string(), Value::scalar(0.5))]),
+                ),
+            ]),
+        )];
+        write_mat(&path, &variables, Format::Mat).unwrap();
+        let buffer = fs::read(&path).unwrap();
+
+        assert!(buffer[..116].starts_with(b"MATLAB 5.0 MAT-file"));
+        assert_eq!(&buffer[116..124], &[0u8; 8]);
+        assert_eq!(u16::from_le_bytes([buffer[124], buffer[125]]), 0x0100);
+        assert_eq!(&buffer[126..128], b"IM");
+        let (data_type, data, next) = element(&buffer, 128);
+        assert_eq!(data_type, 14);
+        assert_eq!(next, buffer.len());
+        assert_eq!(
+            matrix(data),
+            (
+                "OSSM1Lcl".to_string(),
+                Matrix::Struct(vec![
+                    (
+                        "data".to_string(),
+                        Matrix::Double(vec![2, 3], vec![1., 4., 2., 5., 3., 6.])
+                    ),
+                    ("unit".to_string(), Matrix::Char("N.m".to_string())),
+                    (
+                        "nested".to_string(),
+                        Matrix::Struct(vec![(
+                            "warm_up".to_string(),
+                            Matrix::Double(vec![1, 1], vec![0.5])
+                        )])
+                    ),
+                ])
+            )
+        );
+    }
+
+    #[test]
+    fn ragged_samples() {
+        assert!(Value::matrix(&[vec![1., 2.], vec![3.]]).is_err());
+    }
+
+    // Returns the header dictionary and the data of a `.npy` file
+    fn npy_header(npy: &[u8]) -> (&str, &[u8]) {
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        let n_byte = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert_eq!((10 + n_byte) % 64, 0);
+        let header = std::str::from_utf8(&npy[10..10 + n_byte]).unwrap();
+        assert!(header.ends_with('\n'));
+        (header.trim_end(), &npy[10 + n_byte..])
+    }
+
+    #[test]
+    fn npz_round_trip() {
+        let signal = Signal {
+            info: SignalInfo {
+                uid: "OSSM1Lcl".to_string(),
+                file: "data.parquet".to_string(),
+                width: 2,
+                sampling_frequency: 1e3,
+                unit: "m".to_string(),
+                source: "FEM".to_string(),
+            },
+            time: vec![0., 1e-3, 2e-3],
+            samples: vec![vec![1., 2.], vec![3., 4.], vec![5., 6.]],
+            warm_up: 0.,
+        };
+        let path = TempPath::new("export.npz");
+        npz::write(&path, &[signal]).unwrap();
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut npy = |name: &str| -> Vec<u8> {
+            let mut buffer = vec![];
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_end(&mut buffer)
+                .unwrap();
+            buffer
+        };
+        let (samples, time, metadata) = (
+            npy("OSSM1Lcl.npy"),
+            npy("OSSM1Lcl_time.npy"),
+            npy("OSSM1Lcl_metadata.npy"),
+        );
+        let doubles = |data: &[u8]| -> Vec<f64> {
+            data.chunks(8)
+                .map(|x| f64::from_le_bytes(x.try_into().unwrap()))
+                .collect()
+        };
+
+        let (header, data) = npy_header(&samples);
+        assert_eq!(
+            header,
+            "{'descr': '<f8', 'fortran_order': False, 'shape': (3, 2), }"
+        );
+        assert_eq!(doubles(data), vec![1., 2., 3., 4., 5., 6.]);
+
+        let (header, data) = npy_header(&time);
+        assert_eq!(
+            header,
+            "{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }"
+        );
+        assert_eq!(doubles(data), vec![0., 1e-3, 2e-3]);
+
+        let (header, data) = npy_header(&metadata);
+        let text: String = data
+            .chunks(4)
+            .map(|c| char::from_u32(u32::from_le_bytes(c.try_into().unwrap())).unwrap())
+            .collect();
+        assert_eq!(
+            header,
+            format!(
+                "{{'descr': '<U{}', 'fortran_order': False, 'shape': (), }}",
+                text.chars().count()
+            )
+        );
+        let metadata: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(metadata["unit"], "m");
+        assert_eq!(metadata["sampling_frequency"], 1e3);
+    }
+}
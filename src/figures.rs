@@ -0,0 +1,481 @@
+//! Simulation figures
+//!
+//! The time series and the power spectral densities of the key outputs of a run are plotted in
+//! the `figures` directory of the run with [plot_run].
+//! The figure format is given by the `FIGURES` environment variable: `svg` (the default), `png`
+//! or `none` to disable the figures at the end of a simulation.
+//!
+//! The warm-up is shaded in the time series figures and the power spectral densities are
+//! estimated with the default [Analysis] from the samples after the warm-up.
+
+use crate::{
+    analysis::Analysis,
+    manifest::Manifest,
+    results::{decimation, read_time_series},
+    spectrum::Psd,
+    GrimError, Result,
+};
+use plotters::{coord::Shift, prelude::*};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+const SIZE: (u32, u32) = (1024, 640);
+// radians to milli-arcseconds
+const RAD_TO_MAS: f64 = 180. * 3600e3 / std::f64::consts::PI;
+// largest number of channels with a legend
+const MAX_LEGEND: usize = 14;
+
+/// Figure file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Svg,
+    Png,
+}
+impl Format {
+    /// Returns the format given by the `FIGURES` environment variable or `None` if it is `none`
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var("FIGURES") {
+            Ok(value) if value.trim() == "none" => Ok(None),
+            Ok(value) => value.parse().map(Some),
+            Err(_) => Ok(Some(Default::default())),
+        }
+    }
+    /// Returns the file extension
+    pub fn extension(&self) -> &str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+        }
+    }
+}
+impl FromStr for Format {
+    type Err = GrimError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "svg" => Ok(Format::Svg),
+            "png" => Ok(Format::Png),
+            _ => Err(GrimError::Config(format!(
+                "invalid value for FIGURES: {s:?} (svg, png or none)"
+            ))),
+        }
+    }
+}
+
+/// Multi-channel time series
+#[derive(Debug, Clone, Default)]
+pub struct Series {
+    pub title: String,
+    pub unit: String,
+    /// Time [s]
+    pub time: Vec<f64>,
+    /// Channels names and samples
+    pub channels: Vec<(String, Vec<f64>)>,
+}
+impl Series {
+    /// Reads the stream `name` of the parquet file `path` with rows sampled at
+    /// `sampling_frequency` and multiplies the samples by `scale`
+    pub fn from_parquet<P: AsRef<Path>>(
+        path: P,
+        name: &str,
+        sampling_frequency: f64,
+        scale: f64,
+    ) -> Result<Self> {
+        let (time, samples) = read_time_series(path, name, sampling_frequency)?;
+        let n_channel = samples.first().map_or(0, |sample| sample.len());
+        let channels = (0..n_channel)
+            .map(|i| {
+                (
+                    format!("{name}[{i}]"),
+                    samples.iter().map(|sample| sample[i] * scale).collect(),
+                )
+            })
+            .collect();
+        Ok(Self {
+            title: name.to_string(),
+            time,
+            channels,
+            ..Default::default()
+        })
+    }
+    /// Sets the figure title
+    pub fn title<S: Into<String>>(mut self, title: S) -> Self {
+        self.title = title.into();
+        self
+    }
+    /// Sets the unit of the samples
+    pub fn unit<S: Into<String>>(mut self, unit: S) -> Self {
+        self.unit = unit.into();
+        self
+    }
+    /// Returns the power spectral density of each channel after `warm_up` [s]
+    ///
+    /// Returns `None` if there are less than 64 samples after the warm-up
+    pub fn psds(&self, warm_up: f64) -> Option<Vec<(String, Psd)>> {
+        let start = self.time.iter().position(|t| *t >= warm_up)?;
+        let n = self.time.len() - start;
+        if n < 64 {
+            return None;
+        }
+        let sampling_frequency =
+            (n - 1) as f64 / (self.time[self.time.len() - 1] - self.time[start]);
+        let samples: Vec<Vec<f64>> = (start..self.time.len())
+            .map(|k| self.channels.iter().map(|(_, data)| data[k]).collect())
+            .collect();
+        let psds = Analysis::default()
+            .psds(&samples, sampling_frequency)
+            .ok()?;
+        Some(
+            self.channels
+                .iter()
+                .map(|(name, _)| name.clone())
+                .zip(psds)
+                .collect(),
+        )
+    }
+    /// Plots the time series in the file `path` with the warm-up up to `warm_up` [s] shaded
+    pub fn plot<P: AsRef<Path>>(&self, path: P, warm_up: f64, format: Format) -> Result<()> {
+        let path = path.as_ref();
+        match format {
+            Format::Svg => self.draw(SVGBackend::new(path, SIZE).into_drawing_area(), warm_up),
+            Format::Png => self.draw(BitMapBackend::new(path, SIZE).into_drawing_area(), warm_up),
+        }
+        .map_err(|e| GrimError::Plot(format!("{path:?}: {e}")))
+    }
+    /// Plots the power spectral densities after `warm_up` [s] in the file `path`
+    ///
+    /// Returns `false` if there are not enough samples for the power spectral densities
+    pub fn plot_psd<P: AsRef<Path>>(&self, path: P, warm_up: f64, format: Format) -> Result<bool> {
+        let path = path.as_ref();
+        let Some(psds) = self.psds(warm_up) else {
+            return Ok(false);
+        };
+        match format {
+            Format::Svg => self.draw_psd(SVGBackend::new(path, SIZE).into_drawing_area(), &psds),
+            Format::Png => self.draw_psd(BitMapBackend::new(path, SIZE).into_drawing_area(), &psds),
+        }
+        .map_err(|e| GrimError::Plot(format!("{path:?}: {e}")))?;
+        Ok(true)
+    }
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: DrawingArea<DB, Shift>,
+        warm_up: f64,
+    ) -> std::result::Result<(), Box<dyn Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+        let (t0, t1) = match (self.time.first(), self.time.last()) {
+            (Some(t0), Some(t1)) if t1 > t0 => (*t0, *t1),
+            _ => return Err(format!("{}: not enough samples", self.title).into()),
+        };
+        let (y0, y1) = range(
+            self.channels
+                .iter()
+                .flat_map(|(_, data)| data.iter().cloned()),
+        );
+        let mut chart = ChartBuilder::on(&root)
+            .caption(&self.title, ("sans-serif", 24))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(80)
+            .build_cartesian_2d(t0..t1, y0..y1)?;
+        chart
+            .configure_mesh()
+            .x_desc("Time [s]")
+            .y_desc(&self.unit)
+            .draw()?;
+        let legend = self.channels.len() <= MAX_LEGEND;
+        if warm_up > t0 {
+            let shade = RGBColor(220, 220, 220).filled();
+            let series = chart.draw_series(std::iter::once(Rectangle::new(
+                [(t0, y0), (warm_up.min(t1), y1)],
+                shade,
+            )))?;
+            if legend {
+                series
+                    .label("warm-up")
+                    .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], shade));
+            }
+        }
+        for (i, (name, data)) in self.channels.iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            let series = chart.draw_series(LineSeries::new(
+                self.time.iter().cloned().zip(data.iter().cloned()),
+                color,
+            ))?;
+            if legend {
+                series
+                    .label(name)
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+            }
+        }
+        if legend {
+            chart
+                .configure_series_labels()
+                .background_style(WHITE.mix(0.8))
+                .border_style(BLACK)
+                .draw()?;
+        }
+        root.present()?;
+        Ok(())
+    }
+    fn draw_psd<DB: DrawingBackend>(
+        &self,
+        root: DrawingArea<DB, Shift>,
+        psds: &[(String, Psd)],
+    ) -> std::result::Result<(), Box<dyn Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+        // the DC and the null values are not shown on the log scales
+        let points = |psd: &Psd| -> Vec<(f64, f64)> {
+            psd.frequencies
+                .iter()
+                .cloned()
+                .zip(psd.values.iter().cloned())
+                .filter(|(f, p)| *f > 0f64 && *p > 0f64)
+                .collect()
+        };
+        let (f0, f1) = range(
+            psds.iter()
+                .flat_map(|(_, psd)| points(psd).into_iter().map(|(f, _)| f)),
+        );
+        let (p0, p1) = range(
+            psds.iter()
+                .flat_map(|(_, psd)| points(psd).into_iter().map(|(_, p)| p)),
+        );
+        if !(f0 > 0f64 && p0 > 0f64) {
+            return Err(format!("{}: null power spectral densities", self.title).into());
+        }
+        let mut chart = ChartBuilder::on(&root)
+            .caption(format!("{} PSD", self.title), ("sans-serif", 24))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(80)
+            .build_cartesian_2d((f0..f1).log_scale(), (p0..p1).log_scale())?;
+        chart
+            .configure_mesh()
+            .x_desc("Frequency [Hz]")
+            .y_label_formatter(&|p| format!("{p:.0e}"))
+            .y_desc(if self.unit.is_empty() {
+                "PSD [1/Hz]".to_string()
+            } else {
+                format!("PSD [{}²/Hz]", self.unit)
+            })
+            .draw()?;
+        let legend = psds.len() <= MAX_LEGEND;
+        for (i, (name, psd)) in psds.iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            let series = chart.draw_series(LineSeries::new(points(psd), color))?;
+            if legend {
+                series
+                    .label(name)
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+            }
+        }
+        if legend {
+            chart
+                .configure_series_labels()
+                .background_style(WHITE.mix(0.8))
+                .border_style(BLACK)
+                .draw()?;
+        }
+        root.present()?;
+        Ok(())
+    }
+}
+
+// Returns the range of the values, widened if the values are all equal
+fn range<I: Iterator<Item = f64>>(values: I) -> (f64, f64) {
+    let (min, max) = values
+        .filter(|x| x.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| {
+            (min.min(x), max.max(x))
+        });
+    if min > max {
+        (-1f64, 1f64)
+    } else if min == max {
+        let delta = if min == 0f64 { 1f64 } else { min.abs() * 0.1 };
+        (min - delta, max + delta)
+    } else {
+        (min, max)
+    }
+}
+
+/// Figure of a simulation output
+struct Output {
+    file: &'static str,
+    stream: &'static str,
+    title: &'static str,
+    unit: &'static str,
+    scale: f64,
+}
+impl Output {
+    const fn new(
+        file: &'static str,
+        stream: &'static str,
+        title: &'static str,
+        unit: &'static str,
+        scale: f64,
+    ) -> Self {
+        Self {
+            file,
+            stream,
+            title,
+            unit,
+            scale,
+        }
+    }
+}
+
+// The key outputs of a run,
+// the mount set point is null so the mount encoders are the mount tracking error
+const OUTPUTS: [Output; 16] = [
+    Output::new(
+        "grim.parquet",
+        "MountEncoders",
+        "Mount encoders error",
+        "mas",
+        RAD_TO_MAS,
+    ),
+    Output::new(
+        "sh24.parquet",
+        "TipTilt",
+        "SH24 tip-tilt",
+        "mas",
+        RAD_TO_MAS,
+    ),
+    Output::new(
+        "sh24.parquet",
+        "SegmentTipTilt",
+        "SH24 segment tip-tilt",
+        "mas",
+        RAD_TO_MAS,
+    ),
+    Output::new(
+        "sh24.parquet",
+        "SegmentPiston",
+        "SH24 segment piston",
+        "nm",
+        1e9,
+    ),
+    Output::new("sh24.parquet", "WfeRms", "SH24 WFE RMS", "nm", 1e9),
+    Output::new(
+        "lom.parquet",
+        "SegmentTipTilt",
+        "LOM segment tip-tilt",
+        "mas",
+        1.,
+    ),
+    Output::new(
+        "lom.parquet",
+        "SegmentPiston",
+        "LOM segment piston",
+        "nm",
+        1.,
+    ),
+    Output::new("lom.parquet", "WfeRms", "LOM WFE RMS", "nm", 1.),
+    Output::new("sh48.parquet", "M1ModalCmd", "M1 modes commands", "", 1.),
+    Output::new(
+        "m1-loadcells.parquet",
+        "S1HPLC",
+        "M1 S1 load cells forces",
+        "N",
+        1.,
+    ),
+    Output::new(
+        "m1-loadcells.parquet",
+        "S2HPLC",
+        "M1 S2 load cells forces",
+        "N",
+        1.,
+    ),
+    Output::new(
+        "m1-loadcells.parquet",
+        "S3HPLC",
+        "M1 S3 load cells forces",
+        "N",
+        1.,
+    ),
+    Output::new(
+        "m1-loadcells.parquet",
+        "S4HPLC",
+        "M1 S4 load cells forces",
+        "N",
+        1.,
+    ),
+    Output::new(
+        "m1-loadcells.parquet",
+        "S5HPLC",
+        "M1 S5 load cells forces",
+        "N",
+        1.,
+    ),
+    Output::new(
+        "m1-loadcells.parquet",
+        "S6HPLC",
+        "M1 S6 load cells forces",
+        "N",
+        1.,
+    ),
+    Output::new(
+        "m1-loadcells.parquet",
+        "S7HPLC",
+        "M1 S7 load cells forces",
+        "N",
+        1.,
+    ),
+];
+
+/// Plots the key outputs of the run in the directory `data_repo`
+///
+/// The figures are saved in the directory `figures` of the run, the outputs that are not found
+/// in the run results are skipped.
+/// Returns the paths to the figures
+pub fn plot_run<P: AsRef<Path>>(data_repo: P, format: Format) -> Result<Vec<PathBuf>> {
+    let data_repo = data_repo.as_ref();
+    let manifest = Manifest::load(data_repo)?;
+    let sampling_frequency = manifest.sampling_frequency as f64;
+    let dir = data_repo.join("figures");
+    fs::create_dir_all(&dir).map_err(|e| GrimError::Io(e, dir.clone()))?;
+    let mut figures = vec![];
+    for output in OUTPUTS.iter() {
+        let path = data_repo.join(output.file);
+        if !path.exists() {
+            continue;
+        }
+        let series = match Series::from_parquet(
+            &path,
+            output.stream,
+            sampling_frequency / decimation(output.file) as f64,
+            output.scale,
+        ) {
+            Ok(series) if !series.time.is_empty() => series.title(output.title).unit(output.unit),
+            Ok(_) => continue,
+            Err(e) => {
+                log::debug!("{} not plotted: {e}", output.title);
+                continue;
+            }
+        };
+        let name = format!(
+            "{}-{}",
+            output.file.trim_end_matches(".parquet"),
+            output.stream
+        );
+        let path = dir.join(format!("{name}.{}", format.extension()));
+        series.plot(&path, manifest.warm_up, format)?;
+        figures.push(path);
+        let path = dir.join(format!("{name}-psd.{}", format.extension()));
+        if series.plot_psd(&path, manifest.warm_up, format)? {
+            figures.push(path);
+        }
+    }
+    Ok(figures)
+}
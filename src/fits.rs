@@ -0,0 +1,439 @@
+//! FITS detector frames
+//!
+//! The [FrameLogger] client writes each detector frame it receives in a FITS file,
+//! `<UID>-<index>.fits` in the `<DATA_REPO>/<dirname>` directory, where `<index>` is the
+//! number of updates of the logger before the frame, i.e. the row of the frame in a parquet log.
+//!
+//! A frame is written as a 2D image of `width`x`height` pixels or, if the frame holds the
+//! images of several sensors, as a data cube with one image per sensor.
+//! The pixels are 32 bits floating point numbers and the header gives:
+//!  - the pixel scale and the coordinates of the central pixel (`CDELTn`, `CRPIXn`, `CUNITn`)
+//!    if the pixel scale is given to the [FrameLoggerBuilder],
+//!  - the exposure time (`EXPTIME`) [s],
+//!  - the simulation time (`SIMTIME`) [s], as in the time column of the parquet logs,
+//!    and the simulation step (`SIMSTEP`),
+//!  - the pixel unit (`BUNIT`) and the actor that outputs the frames (`INSTRUME`).
+
+use crate::{uid_name, GrimError, Result};
+use dos_actors::{
+    io::{Data, Read},
+    UniqueIdentifier, Update,
+};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+// Size of a FITS block [byte]
+const BLOCK: usize = 2880;
+// Size of a FITS header card [byte]
+const CARD: usize = 80;
+// Largest number of characters of a string value in between the quotes of a card
+const MAX_TEXT: usize = CARD - 12;
+
+/// FITS header card value
+#[derive(Debug, Clone)]
+pub enum Card {
+    Logical(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+impl Card {
+    // Returns the value formatted in the fixed format of the FITS standard
+    fn value(&self) -> String {
+        match self {
+            Card::Logical(value) => format!("{:>20}", if *value { "T" } else { "F" }),
+            Card::Integer(value) => format!("{value:>20}"),
+            Card::Float(value) => format!("{:>20}", format!("{value:.12E}")),
+            Card::Text(value) => {
+                // the quoted string must fit in the value field, the quotes in the string are doubled
+                let mut text = String::new();
+                let mut n_char = 0;
+                for c in value.chars() {
+                    let n = if c == '\'' { 2 } else { 1 };
+                    if n_char + n > MAX_TEXT {
+                        log::warn!("FITS string {value:?} truncated to {MAX_TEXT} characters");
+                        break;
+                    }
+                    text.push(c);
+                    if c == '\'' {
+                        text.push(c);
+                    }
+                    n_char += n;
+                }
+                format!("'{text:<8}'")
+            }
+        }
+    }
+}
+
+/// FITS primary header
+#[derive(Debug, Clone, Default)]
+pub struct Header {
+    cards: Vec<(String, Card, String)>,
+}
+impl Header {
+    /// Appends the keyword `key` with `value` and `comment`
+    pub fn card(mut self, key: &str, value: Card, comment: &str) -> Self {
+        self.cards
+            .push((key.to_uppercase(), value, comment.to_string()));
+        self
+    }
+    // Returns the header padded to a FITS block
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for (key, value, comment) in &self.cards {
+            let mut card = format!("{key:<8}= {}", value.value());
+            if !comment.is_empty() {
+                card.push_str(" / ");
+                card.push_str(comment);
+            }
+            let mut card: Vec<u8> = card
+                .chars()
+                .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+                .collect();
+            card.resize(CARD, b' ');
+            bytes.extend(card);
+        }
+        let mut end = b"END".to_vec();
+        end.resize(CARD, b' ');
+        bytes.extend(end);
+        bytes.resize(bytes.len().next_multiple_of(BLOCK), b' ');
+        bytes
+    }
+}
+
+/// Writes the 32 bits floating point image or data cube `data` of dimensions `naxis`,
+/// fastest varying axis first, with the `header` keywords in the FITS file `path`
+pub fn write_image<P: AsRef<Path>>(
+    path: P,
+    naxis: &[usize],
+    data: &[f32],
+    header: &Header,
+) -> Result<()> {
+    let path = path.as_ref();
+    let n_pixel: usize = naxis.iter().product();
+    if n_pixel != data.len() {
+        return Err(GrimError::Config(format!(
+            "{path:?}: {} pixels do not match the dimensions {naxis:?}",
+            data.len()
+        )));
+    }
+    let mut primary = Header::default()
+        .card("SIMPLE", Card::Logical(true), "conforms to FITS standard")
+        .card("BITPIX", Card::Integer(-32), "32 bits floating point")
+        .card("NAXIS", Card::Integer(naxis.len() as i64), "number of axes");
+    for (i, n) in naxis.iter().enumerate() {
+        primary = primary.card(&format!("NAXIS{}", i + 1), Card::Integer(*n as i64), "");
+    }
+    primary.cards.extend(header.cards.iter().cloned());
+    let mut bytes = primary.to_bytes();
+    // FITS data are big endian
+    bytes.extend(data.iter().flat_map(|x| x.to_be_bytes()));
+    bytes.resize(bytes.len().next_multiple_of(BLOCK), 0);
+    let file = File::create(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all(&bytes)
+        .and_then(|_| writer.flush())
+        .map_err(|e| GrimError::Io(e, path.to_path_buf()))
+}
+
+/// [FrameLogger] builder
+pub struct FrameLoggerBuilder {
+    dirname: String,
+    width: usize,
+    height: usize,
+    pixel_scale: Option<f64>,
+    exposure_time: Option<f64>,
+    sampling_frequency: f64,
+    rate: usize,
+    source: String,
+    unit: String,
+}
+impl Default for FrameLoggerBuilder {
+    fn default() -> Self {
+        Self {
+            dirname: "frames".to_string(),
+            width: 0,
+            height: 0,
+            pixel_scale: None,
+            exposure_time: None,
+            sampling_frequency: 1000f64,
+            rate: 1,
+            source: String::new(),
+            unit: String::new(),
+        }
+    }
+}
+impl FrameLoggerBuilder {
+    /// Sets the name of the directory of the FITS files, created in the `DATA_REPO` directory
+    pub fn dirname<S: Into<String>>(self, dirname: S) -> Self {
+        Self {
+            dirname: dirname.into(),
+            ..self
+        }
+    }
+    /// Sets the number of pixels along the rows (`width`) and the columns (`height`) of a frame
+    ///
+    /// Frames that are a multiple of `width`x`height` are saved as data cubes
+    pub fn frame_size(self, width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            ..self
+        }
+    }
+    /// Sets the pixel scale [arcsec]
+    pub fn pixel_scale(self, pixel_scale: f64) -> Self {
+        Self {
+            pixel_scale: Some(pixel_scale),
+            ..self
+        }
+    }
+    /// Sets the exposure time [s], the default is the time in between 2 updates of the logger
+    pub fn exposure_time(self, exposure_time: f64) -> Self {
+        Self {
+            exposure_time: Some(exposure_time),
+            ..self
+        }
+    }
+    /// Sets the sampling frequency of the simulation [Hz]
+    pub fn sampling_frequency(self, sampling_frequency: f64) -> Self {
+        Self {
+            sampling_frequency,
+            ..self
+        }
+    }
+    /// Sets the number of simulation steps in between 2 updates of the logger
+    ///
+    /// It must match the rate of the logger actor
+    pub fn rate(self, rate: usize) -> Self {
+        Self {
+            rate: rate.max(1),
+            ..self
+        }
+    }
+    /// Sets the name of the actor that outputs the frames
+    pub fn source(self, source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            ..self
+        }
+    }
+    /// Sets the unit of the pixels
+    pub fn unit(self, unit: impl Into<String>) -> Self {
+        Self {
+            unit: unit.into(),
+            ..self
+        }
+    }
+    /// Builds the logger
+    pub fn build(self) -> FrameLogger {
+        let exposure_time = self
+            .exposure_time
+            .unwrap_or(self.rate as f64 / self.sampling_frequency);
+        FrameLogger {
+            dir: crate::data_repo().join(&self.dirname),
+            width: self.width,
+            height: self.height,
+            pixel_scale: self.pixel_scale,
+            exposure_time,
+            sampling_frequency: self.sampling_frequency,
+            rate: self.rate,
+            source: self.source,
+            unit: self.unit,
+            step: 0,
+            n_frame: 0,
+        }
+    }
+}
+
+/// FITS detector frames logger
+pub struct FrameLogger {
+    dir: PathBuf,
+    width: usize,
+    height: usize,
+    pixel_scale: Option<f64>,
+    exposure_time: f64,
+    sampling_frequency: f64,
+    rate: usize,
+    source: String,
+    unit: String,
+    step: usize,
+    n_frame: usize,
+}
+impl FrameLogger {
+    /// Creates a [FrameLoggerBuilder]
+    pub fn builder() -> FrameLoggerBuilder {
+        Default::default()
+    }
+    /// Returns the number of saved frames
+    pub fn size(&self) -> usize {
+        self.n_frame
+    }
+    /// Returns the path to the directory of the FITS files
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    // Returns the dimensions of the frame of `n_pixel` pixels
+    fn naxis(&self, n_pixel: usize) -> Result<Vec<usize>> {
+        let n_frame_pixel = self.width * self.height;
+        if n_frame_pixel == 0 {
+            // square frame by default
+            let n = (n_pixel as f64).sqrt().round() as usize;
+            return if n * n == n_pixel {
+                Ok(vec![n, n])
+            } else {
+                Err(GrimError::Config(format!(
+                    "the frame size of {n_pixel} pixels must be set"
+                )))
+            };
+        }
+        match (n_pixel / n_frame_pixel, n_pixel % n_frame_pixel) {
+            (1, 0) => Ok(vec![self.width, self.height]),
+            (n, 0) if n > 1 => Ok(vec![self.width, self.height, n]),
+            _ => Err(GrimError::Config(format!(
+                "{n_pixel} pixels is not a multiple of the {}x{} frame size",
+                self.width, self.height
+            ))),
+        }
+    }
+    /// Writes the frame `data` of stream `name` in a FITS file
+    pub fn write(&mut self, name: &str, data: &[f32]) -> Result<PathBuf> {
+        let naxis = self.naxis(data.len())?;
+        let time = (self.step * self.rate) as f64 / self.sampling_frequency;
+        let mut header = Header::default()
+            .card(
+                "EXPTIME",
+                Card::Float(self.exposure_time),
+                "exposure time [s]",
+            )
+            .card("SIMTIME", Card::Float(time), "simulation time [s]")
+            .card(
+                "SIMSTEP",
+                Card::Integer((self.step * self.rate) as i64),
+                "simulation step",
+            )
+            .card("EXTNAME", Card::Text(name.to_string()), "stream")
+            .card("ORIGIN", Card::Text("GRIM".to_string()), "")
+            .card(
+                "DATE",
+                Card::Text(chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string()),
+                "file creation date (UTC)",
+            );
+        if !self.unit.is_empty() {
+            header = header.card("BUNIT", Card::Text(self.unit.clone()), "pixel unit");
+        }
+        if !self.source.is_empty() {
+            header = header.card("INSTRUME", Card::Text(self.source.clone()), "");
+        }
+        if let Some(pixel_scale) = self.pixel_scale {
+            for (i, n) in naxis.iter().take(2).enumerate() {
+                let axis = i + 1;
+                header = header
+                    .card(
+                        &format!("CTYPE{axis}"),
+                        Card::Text(if axis == 1 { "XOFFSET" } else { "YOFFSET" }.to_string()),
+                        "",
+                    )
+                    .card(
+                        &format!("CUNIT{axis}"),
+                        Card::Text("arcsec".to_string()),
+                        "",
+                    )
+                    .card(
+                        &format!("CRPIX{axis}"),
+                        Card::Float((*n as f64 + 1f64) / 2f64),
+                        "central pixel",
+                    )
+                    .card(&format!("CRVAL{axis}"), Card::Float(0f64), "")
+                    .card(
+                        &format!("CDELT{axis}"),
+                        Card::Float(pixel_scale),
+                        "pixel scale [arcsec]",
+                    );
+            }
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| GrimError::Io(e, self.dir.clone()))?;
+        let path = self.dir.join(format!("{name}-{:06}.fits", self.step));
+        write_image(&path, &naxis, data, &header)?;
+        self.n_frame += 1;
+        Ok(path)
+    }
+}
+
+impl Update for FrameLogger {
+    fn update(&mut self) {
+        self.step += 1;
+    }
+}
+
+impl<T, U> Read<Vec<T>, U> for FrameLogger
+where
+    T: Copy + Into<f64> + Send + Sync + 'static,
+    U: UniqueIdentifier<Data = Vec<T>>,
+{
+    fn read(&mut self, data: Arc<Data<U>>) {
+        let frame: Vec<f32> = data.iter().map(|x| Into::<f64>::into(*x) as f32).collect();
+        let name = uid_name::<U>();
+        match self.write(&name, &frame) {
+            Ok(path) => log::debug!("{name} frame saved to {path:?}"),
+            Err(e) => log::error!("failed to save the {name} frame: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempPath;
+
+    #[test]
+    fn image() {
+        let path = TempPath::new("image.fits");
+        let data: Vec<f32> = (0..6).map(|x| x as f32 * 1.5).collect();
+        let source = format!("AGWS SH48 {}'s detector", "x".repeat(100));
+        let header = Header::default()
+            .card("BUNIT", Card::Text("photon".to_string()), "pixel unit")
+            .card("INSTRUME", Card::Text(source), "");
+        write_image(&path, &[3, 2], &data, &header).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        assert_eq!(bytes.len() % BLOCK, 0);
+        let cards: Vec<&str> = bytes[..BLOCK]
+            .chunks(CARD)
+            .map(|card| std::str::from_utf8(card).unwrap())
+            .collect();
+        assert!(cards[0].starts_with("SIMPLE  =                    T"));
+        assert!(cards[1].starts_with("BITPIX  =                  -32"));
+        assert!(cards[2].starts_with("NAXIS   =                    2"));
+        assert!(cards[3].starts_with("NAXIS1  =                    3"));
+        assert!(cards[4].starts_with("NAXIS2  =                    2"));
+        assert!(cards[5].starts_with("BUNIT   = 'photon  ' / pixel unit"));
+        let instrume = cards[6];
+        assert!(instrume.starts_with("INSTRUME= 'AGWS SH48 x"));
+        assert!(instrume.trim_end().ends_with('\''));
+        assert!(!instrume.trim_end().ends_with("''"));
+        assert_eq!(cards[7].trim_end(), "END");
+        assert!(cards[8..].iter().all(|card| card.trim().is_empty()));
+
+        let pixels: Vec<f32> = bytes[BLOCK..BLOCK + 4 * data.len()]
+            .chunks(4)
+            .map(|x| f32::from_be_bytes(x.try_into().unwrap()))
+            .collect();
+        assert_eq!(pixels, data);
+        assert!(bytes[BLOCK + 4 * data.len()..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn quoted_text() {
+        assert_eq!(Card::Text("O'Neil".to_string()).value(), "'O''Neil '");
+        let value = Card::Text("'".repeat(40)).value();
+        assert_eq!(value.len(), MAX_TEXT + 2);
+        assert_eq!(value, format!("'{}'", "'".repeat(MAX_TEXT)));
+    }
+}
@@ -0,0 +1,215 @@
+//! Frequency response measurements
+//!
+//! [Injector] adds the excitation of an [Injection] to some channels of a signal.
+//! The injector is inserted in between the actor that outputs the signal and the actors that
+//! read it, e.g. in between the mount controller and the FEM for `MountTorques`,
+//! or replaces the actor that outputs a command, e.g. for `PZTcmd`:
+//! ```ignore
+//! let mut injector: Actor<_> = (Injector::<MountTorques>::new(injection), "Injection").into();
+//! mount
+//!     .add_output()
+//!     .build::<MountTorques>()
+//!     .into_input(&mut injector);
+//! injector
+//!     .add_output()
+//!     .multiplex(2)
+//!     .build::<MountTorques>()
+//!     .into_input(&mut fem)
+//!     .into_input(&mut sink);
+//! ```
+//! The excitation is a deterministic function of the simulation step, so it is regenerated
+//! from the [Injection] saved in the run manifest to estimate the transfer functions.
+
+use crate::{
+    config::{Excitation, Injection},
+    uid_name,
+};
+use dos_actors::{
+    io::{Data, Read, Write},
+    UniqueIdentifier, Update,
+};
+use std::{f64::consts::PI, marker::PhantomData, sync::Arc};
+
+impl Injection {
+    /// Returns the frequencies [Hz] and the Schroeder phases [rd] of the harmonics of the multisine,
+    /// empty for the other excitations
+    pub fn harmonics(&self) -> Vec<(f64, f64)> {
+        let Excitation::Multisine {
+            f_min,
+            f_max,
+            period,
+        } = self.excitation
+        else {
+            return vec![];
+        };
+        let harmonics =
+            (f_min * period).ceil().max(1f64) as usize..=(f_max * period).floor() as usize;
+        let n_harmonic = harmonics.clone().count() as f64;
+        harmonics
+            .enumerate()
+            .map(|(k, n)| (n as f64 / period, -PI * (k * (k + 1)) as f64 / n_harmonic))
+            .collect()
+    }
+    /// Returns the excitation at the simulation step `step`
+    ///
+    /// The harmonics of a multisine are computed at each call,
+    /// use [Injection::samples] for a sequence of steps
+    pub fn value(&self, step: usize) -> f64 {
+        self.excite(&self.harmonics(), step)
+    }
+    /// Returns the excitation at the steps `steps`
+    pub fn samples(&self, steps: impl Iterator<Item = usize>) -> Vec<f64> {
+        let harmonics = self.harmonics();
+        steps.map(|step| self.excite(&harmonics, step)).collect()
+    }
+    // Returns the excitation at the simulation step `step` with the multisine `harmonics`
+    fn excite(&self, harmonics: &[(f64, f64)], step: usize) -> f64 {
+        let t = step as f64 / self.sampling_frequency - self.start;
+        if t < 0f64 {
+            return 0f64;
+        }
+        match self.excitation {
+            Excitation::Chirp {
+                f_min,
+                f_max,
+                duration,
+            } => {
+                let tau = t % duration;
+                let k = (f_max / f_min).ln();
+                let phase = 2f64 * PI * f_min * duration / k * ((k * tau / duration).exp() - 1f64);
+                self.amplitude * phase.sin()
+            }
+            Excitation::Multisine { .. } => {
+                harmonics
+                    .iter()
+                    .map(|(f, phase)| (2f64 * PI * f * t + phase).cos())
+                    .sum::<f64>()
+                    * self.amplitude
+                    / (harmonics.len() as f64).sqrt()
+            }
+            Excitation::Step => self.amplitude,
+        }
+    }
+}
+
+/// Injection of an excitation into the signal `U`
+///
+/// The excitation is added to the channels of the [Injection] of the signal read by the
+/// injector, or of a null signal of [Injector::size] channels if the injector has no input.
+/// The default injector has no excitation and passes the signal through.
+pub struct Injector<U> {
+    injection: Option<Injection>,
+    harmonics: Vec<(f64, f64)>,
+    step: usize,
+    size: usize,
+    data: Option<Vec<f64>>,
+    output: Vec<f64>,
+    uid: PhantomData<U>,
+}
+impl<U: UniqueIdentifier> Injector<U> {
+    /// Creates a new injector of the excitation `injection`
+    pub fn new(injection: Injection) -> Self {
+        if injection.signal != uid_name::<U>() {
+            log::warn!(
+                "the excitation of {} is injected into {}",
+                injection.signal,
+                uid_name::<U>()
+            );
+        }
+        Self {
+            harmonics: injection.harmonics(),
+            injection: Some(injection),
+            ..Default::default()
+        }
+    }
+    /// Creates a new injector of the excitation `injection` if it is injected into `U`,
+    /// or a pass-through injector otherwise
+    pub fn from_config(injection: Option<&Injection>) -> Self {
+        match injection {
+            Some(injection) if injection.signal == uid_name::<U>() => Self::new(injection.clone()),
+            _ => Default::default(),
+        }
+    }
+    /// Sets the number of channels of the signal if the injector has no input
+    pub fn size(self, size: usize) -> Self {
+        Self { size, ..self }
+    }
+}
+impl<U> Default for Injector<U> {
+    fn default() -> Self {
+        Self {
+            injection: None,
+            harmonics: vec![],
+            step: 0,
+            size: 0,
+            data: None,
+            output: vec![],
+            uid: PhantomData,
+        }
+    }
+}
+impl<U: UniqueIdentifier> Update for Injector<U> {
+    fn update(&mut self) {
+        let mut output = self.data.take().unwrap_or_else(|| vec![0f64; self.size]);
+        if let Some(injection) = &self.injection {
+            let value = injection.excite(&self.harmonics, self.step);
+            for &channel in &injection.channels {
+                match output.get_mut(channel) {
+                    Some(x) => *x += value,
+                    None if self.step == 0 => log::warn!(
+                        "{} has {} channels, no excitation on channel {channel}",
+                        uid_name::<U>(),
+                        output.len()
+                    ),
+                    None => (),
+                }
+            }
+        }
+        self.output = output;
+        self.step += 1;
+    }
+}
+impl<U: UniqueIdentifier<Data = Vec<f64>>> Read<Vec<f64>, U> for Injector<U> {
+    fn read(&mut self, data: Arc<Data<U>>) {
+        self.data = Some(data.to_vec());
+    }
+}
+impl<U: UniqueIdentifier<Data = Vec<f64>>> Write<Vec<f64>, U> for Injector<U> {
+    fn write(&mut self) -> Option<Arc<Data<U>>> {
+        Some(Arc::new(Data::new(self.output.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multisine() {
+        let injection = Injection {
+            signal: "MountTorques".to_string(),
+            excitation: Excitation::Multisine {
+                f_min: 0.5,
+                f_max: 3.,
+                period: 1.,
+            },
+            amplitude: 2.,
+            channels: vec![0],
+            start: 1.,
+            sampling_frequency: 100.,
+        };
+        let frequencies: Vec<f64> = injection.harmonics().iter().map(|(f, _)| *f).collect();
+        assert_eq!(frequencies, [1., 2., 3.]);
+        let samples = injection.samples(0..300);
+        assert!(samples[..100].iter().all(|x| *x == 0f64));
+        assert_eq!(samples[150], injection.value(150));
+        // same RMS as a sine of the same amplitude
+        let rms = (samples[100..200].iter().map(|x| x * x).sum::<f64>() / 100.).sqrt();
+        assert!((rms - 2f64.sqrt()).abs() < 1e-12);
+        // periodic
+        assert!(samples[100..200]
+            .iter()
+            .zip(&samples[200..])
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+    }
+}
@@ -0,0 +1,106 @@
+//! # GMT Rust Integrated Model
+//!
+//! Support library for the GRIM binaries
+
+pub mod aco;
+pub mod analysis;
+pub mod cache;
+pub mod calibration;
+pub mod compare;
+pub mod config;
+pub mod diagnostics;
+pub mod export;
+pub mod figures;
+pub mod fits;
+pub mod injection;
+pub mod logging;
+pub mod lti;
+pub mod manifest;
+pub mod probe;
+pub mod profiler;
+pub mod progress;
+pub mod results;
+pub mod shutdown;
+pub mod spectrum;
+pub mod stitch;
+pub mod synthetic;
+pub mod telemetry;
+pub mod windloads;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GrimError {
+    #[error("failed to open/create {1:?}")]
+    Io(#[source] std::io::Error, std::path::PathBuf),
+    #[error("arrow error")]
+    Arrow(#[from] arrow::error::ArrowError),
+    #[error("parquet error")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error("json error")]
+    Json(#[from] serde_json::Error),
+    #[error("bincode error")]
+    Bincode(#[from] bincode::Error),
+    #[error("configuration error: {0}")]
+    Config(String),
+    #[error("plotting error: {0}")]
+    Plot(String),
+    #[error("calibration error: {0}")]
+    Calibration(String),
+    #[error("zip error")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("export error: {0}")]
+    Export(String),
+}
+pub type Result<T> = std::result::Result<T, GrimError>;
+
+/// Returns the name of a [UniqueIdentifier](dos_actors::UniqueIdentifier) type stripped from its module path
+pub fn uid_name<U>() -> String {
+    std::any::type_name::<U>()
+        .rsplit("::")
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Returns the path to the data repository given by the `DATA_REPO` environment variable
+pub fn data_repo() -> std::path::PathBuf {
+    std::env::var("DATA_REPO")
+        .unwrap_or_else(|_| ".".to_string())
+        .into()
+}
+
+/// Temporary file or directory of the tests, removed when dropped
+#[cfg(test)]
+pub(crate) struct TempPath(std::path::PathBuf);
+#[cfg(test)]
+impl TempPath {
+    /// Returns a new path `grim-<pid>-<n>-<name>` in the temporary directory
+    pub fn new(name: &str) -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static N: AtomicUsize = AtomicUsize::new(0);
+        let n = N.fetch_add(1, Ordering::SeqCst);
+        Self(std::env::temp_dir().join(format!("grim-{}-{n}-{name}", std::process::id())))
+    }
+}
+#[cfg(test)]
+impl std::ops::Deref for TempPath {
+    type Target = std::path::Path;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+#[cfg(test)]
+impl AsRef<std::path::Path> for TempPath {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+#[cfg(test)]
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = if self.0.is_dir() {
+            std::fs::remove_dir_all(&self.0)
+        } else {
+            std::fs::remove_file(&self.0)
+        };
+    }
+}
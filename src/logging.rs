@@ -0,0 +1,794 @@
+//! Streaming parquet logger
+//!
+//! The [Logger] client writes the data it receives to disk in parquet row groups every
+//! `row_group_size` samples or every `flush_interval`, whichever comes first, so the memory
+//! footprint is bounded by the size of one row group.
+//!
+//! Each row group is written as a complete parquet file in the `<name>.parts` directory
+//! next to the logger file and the parts are merged into the logger file when the logger
+//! is dropped.
+//! If the process is killed before, the data that has already been flushed can still be
+//! read back from the parts with [merge], before a new logger with the same file is built as
+//! it removes the parts left by a previous run.
+//! If a part cannot be written, its samples are lost and, if the logger is given the
+//! [Shutdown] flag of the simulation, a shutdown is requested so the run is truncated.
+//! A stream that is first received after some parts have been flushed is null in the rows of
+//! these parts.
+//!
+//! Each row of the logger file corresponds to one update of the logger actor and
+//! decimated streams are null for the rows in between two samples.
+//!
+//! If the simulation sampling frequency is given to the [LoggerBuilder], the logger file
+//! starts with a [TIME] column with the time of each row and each stream carries its
+//! [StreamMetadata] as key/value metadata of the parquet schema field.
+
+use crate::{shutdown::Shutdown, uid_name, GrimError, Result};
+use arrow::{
+    array::{new_null_array, ArrayRef, Float32Builder, Float64Array, Float64Builder, ListBuilder},
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use dos_actors::{
+    io::{Data, Read},
+    UniqueIdentifier, Update,
+};
+use parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter},
+    file::properties::WriterProperties,
+};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Logger stream buffer
+#[doc(hidden)]
+pub enum Buffer {
+    F64(ListBuilder<Float64Builder>),
+    F32(ListBuilder<Float32Builder>),
+}
+impl Buffer {
+    fn append_null(&mut self) {
+        match self {
+            Buffer::F64(buffer) => buffer.append(false),
+            Buffer::F32(buffer) => buffer.append(false),
+        }
+    }
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Buffer::F64(buffer) => Arc::new(buffer.finish()),
+            Buffer::F32(buffer) => Arc::new(buffer.finish()),
+        }
+    }
+}
+
+/// Data types that can be logged
+pub trait LogData: Copy + Send + Sync + 'static {
+    #[doc(hidden)]
+    fn buffer() -> Buffer;
+    #[doc(hidden)]
+    fn append(buffer: &mut Buffer, data: &[Self]);
+}
+impl LogData for f64 {
+    fn buffer() -> Buffer {
+        Buffer::F64(ListBuilder::new(Float64Builder::new()))
+    }
+    fn append(buffer: &mut Buffer, data: &[Self]) {
+        if let Buffer::F64(buffer) = buffer {
+            buffer.values().append_slice(data);
+            buffer.append(true);
+        }
+    }
+}
+impl LogData for f32 {
+    fn buffer() -> Buffer {
+        Buffer::F32(ListBuilder::new(Float32Builder::new()))
+    }
+    fn append(buffer: &mut Buffer, data: &[Self]) {
+        if let Buffer::F32(buffer) = buffer {
+            buffer.values().append_slice(data);
+            buffer.append(true);
+        }
+    }
+}
+
+/// Name of the time column of the logger files
+pub const TIME: &str = "Time";
+
+/// Metadata of a logged stream
+///
+/// The metadata are saved as the key/value metadata of the stream field in the parquet schema
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamMetadata {
+    /// Sampling frequency of the stream [Hz]
+    pub sampling_frequency: f64,
+    /// Number of simulation steps in between 2 samples
+    pub decimation: usize,
+    /// Time of the first sample that is not part of the warm-up [s]
+    pub start_time: f64,
+    pub unit: String,
+    /// Name of the actor that outputs the stream
+    pub source: String,
+}
+impl StreamMetadata {
+    /// Returns the metadata as key/value pairs
+    pub fn to_hashmap(&self) -> HashMap<String, String> {
+        [
+            ("sampling_frequency", self.sampling_frequency.to_string()),
+            ("decimation", self.decimation.to_string()),
+            ("start_time", self.start_time.to_string()),
+            ("unit", self.unit.clone()),
+            ("source", self.source.clone()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect()
+    }
+    /// Parses the metadata from key/value pairs
+    ///
+    /// Returns `None` if the sampling frequency or the decimation is missing
+    pub fn from_hashmap(metadata: &HashMap<String, String>) -> Option<Self> {
+        Some(Self {
+            sampling_frequency: metadata.get("sampling_frequency")?.parse().ok()?,
+            decimation: metadata.get("decimation")?.parse().ok()?,
+            start_time: metadata
+                .get("start_time")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_default(),
+            unit: metadata.get("unit").cloned().unwrap_or_default(),
+            source: metadata.get("source").cloned().unwrap_or_default(),
+        })
+    }
+}
+
+/// Logger data stream
+struct Stream {
+    name: String,
+    decimation: usize,
+    metadata: Option<StreamMetadata>,
+    buffer: Buffer,
+    // true if a sample has been received since the last update
+    received: bool,
+}
+
+/// [Logger] builder
+pub struct LoggerBuilder {
+    filename: String,
+    row_group_size: usize,
+    flush_interval: Option<Duration>,
+    decimation: usize,
+    decimations: HashMap<String, usize>,
+    sampling_frequency: Option<f64>,
+    rate: usize,
+    start_time: f64,
+    source: String,
+    units: HashMap<String, String>,
+    sources: HashMap<String, String>,
+    shutdown: Option<Shutdown>,
+}
+impl Default for LoggerBuilder {
+    fn default() -> Self {
+        Self {
+            filename: "data.parquet".to_string(),
+            row_group_size: 1_000,
+            flush_interval: None,
+            decimation: 1,
+            decimations: HashMap::new(),
+            sampling_frequency: None,
+            rate: 1,
+            start_time: 0f64,
+            source: String::new(),
+            units: HashMap::new(),
+            sources: HashMap::new(),
+            shutdown: None,
+        }
+    }
+}
+impl LoggerBuilder {
+    /// Sets the name of the parquet file, the file is written in the `DATA_REPO` directory
+    pub fn filename<S: Into<String>>(self, filename: S) -> Self {
+        Self {
+            filename: filename.into(),
+            ..self
+        }
+    }
+    /// Sets the number of samples written to disk at once
+    pub fn row_group_size(self, row_group_size: usize) -> Self {
+        Self {
+            row_group_size: row_group_size.max(1),
+            ..self
+        }
+    }
+    /// Writes the samples to disk at least every `flush_interval`
+    pub fn flush_interval(self, flush_interval: Duration) -> Self {
+        Self {
+            flush_interval: Some(flush_interval),
+            ..self
+        }
+    }
+    /// Logs only 1 out of `decimation` samples of every streams
+    pub fn decimation(self, decimation: usize) -> Self {
+        Self {
+            decimation: decimation.max(1),
+            ..self
+        }
+    }
+    /// Logs only 1 out of `decimation` samples of stream `U`, overriding [LoggerBuilder::decimation]
+    pub fn stream_decimation<U: UniqueIdentifier>(mut self, decimation: usize) -> Self {
+        self.decimations.insert(uid_name::<U>(), decimation.max(1));
+        self
+    }
+    /// Sets the sampling frequency of the simulation [Hz]
+    ///
+    /// The time column and the streams metadata are written only if the sampling frequency is set
+    pub fn sampling_frequency(self, sampling_frequency: f64) -> Self {
+        Self {
+            sampling_frequency: Some(sampling_frequency),
+            ..self
+        }
+    }
+    /// Sets the number of simulation steps in between 2 updates of the logger
+    ///
+    /// It must match the rate of the logger actor
+    pub fn rate(self, rate: usize) -> Self {
+        Self {
+            rate: rate.max(1),
+            ..self
+        }
+    }
+    /// Sets the time of the end of the warm-up [s]
+    pub fn start_time(self, start_time: f64) -> Self {
+        Self { start_time, ..self }
+    }
+    /// Sets the name of the actor that outputs the streams
+    pub fn source(self, source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            ..self
+        }
+    }
+    /// Sets the name of the actor that outputs stream `U`, overriding [LoggerBuilder::source]
+    pub fn stream_source<U: UniqueIdentifier>(mut self, source: impl Into<String>) -> Self {
+        self.sources.insert(uid_name::<U>(), source.into());
+        self
+    }
+    /// Sets the unit of stream `U`
+    pub fn stream_unit<U: UniqueIdentifier>(mut self, unit: impl Into<String>) -> Self {
+        self.units.insert(uid_name::<U>(), unit.into());
+        self
+    }
+    /// Requests a shutdown of the simulation if some samples cannot be written
+    pub fn shutdown(self, shutdown: &Shutdown) -> Self {
+        Self {
+            shutdown: Some(shutdown.clone()),
+            ..self
+        }
+    }
+    /// Builds the logger
+    ///
+    /// The parts left in the `<name>.parts` directory by a previous run are removed
+    pub fn build(self) -> Logger {
+        let path = crate::data_repo().join(&self.filename);
+        let parts = path.with_extension("parts");
+        if parts.exists() {
+            log::warn!("removing the parts of a previous run in {parts:?}");
+            if let Err(e) = fs::remove_dir_all(&parts) {
+                log::error!("failed to remove {parts:?}: {e}");
+            }
+        }
+        Logger {
+            path,
+            parts,
+            streams: Vec::new(),
+            decimation: self.decimation,
+            decimations: self.decimations,
+            sampling_frequency: self.sampling_frequency,
+            rate: self.rate,
+            start_time: self.start_time,
+            source: self.source,
+            units: self.units,
+            sources: self.sources,
+            row_group_size: self.row_group_size,
+            flush_interval: self.flush_interval,
+            last_flush: Instant::now(),
+            step: 0,
+            n_row: 0,
+            n_part: 0,
+            n_lost: 0,
+            shutdown: self.shutdown,
+            finished: false,
+        }
+    }
+}
+
+/// Streaming parquet logger
+pub struct Logger {
+    path: PathBuf,
+    parts: PathBuf,
+    streams: Vec<Stream>,
+    decimation: usize,
+    decimations: HashMap<String, usize>,
+    sampling_frequency: Option<f64>,
+    rate: usize,
+    start_time: f64,
+    source: String,
+    units: HashMap<String, String>,
+    sources: HashMap<String, String>,
+    row_group_size: usize,
+    flush_interval: Option<Duration>,
+    last_flush: Instant,
+    step: usize,
+    n_row: usize,
+    n_part: usize,
+    n_lost: usize,
+    shutdown: Option<Shutdown>,
+    finished: bool,
+}
+impl Logger {
+    /// Creates a [LoggerBuilder]
+    pub fn builder() -> LoggerBuilder {
+        Default::default()
+    }
+    /// Returns the number of logged samples
+    pub fn size(&self) -> usize {
+        self.step
+    }
+    /// Returns the path to the logger file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Returns the number of samples that could not be written
+    pub fn lost(&self) -> usize {
+        self.n_lost
+    }
+    // Returns the metadata of a stream sampled every `decimation` logger updates
+    fn metadata(&self, name: &str, decimation: usize) -> Option<StreamMetadata> {
+        let sampling_frequency = self.sampling_frequency?;
+        let decimation = decimation * self.rate;
+        Some(StreamMetadata {
+            sampling_frequency: sampling_frequency / decimation as f64,
+            decimation,
+            start_time: self.start_time,
+            unit: self.units.get(name).cloned().unwrap_or_default(),
+            source: self.sources.get(name).unwrap_or(&self.source).clone(),
+        })
+    }
+    fn stream<T: LogData>(&mut self, name: String) -> &mut Stream {
+        let idx = match self.streams.iter().position(|stream| stream.name == name) {
+            Some(idx) => idx,
+            None => {
+                let decimation = *self.decimations.get(&name).unwrap_or(&self.decimation);
+                let metadata = self.metadata(&name, decimation);
+                let mut buffer = T::buffer();
+                (0..self.n_row).for_each(|_| buffer.append_null());
+                self.streams.push(Stream {
+                    name,
+                    decimation,
+                    metadata,
+                    buffer,
+                    received: false,
+                });
+                self.streams.len() - 1
+            }
+        };
+        &mut self.streams[idx]
+    }
+    /// Writes the buffered samples to a new part
+    ///
+    /// The buffers are emptied even if the part cannot be written, the samples are then lost
+    /// and a shutdown is requested
+    pub fn flush(&mut self) -> Result<()> {
+        self.last_flush = Instant::now();
+        if self.n_row == 0 {
+            return Ok(());
+        }
+        let n_row = std::mem::take(&mut self.n_row);
+        let first_row = self.step - n_row;
+        if let Err(e) = self.write_part(first_row) {
+            self.n_lost += n_row;
+            log::error!(
+                "{:?}: the samples {first_row} to {} are lost",
+                self.path,
+                self.step - 1
+            );
+            if let Some(shutdown) = &self.shutdown {
+                shutdown.request();
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+    // Writes the samples from the row `first_row` to a new part, emptying the buffers
+    fn write_part(&mut self, first_row: usize) -> Result<()> {
+        let mut columns: Vec<(Field, ArrayRef)> = vec![];
+        if let Some(time) = self.metadata(TIME, 1) {
+            let column: Float64Array = (first_row..self.step)
+                .map(|row| row as f64 / time.sampling_frequency)
+                .collect();
+            columns.push((
+                Field::new(TIME, DataType::Float64, false).with_metadata(
+                    StreamMetadata {
+                        unit: "s".to_string(),
+                        source: String::new(),
+                        ..time
+                    }
+                    .to_hashmap(),
+                ),
+                Arc::new(column),
+            ));
+        }
+        for stream in self.streams.iter_mut() {
+            let column = stream.buffer.finish();
+            let mut field = Field::new(&stream.name, column.data_type().clone(), true);
+            if let Some(metadata) = &stream.metadata {
+                field = field.with_metadata(metadata.to_hashmap());
+            }
+            columns.push((field, column));
+        }
+        let (fields, columns): (Vec<_>, Vec<_>) = columns.into_iter().unzip();
+        let record = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+        fs::create_dir_all(&self.parts).map_err(|e| GrimError::Io(e, self.parts.clone()))?;
+        let part = self.parts.join(format!("part-{:05}.parquet", self.n_part));
+        let file = File::create(&part).map_err(|e| GrimError::Io(e, part.clone()))?;
+        let mut writer = ArrowWriter::try_new(file, record.schema(), None)?;
+        writer.write(&record)?;
+        writer.close()?;
+        self.n_part += 1;
+        Ok(())
+    }
+    /// Flushes the buffered samples and merges all the parts into the logger file
+    pub fn finish(&mut self) -> Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.flush()?;
+        self.finished = true;
+        if self.n_part > 0 {
+            merge(&self.parts, &self.path)?;
+            log::info!(
+                "{} samples saved to {:?}",
+                self.step - self.n_lost,
+                self.path
+            );
+        }
+        if self.n_lost > 0 {
+            log::warn!("{} samples lost from {:?}", self.n_lost, self.path);
+        }
+        Ok(())
+    }
+}
+impl Drop for Logger {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("failed to save {:?}: {e}", self.path);
+        }
+    }
+}
+
+// Returns the index of the part `file`
+fn part_index(file: &Path) -> Option<usize> {
+    file.file_stem()?
+        .to_str()?
+        .strip_prefix("part-")?
+        .parse()
+        .ok()
+}
+
+/// Merges the parts in the `parts` directory into the parquet file `path` and removes the parts
+///
+/// The schema of the merged file is the union of the schemas of the parts,
+/// the streams missing from a part are null for the rows of this part
+pub fn merge<P: AsRef<Path>, Q: AsRef<Path>>(parts: P, path: Q) -> Result<()> {
+    let parts = parts.as_ref();
+    let path = path.as_ref();
+    let mut files: Vec<(usize, PathBuf)> = fs::read_dir(parts)
+        .map_err(|e| GrimError::Io(e, parts.to_path_buf()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| file.extension().is_some_and(|ext| ext == "parquet"))
+        .filter_map(|file| part_index(&file).map(|index| (index, file)))
+        .collect();
+    files.sort_by_key(|(index, _)| *index);
+    let mut fields: Vec<Field> = vec![];
+    for (i, (_, part)) in files.iter().enumerate() {
+        let file = File::open(part).map_err(|e| GrimError::Io(e, part.clone()))?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)?;
+        for field in reader.schema().fields() {
+            if !fields.iter().any(|f| f.name() == field.name()) {
+                // the streams of the later parts are null in the first ones
+                fields.push(
+                    field
+                        .as_ref()
+                        .clone()
+                        .with_nullable(i > 0 || field.is_nullable()),
+                );
+            }
+        }
+    }
+    let schema = Arc::new(Schema::new(fields));
+    let mut writer: Option<ArrowWriter<File>> = None;
+    for (_, part) in &files {
+        let file = File::open(part).map_err(|e| GrimError::Io(e, part.clone()))?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+        for record in reader {
+            let record = record?;
+            let columns: Vec<ArrayRef> = schema
+                .fields()
+                .iter()
+                .map(|field| {
+                    record
+                        .column_by_name(field.name())
+                        .cloned()
+                        .unwrap_or_else(|| new_null_array(field.data_type(), record.num_rows()))
+                })
+                .collect();
+            let record = RecordBatch::try_new(schema.clone(), columns)?;
+            if writer.is_none() {
+                let file = File::create(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+                let properties = WriterProperties::builder()
+                    .set_max_row_group_size(record.num_rows().max(1))
+                    .build();
+                writer = Some(ArrowWriter::try_new(
+                    file,
+                    schema.clone(),
+                    Some(properties),
+                )?);
+            }
+            if let Some(writer) = writer.as_mut() {
+                writer.write(&record)?;
+            }
+        }
+    }
+    if let Some(writer) = writer {
+        writer.close()?;
+        fs::remove_dir_all(parts).map_err(|e| GrimError::Io(e, parts.to_path_buf()))?;
+    }
+    Ok(())
+}
+
+impl Update for Logger {
+    fn update(&mut self) {
+        for stream in self.streams.iter_mut() {
+            if !stream.received {
+                stream.buffer.append_null();
+            }
+            stream.received = false;
+        }
+        self.step += 1;
+        self.n_row += 1;
+        let flush = self.n_row >= self.row_group_size
+            || self
+                .flush_interval
+                .is_some_and(|interval| self.last_flush.elapsed() >= interval);
+        if flush {
+            if let Err(e) = self.flush() {
+                log::error!("failed to flush {:?}: {e}", self.path);
+            }
+        }
+    }
+}
+
+impl<T: LogData, U: UniqueIdentifier<Data = Vec<T>>> Read<Vec<T>, U> for Logger {
+    fn read(&mut self, data: Arc<Data<U>>) {
+        let step = self.step;
+        let stream = self.stream::<T>(uid_name::<U>());
+        if step % stream.decimation == 0 {
+            T::append(&mut stream.buffer, &data);
+            stream.received = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempPath;
+    use arrow::array::{Array, ListArray};
+    use dos_actors::prelude::*;
+
+    #[derive(UID)]
+    #[uid(data = "Vec<f64>")]
+    enum A {}
+    #[derive(UID)]
+    #[uid(data = "Vec<f64>")]
+    enum B {}
+
+    // Writes the columns to the part `index` of the `parts` directory
+    fn part(parts: &Path, index: usize, columns: Vec<(&str, Vec<Option<Vec<f64>>>)>) {
+        let (fields, columns): (Vec<_>, Vec<_>) = columns
+            .into_iter()
+            .map(|(name, rows)| {
+                let mut buffer = ListBuilder::new(Float64Builder::new());
+                for row in rows {
+                    buffer.append_option(row.map(|row| row.into_iter().map(Some)));
+                }
+                let column: ArrayRef = Arc::new(buffer.finish());
+                (Field::new(name, column.data_type().clone(), true), column)
+            })
+            .unzip();
+        let record = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap();
+        fs::create_dir_all(parts).unwrap();
+        let file = File::create(parts.join(format!("part-{index:05}.parquet"))).unwrap();
+        let mut writer = ArrowWriter::try_new(file, record.schema(), None).unwrap();
+        writer.write(&record).unwrap();
+        writer.close().unwrap();
+    }
+
+    // Reads back all the records of the parquet file `path`
+    fn read(path: &Path) -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(|record| record.unwrap())
+            .collect()
+    }
+
+    // Returns the first value of each row of a list column, None for null rows
+    fn firsts(records: &[RecordBatch], name: &str) -> Vec<Option<f64>> {
+        records
+            .iter()
+            .flat_map(|record| {
+                let column = record
+                    .column_by_name(name)
+                    .unwrap()
+                    .as_any()
+                    .downcast_ref::<ListArray>()
+                    .unwrap()
+                    .clone();
+                (0..column.len())
+                    .map(|i| {
+                        column.is_valid(i).then(|| {
+                            column
+                                .value(i)
+                                .as_any()
+                                .downcast_ref::<Float64Array>()
+                                .unwrap()
+                                .value(0)
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_parts() {
+        let dir = TempPath::new("merge");
+        let parts = dir.join("logs.parts");
+        let path = dir.join("logs.parquet");
+        // the numeric order of the parts is not their lexicographic order
+        part(
+            &parts,
+            100000,
+            vec![
+                ("A", vec![Some(vec![4.]), Some(vec![5.])]),
+                ("B", vec![None, Some(vec![50.])]),
+            ],
+        );
+        part(&parts, 0, vec![("A", vec![Some(vec![0.]), Some(vec![1.])])]);
+        part(
+            &parts,
+            99999,
+            vec![("A", vec![Some(vec![2.])]), ("B", vec![Some(vec![30.])])],
+        );
+        part(&parts, 1, vec![("A", vec![None])]);
+        merge(&parts, &path).unwrap();
+        assert!(!parts.exists());
+        let records = read(&path);
+        let schema = records[0].schema();
+        let names: Vec<_> = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(names, ["A", "B"]);
+        assert!(schema.field_with_name("B").unwrap().is_nullable());
+        assert_eq!(
+            firsts(&records, "A"),
+            [Some(0.), Some(1.), None, Some(2.), Some(4.), Some(5.)]
+        );
+        // the late stream is null in the parts written before it is received
+        assert_eq!(
+            firsts(&records, "B"),
+            [None, None, None, Some(30.), None, Some(50.)]
+        );
+    }
+
+    #[test]
+    fn decimation() {
+        let dir = TempPath::new("decimation");
+        let filename = dir.join("logs.parquet");
+        fs::create_dir_all(&*dir).unwrap();
+        // a stale part of a previous run
+        part(
+            &filename.with_extension("parts"),
+            7,
+            vec![("A", vec![Some(vec![-1.])])],
+        );
+        let mut logger = Logger::builder()
+            .filename(filename.to_str().unwrap())
+            .row_group_size(4)
+            .sampling_frequency(10.)
+            .stream_decimation::<B>(3)
+            .build();
+        for i in 0..10 {
+            let a: Arc<Data<A>> = Arc::new(Data::new(vec![i as f64]));
+            let b: Arc<Data<B>> = Arc::new(Data::new(vec![10. * i as f64]));
+            <Logger as Read<Vec<f64>, A>>::read(&mut logger, a);
+            <Logger as Read<Vec<f64>, B>>::read(&mut logger, b);
+            logger.update();
+        }
+        logger.finish().unwrap();
+        assert_eq!(logger.lost(), 0);
+        let records = read(&filename);
+        let time: Vec<f64> = records
+            .iter()
+            .flat_map(|record| {
+                record
+                    .column_by_name(TIME)
+                    .unwrap()
+                    .as_any()
+                    .downcast_ref::<Float64Array>()
+                    .unwrap()
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        assert_eq!(time, (0..10).map(|i| i as f64 / 10.).collect::<Vec<_>>());
+        assert_eq!(
+            firsts(&records, "A"),
+            (0..10).map(|i| Some(i as f64)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            firsts(&records, "B"),
+            (0..10)
+                .map(|i| (i % 3 == 0).then_some(10. * i as f64))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn flush_failure() {
+        let dir = TempPath::new("flush");
+        let filename = dir.join("logs.parquet");
+        fs::create_dir_all(&*dir).unwrap();
+        let shutdown = Shutdown::default();
+        let mut logger = Logger::builder()
+            .filename(filename.to_str().unwrap())
+            .row_group_size(4)
+            .sampling_frequency(10.)
+            .shutdown(&shutdown)
+            .build();
+        // a file in place of the parts directory
+        let parts = filename.with_extension("parts");
+        fs::write(&parts, "").unwrap();
+        for i in 0..8 {
+            if i == 4 {
+                assert_eq!(logger.lost(), 4);
+                assert!(shutdown.is_requested());
+                fs::remove_file(&parts).unwrap();
+            }
+            let a: Arc<Data<A>> = Arc::new(Data::new(vec![i as f64]));
+            <Logger as Read<Vec<f64>, A>>::read(&mut logger, a);
+            logger.update();
+        }
+        logger.finish().unwrap();
+        let records = read(&filename);
+        assert_eq!(
+            firsts(&records, "A"),
+            (4..8).map(|i| Some(i as f64)).collect::<Vec<_>>()
+        );
+        let time = records[0]
+            .column_by_name(TIME)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap()
+            .values()
+            .to_vec();
+        assert_eq!(time, [0.4, 0.5, 0.6, 0.7]);
+    }
+}
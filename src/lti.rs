@@ -0,0 +1,1616 @@
+//! Linear time-invariant models
+//!
+//! [StateSpace] is a discrete state space model with named inputs and outputs, the [Port]s,
+//! named after the unique identifiers of the signals exchanged by the actors of the simulation.
+//!
+//! The state space models of the integrated model are:
+//!  - the FEM, built by [FemModel] from the modal model of the FEM as the `DiscreteModalSolver`,
+//!  - the controllers, identified by [Identification] from the impulse responses of the
+//!    controllers clients with the eigensystem realization algorithm.
+//!
+//! [ClosedLoop] connects the outputs and the inputs with the same name into a single
+//! [StateSpace], the inputs without a matching output are the inputs of the closed-loop model.
+//! The poles, the [Margins] of a loop and the frequency response between any input and output
+//! channels are computed from the closed-loop model, which is saved in a MAT-file with
+//! [StateSpace::to_mat].
+
+use crate::{
+    export::{self, Value},
+    uid_name, GrimError, Result,
+};
+use dos_actors::{
+    io::{Data, Read, Write},
+    UniqueIdentifier, Update,
+};
+use fem::{
+    fem_io::{self, FemIo},
+    FEM,
+};
+use nalgebra::{self as na, Complex, DMatrix};
+use std::{f64::consts::PI, fmt::Display, path::Path, sync::Arc};
+
+/// Named input or output of a [StateSpace]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    pub name: String,
+    /// Number of channels
+    pub size: usize,
+}
+impl Port {
+    pub fn new(name: impl Into<String>, size: usize) -> Self {
+        Self {
+            name: name.into(),
+            size,
+        }
+    }
+}
+
+// Returns the index of the first channel and the size of the port `name`
+fn offset(ports: &[Port], name: &str) -> Option<(usize, usize)> {
+    let mut first = 0;
+    for port in ports {
+        if port.name == name {
+            return Some((first, port.size));
+        }
+        first += port.size;
+    }
+    None
+}
+
+// Returns the ports with the ports `parts` merged into the port `name`, in the order of `parts`,
+// at the position of the first one and the indices of the channels of the new ports
+fn regroup(ports: &[Port], name: &str, parts: &[&str]) -> Result<(Vec<Port>, Vec<usize>)> {
+    let mut merged = Port::new(name, 0);
+    let mut merged_indices = vec![];
+    for part in parts {
+        let (first, size) =
+            offset(ports, part).ok_or_else(|| GrimError::Config(format!("{part} not found")))?;
+        merged.size += size;
+        merged_indices.extend(first..first + size);
+    }
+    let mut new_ports = vec![];
+    let mut indices = vec![];
+    let mut first = 0;
+    for port in ports {
+        if port.name == parts[0] {
+            new_ports.push(merged.clone());
+            indices.extend(merged_indices.iter().cloned());
+        } else if !parts.contains(&port.name.as_str()) {
+            new_ports.push(port.clone());
+            indices.extend(first..first + port.size);
+        }
+        first += port.size;
+    }
+    Ok((new_ports, indices))
+}
+
+/// Discrete state space model
+///
+/// x[k+1] = A x[k] + B u[k], y[k] = C x[k] + D u[k]
+#[derive(Debug, Clone)]
+pub struct StateSpace {
+    pub a: DMatrix<f64>,
+    pub b: DMatrix<f64>,
+    pub c: DMatrix<f64>,
+    pub d: DMatrix<f64>,
+    /// Sampling frequency [Hz]
+    pub sampling_frequency: f64,
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+}
+impl StateSpace {
+    /// Creates a new state space model, checking the consistency of the dimensions
+    pub fn new(
+        a: DMatrix<f64>,
+        b: DMatrix<f64>,
+        c: DMatrix<f64>,
+        d: DMatrix<f64>,
+        sampling_frequency: f64,
+        inputs: Vec<Port>,
+        outputs: Vec<Port>,
+    ) -> Result<Self> {
+        let n_input: usize = inputs.iter().map(|port| port.size).sum();
+        let n_output: usize = outputs.iter().map(|port| port.size).sum();
+        let n_state = a.nrows();
+        if a.ncols() != n_state
+            || b.shape() != (n_state, n_input)
+            || c.shape() != (n_output, n_state)
+            || d.shape() != (n_output, n_input)
+        {
+            return Err(GrimError::Config(format!(
+                "inconsistent state space dimensions: A{:?}, B{:?}, C{:?}, D{:?} for {n_input} inputs and {n_output} outputs",
+                a.shape(),
+                b.shape(),
+                c.shape(),
+                d.shape()
+            )));
+        }
+        Ok(Self {
+            a,
+            b,
+            c,
+            d,
+            sampling_frequency,
+            inputs,
+            outputs,
+        })
+    }
+    /// Returns a one sample delay of `size` channels from the input `input` to the output `output`
+    pub fn delay(input: &str, output: &str, size: usize, sampling_frequency: f64) -> Self {
+        Self {
+            a: DMatrix::zeros(size, size),
+            b: DMatrix::identity(size, size),
+            c: DMatrix::identity(size, size),
+            d: DMatrix::zeros(size, size),
+            sampling_frequency,
+            inputs: vec![Port::new(input, size)],
+            outputs: vec![Port::new(output, size)],
+        }
+    }
+    /// Returns the number of states
+    pub fn n_state(&self) -> usize {
+        self.a.nrows()
+    }
+    /// Returns the number of input channels
+    pub fn n_input(&self) -> usize {
+        self.b.ncols()
+    }
+    /// Returns the number of output channels
+    pub fn n_output(&self) -> usize {
+        self.c.nrows()
+    }
+    /// Returns the number of channels of the input `name`
+    pub fn input_size(&self, name: &str) -> Result<usize> {
+        offset(&self.inputs, name)
+            .map(|(_, size)| size)
+            .ok_or_else(|| GrimError::Config(format!("input {name} not found")))
+    }
+    /// Returns the number of channels of the output `name`
+    pub fn output_size(&self, name: &str) -> Result<usize> {
+        offset(&self.outputs, name)
+            .map(|(_, size)| size)
+            .ok_or_else(|| GrimError::Config(format!("output {name} not found")))
+    }
+    /// Returns the column of B of the channel `channel` of the input `name`
+    pub fn input_index(&self, name: &str, channel: usize) -> Result<usize> {
+        match offset(&self.inputs, name) {
+            Some((first, size)) if channel < size => Ok(first + channel),
+            Some((_, size)) => Err(GrimError::Config(format!(
+                "input {name} has {size} channels, channel {channel} requested"
+            ))),
+            None => Err(GrimError::Config(format!("input {name} not found"))),
+        }
+    }
+    /// Returns the row of C of the channel `channel` of the output `name`
+    pub fn output_index(&self, name: &str, channel: usize) -> Result<usize> {
+        match offset(&self.outputs, name) {
+            Some((first, size)) if channel < size => Ok(first + channel),
+            Some((_, size)) => Err(GrimError::Config(format!(
+                "output {name} has {size} channels, channel {channel} requested"
+            ))),
+            None => Err(GrimError::Config(format!("output {name} not found"))),
+        }
+    }
+    /// Merges the inputs `parts` into the input `name`, in the order of `parts`
+    pub fn merge_inputs(self, name: &str, parts: &[&str]) -> Result<Self> {
+        let (inputs, indices) = regroup(&self.inputs, name, parts)?;
+        Ok(Self {
+            b: self.b.select_columns(&indices),
+            d: self.d.select_columns(&indices),
+            inputs,
+            ..self
+        })
+    }
+    /// Merges the outputs `parts` into the output `name`, in the order of `parts`
+    pub fn merge_outputs(self, name: &str, parts: &[&str]) -> Result<Self> {
+        let (outputs, indices) = regroup(&self.outputs, name, parts)?;
+        Ok(Self {
+            c: self.c.select_rows(&indices),
+            d: self.d.select_rows(&indices),
+            outputs,
+            ..self
+        })
+    }
+    /// Resamples the model at `sampling_frequency` assuming a zero-order hold on the inputs
+    ///
+    /// The matrix `[A B; 0 I]` of the resampled model is the matrix of the model to the power of
+    /// the ratio of the sampling periods, computed from the matrix logarithm.
+    /// The model must not have a pole at the origin or on the negative real axis.
+    ///
+    /// The logarithm is computed with dense Denman-Beavers iterations, each one inverting two
+    /// matrices of the size of `[A B; 0 I]`, for each of the successive square roots: the cost
+    /// grows as the cube of the number of states and inputs and resampling is meant for the
+    /// small models of the controllers, not for the FEM which is built at the right sampling
+    /// frequency by [FemModel]
+    pub fn resample(&self, sampling_frequency: f64) -> Result<Self> {
+        let (n, m) = (self.n_state(), self.n_input());
+        let mut zoh = DMatrix::<f64>::identity(n + m, n + m);
+        zoh.slice_mut((0, 0), (n, n)).copy_from(&self.a);
+        zoh.slice_mut((0, n), (n, m)).copy_from(&self.b);
+        let log = logm(&zoh).ok_or_else(|| {
+            GrimError::Config(format!(
+                "the model sampled at {}Hz cannot be resampled, it has a pole at the origin or on the negative real axis",
+                self.sampling_frequency
+            ))
+        })?;
+        let zoh = (log * (self.sampling_frequency / sampling_frequency)).exp();
+        Ok(Self {
+            a: zoh.slice((0, 0), (n, n)).into_owned(),
+            b: zoh.slice((0, n), (n, m)).into_owned(),
+            sampling_frequency,
+            ..self.clone()
+        })
+    }
+    /// Returns the poles of the model
+    pub fn poles(&self) -> Vec<Complex<f64>> {
+        if self.n_state() == 0 {
+            return vec![];
+        }
+        self.a.complex_eigenvalues().iter().cloned().collect()
+    }
+    /// Returns the largest magnitude of the poles
+    pub fn spectral_radius(&self) -> f64 {
+        self.poles().iter().map(|p| p.norm()).fold(0f64, f64::max)
+    }
+    /// Checks if all the poles are strictly inside the unit circle
+    pub fn is_stable(&self) -> bool {
+        self.spectral_radius() < 1f64
+    }
+    /// Returns the frequency response at `frequencies` [Hz] from the channel `input.1` of the
+    /// input `input.0` to the channel `output.1` of the output `output.0`
+    pub fn frequency_response(
+        &self,
+        input: (&str, usize),
+        output: (&str, usize),
+        frequencies: &[f64],
+    ) -> Result<Vec<Complex<f64>>> {
+        let j = self.input_index(input.0, input.1)?;
+        let i = self.output_index(output.0, output.1)?;
+        let d = self.d[(i, j)];
+        if self.n_state() == 0 {
+            return Ok(vec![Complex::new(d, 0f64); frequencies.len()]);
+        }
+        // A = Q H Q' with H upper Hessenberg, so C (zI-A)⁻¹ B = C Q (zI-H)⁻¹ Q' B
+        let (q, h) = na::linalg::Hessenberg::new(self.a.clone()).unpack();
+        let b = q.transpose() * self.b.column(j);
+        let c = self.c.row(i) * &q;
+        let h = h.map(|x| Complex::new(x, 0f64));
+        let b: Vec<Complex<f64>> = b.iter().map(|x| Complex::new(*x, 0f64)).collect();
+        Ok(frequencies
+            .iter()
+            .map(|f| {
+                let z = Complex::new(0f64, 2f64 * PI * f / self.sampling_frequency).exp();
+                let x = solve_hessenberg(&h, z, &b);
+                c.iter().zip(&x).map(|(c, x)| x * *c).sum::<Complex<f64>>() + d
+            })
+            .collect())
+    }
+    /// Returns the model variables of a MAT-file
+    ///
+    /// The inputs and the outputs are structures with the first and the last indices
+    /// (starting at 1) of the columns of B and of the rows of C of each port
+    pub fn to_variables(&self) -> Vec<(String, Value)> {
+        let ranges = |ports: &[Port]| {
+            let mut first = 0;
+            Value::Struct(
+                ports
+                    .iter()
+                    .map(|port| {
+                        let range = Value::Double(
+                            vec![1, 2],
+                            vec![(first + 1) as f64, (first + port.size) as f64],
+                        );
+                        first += port.size;
+                        (export::variable_name(&port.name), range)
+                    })
+                    .collect(),
+            )
+        };
+        vec![
+            ("A".to_string(), Value::from(&self.a)),
+            ("B".to_string(), Value::from(&self.b)),
+            ("C".to_string(), Value::from(&self.c)),
+            ("D".to_string(), Value::from(&self.d)),
+            (
+                "Ts".to_string(),
+                Value::scalar(self.sampling_frequency.recip()),
+            ),
+            ("inputs".to_string(), ranges(&self.inputs)),
+            ("outputs".to_string(), ranges(&self.outputs)),
+        ]
+    }
+    /// Saves the model in the MAT-file `path`
+    ///
+    /// The model is loaded in MATLAB with `load(path); sys = ss(A, B, C, D, Ts)`
+    pub fn to_mat<P: AsRef<Path>>(&self, path: P, format: export::Format) -> Result<()> {
+        export::write_mat(path, &self.to_variables(), format)
+    }
+}
+impl Display for StateSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} states, {} inputs, {} outputs sampled at {}Hz",
+            self.n_state(),
+            self.n_input(),
+            self.n_output(),
+            self.sampling_frequency
+        )?;
+        let ports = |ports: &[Port]| {
+            ports
+                .iter()
+                .map(|port| format!("{}[{}]", port.name, port.size))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(f, " . inputs: {}", ports(&self.inputs))?;
+        write!(f, " . outputs: {}", ports(&self.outputs))
+    }
+}
+
+// Solves (zI-H)x = b for the upper Hessenberg matrix H
+//
+// The elimination only involves the sub-diagonal, with partial pivoting between adjacent rows
+fn solve_hessenberg(
+    h: &DMatrix<Complex<f64>>,
+    z: Complex<f64>,
+    b: &[Complex<f64>],
+) -> Vec<Complex<f64>> {
+    let n = b.len();
+    let mut m = -h.clone();
+    for i in 0..n {
+        m[(i, i)] += z;
+    }
+    let mut x = b.to_vec();
+    for k in 0..n.saturating_sub(1) {
+        if m[(k + 1, k)].norm() > m[(k, k)].norm() {
+            m.swap_rows(k, k + 1);
+            x.swap(k, k + 1);
+        }
+        if m[(k + 1, k)] == Complex::new(0f64, 0f64) {
+            continue;
+        }
+        let l = m[(k + 1, k)] / m[(k, k)];
+        for j in k..n {
+            let v = m[(k, j)];
+            m[(k + 1, j)] -= l * v;
+        }
+        let v = x[k];
+        x[k + 1] -= l * v;
+    }
+    for k in (0..n).rev() {
+        let s: Complex<f64> = (k + 1..n).map(|j| m[(k, j)] * x[j]).sum();
+        x[k] = (x[k] - s) / m[(k, k)];
+    }
+    x
+}
+
+// Principal square root of a matrix (Denman-Beavers iteration)
+fn sqrtm(m: &DMatrix<f64>) -> Option<DMatrix<f64>> {
+    let n = m.nrows();
+    let mut y = m.clone();
+    let mut z = DMatrix::<f64>::identity(n, n);
+    for _ in 0..100 {
+        let y_inv = y.clone().try_inverse()?;
+        let z_inv = z.clone().try_inverse()?;
+        let next_y = (&y + z_inv) * 0.5;
+        z = (&z + y_inv) * 0.5;
+        let delta = (&next_y - &y).norm();
+        y = next_y;
+        if !delta.is_finite() {
+            return None;
+        }
+        if delta <= 1e-14 * y.norm() {
+            return Some(y);
+        }
+    }
+    None
+}
+
+// Principal logarithm of a matrix (inverse scaling and squaring)
+fn logm(m: &DMatrix<f64>) -> Option<DMatrix<f64>> {
+    let n = m.nrows();
+    let identity = DMatrix::<f64>::identity(n, n);
+    let mut x = m.clone();
+    let mut k = 0;
+    while (&x - &identity).norm() > 0.25 {
+        x = sqrtm(&x)?;
+        k += 1;
+        if k > 40 {
+            return None;
+        }
+    }
+    // log(I+E) = E - E²/2 + E³/3 - ...
+    let e = &x - &identity;
+    let mut term = e.clone();
+    let mut log = e.clone();
+    for i in 2..200 {
+        term = -(&term * &e);
+        let next = &term / i as f64;
+        log += &next;
+        if next.norm() <= 1e-16 * log.norm().max(f64::MIN_POSITIVE) {
+            break;
+        }
+    }
+    Some(log * 2f64.powi(k))
+}
+
+/// Stability margins of a loop
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Margins {
+    /// Smallest gain margin [dB]
+    pub gain_margin: Option<f64>,
+    /// Frequency of the gain margin [Hz]
+    pub phase_crossover: Option<f64>,
+    /// Smallest phase margin [deg]
+    pub phase_margin: Option<f64>,
+    /// Frequency of the phase margin [Hz]
+    pub gain_crossover: Option<f64>,
+}
+impl Margins {
+    /// Computes the margins from the loop transfer function `response` at `frequencies` [Hz]
+    ///
+    /// The loop transfer function follows the negative feedback convention, the loop is closed
+    /// with 1/(1+L), and the crossovers are linearly interpolated in between 2 frequencies
+    pub fn new(frequencies: &[f64], response: &[Complex<f64>]) -> Self {
+        let mut margins = Self::default();
+        // phase of L in ]-360,0] degrees
+        let phase = |l: Complex<f64>| {
+            let phase = l.arg().to_degrees();
+            if phase > 0f64 {
+                phase - 360f64
+            } else {
+                phase
+            }
+        };
+        for (f, l) in frequencies.windows(2).zip(response.windows(2)) {
+            let (g0, g1) = (l[0].norm().log10(), l[1].norm().log10());
+            if g0.signum() != g1.signum() && g0 != g1 {
+                let t = g0 / (g0 - g1);
+                let l = l[0] + (l[1] - l[0]) * t;
+                let phase_margin = 180f64 + phase(l);
+                if margins
+                    .phase_margin
+                    .is_none_or(|pm| phase_margin.abs() < pm.abs())
+                {
+                    margins.phase_margin = Some(phase_margin);
+                    margins.gain_crossover = Some(f[0] + (f[1] - f[0]) * t);
+                }
+            }
+            let (i0, i1) = (l[0].im, l[1].im);
+            if i0.signum() != i1.signum() && i0 != i1 {
+                let t = i0 / (i0 - i1);
+                let l = l[0] + (l[1] - l[0]) * t;
+                if l.re < 0f64 {
+                    let gain_margin = -20f64 * l.norm().log10();
+                    if margins.gain_margin.is_none_or(|gm| gain_margin < gm) {
+                        margins.gain_margin = Some(gain_margin);
+                        margins.phase_crossover = Some(f[0] + (f[1] - f[0]) * t);
+                    }
+                }
+            }
+        }
+        margins
+    }
+}
+impl Display for Margins {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.gain_margin, self.phase_crossover) {
+            (Some(gm), Some(fc)) => write!(f, "gain margin: {gm:.2}dB @ {fc:.3}Hz")?,
+            _ => write!(f, "gain margin: inf")?,
+        }
+        match (self.phase_margin, self.gain_crossover) {
+            (Some(pm), Some(fc)) => write!(f, ", phase margin: {pm:.2}deg @ {fc:.3}Hz"),
+            _ => write!(f, ", phase margin: inf"),
+        }
+    }
+}
+
+/// Returns `n` frequencies [Hz] logarithmically spaced from `f_min` to `f_max`
+pub fn log_frequencies(f_min: f64, f_max: f64, n: usize) -> Vec<f64> {
+    let (l_min, l_max) = (f_min.log10(), f_max.log10());
+    (0..n)
+        .map(|i| 10f64.powf(l_min + (l_max - l_min) * i as f64 / (n.max(2) - 1) as f64))
+        .collect()
+}
+
+/// Discrete state space model of the FEM
+///
+/// The model is the modal model of the FEM discretized with a zero-order hold on the inputs,
+/// as the `DiscreteModalSolver` with `ExponentialMatrix` but without static gain compensation:
+/// the states are the modal coordinates and their velocities, and the outputs are the nodes
+/// displacements y[k] = C x[k], without feedthrough.
+/// The inputs and the outputs are ordered as in the FEM
+pub struct FemModel {
+    fem: FEM,
+    sampling_frequency: f64,
+    damping: Option<f64>,
+    max_eigen_frequency: Option<f64>,
+    ins: Vec<(String, usize)>,
+    outs: Vec<(String, usize)>,
+}
+impl FemModel {
+    /// Creates a new FEM model builder
+    pub fn new(fem: FEM) -> Self {
+        Self {
+            fem,
+            sampling_frequency: 1000f64,
+            damping: None,
+            max_eigen_frequency: None,
+            ins: vec![],
+            outs: vec![],
+        }
+    }
+    /// Sets the sampling frequency [Hz]
+    pub fn sampling(self, sampling_frequency: f64) -> Self {
+        Self {
+            sampling_frequency,
+            ..self
+        }
+    }
+    /// Sets the same damping coefficient to all the modes
+    pub fn proportional_damping(self, damping: f64) -> Self {
+        Self {
+            damping: Some(damping),
+            ..self
+        }
+    }
+    /// Removes the modes above `max_eigen_frequency` [Hz]
+    pub fn max_eigen_frequency(self, max_eigen_frequency: f64) -> Self {
+        Self {
+            max_eigen_frequency: Some(max_eigen_frequency),
+            ..self
+        }
+    }
+    /// Adds the FEM input `U`
+    pub fn ins<U>(mut self) -> Self
+    where
+        Vec<Option<fem_io::Inputs>>: FemIo<U>,
+    {
+        match <Vec<Option<fem_io::Inputs>> as FemIo<U>>::position(&self.fem.inputs) {
+            Some(position) => self.ins.push((uid_name::<U>(), position)),
+            None => log::warn!("FEM input {} not found", uid_name::<U>()),
+        }
+        self
+    }
+    /// Adds the FEM output `U`
+    pub fn outs<U>(mut self) -> Self
+    where
+        Vec<Option<fem_io::Outputs>>: FemIo<U>,
+    {
+        match <Vec<Option<fem_io::Outputs>> as FemIo<U>>::position(&self.fem.outputs) {
+            Some(position) => self.outs.push((uid_name::<U>(), position)),
+            None => log::warn!("FEM output {} not found", uid_name::<U>()),
+        }
+        self
+    }
+    /// Builds the state space model
+    pub fn build(mut self) -> Result<StateSpace> {
+        self.ins.sort_by_key(|(_, position)| *position);
+        self.outs.sort_by_key(|(_, position)| *position);
+        let inputs: Vec<Port> = self
+            .ins
+            .iter()
+            .map(|(name, position)| {
+                let size = self.fem.inputs[*position]
+                    .as_ref()
+                    .map_or(0, |input| input.get_by(|_| Some(())).len());
+                Port::new(name, size)
+            })
+            .collect();
+        let outputs: Vec<Port> = self
+            .outs
+            .iter()
+            .map(|(name, position)| {
+                let size = self.fem.outputs[*position]
+                    .as_ref()
+                    .map_or(0, |output| output.get_by(|_| Some(())).len());
+                Port::new(name, size)
+            })
+            .collect();
+        let in_positions: Vec<usize> = self.ins.iter().map(|(_, position)| *position).collect();
+        let out_positions: Vec<usize> = self.outs.iter().map(|(_, position)| *position).collect();
+        self.fem.keep_inputs(&in_positions);
+        self.fem.keep_outputs(&out_positions);
+        let n_mode = self.fem.n_modes();
+        let forces_2_modes =
+            DMatrix::from_row_slice(n_mode, self.fem.n_inputs(), &self.fem.inputs2modes());
+        let modes_2_nodes =
+            DMatrix::from_row_slice(self.fem.n_outputs(), n_mode, &self.fem.modes2outputs());
+        let damping = match self.damping {
+            Some(damping) => vec![damping; n_mode],
+            None => self.fem.proportional_damping_vec.clone(),
+        };
+        let modes: Vec<usize> = (0..n_mode)
+            .filter(|&i| {
+                self.max_eigen_frequency
+                    .is_none_or(|f_max| self.fem.eigen_frequencies[i] <= f_max)
+            })
+            .collect();
+        let tau = self.sampling_frequency.recip();
+        let n_state = 2 * modes.len();
+        let mut a = DMatrix::<f64>::zeros(n_state, n_state);
+        let mut b = DMatrix::<f64>::zeros(n_state, forces_2_modes.ncols());
+        let mut c = DMatrix::<f64>::zeros(modes_2_nodes.nrows(), n_state);
+        for (k, &i) in modes.iter().enumerate() {
+            let omega = 2f64 * PI * self.fem.eigen_frequencies[i];
+            // zero-order hold discretization of the mode [x, dx/dt] driven by a unit modal force
+            #[rustfmt::skip]
+            let zoh = (na::Matrix3::new(
+                0f64, 1f64, 0f64,
+                -omega * omega, -2f64 * damping[i] * omega, 1f64,
+                0f64, 0f64, 0f64,
+            ) * tau)
+                .exp();
+            a.fixed_slice_mut::<2, 2>(2 * k, 2 * k)
+                .copy_from(&zoh.fixed_slice::<2, 2>(0, 0));
+            let modal_force = forces_2_modes.row(i);
+            b.row_mut(2 * k).copy_from(&(modal_force * zoh[(0, 2)]));
+            b.row_mut(2 * k + 1).copy_from(&(modal_force * zoh[(1, 2)]));
+            c.column_mut(2 * k).copy_from(&modes_2_nodes.column(i));
+        }
+        let d = DMatrix::zeros(c.nrows(), b.ncols());
+        StateSpace::new(a, b, c, d, self.sampling_frequency, inputs, outputs)
+    }
+}
+
+type Input<C> = Box<dyn Fn(&mut C, Vec<f64>)>;
+type Output<C> = Box<dyn Fn(&mut C) -> Option<Vec<f64>>>;
+
+/// Identification of the state space model of a controller
+///
+/// A new client is created for each input channel and the impulse response of the client
+/// to the channel is recorded for `2n_block+1` updates, the inputs being read before the
+/// update and the outputs written after it as in an actor.
+/// The state space model is realized from the impulse responses with the eigensystem
+/// realization algorithm on `n_block`x`n_block` block Hankel matrices, the order of the model
+/// is the number of singular values of the Hankel matrix larger than `tolerance` times the
+/// largest one.
+pub struct Identification<C> {
+    name: String,
+    client: Box<dyn Fn() -> C>,
+    sampling_frequency: f64,
+    n_block: usize,
+    tolerance: f64,
+    amplitude: f64,
+    inputs: Vec<(Port, Input<C>)>,
+    outputs: Vec<(String, Output<C>)>,
+}
+impl<C: Update + 'static> Identification<C> {
+    /// Creates a new identification of the controller `name` with the clients created by `client`
+    pub fn new<F: Fn() -> C + 'static>(name: impl Into<String>, client: F) -> Self {
+        Self {
+            name: name.into(),
+            client: Box::new(client),
+            sampling_frequency: 1000f64,
+            n_block: 20,
+            tolerance: 1e-9,
+            amplitude: 1e-6,
+            inputs: vec![],
+            outputs: vec![],
+        }
+    }
+    /// Sets the sampling frequency of the controller [Hz]
+    pub fn sampling_frequency(self, sampling_frequency: f64) -> Self {
+        Self {
+            sampling_frequency,
+            ..self
+        }
+    }
+    /// Sets the number of blocks of the Hankel matrices, the model has at most
+    /// `n_block` times the number of outputs states
+    pub fn n_block(self, n_block: usize) -> Self {
+        Self {
+            n_block: n_block.max(1),
+            ..self
+        }
+    }
+    /// Sets the relative tolerance on the Hankel singular values
+    pub fn tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+    /// Sets the amplitude of the impulses, small enough for the controller to stay linear
+    pub fn amplitude(self, amplitude: f64) -> Self {
+        Self { amplitude, ..self }
+    }
+    /// Adds the input `U` of `size` channels
+    pub fn input<U>(mut self, size: usize) -> Self
+    where
+        C: Read<Vec<f64>, U>,
+        U: UniqueIdentifier<Data = Vec<f64>> + 'static,
+    {
+        self.inputs.push((
+            Port::new(uid_name::<U>(), size),
+            Box::new(|client: &mut C, u: Vec<f64>| {
+                <C as Read<Vec<f64>, U>>::read(client, Arc::new(Data::new(u)))
+            }),
+        ));
+        self
+    }
+    /// Adds the output `U`
+    pub fn output<U>(mut self) -> Self
+    where
+        C: Write<Vec<f64>, U>,
+        U: UniqueIdentifier<Data = Vec<f64>> + 'static,
+    {
+        self.outputs.push((
+            uid_name::<U>(),
+            Box::new(|client: &mut C| {
+                <C as Write<Vec<f64>, U>>::write(client).map(|data| data.iter().cloned().collect())
+            }),
+        ));
+        self
+    }
+    // Returns the outputs of the client after the impulse on channel `channel`,
+    // one vector per update
+    fn impulse_response(&self, channel: usize, n_step: usize) -> Result<Vec<Vec<Vec<f64>>>> {
+        let mut client = (self.client)();
+        let mut responses = vec![];
+        for step in 0..n_step {
+            let mut first = 0;
+            for (port, read) in &self.inputs {
+                let mut u = vec![0f64; port.size];
+                if step == 0 && (first..first + port.size).contains(&channel) {
+                    u[channel - first] = self.amplitude;
+                }
+                read(&mut client, u);
+                first += port.size;
+            }
+            client.update();
+            responses.push(
+                self.outputs
+                    .iter()
+                    .map(|(name, write)| {
+                        write(&mut client).ok_or_else(|| {
+                            GrimError::Config(format!("{}: no {name} output", self.name))
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            );
+        }
+        Ok(responses)
+    }
+    /// Identifies the state space model of the controller
+    pub fn identify(&self) -> Result<StateSpace> {
+        let n_step = 2 * self.n_block + 1;
+        let n_input: usize = self.inputs.iter().map(|(port, _)| port.size).sum();
+        let mut outputs: Vec<Port> = vec![];
+        // Markov parameters h[k], the response at step k to unit impulses
+        let mut markov: Vec<DMatrix<f64>> = vec![];
+        for channel in 0..n_input {
+            let responses = self.impulse_response(channel, n_step)?;
+            if markov.is_empty() {
+                outputs = self
+                    .outputs
+                    .iter()
+                    .zip(&responses[0])
+                    .map(|((name, _), y)| Port::new(name, y.len()))
+                    .collect();
+                let n_output = outputs.iter().map(|port| port.size).sum();
+                markov = vec![DMatrix::zeros(n_output, n_input); n_step];
+            }
+            for (h, y) in markov.iter_mut().zip(responses) {
+                let y: Vec<f64> = y.concat().iter().map(|y| y / self.amplitude).collect();
+                if y.len() != h.nrows() {
+                    return Err(GrimError::Config(format!(
+                        "{}: the size of the outputs changes from step to step",
+                        self.name
+                    )));
+                }
+                h.column_mut(channel).copy_from_slice(&y);
+            }
+        }
+        let n_output: usize = outputs.iter().map(|port| port.size).sum();
+        if markov.is_empty() {
+            return Err(GrimError::Config(format!("{}: no input", self.name)));
+        }
+        // block Hankel matrices of h[1..] and h[2..]
+        let (p, m, r) = (n_output, n_input, self.n_block);
+        let mut h0 = DMatrix::<f64>::zeros(p * r, m * r);
+        let mut h1 = DMatrix::<f64>::zeros(p * r, m * r);
+        for i in 0..r {
+            for j in 0..r {
+                h0.slice_mut((i * p, j * m), (p, m))
+                    .copy_from(&markov[i + j + 1]);
+                h1.slice_mut((i * p, j * m), (p, m))
+                    .copy_from(&markov[i + j + 2]);
+            }
+        }
+        let svd = h0.svd(true, true);
+        let (Some(u), Some(v_t)) = (svd.u, svd.v_t) else {
+            return Err(GrimError::Config(format!(
+                "{}: the SVD of the Hankel matrix failed",
+                self.name
+            )));
+        };
+        let mut order: Vec<usize> = (0..svd.singular_values.len()).collect();
+        order.sort_by(|&i, &j| svd.singular_values[j].total_cmp(&svd.singular_values[i]));
+        let s_max = order.first().map_or(0f64, |&i| svd.singular_values[i]);
+        let order: Vec<usize> = order
+            .into_iter()
+            .filter(|&i| s_max > 0f64 && svd.singular_values[i] > self.tolerance * s_max)
+            .collect();
+        let n = order.len();
+        if n == r * p.min(m) && r > 1 {
+            log::warn!(
+                "{}: the model order ({n}) is the largest allowed by {} Hankel blocks",
+                self.name,
+                self.n_block
+            );
+        }
+        let sqrt_s = DMatrix::from_diagonal(&na::DVector::from_iterator(
+            n,
+            order.iter().map(|&i| svd.singular_values[i].sqrt()),
+        ));
+        let inv_sqrt_s = sqrt_s.map(|x| if x > 0f64 { x.recip() } else { 0f64 });
+        let u_n = u.select_columns(&order);
+        let v_t_n = v_t.select_rows(&order);
+        let a = &inv_sqrt_s * u_n.transpose() * &h1 * v_t_n.transpose() * &inv_sqrt_s;
+        let b = (&sqrt_s * &v_t_n).columns(0, m).into_owned();
+        let c = (&u_n * &sqrt_s).rows(0, p).into_owned();
+        let d = markov[0].clone();
+        let inputs = self.inputs.iter().map(|(port, _)| port.clone()).collect();
+        let model = StateSpace::new(a, b, c, d, self.sampling_frequency, inputs, outputs)?;
+        log::info!("{}: {} states", self.name, model.n_state());
+        Ok(model)
+    }
+}
+
+/// Closed-loop model
+///
+/// Each input of a model is connected to the output of another model with the same name,
+/// the inputs without a matching output become the inputs of the closed-loop model and
+/// the outputs of all the models are the outputs of the closed-loop model.
+/// The signals declared with [ClosedLoop::delay] are delayed by one sample, as the
+/// bootstrapped outputs of the simulation actors.
+#[derive(Debug, Clone, Default)]
+pub struct ClosedLoop {
+    models: Vec<(String, StateSpace)>,
+    delays: Vec<String>,
+}
+impl ClosedLoop {
+    /// Creates a new empty closed-loop model
+    pub fn new() -> Self {
+        Default::default()
+    }
+    /// Adds the model `name`
+    pub fn model(mut self, name: impl Into<String>, model: StateSpace) -> Self {
+        self.models.push((name.into(), model));
+        self
+    }
+    /// Delays the signal `name` by one sample
+    pub fn delay(mut self, name: impl Into<String>) -> Self {
+        self.delays.push(name.into());
+        self
+    }
+    /// Checks if the signal `name` is delayed by one sample
+    pub fn is_delayed(&self, name: &str) -> bool {
+        self.delays.iter().any(|delay| delay == name)
+    }
+    /// Builds the closed-loop model
+    pub fn build(&self) -> Result<StateSpace> {
+        self.connect(None)
+    }
+    /// Builds the closed-loop model with the loop opened at the signal `name`
+    ///
+    /// The signal `name` is both an input and an output of the model and,
+    /// as the models are connected with positive feedback, the loop transfer function
+    /// in the negative feedback convention of [Margins] is the opposite of the frequency
+    /// response from the input to the output
+    pub fn open_at(&self, name: &str) -> Result<StateSpace> {
+        self.connect(Some(name))
+    }
+    /// Returns the margins of the loop opened at the channel `channel` of the signal `name`
+    pub fn margins(&self, name: &str, channel: usize, frequencies: &[f64]) -> Result<Margins> {
+        let response: Vec<Complex<f64>> = self
+            .open_at(name)?
+            .frequency_response((name, channel), (name, channel), frequencies)?
+            .into_iter()
+            .map(|h| -h)
+            .collect();
+        Ok(Margins::new(frequencies, &response))
+    }
+    fn connect(&self, open: Option<&str>) -> Result<StateSpace> {
+        let Some((_, first)) = self.models.first() else {
+            return Err(GrimError::Config("no model to connect".to_string()));
+        };
+        let sampling_frequency = first.sampling_frequency;
+        if let Some((name, _)) = self
+            .models
+            .iter()
+            .find(|(_, model)| model.sampling_frequency != sampling_frequency)
+        {
+            return Err(GrimError::Config(format!(
+                "{name} is not sampled at {sampling_frequency}Hz"
+            )));
+        }
+        let delayed = |name: &str| format!("{name} (z⁻¹)");
+        let mut models: Vec<&StateSpace> = self.models.iter().map(|(_, model)| model).collect();
+        let delays = self
+            .delays
+            .iter()
+            .map(|name| {
+                let size = models
+                    .iter()
+                    .find_map(|model| model.output_size(name).ok())
+                    .ok_or_else(|| GrimError::Config(format!("delayed signal {name} not found")))?;
+                Ok(StateSpace::delay(
+                    name,
+                    &delayed(name),
+                    size,
+                    sampling_frequency,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let n_model = models.len();
+        models.extend(delays.iter());
+        // block diagonal model of all the models
+        let n_state: usize = models.iter().map(|model| model.n_state()).sum();
+        let n_u: usize = models.iter().map(|model| model.n_input()).sum();
+        let n_y: usize = models.iter().map(|model| model.n_output()).sum();
+        let mut a = DMatrix::<f64>::zeros(n_state, n_state);
+        let mut b = DMatrix::<f64>::zeros(n_state, n_u);
+        let mut c = DMatrix::<f64>::zeros(n_y, n_state);
+        let mut d = DMatrix::<f64>::zeros(n_y, n_u);
+        let mut y_ports: Vec<(usize, &Port, usize)> = vec![];
+        let mut u_ports: Vec<(usize, &Port, usize)> = vec![];
+        let (mut i_x, mut i_u, mut i_y) = (0, 0, 0);
+        for (k, model) in models.iter().enumerate() {
+            let (n_x, m, p) = (model.n_state(), model.n_input(), model.n_output());
+            a.slice_mut((i_x, i_x), (n_x, n_x)).copy_from(&model.a);
+            b.slice_mut((i_x, i_u), (n_x, m)).copy_from(&model.b);
+            c.slice_mut((i_y, i_x), (p, n_x)).copy_from(&model.c);
+            d.slice_mut((i_y, i_u), (p, m)).copy_from(&model.d);
+            let mut first = i_u;
+            for port in &model.inputs {
+                u_ports.push((k, port, first));
+                first += port.size;
+            }
+            let mut first = i_y;
+            for port in &model.outputs {
+                y_ports.push((k, port, first));
+                first += port.size;
+            }
+            i_x += n_x;
+            i_u += m;
+            i_y += p;
+        }
+        // u = M y + E w, with M given by the output channel of each input channel
+        let mut sources: Vec<Option<usize>> = vec![None; n_u];
+        let mut externals: Vec<(Port, Vec<usize>)> = vec![];
+        for (k, port, first) in &u_ports {
+            let is_delay = *k >= n_model;
+            // a delayed signal is opened at the input of its delay
+            let is_delayed = !is_delay && self.delays.contains(&port.name);
+            let source = if is_delayed {
+                delayed(&port.name)
+            } else {
+                port.name.clone()
+            };
+            let is_open = !is_delayed && Some(port.name.as_str()) == open;
+            let producers: Vec<_> = y_ports
+                .iter()
+                .filter(|(j, output, _)| *j != *k && output.name == source)
+                .collect();
+            match producers.as_slice() {
+                [(_, output, y_first)] if !is_open => {
+                    if output.size != port.size {
+                        return Err(GrimError::Config(format!(
+                            "{}: {} outputs connected to {} inputs",
+                            port.name, output.size, port.size
+                        )));
+                    }
+                    for i in 0..port.size {
+                        sources[first + i] = Some(y_first + i);
+                    }
+                }
+                [] | [_] => {
+                    let channels: Vec<usize> = (*first..first + port.size).collect();
+                    match externals
+                        .iter_mut()
+                        .find(|(input, _)| input.name == port.name)
+                    {
+                        Some((input, indices)) if input.size == port.size => {
+                            indices.extend(channels)
+                        }
+                        Some(_) => {
+                            return Err(GrimError::Config(format!(
+                                "input {} has different sizes",
+                                port.name
+                            )))
+                        }
+                        None => externals.push(((*port).clone(), channels)),
+                    }
+                }
+                _ => {
+                    return Err(GrimError::Config(format!(
+                        "{source} is the output of several models"
+                    )))
+                }
+            }
+        }
+        // E maps the external inputs to the inputs of the models
+        let n_w: usize = externals.iter().map(|(port, _)| port.size).sum();
+        let mut e = DMatrix::<f64>::zeros(n_u, n_w);
+        let mut first = 0;
+        for (port, channels) in &externals {
+            for (i, channel) in channels.iter().enumerate() {
+                e[(*channel, first + i % port.size)] = 1f64;
+            }
+            first += port.size;
+        }
+        // M applied to the rows of a matrix
+        let select = |y: &DMatrix<f64>| {
+            let mut u = DMatrix::<f64>::zeros(n_u, y.ncols());
+            for (i, source) in sources.iter().enumerate() {
+                if let Some(j) = source {
+                    u.row_mut(i).copy_from(&y.row(*j));
+                }
+            }
+            u
+        };
+        // (I-MD)⁻¹ X = X + MDX + (MD)²X + ..., the series is finite without algebraic loop
+        let feedthrough = |x: DMatrix<f64>| -> Result<DMatrix<f64>> {
+            let mut sum = x.clone();
+            let mut term = x;
+            for _ in 0..=n_u {
+                term = select(&(&d * &term));
+                if term.iter().all(|x| *x == 0f64) {
+                    return Ok(sum);
+                }
+                sum += &term;
+            }
+            Err(GrimError::Config(
+                "the models are connected in an algebraic loop".to_string(),
+            ))
+        };
+        let u_x = feedthrough(select(&c))?;
+        let u_w = feedthrough(e)?;
+        // the outputs of the delays are not outputs of the closed-loop model
+        let n_y_model: usize = models[..n_model].iter().map(|model| model.n_output()).sum();
+        let c_cl = (&c + &d * &u_x).rows(0, n_y_model).into_owned();
+        let d_cl = (&d * &u_w).rows(0, n_y_model).into_owned();
+        let outputs = models[..n_model]
+            .iter()
+            .flat_map(|model| model.outputs.iter().cloned())
+            .collect();
+        StateSpace::new(
+            &a + &b * &u_x,
+            &b * &u_w,
+            c_cl,
+            d_cl,
+            sampling_frequency,
+            externals.into_iter().map(|(port, _)| port).collect(),
+            outputs,
+        )
+    }
+}
+
+/// Closed-loop model of the integrated model
+///
+/// The FEM is connected to the mount controller, to the M1 hardpoints, load cells and
+/// actuators controllers and to the M2 positioners and piezostacks controllers as in the
+/// simulation, with the same bootstrapped signals.
+/// The inputs of the closed-loop model are the commands of the controllers
+/// (`MountSetPoint`, `M1RBMcmd`, `S<i>SAoffsetFcmd`, `M2poscmd` and `PZTcmd`) and the CFD loads
+/// (`CFD2021106F`).
+///
+/// The M1 actuators controllers are identified at the sampling frequency divided by `m1_rate`
+/// and resampled at the sampling frequency, the sample-and-hold of the rate transitions is
+/// not modeled. If the actuators models cannot be resampled, the M1 actuators loops are left
+/// open and their forces become inputs of the closed-loop model.
+pub struct IntegratedModel {
+    fem: FEM,
+    sampling_frequency: f64,
+    damping: f64,
+    max_eigen_frequency: Option<f64>,
+    m1_rate: usize,
+    n_block: usize,
+    tolerance: f64,
+}
+impl IntegratedModel {
+    /// Creates a new integrated model builder
+    pub fn new(fem: FEM) -> Self {
+        Self {
+            fem,
+            sampling_frequency: 1000f64,
+            damping: 0.02,
+            max_eigen_frequency: None,
+            m1_rate: 10,
+            n_block: 20,
+            tolerance: 1e-9,
+        }
+    }
+    /// Sets the sampling frequency [Hz]
+    pub fn sampling(self, sampling_frequency: f64) -> Self {
+        Self {
+            sampling_frequency,
+            ..self
+        }
+    }
+    /// Sets the same damping coefficient to all the modes of the FEM
+    pub fn proportional_damping(self, damping: f64) -> Self {
+        Self { damping, ..self }
+    }
+    /// Removes the modes of the FEM above `max_eigen_frequency` [Hz]
+    pub fn max_eigen_frequency(self, max_eigen_frequency: f64) -> Self {
+        Self {
+            max_eigen_frequency: Some(max_eigen_frequency),
+            ..self
+        }
+    }
+    /// Sets the ratio of the sampling frequency to the M1 actuators controllers sampling frequency
+    pub fn m1_rate(self, m1_rate: usize) -> Self {
+        Self {
+            m1_rate: m1_rate.max(1),
+            ..self
+        }
+    }
+    /// Sets the number of blocks of the Hankel matrices of the controllers identification
+    pub fn n_block(self, n_block: usize) -> Self {
+        Self { n_block, ..self }
+    }
+    /// Sets the relative tolerance on the Hankel singular values of the controllers identification
+    pub fn tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+    /// Builds the closed-loop model
+    pub fn build(self) -> Result<ClosedLoop> {
+        use dos_actors::clients::{
+            fsm::*,
+            m1::*,
+            mount::{Mount, MountEncoders, MountSetPoint, MountTorques},
+        };
+        use fem_io::*;
+
+        let Self {
+            fem,
+            sampling_frequency: fs,
+            damping,
+            max_eigen_frequency,
+            m1_rate,
+            n_block,
+            tolerance,
+        } = self;
+        let mut fem_model = FemModel::new(fem)
+            .sampling(fs)
+            .proportional_damping(damping);
+        if let Some(max_eigen_frequency) = max_eigen_frequency {
+            fem_model = fem_model.max_eigen_frequency(max_eigen_frequency);
+        }
+        let fem_model = fem_model
+            .ins::<CFD2021106F>()
+            .ins::<OSSElDriveTorque>()
+            .ins::<OSSAzDriveTorque>()
+            .ins::<OSSRotDriveTorque>()
+            .ins::<OSSHarpointDeltaF>()
+            .ins::<M1ActuatorsSegment1>()
+            .ins::<M1ActuatorsSegment2>()
+            .ins::<M1ActuatorsSegment3>()
+            .ins::<M1ActuatorsSegment4>()
+            .ins::<M1ActuatorsSegment5>()
+            .ins::<M1ActuatorsSegment6>()
+            .ins::<M1ActuatorsSegment7>()
+            .ins::<MCM2SmHexF>()
+            .ins::<MCM2PZTF>()
+            .outs::<OSSAzEncoderAngle>()
+            .outs::<OSSElEncoderAngle>()
+            .outs::<OSSRotEncoderAngle>()
+            .outs::<OSSHardpointD>()
+            .outs::<OSSM1Lcl>()
+            .outs::<MCM2Lcl6D>()
+            .outs::<MCM2SmHexD>()
+            .outs::<MCM2PZTD>()
+            .build()?
+            // the mount torques and encoders as read and written by the FEM actor
+            .merge_inputs(
+                &uid_name::<MountTorques>(),
+                &[
+                    &uid_name::<OSSElDriveTorque>(),
+                    &uid_name::<OSSAzDriveTorque>(),
+                    &uid_name::<OSSRotDriveTorque>(),
+                ],
+            )?
+            .merge_outputs(
+                &uid_name::<MountEncoders>(),
+                &[
+                    &uid_name::<OSSElEncoderAngle>(),
+                    &uid_name::<OSSAzEncoderAngle>(),
+                    &uid_name::<OSSRotEncoderAngle>(),
+                ],
+            )?;
+        log::info!("FEM: {} states", fem_model.n_state());
+
+        let mount = Identification::new("Mount Control", Mount::new)
+            .sampling_frequency(fs)
+            .n_block(n_block)
+            .tolerance(tolerance)
+            .input::<MountSetPoint>(3)
+            .input::<MountEncoders>(fem_model.output_size(&uid_name::<MountEncoders>())?)
+            .output::<MountTorques>()
+            .identify()?;
+        let m1_hardpoints =
+            Identification::new("M1 Hardpoints", m1_ctrl::hp_dynamics::Controller::new)
+                .sampling_frequency(fs)
+                .n_block(n_block)
+                .tolerance(tolerance)
+                .input::<M1RBMcmd>(42)
+                .output::<OSSHarpointDeltaF>()
+                .identify()?;
+        let m1_hp_loadcells =
+            Identification::new("M1 LoadCells", m1_ctrl::hp_load_cells::Controller::new)
+                .sampling_frequency(fs)
+                .n_block(n_block)
+                .tolerance(tolerance)
+                .input::<OSSHardpointD>(fem_model.output_size(&uid_name::<OSSHardpointD>())?)
+                .input::<OSSHarpointDeltaF>(fem_model.input_size(&uid_name::<OSSHarpointDeltaF>())?)
+                .output::<S1HPLC>()
+                .output::<S2HPLC>()
+                .output::<S3HPLC>()
+                .output::<S4HPLC>()
+                .output::<S5HPLC>()
+                .output::<S6HPLC>()
+                .output::<S7HPLC>()
+                .identify()?;
+        let m2_positionner =
+            Identification::new("M2 Positionners", fsm::positionner::Controller::new)
+                .sampling_frequency(fs)
+                .n_block(n_block)
+                .tolerance(tolerance)
+                .input::<M2poscmd>(42)
+                .input::<MCM2SmHexD>(fem_model.output_size(&uid_name::<MCM2SmHexD>())?)
+                .output::<MCM2SmHexF>()
+                .identify()?;
+        let m2_piezostack =
+            Identification::new("M2 PZT Actuators", fsm::piezostack::Controller::new)
+                .sampling_frequency(fs)
+                .n_block(n_block)
+                .tolerance(tolerance)
+                .input::<PZTcmd>(21)
+                .input::<MCM2PZTD>(fem_model.output_size(&uid_name::<MCM2PZTD>())?)
+                .output::<MCM2PZTF>()
+                .identify()?;
+
+        let m1_fs = fs / m1_rate as f64;
+        macro_rules! segment {
+            ($name:expr, $controller:path, $hplc:ty, $offset:ty, $n_actuator:expr, $actuators:ty) => {
+                Identification::new($name, $controller)
+                    .sampling_frequency(m1_fs)
+                    .n_block(n_block)
+                    .tolerance(tolerance)
+                    .input::<$hplc>(m1_hp_loadcells.output_size(&uid_name::<$hplc>())?)
+                    .input::<$offset>($n_actuator)
+                    .output::<$actuators>()
+                    .identify()
+                    .and_then(|model| {
+                        if m1_rate > 1 {
+                            model.resample(fs)
+                        } else {
+                            Ok(model)
+                        }
+                    })
+            };
+        }
+        let m1_segments = [
+            segment!(
+                "M1S1 Actuators",
+                m1_ctrl::actuators::segment1::Controller::new,
+                S1HPLC,
+                S1SAoffsetFcmd,
+                335,
+                M1ActuatorsSegment1
+            ),
+            segment!(
+                "M1S2 Actuators",
+                m1_ctrl::actuators::segment2::Controller::new,
+                S2HPLC,
+                S2SAoffsetFcmd,
+                335,
+                M1ActuatorsSegment2
+            ),
+            segment!(
+                "M1S3 Actuators",
+                m1_ctrl::actuators::segment3::Controller::new,
+                S3HPLC,
+                S3SAoffsetFcmd,
+                335,
+                M1ActuatorsSegment3
+            ),
+            segment!(
+                "M1S4 Actuators",
+                m1_ctrl::actuators::segment4::Controller::new,
+                S4HPLC,
+                S4SAoffsetFcmd,
+                335,
+                M1ActuatorsSegment4
+            ),
+            segment!(
+                "M1S5 Actuators",
+                m1_ctrl::actuators::segment5::Controller::new,
+                S5HPLC,
+                S5SAoffsetFcmd,
+                335,
+                M1ActuatorsSegment5
+            ),
+            segment!(
+                "M1S6 Actuators",
+                m1_ctrl::actuators::segment6::Controller::new,
+                S6HPLC,
+                S6SAoffsetFcmd,
+                335,
+                M1ActuatorsSegment6
+            ),
+            segment!(
+                "M1S7 Actuators",
+                m1_ctrl::actuators::segment7::Controller::new,
+                S7HPLC,
+                S7SAoffsetFcmd,
+                306,
+                M1ActuatorsSegment7
+            ),
+        ];
+
+        let mut closed_loop = ClosedLoop::new()
+            .model("GMT Finite Element Model", fem_model)
+            .model("Mount Control", mount)
+            .model("M1 Hardpoints", m1_hardpoints)
+            .model("M1 LoadCells", m1_hp_loadcells)
+            .model("M2 Positionners", m2_positionner)
+            .model("M2 PZT Actuators", m2_piezostack)
+            .delay(uid_name::<MountEncoders>())
+            .delay(uid_name::<OSSHardpointD>())
+            .delay(uid_name::<MCM2SmHexD>())
+            .delay(uid_name::<MCM2PZTD>());
+        for (i, segment) in m1_segments.into_iter().enumerate() {
+            match segment {
+                Ok(model) => {
+                    closed_loop = closed_loop
+                        .model(format!("M1S{} Actuators", i + 1), model)
+                        .delay(format!("S{}HPLC", i + 1));
+                }
+                Err(e) => log::warn!("M1S{} actuators loop left open: {e}", i + 1),
+            }
+        }
+        Ok(closed_loop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dos_actors::prelude::*;
+
+    // zero-order hold discretization of the continuous model (ac, bc) at `sampling_frequency`
+    fn zoh(ac: &DMatrix<f64>, bc: &DMatrix<f64>, sampling_frequency: f64) -> StateSpace {
+        let (n, m) = (ac.nrows(), bc.ncols());
+        let mut x = DMatrix::<f64>::zeros(n + m, n + m);
+        x.slice_mut((0, 0), (n, n)).copy_from(ac);
+        x.slice_mut((0, n), (n, m)).copy_from(bc);
+        let x = (x / sampling_frequency).exp();
+        StateSpace::new(
+            x.slice((0, 0), (n, n)).into_owned(),
+            x.slice((0, n), (n, m)).into_owned(),
+            DMatrix::from_fn(1, n, |_, j| if j == 0 { 1f64 } else { 0f64 }),
+            DMatrix::zeros(1, m),
+            sampling_frequency,
+            vec![Port::new("U", m)],
+            vec![Port::new("Y", 1)],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn resample() {
+        let (omega, zeta) = (2f64 * PI * 12f64, 0.05);
+        #[rustfmt::skip]
+        let ac = DMatrix::from_row_slice(2, 2, &[
+            0f64, 1f64,
+            -omega * omega, -2f64 * zeta * omega,
+        ]);
+        let bc = DMatrix::from_row_slice(2, 1, &[0f64, 1f64]);
+        let slow = zoh(&ac, &bc, 100f64);
+        let fast = zoh(&ac, &bc, 1000f64);
+        for (model, exact) in [(&slow, &fast), (&fast, &slow)] {
+            let resampled = model.resample(exact.sampling_frequency).unwrap();
+            assert_eq!(resampled.sampling_frequency, exact.sampling_frequency);
+            assert!((&resampled.a - &exact.a).norm() < 1e-9 * exact.a.norm());
+            assert!((&resampled.b - &exact.b).norm() < 1e-9 * exact.b.norm());
+            assert_eq!(resampled.c, exact.c);
+        }
+        // a pole at the origin
+        let delay = StateSpace::delay("U", "Y", 1, 100f64);
+        assert!(delay.resample(1000f64).is_err());
+    }
+
+    #[test]
+    fn frequency_response() {
+        #[rustfmt::skip]
+        let a = DMatrix::from_row_slice(3, 3, &[
+            0.5, 0.1, 0.0,
+            -0.2, 0.3, 0.4,
+            0.0, 0.1, -0.6,
+        ]);
+        let b = DMatrix::from_row_slice(3, 2, &[1.0, 0.0, 0.5, -1.0, 0.0, 2.0]);
+        let c = DMatrix::from_row_slice(2, 3, &[1.0, 0.0, 1.0, 0.0, -1.0, 0.5]);
+        let d = DMatrix::from_row_slice(2, 2, &[0.0, 0.1, 0.2, 0.3]);
+        let fs = 1000f64;
+        let model = StateSpace::new(
+            a.clone(),
+            b.clone(),
+            c.clone(),
+            d.clone(),
+            fs,
+            vec![Port::new("U", 1), Port::new("V", 1)],
+            vec![Port::new("Y", 2)],
+        )
+        .unwrap();
+        let frequencies = log_frequencies(0.1, 499.0, 50);
+        for (input, j) in [(("U", 0), 0), (("V", 0), 1)] {
+            for i in 0..2 {
+                let response = model
+                    .frequency_response(input, ("Y", i), &frequencies)
+                    .unwrap();
+                for (f, h) in frequencies.iter().zip(response) {
+                    let z = Complex::new(0f64, 2f64 * PI * f / fs).exp();
+                    let resolvent = (DMatrix::<Complex<f64>>::identity(3, 3) * z
+                        - a.map(|x| Complex::new(x, 0f64)))
+                    .try_inverse()
+                    .unwrap();
+                    let expected = (c.map(|x| Complex::new(x, 0f64))
+                        * resolvent
+                        * b.map(|x| Complex::new(x, 0f64)))[(i, j)]
+                        + d[(i, j)];
+                    assert!((h - expected).norm() < 1e-12 * expected.norm().max(1f64));
+                }
+            }
+        }
+        assert!(model
+            .frequency_response(("U", 1), ("Y", 0), &frequencies)
+            .is_err());
+        assert!(model
+            .frequency_response(("W", 0), ("Y", 0), &frequencies)
+            .is_err());
+    }
+
+    #[derive(UID)]
+    #[uid(data = "Vec<f64>")]
+    enum FilterIn {}
+    #[derive(UID)]
+    #[uid(data = "Vec<f64>")]
+    enum FilterOut {}
+
+    // x[k+1] = a x[k] + b u[k], y[k] = c x[k] + d u[k]
+    struct Filter {
+        x: f64,
+        u: f64,
+        y: f64,
+    }
+    impl Filter {
+        const A: f64 = 0.9;
+        const B: f64 = 0.5;
+        const C: f64 = 2.0;
+        const D: f64 = 0.1;
+        fn new() -> Self {
+            Self {
+                x: 0f64,
+                u: 0f64,
+                y: 0f64,
+            }
+        }
+    }
+    impl Update for Filter {
+        fn update(&mut self) {
+            self.y = Self::C * self.x + Self::D * self.u;
+            self.x = Self::A * self.x + Self::B * self.u;
+        }
+    }
+    impl Read<Vec<f64>, FilterIn> for Filter {
+        fn read(&mut self, data: Arc<Data<FilterIn>>) {
+            self.u = data[0];
+        }
+    }
+    impl Write<Vec<f64>, FilterOut> for Filter {
+        fn write(&mut self) -> Option<Arc<Data<FilterOut>>> {
+            Some(Arc::new(Data::new(vec![self.y])))
+        }
+    }
+
+    #[test]
+    fn identification() {
+        let model = Identification::new("Filter", Filter::new)
+            .sampling_frequency(100f64)
+            .n_block(5)
+            .input::<FilterIn>(1)
+            .output::<FilterOut>()
+            .identify()
+            .unwrap();
+        assert_eq!(model.n_state(), 1);
+        assert_eq!(model.inputs, vec![Port::new("FilterIn", 1)]);
+        assert_eq!(model.outputs, vec![Port::new("FilterOut", 1)]);
+        assert!((model.a[(0, 0)] - Filter::A).abs() < 1e-9);
+        assert!((model.c[(0, 0)] * model.b[(0, 0)] - Filter::C * Filter::B).abs() < 1e-9);
+        assert!((model.d[(0, 0)] - Filter::D).abs() < 1e-9);
+    }
+
+    // a static gain from the input `input` to the output `output`
+    fn gain(input: &[&str], output: &str, gain: &[f64]) -> StateSpace {
+        StateSpace::new(
+            DMatrix::zeros(0, 0),
+            DMatrix::zeros(0, input.len()),
+            DMatrix::zeros(1, 0),
+            DMatrix::from_row_slice(1, gain.len(), gain),
+            1000f64,
+            input.iter().map(|name| Port::new(*name, 1)).collect(),
+            vec![Port::new(output, 1)],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn connect() {
+        // e = r - k y, y = g e
+        let (g, k) = (1.5, 0.5);
+        let closed_loop = ClosedLoop::new()
+            .model("G", gain(&["E"], "Y", &[g]))
+            .model("K", gain(&["R", "Y"], "E", &[1f64, -k]));
+        assert!(closed_loop.build().is_err());
+        // with y delayed by one sample: x[k+1] = y[k] = g (r[k] - k x[k])
+        let model = closed_loop.delay("Y").build().unwrap();
+        assert_eq!(model.inputs, vec![Port::new("R", 1)]);
+        assert_eq!(model.outputs, vec![Port::new("Y", 1), Port::new("E", 1)]);
+        assert_eq!(model.n_state(), 1);
+        assert!((model.a[(0, 0)] + g * k).abs() < 1e-12);
+        assert!((model.b[(0, 0)] - g).abs() < 1e-12);
+        assert!((model.c[(0, 0)] + g * k).abs() < 1e-12);
+        assert!((model.d[(0, 0)] - g).abs() < 1e-12);
+        assert!(model.is_stable());
+        // static gain g/(1+gk)
+        let dc = model
+            .frequency_response(("R", 0), ("Y", 0), &[0f64])
+            .unwrap();
+        assert!((dc[0] - g / (1f64 + g * k)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn margins() {
+        // L(z) = k/(z(z-1)) with the phase -90-1.5ωT [deg] and the gain k/(2sin(ωT/2))
+        let (k, fs) = (0.5f64, 1000f64);
+        let frequencies: Vec<f64> = (1..5000).map(|i| i as f64 * 0.1).collect();
+        let gain_margin = -20f64 * k.log10();
+        let phase_crossover = fs / 6f64;
+        let omega = 2f64 * (k / 2f64).asin();
+        let phase_margin = 90f64 - 1.5 * omega.to_degrees();
+        let gain_crossover = omega * fs / (2f64 * PI);
+        let check = |margins: Margins| {
+            assert!((margins.gain_margin.unwrap() - gain_margin).abs() < 1e-3);
+            assert!((margins.phase_crossover.unwrap() - phase_crossover).abs() < 1e-2);
+            assert!((margins.phase_margin.unwrap() - phase_margin).abs() < 1e-2);
+            assert!((margins.gain_crossover.unwrap() - gain_crossover).abs() < 1e-2);
+        };
+        let response: Vec<Complex<f64>> = frequencies
+            .iter()
+            .map(|f| {
+                let z = Complex::new(0f64, 2f64 * PI * f / fs).exp();
+                k / (z * (z - 1f64))
+            })
+            .collect();
+        check(Margins::new(&frequencies, &response));
+        // the same loop from an integrator, a delay and a gain in negative feedback
+        let integrator = StateSpace::new(
+            DMatrix::identity(1, 1),
+            DMatrix::identity(1, 1),
+            DMatrix::identity(1, 1),
+            DMatrix::zeros(1, 1),
+            fs,
+            vec![Port::new("U", 1)],
+            vec![Port::new("Y", 1)],
+        )
+        .unwrap();
+        let closed_loop = ClosedLoop::new()
+            .model("P", integrator)
+            .model("K", gain(&["Y"], "U", &[-k]))
+            .delay("Y");
+        check(closed_loop.margins("U", 0, &frequencies).unwrap());
+        assert!(closed_loop.build().unwrap().is_stable());
+    }
+}
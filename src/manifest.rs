@@ -0,0 +1,88 @@
+//! Run manifest
+//!
+//! The manifest is saved in the file `manifest.json` in the `DATA_REPO` directory.
+//! It records the simulation parameters and whether the simulation ran to completion.
+
+use crate::{
+    config::{Disturbances, Injection, WindLoads},
+    GrimError, Result,
+};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, path::Path};
+
+/// Simulation status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    Running,
+    Completed,
+    /// The simulation was interrupted before the end
+    Truncated,
+}
+
+/// Run manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub start: DateTime<Local>,
+    pub end: Option<DateTime<Local>>,
+    /// Simulation sampling frequency [Hz]
+    pub sampling_frequency: usize,
+    /// Requested simulation duration [s]
+    pub duration: f64,
+    /// Duration of the warm-up before the closed loop [s]
+    #[serde(default)]
+    pub warm_up: f64,
+    pub status: Status,
+    /// Time of the last completed simulation step [s]
+    pub last_time: Option<f64>,
+    /// Optical disturbances
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disturbances: Option<Disturbances>,
+    /// Wind loads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wind_loads: Option<WindLoads>,
+    /// Excitation of a frequency response measurement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub injection: Option<Injection>,
+}
+impl Manifest {
+    /// Creates a new manifest for a simulation sampled at `sampling_frequency` and lasting `duration` seconds
+    pub fn new(sampling_frequency: usize, duration: f64) -> Self {
+        Self {
+            start: Local::now(),
+            end: None,
+            sampling_frequency,
+            duration,
+            warm_up: 0f64,
+            status: Status::Running,
+            last_time: None,
+            disturbances: None,
+            wind_loads: None,
+            injection: None,
+        }
+    }
+    /// Loads the manifest from the directory `data_repo`
+    pub fn load<P: AsRef<Path>>(data_repo: P) -> Result<Self> {
+        let path = data_repo.as_ref().join("manifest.json");
+        let file = File::open(&path).map_err(|e| GrimError::Io(e, path))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+    /// Saves the manifest in the `DATA_REPO` directory
+    pub fn save(&self) -> Result<()> {
+        let path = crate::data_repo().join("manifest.json");
+        let file = File::create(&path).map_err(|e| GrimError::Io(e, path))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+    /// Sets the simulation status after the last simulation step at `last_time`
+    pub fn end(&mut self, last_time: f64, truncated: bool) -> &mut Self {
+        self.end = Some(Local::now());
+        self.last_time = Some(last_time);
+        self.status = if truncated {
+            Status::Truncated
+        } else {
+            Status::Completed
+        };
+        self
+    }
+}
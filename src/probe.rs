@@ -0,0 +1,180 @@
+//! Actor probes
+//!
+//! A [Probe] wraps an actor client and counts the number of times the client is updated.
+//! The probes are created from and registered into a [Probes] registry.
+//! If the registry is created with [Probes::with_profiling], the probes also record the
+//! time spent by the actors in compute and waiting on their inputs and outputs
+//! (see [profiler](crate::profiler)).
+
+use crate::profiler::{ActorProfile, Profile, ProfileReport, Timer};
+use dos_actors::{
+    io::{Data, Read, Write},
+    UniqueIdentifier, Update,
+};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// Probe statistics
+#[derive(Debug)]
+pub struct ProbeStats {
+    name: String,
+    step: AtomicUsize,
+    profile: Option<Mutex<Profile>>,
+}
+impl ProbeStats {
+    /// Returns the name of the probed actor
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Returns the number of updates of the probed actor
+    pub fn step(&self) -> usize {
+        self.step.load(Ordering::Relaxed)
+    }
+    /// Returns the actor profile if profiling is enabled
+    pub fn profile(&self) -> Option<ActorProfile> {
+        self.profile.as_ref().map(|profile| {
+            let profile = profile.lock().unwrap();
+            ActorProfile {
+                name: self.name.clone(),
+                steps: self.step(),
+                compute: profile.compute.stats(),
+                inputs: profile.inputs.stats(),
+                outputs: profile.outputs.stats(),
+                input_uids: profile.input_uids.clone(),
+                output_uids: profile.output_uids.clone(),
+            }
+        })
+    }
+}
+
+/// Registry of probes
+#[derive(Debug, Clone)]
+pub struct Probes {
+    stats: Arc<Mutex<Vec<Arc<ProbeStats>>>>,
+    profiling: bool,
+    start: Instant,
+}
+impl Default for Probes {
+    fn default() -> Self {
+        Self {
+            stats: Default::default(),
+            profiling: false,
+            start: Instant::now(),
+        }
+    }
+}
+impl Probes {
+    /// Creates a registry of probes that profile the actors
+    pub fn with_profiling() -> Self {
+        Self {
+            profiling: true,
+            ..Default::default()
+        }
+    }
+    /// Wraps `client` into a [Probe] named `name`
+    pub fn probe<C, S: Into<String>>(&self, name: S, client: C) -> Probe<C> {
+        let stats = Arc::new(ProbeStats {
+            name: name.into(),
+            step: AtomicUsize::new(0),
+            profile: self.profiling.then(Default::default),
+        });
+        self.stats.lock().unwrap().push(stats.clone());
+        Probe {
+            client,
+            stats,
+            timer: self.profiling.then(Default::default),
+        }
+    }
+    /// Returns the statistics of all the probes
+    pub fn stats(&self) -> Vec<Arc<ProbeStats>> {
+        self.stats.lock().unwrap().clone()
+    }
+    /// Returns the statistics of the probe `name`
+    pub fn get(&self, name: &str) -> Option<Arc<ProbeStats>> {
+        self.stats
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|stats| stats.name == name)
+            .cloned()
+    }
+    /// Returns the profiling report if profiling is enabled
+    pub fn report(&self) -> Option<ProfileReport> {
+        self.profiling.then(|| {
+            ProfileReport::new(
+                self.start.elapsed().as_secs_f64(),
+                self.stats()
+                    .iter()
+                    .filter_map(|stats| stats.profile())
+                    .collect(),
+            )
+        })
+    }
+}
+
+/// Client probe
+pub struct Probe<C> {
+    client: C,
+    stats: Arc<ProbeStats>,
+    timer: Option<Timer>,
+}
+impl<C> Probe<C> {
+    /// Returns the probe statistics
+    pub fn stats(&self) -> Arc<ProbeStats> {
+        self.stats.clone()
+    }
+}
+impl<C> Deref for Probe<C> {
+    type Target = C;
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+impl<C> DerefMut for Probe<C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client
+    }
+}
+impl<C: Update> Update for Probe<C> {
+    fn update(&mut self) {
+        match (self.timer.as_mut(), self.stats.profile.as_ref()) {
+            (Some(timer), Some(profile)) => {
+                let client = &mut self.client;
+                timer.update(&mut profile.lock().unwrap(), || client.update());
+            }
+            _ => self.client.update(),
+        }
+        self.stats.step.fetch_add(1, Ordering::Relaxed);
+    }
+}
+impl<T, U, C> Read<T, U> for Probe<C>
+where
+    U: UniqueIdentifier<Data = T>,
+    C: Read<T, U>,
+{
+    fn read(&mut self, data: Arc<Data<U>>) {
+        self.client.read(data);
+        if let Some(timer) = self.timer.as_mut() {
+            timer.read::<U>();
+        }
+    }
+}
+impl<T, U, C> Write<T, U> for Probe<C>
+where
+    U: UniqueIdentifier<Data = T>,
+    C: Write<T, U>,
+{
+    fn write(&mut self) -> Option<Arc<Data<U>>> {
+        let data = self.client.write();
+        if let Some(timer) = self.timer.as_mut() {
+            timer.write::<U>();
+        }
+        data
+    }
+}
@@ -0,0 +1,460 @@
+//! Actor profiler
+//!
+//! When profiling is enabled with [Probes::with_profiling](crate::probe::Probes::with_profiling),
+//! each [Probe](crate::probe::Probe) records, at every step of the probed actor:
+//!  - the compute time: the time spent in the client `update` method,
+//!  - the input time: the time from the end of the previous step to the reading of the last input,
+//!    including the time waiting for the inputs to be available,
+//!  - the output time: the time from the end of the `update` method to the writing of the last output,
+//!    including the time waiting for the outputs to be sent.
+//!
+//! The time to send the last output of a step cannot be observed from the client and is
+//! included in the input time of the next step.
+//!
+//! The [ProfileReport] gives the totals and the percentiles of these times for each actor and
+//! the critical path, i.e. the chain of connected actors with the largest total compute time.
+//! The actors of a feedback loop are updated one after the other at each step, so each feedback
+//! loop (strongly connected component of the actors graph) is one link of the chain weighted by
+//! the compute time of all its actors.
+
+use crate::{GrimError, Result};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    fs::File,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const BINS_PER_DECADE: usize = 20;
+const N_DECADE: usize = 9;
+const MIN_DURATION: f64 = 1e-6; // s
+
+/// Histogram of durations with logarithmic bins from 1µs to 1000s
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bins: Vec<usize>,
+    count: usize,
+    total: f64,
+    max: f64,
+}
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            bins: vec![0; BINS_PER_DECADE * N_DECADE],
+            count: 0,
+            total: 0f64,
+            max: 0f64,
+        }
+    }
+}
+impl Histogram {
+    /// Adds a duration to the histogram
+    pub fn add(&mut self, duration: Duration) {
+        let d = duration.as_secs_f64();
+        let idx = ((d.max(MIN_DURATION) / MIN_DURATION).log10() * BINS_PER_DECADE as f64) as usize;
+        let n_bin = self.bins.len();
+        self.bins[idx.min(n_bin - 1)] += 1;
+        self.count += 1;
+        self.total += d;
+        self.max = self.max.max(d);
+    }
+    /// Returns the upper edge of the bin that contains the `q` quantile
+    pub fn quantile(&self, q: f64) -> f64 {
+        let rank = (q * self.count as f64).ceil() as usize;
+        let mut n = 0;
+        for (i, bin) in self.bins.iter().enumerate() {
+            n += bin;
+            if n >= rank.max(1) {
+                let edge = MIN_DURATION * 10f64.powf((i + 1) as f64 / BINS_PER_DECADE as f64);
+                return edge.min(self.max);
+            }
+        }
+        self.max
+    }
+    /// Returns the histogram statistics
+    pub fn stats(&self) -> Stats {
+        Stats {
+            total: self.total,
+            mean: if self.count > 0 {
+                self.total / self.count as f64
+            } else {
+                0f64
+            },
+            p50: self.quantile(0.5),
+            p90: self.quantile(0.9),
+            p99: self.quantile(0.99),
+            max: self.max,
+        }
+    }
+}
+
+/// Duration statistics [s]
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub total: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// Actor profile
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub compute: Histogram,
+    pub inputs: Histogram,
+    pub outputs: Histogram,
+    pub input_uids: BTreeSet<String>,
+    pub output_uids: BTreeSet<String>,
+}
+
+/// Step timer of a probe
+#[derive(Debug)]
+pub struct Timer {
+    step_end: Instant,
+    update_end: Option<Instant>,
+    last_read: Option<Instant>,
+    last_write: Option<Instant>,
+    inputs: HashSet<&'static str>,
+    outputs: HashSet<&'static str>,
+}
+impl Default for Timer {
+    fn default() -> Self {
+        Self {
+            step_end: Instant::now(),
+            update_end: None,
+            last_read: None,
+            last_write: None,
+            inputs: HashSet::new(),
+            outputs: HashSet::new(),
+        }
+    }
+}
+impl Timer {
+    /// Records the reading of input `U`
+    pub fn read<U>(&mut self) {
+        self.inputs.insert(std::any::type_name::<U>());
+        self.last_read = Some(Instant::now());
+    }
+    /// Records the writing of output `U`
+    pub fn write<U>(&mut self) {
+        self.outputs.insert(std::any::type_name::<U>());
+        self.last_write = Some(Instant::now());
+    }
+    /// Times the client `update` and records the step durations into `profile`
+    pub fn update<F: FnOnce()>(&mut self, profile: &mut Profile, update: F) {
+        // outputs of the previous step
+        if let (Some(update_end), Some(last_write)) = (self.update_end, self.last_write) {
+            if last_write > update_end {
+                profile.outputs.add(last_write - update_end);
+                self.step_end = last_write;
+            }
+        }
+        if let Some(last_read) = self.last_read.take() {
+            profile
+                .inputs
+                .add(last_read.saturating_duration_since(self.step_end));
+        }
+        let now = Instant::now();
+        update();
+        let update_end = Instant::now();
+        profile.compute.add(update_end - now);
+        self.update_end = Some(update_end);
+        self.step_end = update_end;
+        if profile.input_uids.len() < self.inputs.len() {
+            profile.input_uids = self.inputs.iter().map(|uid| short(uid)).collect();
+        }
+        if profile.output_uids.len() < self.outputs.len() {
+            profile.output_uids = self.outputs.iter().map(|uid| short(uid)).collect();
+        }
+    }
+}
+fn short(type_name: &str) -> String {
+    type_name
+        .rsplit("::")
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Profile of an actor
+#[derive(Debug, Clone, Serialize)]
+pub struct ActorProfile {
+    pub name: String,
+    pub steps: usize,
+    pub compute: Stats,
+    pub inputs: Stats,
+    pub outputs: Stats,
+    pub input_uids: BTreeSet<String>,
+    pub output_uids: BTreeSet<String>,
+}
+
+/// Profiling report
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileReport {
+    /// Wall clock time since the start of the profiling [s]
+    pub elapsed: f64,
+    pub actors: Vec<ActorProfile>,
+    /// Chain of connected actors with the largest total compute time,
+    /// the actors of a feedback loop are given as `(A + B + ...)`
+    pub critical_path: Vec<String>,
+    /// Total compute time of the critical path [s]
+    pub critical_path_compute: f64,
+}
+impl ProfileReport {
+    /// Creates a new report from the actors profile
+    pub fn new(elapsed: f64, actors: Vec<ActorProfile>) -> Self {
+        let (critical_path, critical_path_compute) = critical_path(&actors);
+        Self {
+            elapsed,
+            critical_path: critical_path
+                .into_iter()
+                .map(|component| match component.as_slice() {
+                    [i] => actors[*i].name.clone(),
+                    _ => format!(
+                        "({})",
+                        component
+                            .iter()
+                            .map(|i| actors[*i].name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" + ")
+                    ),
+                })
+                .collect(),
+            critical_path_compute,
+            actors,
+        }
+    }
+    /// Saves the report in the JSON file `path`
+    pub fn to_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+// Strongly connected components of the graph `edges` in reverse topological order (Tarjan algorithm)
+fn strongly_connected_components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct Tarjan<'a> {
+        edges: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+    impl Tarjan<'_> {
+        fn visit(&mut self, v: usize) {
+            self.index[v] = Some(self.next);
+            self.low[v] = self.next;
+            self.next += 1;
+            self.stack.push(v);
+            self.on_stack[v] = true;
+            let edges = self.edges;
+            for &w in &edges[v] {
+                match self.index[w] {
+                    None => {
+                        self.visit(w);
+                        self.low[v] = self.low[v].min(self.low[w]);
+                    }
+                    Some(index) if self.on_stack[w] => self.low[v] = self.low[v].min(index),
+                    _ => (),
+                }
+            }
+            if self.index[v] == Some(self.low[v]) {
+                let mut component = vec![];
+                while let Some(w) = self.stack.pop() {
+                    self.on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                self.components.push(component);
+            }
+        }
+    }
+    let n = edges.len();
+    let mut tarjan = Tarjan {
+        edges,
+        index: vec![None; n],
+        low: vec![0; n],
+        on_stack: vec![false; n],
+        stack: vec![],
+        next: 0,
+        components: vec![],
+    };
+    for v in 0..n {
+        if tarjan.index[v].is_none() {
+            tarjan.visit(v);
+        }
+    }
+    tarjan.components
+}
+
+// Longest path, weighted by the actors total compute time, in the graph of actors connected by
+// their inputs and outputs with the feedback loops collapsed into single nodes
+fn critical_path(actors: &[ActorProfile]) -> (Vec<Vec<usize>>, f64) {
+    // the actors reading each input
+    let mut consumers: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (j, actor) in actors.iter().enumerate() {
+        for uid in &actor.input_uids {
+            consumers.entry(uid.as_str()).or_default().push(j);
+        }
+    }
+    let edges: Vec<Vec<usize>> = actors
+        .iter()
+        .enumerate()
+        .map(|(i, actor)| {
+            actor
+                .output_uids
+                .iter()
+                .filter_map(|uid| consumers.get(uid.as_str()))
+                .flatten()
+                .copied()
+                .filter(|&j| j != i)
+                .collect::<BTreeSet<usize>>()
+                .into_iter()
+                .collect()
+        })
+        .collect();
+    let components = strongly_connected_components(&edges);
+    let mut component_of = vec![0; actors.len()];
+    for (c, component) in components.iter().enumerate() {
+        component.iter().for_each(|&i| component_of[i] = c);
+    }
+    // the successors of a component come before it in reverse topological order
+    let mut longest = vec![0f64; components.len()];
+    let mut next: Vec<Option<usize>> = vec![None; components.len()];
+    for (c, component) in components.iter().enumerate() {
+        let weight: f64 = component.iter().map(|&i| actors[i].compute.total).sum();
+        let successor = component
+            .iter()
+            .flat_map(|&i| edges[i].iter().map(|&j| component_of[j]))
+            .filter(|&d| d != c)
+            .max_by(|&a, &b| longest[a].total_cmp(&longest[b]));
+        longest[c] = weight + successor.map_or(0f64, |d| longest[d]);
+        next[c] = successor;
+    }
+    let Some(mut c) = (0..components.len()).max_by(|&a, &b| longest[a].total_cmp(&longest[b]))
+    else {
+        return (Vec::new(), 0f64);
+    };
+    let compute = longest[c];
+    let mut path = vec![components[c].clone()];
+    while let Some(d) = next[c] {
+        path.push(components[d].clone());
+        c = d;
+    }
+    (path, compute)
+}
+
+impl Display for ProfileReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Profile ({:.3}s):", self.elapsed)?;
+        writeln!(
+            f,
+            " {:<28} {:>9} {:>10} {:>9} {:>9} {:>9} {:>10} {:>10}",
+            "ACTOR", "STEPS", "COMPUTE", "P50", "P90", "P99", "INPUTS", "OUTPUTS"
+        )?;
+        let mut actors: Vec<_> = self.actors.iter().collect();
+        actors.sort_by(|a, b| b.compute.total.total_cmp(&a.compute.total));
+        for actor in actors {
+            writeln!(
+                f,
+                " {:<28} {:>9} {:>9.3}s {:>8.2}ms {:>8.2}ms {:>8.2}ms {:>9.3}s {:>9.3}s",
+                actor.name,
+                actor.steps,
+                actor.compute.total,
+                actor.compute.p50 * 1e3,
+                actor.compute.p90 * 1e3,
+                actor.compute.p99 * 1e3,
+                actor.inputs.total,
+                actor.outputs.total
+            )?;
+        }
+        write!(
+            f,
+            "Critical path ({:.3}s): {}",
+            self.critical_path_compute,
+            self.critical_path.join(" -> ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantile() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.quantile(0.5), 0f64);
+        (0..90).for_each(|_| histogram.add(Duration::from_micros(1500)));
+        (0..10).for_each(|_| histogram.add(Duration::from_millis(150)));
+        // upper edge of the bin of 1.5ms
+        let edge = 1.5e-3 * 10f64.powf(1. / BINS_PER_DECADE as f64);
+        let stats = histogram.stats();
+        assert!(stats.p50 > 1.5e-3 && stats.p50 <= edge);
+        assert_eq!(stats.p90, stats.p50);
+        // the upper edge of the bin of the largest duration is the largest duration
+        assert_eq!(stats.p99, 0.15);
+        assert_eq!(stats.max, 0.15);
+        assert!((stats.mean - (0.9 * 1.5e-3 + 0.1 * 0.15)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn components() {
+        // 0 -> 1 <-> 2 -> 3
+        let edges = vec![vec![1], vec![2], vec![1, 3], vec![]];
+        assert_eq!(
+            strongly_connected_components(&edges),
+            vec![vec![3], vec![1, 2], vec![0]]
+        );
+    }
+
+    fn actor(name: &str, compute: f64, inputs: &[&str], outputs: &[&str]) -> ActorProfile {
+        let stats = |total| Stats {
+            total,
+            mean: 0f64,
+            p50: 0f64,
+            p90: 0f64,
+            p99: 0f64,
+            max: 0f64,
+        };
+        ActorProfile {
+            name: name.to_string(),
+            steps: 1,
+            compute: stats(compute),
+            inputs: stats(0f64),
+            outputs: stats(0f64),
+            input_uids: inputs.iter().map(|uid| uid.to_string()).collect(),
+            output_uids: outputs.iter().map(|uid| uid.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn path() {
+        // A -> (B <-> C) -> D and E alone
+        let actors = vec![
+            actor("A", 1., &[], &["a"]),
+            actor("B", 2., &["a", "c"], &["b"]),
+            actor("C", 3., &["b"], &["c"]),
+            actor("D", 10., &["b"], &[]),
+            actor("E", 15., &["e"], &[]),
+        ];
+        assert_eq!(
+            critical_path(&actors),
+            (vec![vec![0], vec![1, 2], vec![3]], 16.)
+        );
+        let report = ProfileReport::new(1., actors);
+        assert_eq!(report.critical_path, ["A", "(B + C)", "D"]);
+        assert_eq!(report.critical_path_compute, 16.);
+    }
+}
@@ -0,0 +1,202 @@
+//! Progress reporting
+//!
+//! A simulation is divided into phases (FEM build, calibrations, warm-up, closed loop, ...).
+//! The progress of each phase is displayed with progress bars if the standard output
+//! is a terminal, otherwise the progress is written to the standard output as JSON lines:
+//! ```json
+//! {"phase":"Closed loop","event":"progress","step":12000,"total":150000,"elapsed":61.2,"rate":196.1,"eta":703.7}
+//! ```
+
+use serde::Serialize;
+use std::{
+    io::IsTerminal,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+
+/// Progress record
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    pub phase: &'a str,
+    pub event: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    /// Time since the start of the phase [s]
+    pub elapsed: f64,
+    /// Step rate [step/s]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f64>,
+    /// Estimated time to the end of the phase [s]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta: Option<f64>,
+}
+
+enum Display {
+    Bars(linya::Progress),
+    JsonLines,
+}
+
+/// Progress reporter shared by all the phases of a simulation
+#[derive(Clone)]
+pub struct Progress(Arc<Mutex<Display>>);
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Progress {
+    /// Creates a new progress reporter, with progress bars if the standard output is a terminal
+    pub fn new() -> Self {
+        if std::io::stdout().is_terminal() {
+            Self::bars()
+        } else {
+            Self::json_lines()
+        }
+    }
+    /// Creates a new progress reporter with progress bars
+    pub fn bars() -> Self {
+        Self(Arc::new(Mutex::new(Display::Bars(linya::Progress::new()))))
+    }
+    /// Creates a new progress reporter with JSON lines records
+    pub fn json_lines() -> Self {
+        Self(Arc::new(Mutex::new(Display::JsonLines)))
+    }
+    /// Starts a new phase of `total` steps or of unknown length if `total` is `None`
+    pub fn phase<S: Into<String>>(&self, name: S, total: Option<usize>) -> Phase {
+        let name = name.into();
+        let bar = match &mut *self.0.lock().unwrap() {
+            Display::Bars(progress) => match total {
+                Some(total) => Some(progress.bar(total, &name)),
+                None => {
+                    println!("{name} ...");
+                    None
+                }
+            },
+            Display::JsonLines => None,
+        };
+        let phase = Phase {
+            name,
+            total,
+            step: 0,
+            start: Instant::now(),
+            progress: self.clone(),
+            bar,
+        };
+        phase.emit("start");
+        phase
+    }
+}
+
+/// Simulation phase
+pub struct Phase {
+    name: String,
+    total: Option<usize>,
+    step: usize,
+    start: Instant,
+    progress: Progress,
+    bar: Option<linya::Bar>,
+}
+impl Phase {
+    fn record<'a>(&'a self, event: &'a str) -> Record<'a> {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = (self.step > 0 && elapsed > 0f64).then(|| self.step as f64 / elapsed);
+        Record {
+            phase: &self.name,
+            event,
+            step: self.total.map(|_| self.step),
+            total: self.total,
+            elapsed,
+            rate,
+            eta: self
+                .total
+                .zip(rate)
+                .map(|(total, rate)| total.saturating_sub(self.step) as f64 / rate),
+        }
+    }
+    fn emit(&self, event: &str) {
+        if let Display::JsonLines = &*self.progress.0.lock().unwrap() {
+            if let Ok(record) = serde_json::to_string(&self.record(event)) {
+                println!("{record}");
+            }
+        }
+    }
+    /// Returns the name of the phase
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Sets the number of completed steps
+    pub fn set(&mut self, step: usize) {
+        self.step = step;
+        match &mut *self.progress.0.lock().unwrap() {
+            Display::Bars(progress) => {
+                if let Some(bar) = self.bar.as_ref() {
+                    progress.set_and_draw(bar, step);
+                }
+            }
+            Display::JsonLines => {
+                if let Ok(record) = serde_json::to_string(&self.record("progress")) {
+                    println!("{record}");
+                }
+            }
+        }
+    }
+    /// Ends the phase
+    pub fn finish(self) {
+        if let Display::Bars(_) = &*self.progress.0.lock().unwrap() {
+            if self.bar.is_none() {
+                println!(
+                    "{} done in {:.3}s",
+                    self.name,
+                    self.start.elapsed().as_secs_f64()
+                );
+            }
+        }
+        self.emit("finish");
+    }
+    /// Spawns a task that sets the phase progress every `interval` with the value returned by `counter`
+    ///
+    /// The task ends when the phase total is reached or when [Tracker::finish] is called
+    pub fn track<F>(mut self, interval: Duration, counter: F) -> Tracker
+    where
+        F: Fn() -> usize + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_tracker = stop.clone();
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                let step = counter();
+                self.set(step);
+                if stop_tracker.load(Ordering::SeqCst)
+                    || self.total.is_some_and(|total| step >= total)
+                {
+                    break;
+                }
+            }
+            self.finish();
+        });
+        Tracker { stop, handle }
+    }
+}
+
+/// Handle to a phase tracking task
+pub struct Tracker {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+impl Tracker {
+    /// Updates the phase progress one last time and ends the phase
+    pub async fn finish(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Err(e) = self.handle.await {
+            log::warn!("progress tracking failed: {e}");
+        }
+    }
+}
@@ -0,0 +1,573 @@
+//! Simulation results
+//!
+//! The streams of the parquet files written by the [Logger](crate::logging::Logger) are
+//! columns of lists, one list per logger update, with null lists for the updates without samples.
+//! [read_column] and [read_time_series] read a stream back and [write_columns] and
+//! [write_time_series] write the result of an analysis in the same format.
+//! The [StreamMetadata] of a stream are read with [stream_metadata], the result files written
+//! before the streams carried their metadata fall back to [DECIMATIONS] and [UNITS].
+//! [Stats] are the summary statistics of a stream.
+//!
+//! [Run] opens a run directory and gives the [Signal]s logged by the `main` binary with their
+//! unique identifier, width, sampling rate, units and time vector:
+//! ```ignore
+//! use fem::fem_io::OSSM1Lcl;
+//! use grim::results::Run;
+//!
+//! let run = Run::open(grim::data_repo())?;
+//! let m1_rbm = run.get::<OSSM1Lcl>()?.after_warm_up();
+//! let m1_rbm = m1_rbm.to_matrix(); // samples x 42
+//! # Ok::<(), grim::GrimError>(())
+//! ```
+
+use crate::{
+    logging::{StreamMetadata, TIME},
+    manifest::Manifest,
+    uid_name, GrimError, Result,
+};
+use arrow::{
+    array::{Array, ArrayRef, AsArray, Float64Array, Float64Builder, ListBuilder},
+    datatypes::{DataType, Field, Float32Type, Float64Type, Schema},
+    record_batch::RecordBatch,
+};
+use dos_actors::{
+    clients::{arrow_client::Arrow, gmt_state::GmtState},
+    UniqueIdentifier,
+};
+use nalgebra as na;
+use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter, ProjectionMask};
+use std::{
+    fmt::Display,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Number of simulation steps per row of the result files of the `main` binary
+pub const DECIMATIONS: [(&str, usize); 6] = [
+    ("grim.parquet", 1),
+    ("lom.parquet", 1),
+    ("m1-loadcells.parquet", 10),
+    ("sh24.parquet", 5),
+    ("sh24-frame.parquet", 1_000),
+    ("sh48.parquet", 30_000),
+];
+/// Units of the streams of the result files
+pub const UNITS: [(&str, &str, &str); 25] = [
+    ("grim.parquet", "OSSM1Lcl", "m, rad"),
+    ("grim.parquet", "MCM2Lcl6D", "m, rad"),
+    ("grim.parquet", "M1modes", "m"),
+    ("grim.parquet", "MountEncoders", "rad"),
+    ("lom.parquet", "TipTilt", "mas"),
+    ("lom.parquet", "SegmentTipTilt", "mas"),
+    ("lom.parquet", "SegmentPiston", "nm"),
+    ("lom.parquet", "SegmentWfeRms", "nm"),
+    ("lom.parquet", "WfeRms", "nm"),
+    ("m1-loadcells.parquet", "S1HPLC", "N"),
+    ("m1-loadcells.parquet", "S2HPLC", "N"),
+    ("m1-loadcells.parquet", "S3HPLC", "N"),
+    ("m1-loadcells.parquet", "S4HPLC", "N"),
+    ("m1-loadcells.parquet", "S5HPLC", "N"),
+    ("m1-loadcells.parquet", "S6HPLC", "N"),
+    ("m1-loadcells.parquet", "S7HPLC", "N"),
+    ("sh24.parquet", "WfeRms", "m"),
+    ("sh24.parquet", "TipTilt", "rad"),
+    ("sh24.parquet", "SegmentWfeRms", "m"),
+    ("sh24.parquet", "SegmentPiston", "m"),
+    ("sh24.parquet", "SegmentTipTilt", "rad"),
+    ("sh24-frame.parquet", "SH24Frame", "photon"),
+    ("sh48.parquet", "WfeRms", "m"),
+    ("sh48.parquet", "DetectorFrame", "photon"),
+    ("sh48.parquet", "M1ModalCmd", "m"),
+];
+/// Returns the unit of the stream `name` of the result file `file`, empty if the stream is unknown
+pub fn unit(file: &str, name: &str) -> &'static str {
+    UNITS
+        .iter()
+        .find(|(f, n, _)| *f == file && *n == name)
+        .map_or("", |(_, _, unit)| unit)
+}
+/// Returns the number of simulation steps per row of the result file `file`, 1 if the file is unknown
+pub fn decimation(file: &str) -> usize {
+    DECIMATIONS
+        .iter()
+        .find(|(name, _)| *name == file)
+        .map_or(1, |(_, decimation)| *decimation)
+}
+
+/// Returns the names of the streams of the parquet file `path`, the [TIME] column excluded
+pub fn stream_names<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    Ok(builder
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .filter(|name| name != TIME)
+        .collect())
+}
+
+/// Returns the metadata of the stream `name` of the parquet file `path`
+///
+/// Returns `None` if the stream has no metadata
+pub fn stream_metadata<P: AsRef<Path>>(path: P, name: &str) -> Result<Option<StreamMetadata>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    let schema = builder.schema();
+    let field = schema.field_with_name(name)?;
+    Ok(StreamMetadata::from_hashmap(field.metadata()))
+}
+
+/// Reads the stream `name` of the parquet file `path`
+///
+/// Returns one sample per row, `None` for the rows without sample
+pub fn read_column<P: AsRef<Path>>(path: P, name: &str) -> Result<Vec<Option<Vec<f64>>>> {
+    read_rows(path, name, None)
+}
+
+// Reads the rows of the stream `name` of the parquet file `path`,
+// stopping after `n_sample` samples if given
+fn read_rows<P: AsRef<Path>>(
+    path: P,
+    name: &str,
+    n_sample: Option<usize>,
+) -> Result<Vec<Option<Vec<f64>>>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    let index = builder.schema().index_of(name)?;
+    let mask = ProjectionMask::roots(builder.parquet_schema(), [index]);
+    let reader = builder.with_projection(mask).build()?;
+    let mut samples = vec![];
+    let mut count = 0;
+    for record in reader {
+        let record = record?;
+        // the time column is a column of scalars
+        if let Some(column) = record.column(0).as_primitive_opt::<Float64Type>() {
+            for x in column.iter() {
+                if n_sample.is_some_and(|n| count >= n) {
+                    return Ok(samples);
+                }
+                samples.push(x.map(|x| vec![x]));
+                count += x.is_some() as usize;
+            }
+            continue;
+        }
+        let column = record.column(0).as_list::<i32>();
+        for i in 0..column.len() {
+            if n_sample.is_some_and(|n| count >= n) {
+                return Ok(samples);
+            }
+            if column.is_null(i) {
+                samples.push(None);
+                continue;
+            }
+            let values = column.value(i);
+            let sample: Vec<f64> = match values.data_type() {
+                DataType::Float64 => values.as_primitive::<Float64Type>().values().to_vec(),
+                DataType::Float32 => values
+                    .as_primitive::<Float32Type>()
+                    .values()
+                    .iter()
+                    .map(|x| *x as f64)
+                    .collect(),
+                data_type => {
+                    return Err(GrimError::Arrow(arrow::error::ArrowError::CastError(
+                        format!("{name} has unsupported data type {data_type}"),
+                    )))
+                }
+            };
+            samples.push(Some(sample));
+            count += 1;
+        }
+    }
+    Ok(samples)
+}
+
+/// Reads the stream `name` of the parquet file `path` with rows sampled at `sampling_frequency`
+///
+/// Returns the time [s] and the value of the samples, skipping the rows without sample.
+/// The time is read from the [TIME] column if the file has one
+pub fn read_time_series<P: AsRef<Path>>(
+    path: P,
+    name: &str,
+    sampling_frequency: f64,
+) -> Result<(Vec<f64>, Vec<Vec<f64>>)> {
+    let path = path.as_ref();
+    let time = read_time(path)?.unwrap_or_default();
+    Ok(read_column(path, name)?
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, sample)| {
+            let t = time
+                .get(i)
+                .cloned()
+                .unwrap_or(i as f64 / sampling_frequency);
+            sample.map(|sample| (t, sample))
+        })
+        .unzip())
+}
+
+/// Reads the [TIME] column of the parquet file `path`
+///
+/// Returns `None` if the file has no time column
+pub fn read_time<P: AsRef<Path>>(path: P) -> Result<Option<Vec<f64>>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+    if ParquetRecordBatchReaderBuilder::try_new(file)?
+        .schema()
+        .field_with_name(TIME)
+        .is_err()
+    {
+        return Ok(None);
+    }
+    Ok(Some(
+        read_column(path, TIME)?
+            .into_iter()
+            .map(|t| t.map_or(f64::NAN, |t| t[0]))
+            .collect(),
+    ))
+}
+
+// Returns the list array of the samples
+fn list_array(samples: &[Vec<f64>]) -> ArrayRef {
+    let mut builder = ListBuilder::new(Float64Builder::new());
+    for sample in samples {
+        builder.values().append_slice(sample);
+        builder.append(true);
+    }
+    Arc::new(builder.finish())
+}
+
+/// Writes the streams `columns`, `(name, samples)`, in the parquet file `path`
+pub fn write_columns<P: AsRef<Path>>(path: P, columns: &[(String, Vec<Vec<f64>>)]) -> Result<()> {
+    let arrays: Vec<(String, ArrayRef)> = columns
+        .iter()
+        .map(|(name, samples)| (name.clone(), list_array(samples)))
+        .collect();
+    write_record(path.as_ref(), RecordBatch::try_from_iter(arrays)?)
+}
+
+/// Writes the [TIME] column `time` [s] and the streams `columns`, `(name, metadata, samples)`,
+/// with one sample per time step, in the parquet file `path`
+pub fn write_time_series<P: AsRef<Path>>(
+    path: P,
+    time: &[f64],
+    columns: &[(String, StreamMetadata, Vec<Vec<f64>>)],
+) -> Result<()> {
+    let mut fields = vec![Field::new(TIME, DataType::Float64, false)
+        .with_metadata([("unit".to_string(), "s".to_string())].into())];
+    let mut arrays: Vec<ArrayRef> = vec![Arc::new(Float64Array::from(time.to_vec()))];
+    for (name, metadata, samples) in columns {
+        let array = list_array(samples);
+        fields.push(
+            Field::new(name, array.data_type().clone(), true).with_metadata(metadata.to_hashmap()),
+        );
+        arrays.push(array);
+    }
+    write_record(
+        path.as_ref(),
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?,
+    )
+}
+
+// Writes the record in the parquet file `path`
+fn write_record(path: &Path, record: RecordBatch) -> Result<()> {
+    let file = File::create(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+    let mut writer = ArrowWriter::try_new(file, record.schema(), None)?;
+    writer.write(&record)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Description of a logged signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalInfo {
+    /// Unique identifier name
+    pub uid: String,
+    /// Result file name
+    pub file: String,
+    /// Number of channels
+    pub width: usize,
+    /// Sampling frequency [Hz]
+    pub sampling_frequency: f64,
+    pub unit: String,
+    /// Name of the actor that outputs the signal, empty if unknown
+    pub source: String,
+}
+impl Display for SignalInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<20} {:<16} {:>8} {:>12.6}Hz [{}]",
+            self.file, self.uid, self.width, self.sampling_frequency, self.unit
+        )?;
+        if !self.source.is_empty() {
+            write!(f, " from {}", self.source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Logged signal
+#[derive(Debug, Clone)]
+pub struct Signal {
+    pub info: SignalInfo,
+    /// Time since the start of the simulation [s]
+    pub time: Vec<f64>,
+    pub samples: Vec<Vec<f64>>,
+    /// Duration of the warm-up [s]
+    pub warm_up: f64,
+}
+impl Signal {
+    /// Returns the index of the first sample after the warm-up
+    pub fn warm_up_index(&self) -> usize {
+        self.time
+            .iter()
+            .position(|t| *t >= self.warm_up)
+            .unwrap_or(self.time.len())
+    }
+    /// Returns the signal without the samples of the warm-up
+    pub fn after_warm_up(&self) -> Self {
+        let i = self.warm_up_index();
+        Self {
+            info: self.info.clone(),
+            time: self.time[i..].to_vec(),
+            samples: self.samples[i..].to_vec(),
+            warm_up: self.warm_up,
+        }
+    }
+    /// Returns the time series of the channel `i`
+    pub fn channel(&self, i: usize) -> Vec<f64> {
+        self.samples.iter().map(|sample| sample[i]).collect()
+    }
+    /// Returns the samples as a matrix with one sample per row
+    pub fn to_matrix(&self) -> na::DMatrix<f64> {
+        na::DMatrix::from_row_slice(self.samples.len(), self.info.width, &self.samples.concat())
+    }
+}
+
+/// Simulation run
+///
+/// The results of a run are read from the run directory, the sampling frequency and the
+/// warm-up duration are read from the run manifest
+pub struct Run {
+    dir: PathBuf,
+    sampling_frequency: f64,
+    warm_up: f64,
+}
+impl Run {
+    /// Opens the run in the directory `dir`
+    ///
+    /// Without manifest, the run is assumed sampled at 1kHz with a 10s warm-up
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let (sampling_frequency, warm_up) = match Manifest::load(&dir) {
+            Ok(manifest) => (manifest.sampling_frequency as f64, manifest.warm_up),
+            Err(GrimError::Io(e, path)) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::warn!(
+                    "{path:?} not found, assuming a 1kHz sampling frequency and a 10s warm-up"
+                );
+                (1000f64, 10f64)
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            dir,
+            sampling_frequency,
+            warm_up,
+        })
+    }
+    /// Returns the run directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    /// Returns the simulation sampling frequency [Hz]
+    pub fn sampling_frequency(&self) -> f64 {
+        self.sampling_frequency
+    }
+    /// Returns the warm-up duration [s]
+    pub fn warm_up(&self) -> f64 {
+        self.warm_up
+    }
+    /// Returns the number of simulation steps of the warm-up
+    pub fn n_warm_up(&self) -> usize {
+        (self.warm_up * self.sampling_frequency).round() as usize
+    }
+    /// Returns the description of the signals of all the result files of the run
+    pub fn signals(&self) -> Result<Vec<SignalInfo>> {
+        let mut signals = vec![];
+        for (file, _) in DECIMATIONS.iter() {
+            let path = self.dir.join(file);
+            if !path.exists() {
+                continue;
+            }
+            for name in stream_names(&path)? {
+                signals.push(self.info(file, &name)?);
+            }
+        }
+        Ok(signals)
+    }
+    // Returns the description of the stream `name` of the result file `file`
+    fn info(&self, file: &str, name: &str) -> Result<SignalInfo> {
+        let path = self.dir.join(file);
+        let rows = read_rows(&path, name, Some(2))?;
+        let width = rows
+            .iter()
+            .flatten()
+            .next()
+            .map_or(0, |sample| sample.len());
+        if let Some(metadata) = stream_metadata(&path, name)? {
+            return Ok(SignalInfo {
+                uid: name.to_string(),
+                file: file.to_string(),
+                width,
+                sampling_frequency: metadata.sampling_frequency,
+                unit: metadata.unit,
+                source: metadata.source,
+            });
+        }
+        let mut samples = rows
+            .iter()
+            .enumerate()
+            .filter_map(|(i, sample)| sample.as_ref().map(|sample| (i, sample.len())));
+        let (first, width) = samples.next().unwrap_or_default();
+        // decimated streams have null rows in between 2 samples
+        let stream_decimation = samples.next().map_or(1, |(second, _)| second - first);
+        Ok(SignalInfo {
+            uid: name.to_string(),
+            file: file.to_string(),
+            width,
+            sampling_frequency: self.sampling_frequency
+                / (decimation(file) * stream_decimation) as f64,
+            unit: unit(file, name).to_string(),
+            source: String::new(),
+        })
+    }
+    /// Reads the stream `name` of the result file `file`
+    pub fn stream(&self, file: &str, name: &str) -> Result<Signal> {
+        let path = self.dir.join(file);
+        let row_sampling_frequency = self.sampling_frequency / decimation(file) as f64;
+        let (time, samples) = read_time_series(&path, name, row_sampling_frequency)?;
+        let width = samples.first().map_or(0, |sample| sample.len());
+        if samples.iter().any(|sample| sample.len() != width) {
+            return Err(GrimError::Config(format!(
+                "{path:?}: the width of {name} changes from sample to sample"
+            )));
+        }
+        let info = match stream_metadata(&path, name)? {
+            Some(metadata) => SignalInfo {
+                uid: name.to_string(),
+                file: file.to_string(),
+                width,
+                sampling_frequency: metadata.sampling_frequency,
+                unit: metadata.unit,
+                source: metadata.source,
+            },
+            None => SignalInfo {
+                uid: name.to_string(),
+                file: file.to_string(),
+                width,
+                sampling_frequency: match time.as_slice() {
+                    [t0, t1, ..] => (t1 - t0).recip(),
+                    _ => row_sampling_frequency,
+                },
+                unit: unit(file, name).to_string(),
+                source: String::new(),
+            },
+        };
+        Ok(Signal {
+            info,
+            time,
+            samples,
+            warm_up: self.warm_up,
+        })
+    }
+    /// Reads the signal `U` from the result file `file`
+    pub fn get_from<U: UniqueIdentifier>(&self, file: &str) -> Result<Signal> {
+        self.stream(file, &uid_name::<U>())
+    }
+    /// Reads the signal `U` from the first result file that holds it
+    ///
+    /// The result files are searched in the order of [DECIMATIONS]
+    pub fn get<U: UniqueIdentifier>(&self) -> Result<Signal> {
+        let name = uid_name::<U>();
+        for (file, _) in DECIMATIONS.iter() {
+            let path = self.dir.join(file);
+            if path.exists() && stream_names(&path)?.contains(&name) {
+                return self.stream(file, &name);
+            }
+        }
+        Err(GrimError::Config(format!(
+            "{name} not found in the results of {:?}",
+            self.dir
+        )))
+    }
+    /// Returns the [GmtState] client replaying the M1 and M2 rigid body motions and the M1 modes
+    /// of `grim.parquet` from the end of the warm-up for `n_step` steps or until the end of the record
+    pub fn gmt_state(&self, n_step: Option<usize>) -> Result<GmtState> {
+        let path = self.dir.join("grim.parquet");
+        let arrow =
+            Arrow::from_parquet(&path).map_err(|e| GrimError::Config(format!("{path:?}: {e}")))?;
+        Ok((arrow, self.n_warm_up(), n_step).into())
+    }
+}
+
+/// Summary statistics of a time series
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub mean: f64,
+    pub std: f64,
+    pub rms: f64,
+    pub min: f64,
+    pub max: f64,
+}
+impl Stats {
+    /// Computes the statistics of the time series `data`
+    pub fn new(data: &[f64]) -> Self {
+        if data.is_empty() {
+            return Default::default();
+        }
+        let n = data.len() as f64;
+        let mean = data.iter().sum::<f64>() / n;
+        // the variance is computed from the deviations to the mean, the difference of the
+        // mean square and of the squared mean cancels out for a large mean
+        let var = data.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        let ms = data.iter().map(|x| x * x).sum::<f64>() / n;
+        Self {
+            mean,
+            std: var.sqrt(),
+            rms: ms.sqrt(),
+            min: data.iter().cloned().fold(f64::INFINITY, f64::min),
+            max: data.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>12.3} {:>12.3} {:>12.3} {:>12.3} {:>12.3}",
+            self.mean, self.std, self.rms, self.min, self.max
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+        // 0 to 9 around a large offset
+        let data: Vec<f64> = (0..10).map(|i| 1e9 + i as f64).collect();
+        let stats = Stats::new(&data);
+        assert_eq!(stats.mean, 1e9 + 4.5);
+        assert!((stats.std - 8.25f64.sqrt()).abs() < 1e-9);
+        assert_eq!((stats.min, stats.max), (1e9, 1e9 + 9.));
+        assert_eq!(Stats::new(&[]).std, 0f64);
+    }
+}
@@ -0,0 +1,109 @@
+//! Graceful shutdown
+//!
+//! [Shutdown::listen] catches SIGINT and SIGTERM and raises a flag that stops
+//! the [Interruptible] clients at the next step.
+//! An interruptible initiator stops sending data, the actors downstream terminate when
+//! their inputs are disconnected and the loggers are flushed when they are dropped.
+//! A second signal terminates the process immediately.
+
+use dos_actors::{
+    io::{Data, Write},
+    UniqueIdentifier, Update,
+};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Shutdown request flag
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown(Arc<AtomicBool>);
+impl Shutdown {
+    /// Spawns a task listening to SIGINT and SIGTERM
+    pub fn listen() -> Self {
+        let shutdown = Self::default();
+        let flag = shutdown.0.clone();
+        tokio::spawn(async move {
+            loop {
+                signal().await;
+                if flag.swap(true, Ordering::SeqCst) {
+                    log::warn!("Second shutdown request, exiting now!");
+                    std::process::exit(130);
+                }
+                log::warn!("Shutdown requested, stopping the simulation at the next step");
+            }
+        });
+        shutdown
+    }
+    /// Returns true if a shutdown has been requested
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+    /// Requests a shutdown
+    pub fn request(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+    /// Wraps a client into an [Interruptible] client
+    pub fn interruptible<C>(&self, client: C) -> Interruptible<C> {
+        Interruptible {
+            client,
+            shutdown: self.clone(),
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen to SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
+}
+#[cfg(not(unix))]
+async fn signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("failed to listen to SIGINT");
+}
+
+/// Client that stops writing its outputs once a shutdown is requested
+pub struct Interruptible<C> {
+    client: C,
+    shutdown: Shutdown,
+}
+impl<C> Deref for Interruptible<C> {
+    type Target = C;
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+impl<C> DerefMut for Interruptible<C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client
+    }
+}
+impl<C: Update> Update for Interruptible<C> {
+    fn update(&mut self) {
+        if !self.shutdown.is_requested() {
+            self.client.update();
+        }
+    }
+}
+impl<T, U, C> Write<T, U> for Interruptible<C>
+where
+    U: UniqueIdentifier<Data = T>,
+    C: Write<T, U>,
+{
+    fn write(&mut self) -> Option<Arc<Data<U>>> {
+        if self.shutdown.is_requested() {
+            None
+        } else {
+            self.client.write()
+        }
+    }
+}
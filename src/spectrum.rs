@@ -0,0 +1,578 @@
+//! Power spectral densities
+//!
+//! [Welch] estimates the one-sided power spectral density of a multi-channel time series,
+//! summed over the channels, from windowed segments overlapping by half a segment.
+//! The samples are pushed one time step at a time so the estimate can be updated while
+//! the simulation is running.
+//! The segments are Hann windowed by default, the [Window], the overlap and the [Detrend]ing
+//! of the segments are set with the [Welch] builder methods.
+//!
+//! A [Psd] gives the RMS in frequency bands and the reverse cumulative RMS used in the error budgets.
+//!
+//! [Tfe] estimates the [TransferFunction] from an input to an output of the same segments,
+//! with the coherence of the input and the output.
+
+use crate::{GrimError, Result};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::{f64::consts::PI, fmt::Display, str::FromStr, sync::Arc};
+
+/// Segment window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Window {
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+    Rectangular,
+}
+impl Window {
+    /// Returns the `n` coefficients of the periodic window
+    pub fn coefficients(&self, n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| {
+                let x = 2f64 * PI * i as f64 / n as f64;
+                match self {
+                    Window::Hann => 0.5 - 0.5 * x.cos(),
+                    Window::Hamming => 0.54 - 0.46 * x.cos(),
+                    Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2f64 * x).cos(),
+                    Window::Rectangular => 1f64,
+                }
+            })
+            .collect()
+    }
+}
+impl FromStr for Window {
+    type Err = GrimError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "hann" => Ok(Window::Hann),
+            "hamming" => Ok(Window::Hamming),
+            "blackman" => Ok(Window::Blackman),
+            "rectangular" | "none" => Ok(Window::Rectangular),
+            _ => Err(GrimError::Config(format!(
+                "invalid window {s:?} (hann, hamming, blackman or rectangular)"
+            ))),
+        }
+    }
+}
+
+/// Trend removed from each segment before windowing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Detrend {
+    #[default]
+    None,
+    /// Segment mean
+    Constant,
+    /// Segment least-squares line
+    Linear,
+}
+impl Detrend {
+    /// Removes the trend from the segment `x`
+    pub fn apply(&self, x: &mut [f64]) {
+        let n = x.len() as f64;
+        match self {
+            Detrend::None => (),
+            Detrend::Constant => {
+                let mean = x.iter().sum::<f64>() / n;
+                x.iter_mut().for_each(|x| *x -= mean);
+            }
+            Detrend::Linear => {
+                // least-squares fit of a + b(i-i_mean)
+                let i_mean = (n - 1f64) / 2f64;
+                let mean = x.iter().sum::<f64>() / n;
+                let (sxy, sxx) = x
+                    .iter()
+                    .enumerate()
+                    .fold((0f64, 0f64), |(sxy, sxx), (i, x)| {
+                        let di = i as f64 - i_mean;
+                        (sxy + di * x, sxx + di * di)
+                    });
+                let slope = if sxx > 0f64 { sxy / sxx } else { 0f64 };
+                x.iter_mut()
+                    .enumerate()
+                    .for_each(|(i, x)| *x -= mean + slope * (i as f64 - i_mean));
+            }
+        }
+    }
+}
+impl FromStr for Detrend {
+    type Err = GrimError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(Detrend::None),
+            "constant" | "mean" => Ok(Detrend::Constant),
+            "linear" => Ok(Detrend::Linear),
+            _ => Err(GrimError::Config(format!(
+                "invalid detrend {s:?} (none, constant or linear)"
+            ))),
+        }
+    }
+}
+
+/// Windowed overlapping segments of a multi-channel time series
+///
+/// The segments are Hann windowed and overlap by half a segment by default
+pub struct Segments {
+    n_fft: usize,
+    // number of samples between the start of 2 segments
+    step: usize,
+    window: Vec<f64>,
+    detrend: Detrend,
+    fft: Arc<dyn Fft<f64>>,
+    buffer: Vec<Vec<f64>>,
+}
+impl Segments {
+    /// Creates segments of `n_fft` samples
+    pub fn new(n_fft: usize) -> Self {
+        Self {
+            n_fft,
+            step: (n_fft / 2).max(1),
+            window: Window::default().coefficients(n_fft),
+            detrend: Detrend::default(),
+            fft: FftPlanner::new().plan_fft_forward(n_fft),
+            buffer: Vec::with_capacity(n_fft),
+        }
+    }
+    /// Sets the segment window
+    pub fn window(mut self, window: Window) -> Self {
+        self.window = window.coefficients(self.n_fft);
+        self
+    }
+    /// Sets the overlap of consecutive segments as a fraction of the segment length in `[0,1[`
+    pub fn overlap(mut self, overlap: f64) -> Self {
+        self.step = (((1f64 - overlap) * self.n_fft as f64).round() as usize).clamp(1, self.n_fft);
+        self
+    }
+    /// Sets the trend removed from each segment
+    pub fn detrend(mut self, detrend: Detrend) -> Self {
+        self.detrend = detrend;
+        self
+    }
+    /// Returns the segment length
+    pub fn n_fft(&self) -> usize {
+        self.n_fft
+    }
+    /// Returns the sum of the squared window
+    pub fn window_power(&self) -> f64 {
+        self.window.iter().map(|w| w * w).sum()
+    }
+    /// Pushes the sample of all the channels at the next time step
+    ///
+    /// Once a segment is complete, returns the one-sided Fourier transforms, `n_fft/2+1` frequencies,
+    /// of the detrended and windowed segment of each channel
+    pub fn push(&mut self, sample: &[f64]) -> Option<Vec<Vec<Complex<f64>>>> {
+        self.buffer.push(sample.to_vec());
+        if self.buffer.len() < self.n_fft {
+            return None;
+        }
+        let n_channel = self
+            .buffer
+            .iter()
+            .map(|s| s.len())
+            .min()
+            .unwrap_or_default();
+        let spectra = (0..n_channel)
+            .map(|channel| {
+                let mut segment: Vec<_> =
+                    self.buffer.iter().map(|sample| sample[channel]).collect();
+                self.detrend.apply(&mut segment);
+                let mut spectrum: Vec<_> = segment
+                    .iter()
+                    .zip(&self.window)
+                    .map(|(x, w)| Complex::new(x * w, 0f64))
+                    .collect();
+                self.fft.process(&mut spectrum);
+                spectrum.truncate(self.n_fft / 2 + 1);
+                spectrum
+            })
+            .collect();
+        self.buffer.drain(..self.step);
+        Some(spectra)
+    }
+}
+
+/// Welch power spectral density estimator
+pub struct Welch {
+    sampling_frequency: f64,
+    segments: Segments,
+    psd: Vec<f64>,
+    n_segment: usize,
+}
+impl Welch {
+    /// Creates a new estimator with segments of `n_fft` samples sampled at `sampling_frequency`
+    pub fn new(n_fft: usize, sampling_frequency: f64) -> Self {
+        Self {
+            sampling_frequency,
+            segments: Segments::new(n_fft),
+            psd: vec![0f64; n_fft / 2 + 1],
+            n_segment: 0,
+        }
+    }
+    /// Sets the segment window
+    pub fn window(mut self, window: Window) -> Self {
+        self.segments = self.segments.window(window);
+        self
+    }
+    /// Sets the overlap of consecutive segments as a fraction of the segment length in `[0,1[`
+    pub fn overlap(mut self, overlap: f64) -> Self {
+        self.segments = self.segments.overlap(overlap);
+        self
+    }
+    /// Sets the trend removed from each segment
+    pub fn detrend(mut self, detrend: Detrend) -> Self {
+        self.segments = self.segments.detrend(detrend);
+        self
+    }
+    /// Pushes the sample of all the channels at the next time step
+    pub fn push(&mut self, sample: &[f64]) {
+        let Some(spectra) = self.segments.push(sample) else {
+            return;
+        };
+        for spectrum in spectra {
+            for (psd, x) in self.psd.iter_mut().zip(&spectrum) {
+                *psd += x.norm_sqr();
+            }
+        }
+        self.n_segment += 1;
+    }
+    /// Returns the number of averaged segments
+    pub fn n_segment(&self) -> usize {
+        self.n_segment
+    }
+    /// Returns the power spectral density estimate
+    pub fn psd(&self) -> Psd {
+        let df = self.sampling_frequency / self.segments.n_fft() as f64;
+        let norm =
+            self.sampling_frequency * self.segments.window_power() * self.n_segment.max(1) as f64;
+        let n = self.psd.len();
+        Psd {
+            frequencies: (0..n).map(|i| i as f64 * df).collect(),
+            values: self
+                .psd
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    // one-sided density, DC and Nyquist excepted
+                    let k = if i == 0 || i == n - 1 { 1f64 } else { 2f64 };
+                    k * p / norm
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Transfer function estimator
+///
+/// The samples are pushed as `[reference, input, output]` and the transfer function from the
+/// input to the output is the ratio of the cross spectral densities of the reference with the
+/// output and with the input.
+/// With the injected excitation as reference, the estimate is not biased by the disturbances
+/// that are fed back to the input in a closed loop; with the input as reference, this is the
+/// usual H1 estimate.
+pub struct Tfe {
+    sampling_frequency: f64,
+    segments: Segments,
+    // cross spectral densities: reference/input, reference/output, input/output
+    s_ru: Vec<Complex<f64>>,
+    s_ry: Vec<Complex<f64>>,
+    s_uy: Vec<Complex<f64>>,
+    // power spectral densities: input, output
+    s_uu: Vec<f64>,
+    s_yy: Vec<f64>,
+    n_segment: usize,
+}
+impl Tfe {
+    /// Creates a new estimator with segments of `n_fft` samples sampled at `sampling_frequency`
+    pub fn new(n_fft: usize, sampling_frequency: f64) -> Self {
+        let n = n_fft / 2 + 1;
+        Self {
+            sampling_frequency,
+            segments: Segments::new(n_fft),
+            s_ru: vec![Complex::default(); n],
+            s_ry: vec![Complex::default(); n],
+            s_uy: vec![Complex::default(); n],
+            s_uu: vec![0f64; n],
+            s_yy: vec![0f64; n],
+            n_segment: 0,
+        }
+    }
+    /// Sets the segment window
+    pub fn window(mut self, window: Window) -> Self {
+        self.segments = self.segments.window(window);
+        self
+    }
+    /// Sets the overlap of consecutive segments as a fraction of the segment length in `[0,1[`
+    pub fn overlap(mut self, overlap: f64) -> Self {
+        self.segments = self.segments.overlap(overlap);
+        self
+    }
+    /// Sets the trend removed from each segment
+    pub fn detrend(mut self, detrend: Detrend) -> Self {
+        self.segments = self.segments.detrend(detrend);
+        self
+    }
+    /// Pushes the reference, the input and the output at the next time step
+    pub fn push(&mut self, reference: f64, input: f64, output: f64) {
+        let Some(spectra) = self.segments.push(&[reference, input, output]) else {
+            return;
+        };
+        let (r, u, y) = (&spectra[0], &spectra[1], &spectra[2]);
+        for i in 0..self.s_uu.len() {
+            self.s_ru[i] += r[i].conj() * u[i];
+            self.s_ry[i] += r[i].conj() * y[i];
+            self.s_uy[i] += u[i].conj() * y[i];
+            self.s_uu[i] += u[i].norm_sqr();
+            self.s_yy[i] += y[i].norm_sqr();
+        }
+        self.n_segment += 1;
+    }
+    /// Returns the number of averaged segments
+    pub fn n_segment(&self) -> usize {
+        self.n_segment
+    }
+    /// Returns the transfer function estimate
+    pub fn transfer_function(&self) -> TransferFunction {
+        let df = self.sampling_frequency / self.segments.n_fft() as f64;
+        TransferFunction {
+            frequencies: (0..self.s_uu.len()).map(|i| i as f64 * df).collect(),
+            values: self
+                .s_ry
+                .iter()
+                .zip(&self.s_ru)
+                .map(|(s_ry, s_ru)| s_ry / s_ru)
+                .collect(),
+            coherence: self
+                .s_uy
+                .iter()
+                .zip(self.s_uu.iter().zip(&self.s_yy))
+                .map(|(s_uy, (s_uu, s_yy))| s_uy.norm_sqr() / (s_uu * s_yy))
+                .collect(),
+        }
+    }
+}
+
+/// Transfer function estimate
+#[derive(Debug, Clone, Default)]
+pub struct TransferFunction {
+    pub frequencies: Vec<f64>,
+    pub values: Vec<Complex<f64>>,
+    /// Magnitude squared coherence of the input and the output in `[0,1]`
+    pub coherence: Vec<f64>,
+}
+impl TransferFunction {
+    /// Returns the magnitude of the transfer function
+    pub fn magnitude(&self) -> Vec<f64> {
+        self.values.iter().map(|h| h.norm()).collect()
+    }
+    /// Returns the phase of the transfer function [deg]
+    pub fn phase(&self) -> Vec<f64> {
+        self.values.iter().map(|h| h.arg().to_degrees()).collect()
+    }
+    /// Returns the frequencies, the values and the coherence in between `f_min` and `f_max`
+    /// where the coherence is larger than `min_coherence`
+    pub fn select(&self, f_min: f64, f_max: f64, min_coherence: f64) -> Self {
+        let (frequencies, (values, coherence)) = self
+            .frequencies
+            .iter()
+            .zip(self.values.iter().zip(&self.coherence))
+            .filter(|(f, (_, c))| (f_min..=f_max).contains(*f) && **c >= min_coherence)
+            .map(|(f, (h, c))| (*f, (*h, *c)))
+            .unzip();
+        Self {
+            frequencies,
+            values,
+            coherence,
+        }
+    }
+}
+
+/// One-sided power spectral density
+#[derive(Debug, Clone, Default)]
+pub struct Psd {
+    /// Frequencies [Hz]
+    pub frequencies: Vec<f64>,
+    pub values: Vec<f64>,
+}
+impl Psd {
+    /// Returns the frequency resolution [Hz]
+    pub fn resolution(&self) -> f64 {
+        self.frequencies.get(1).copied().unwrap_or_default()
+    }
+    /// Returns the power in the frequency band `[f_min,f_max[`
+    pub fn band_power(&self, f_min: f64, f_max: f64) -> f64 {
+        self.frequencies
+            .iter()
+            .zip(&self.values)
+            .filter(|(f, _)| (f_min..f_max).contains(*f))
+            .map(|(_, p)| p)
+            .sum::<f64>()
+            * self.resolution()
+    }
+    /// Returns the RMS in the frequency band `[f_min,f_max[`
+    pub fn band_rms(&self, f_min: f64, f_max: f64) -> f64 {
+        self.band_power(f_min, f_max).sqrt()
+    }
+    /// Returns the RMS over all the frequencies
+    pub fn rms(&self) -> f64 {
+        self.band_rms(0f64, f64::INFINITY)
+    }
+    /// Returns the reverse cumulative RMS
+    ///
+    /// The RMS at a frequency is the RMS of all the frequencies larger than or equal to this frequency
+    pub fn cumulative_rms(&self) -> Vec<f64> {
+        let df = self.resolution();
+        let mut power = 0f64;
+        let mut rms: Vec<f64> = self
+            .values
+            .iter()
+            .rev()
+            .map(|p| {
+                power += p * df;
+                power.sqrt()
+            })
+            .collect();
+        rms.reverse();
+        rms
+    }
+    /// Returns the decade bands starting at the frequency resolution up to the Nyquist frequency
+    pub fn decade_bands(&self) -> Vec<(f64, f64)> {
+        let f_nyquist = self.frequencies.last().copied().unwrap_or_default();
+        let df = self.resolution();
+        if df <= 0f64 {
+            return vec![];
+        }
+        let mut bands = vec![];
+        let mut f = 10f64.powf(df.log10().floor());
+        while f < f_nyquist {
+            bands.push((f.max(df), 10f64 * f));
+            f *= 10f64;
+        }
+        bands
+    }
+    /// Returns the octave bands starting at twice the frequency resolution up to the Nyquist frequency
+    pub fn octave_bands(&self) -> Vec<(f64, f64)> {
+        let f_nyquist = self.frequencies.last().copied().unwrap_or_default();
+        let mut bands = vec![];
+        let mut f = 2f64 * self.resolution();
+        while f > 0f64 && 2f64 * f <= f_nyquist {
+            bands.push((f, 2f64 * f));
+            f *= 2f64;
+        }
+        bands
+    }
+}
+
+/// Spectral comparison of two power spectral densities in octave bands
+#[derive(Debug, Clone)]
+pub struct SpectralCheck {
+    pub name: String,
+    /// Octave bands [Hz]
+    pub bands: Vec<(f64, f64)>,
+    /// Power ratios in the octave bands [dB]
+    pub errors: Vec<f64>,
+    /// Largest absolute power ratio [dB]
+    pub max_error: f64,
+}
+impl SpectralCheck {
+    /// Compares the power spectral density `psd` to the `reference` one
+    ///
+    /// The bands without power in the reference are ignored
+    pub fn new<S: Into<String>>(name: S, reference: &Psd, psd: &Psd) -> Self {
+        let (bands, errors): (Vec<_>, Vec<_>) = reference
+            .octave_bands()
+            .into_iter()
+            .filter_map(|(f_min, f_max)| {
+                let p_ref = reference.band_power(f_min, f_max);
+                (p_ref > 0f64).then(|| {
+                    (
+                        (f_min, f_max),
+                        10f64 * (psd.band_power(f_min, f_max) / p_ref).log10(),
+                    )
+                })
+            })
+            .unzip();
+        let max_error = errors
+            .iter()
+            .map(|e: &f64| if e.is_nan() { f64::INFINITY } else { e.abs() })
+            .fold(0f64, f64::max);
+        Self {
+            name: name.into(),
+            bands,
+            errors,
+            max_error,
+        }
+    }
+}
+impl Display for SpectralCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: max. octave band power error: {:.2}dB [",
+            self.name, self.max_error
+        )?;
+        let errors: Vec<_> = self
+            .bands
+            .iter()
+            .zip(&self.errors)
+            .map(|((f_min, f_max), e)| format!("{f_min:.3}-{f_max:.3}Hz:{e:+.2}"))
+            .collect();
+        write!(f, "{}]", errors.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine() {
+        // a sine on the 50th frequency of the segments
+        let (n_fft, fs, amplitude) = (256, 1000f64, 3f64);
+        let f0 = 50f64 * fs / n_fft as f64;
+        let mut welch = Welch::new(n_fft, fs);
+        for i in 0..16 * n_fft {
+            welch.push(&[amplitude * (2f64 * PI * f0 * i as f64 / fs + 0.3).sin()]);
+        }
+        assert_eq!(welch.n_segment(), 31);
+        let psd = welch.psd();
+        assert_eq!(psd.frequencies.len(), n_fft / 2 + 1);
+        assert_eq!(psd.resolution(), fs / n_fft as f64);
+        let peak = psd
+            .values
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| psd.frequencies[i])
+            .unwrap();
+        assert_eq!(peak, f0);
+        let rms = amplitude / 2f64.sqrt();
+        assert!((psd.rms() - rms).abs() < 1e-9 * rms);
+        let df = psd.resolution();
+        assert!((psd.band_rms(f0 - 2f64 * df, f0 + 2f64 * df) - rms).abs() < 1e-9 * rms);
+        assert!(psd.band_rms(0f64, f0 - 2f64 * df) < 1e-6 * rms);
+    }
+
+    #[test]
+    fn parseval() {
+        // the mean square of the samples is the sum of the power of all the frequencies
+        // for contiguous rectangular segments
+        let n_fft = 128;
+        let x: Vec<f64> = (0..8 * n_fft)
+            .map(|i| ((i * 7919) % 101) as f64 / 101f64 - 0.3)
+            .collect();
+        let mut welch = Welch::new(n_fft, 100f64)
+            .window(Window::Rectangular)
+            .overlap(0f64);
+        x.iter().for_each(|x| welch.push(&[*x]));
+        assert_eq!(welch.n_segment(), 8);
+        let psd = welch.psd();
+        let rms = (x.iter().map(|x| x * x).sum::<f64>() / x.len() as f64).sqrt();
+        let cumulative_rms = psd.cumulative_rms();
+        assert_eq!(cumulative_rms.len(), psd.values.len());
+        assert!((cumulative_rms[0] - rms).abs() < 1e-12 * rms);
+        assert!((psd.rms() - rms).abs() < 1e-12 * rms);
+        assert!(cumulative_rms.windows(2).all(|r| r[0] >= r[1]));
+        let f = psd.frequencies[10];
+        assert!((cumulative_rms[10] - psd.band_rms(f, f64::INFINITY)).abs() < 1e-12 * rms);
+    }
+}
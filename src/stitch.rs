@@ -0,0 +1,354 @@
+//! Wind loads time history stitching
+//!
+//! [Stitched] extends the wind loads beyond the CFD record by repeating the record.
+//! The record of `n_record` samples is read once from the wind loads client and, if the
+//! simulation is longer than the record, it is repeated with a period of `n_record-n_fade` samples.
+//! The repeated samples are read back from the client if it implements [Replay],
+//! e.g. the memory-mapped CFD loads cache, otherwise the record is kept in memory and
+//! its size must not exceed [MAX_RECORD_SIZE].
+//! At each repetition, the last `n_fade` samples of the record are cross-faded with its first
+//! `n_fade` samples using equal-power weights, `cos(θ)` and `sin(θ)` with `θ` going from 0 to `π/2`,
+//! so that the variance of the loads is preserved across the junction.
+//!
+//! The power spectral densities of the record and of the stitched loads are estimated with
+//! [Welch] and compared in octave bands by [SpectralCheck].
+
+use crate::{
+    spectrum::{SpectralCheck, Welch},
+    uid_name, GrimError, Result,
+};
+use dos_actors::{
+    io::{Data, Write},
+    UniqueIdentifier, Update,
+};
+use std::{
+    collections::BTreeMap,
+    f64::consts::FRAC_PI_2,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+/// Largest record, in bytes, that is repeated from memory
+pub const MAX_RECORD_SIZE: usize = 1 << 30;
+
+/// Wind loads client that can read back the samples of its record
+pub trait Replay<U: UniqueIdentifier> {
+    /// Returns the sample `step` of the record of the output `U`,
+    /// `None` if the client cannot read it back
+    fn replay(&self, step: usize) -> Option<Vec<f64>>;
+}
+
+// Stitching state of an output
+struct Channel {
+    step: usize,
+    // the record, if it is not read back from the client
+    record: Vec<Vec<f64>>,
+    buffered: bool,
+    record_psd: Welch,
+    stitched_psd: Welch,
+}
+
+/// Wind loads client with the record repeated to the simulation duration
+pub struct Stitched<C> {
+    client: C,
+    n_record: usize,
+    n_step: usize,
+    // cross-fade weights of the end and of the start of the record
+    fade: Vec<(f64, f64)>,
+    n_fft: usize,
+    sampling_frequency: f64,
+    stop: Option<usize>,
+    channels: BTreeMap<String, Channel>,
+}
+impl<C> Stitched<C> {
+    /// Wraps the wind loads `client` that provides a record of `n_record` samples
+    /// for a simulation of `n_step` samples with a cross-fade of `n_fade` samples
+    pub fn new(client: C, n_record: usize, n_fade: usize, n_step: usize) -> Self {
+        let n_fade = n_fade.min(n_record / 2);
+        let fade = (0..n_fade)
+            .map(|j| {
+                let theta = FRAC_PI_2 * (j as f64 + 0.5) / n_fade as f64;
+                (theta.cos(), theta.sin())
+            })
+            .collect();
+        Self {
+            client,
+            n_record,
+            n_step,
+            fade,
+            n_fft: 1 << (n_record / 4).clamp(2, 8192).ilog2(),
+            sampling_frequency: 1f64,
+            stop: None,
+            channels: BTreeMap::new(),
+        }
+    }
+    /// Sets the sampling frequency of the loads for the power spectral densities
+    pub fn sampling_frequency(mut self, sampling_frequency: f64) -> Self {
+        self.sampling_frequency = sampling_frequency;
+        self
+    }
+    /// Checks if the record is repeated
+    pub fn is_stitched(&self) -> bool {
+        self.n_step > self.n_record
+    }
+    /// Returns the repetition period in samples
+    pub fn period(&self) -> usize {
+        self.n_record - self.fade.len()
+    }
+    /// Checks that the record of outputs of `width` loads can be repeated from memory
+    pub fn check_memory(&self, width: usize) -> Result<()> {
+        let size = 8 * width * self.n_record;
+        if self.is_stitched() && size > MAX_RECORD_SIZE {
+            return Err(GrimError::Config(format!(
+                "the {:.1}GB record of the wind loads cannot be repeated from memory (max. {:.1}GB), cache the CFD loads with WIND_LOADS_CACHE",
+                size as f64 * 1e-9,
+                MAX_RECORD_SIZE as f64 * 1e-9
+            )));
+        }
+        Ok(())
+    }
+    /// Stops writing the loads after `n` samples
+    pub fn stop_after(&mut self, n: usize) {
+        self.stop = Some(n);
+    }
+    /// Writes the loads again from sample `n`
+    pub fn start_from(&mut self, n: usize) {
+        self.stop = None;
+        self.channels
+            .values_mut()
+            .for_each(|channel| channel.step = channel.step.max(n));
+    }
+    /// Compares the power spectral densities of the stitched loads and of the record of each output
+    ///
+    /// Returns an empty vector if the record is not repeated
+    pub fn spectral_check(&self) -> Vec<SpectralCheck> {
+        if !self.is_stitched() {
+            return vec![];
+        }
+        self.channels
+            .iter()
+            .filter(|(_, channel)| channel.stitched_psd.n_segment() > 0)
+            .map(|(name, channel)| {
+                SpectralCheck::new(
+                    name.clone(),
+                    &channel.record_psd.psd(),
+                    &channel.stitched_psd.psd(),
+                )
+            })
+            .collect()
+    }
+}
+impl<C> Deref for Stitched<C> {
+    type Target = C;
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+impl<C> DerefMut for Stitched<C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client
+    }
+}
+impl<C: Update> Update for Stitched<C> {
+    fn update(&mut self) {
+        self.client.update();
+    }
+}
+impl<C> Stitched<C> {
+    // Returns the sample `j` of the record of the output `U`
+    fn sample<U: UniqueIdentifier>(&self, j: usize) -> Option<Vec<f64>>
+    where
+        C: Replay<U>,
+    {
+        let channel = self.channels.get(&uid_name::<U>())?;
+        if channel.buffered {
+            channel.record.get(j).cloned()
+        } else {
+            self.client.replay(j)
+        }
+    }
+    // Cross-fades the end of the record `x_end` with its start `x_start` at sample `j` of the fade
+    fn fade(&self, j: usize, x_end: &[f64], x_start: &[f64]) -> Vec<f64> {
+        let (w_end, w_start) = self.fade[j];
+        x_end
+            .iter()
+            .zip(x_start)
+            .map(|(x_end, x_start)| w_end * x_end + w_start * x_start)
+            .collect()
+    }
+}
+impl<U, C> Write<Vec<f64>, U> for Stitched<C>
+where
+    U: UniqueIdentifier<Data = Vec<f64>>,
+    C: Write<Vec<f64>, U> + Replay<U>,
+{
+    fn write(&mut self) -> Option<Arc<Data<U>>> {
+        let (n_fft, fs) = (self.n_fft, self.sampling_frequency);
+        let name = uid_name::<U>();
+        if !self.channels.contains_key(&name) {
+            let channel = Channel {
+                step: 0,
+                record: vec![],
+                buffered: self.client.replay(0).is_none(),
+                record_psd: Welch::new(n_fft, fs),
+                stitched_psd: Welch::new(n_fft, fs),
+            };
+            self.channels.insert(name.clone(), channel);
+        }
+        let step = self.channels[&name].step;
+        if self.stop.is_some_and(|stop| step >= stop) {
+            return None;
+        }
+        if !self.is_stitched() {
+            self.channels.get_mut(&name)?.step += 1;
+            return self.client.write();
+        }
+        let (period, n_fade) = (self.period(), self.fade.len());
+        let loads: Vec<f64> = if step < self.n_record {
+            let x: Vec<f64> = (**self.client.write()?).clone();
+            let channel = self.channels.get_mut(&name)?;
+            channel.record_psd.push(&x);
+            if channel.buffered {
+                channel.record.push(x.clone());
+            }
+            if step < period {
+                x
+            } else {
+                // first cross-fade, the end of the record is read from the client
+                self.fade(step - period, &x, &self.sample::<U>(step - period)?)
+            }
+        } else {
+            // the start of the record is replaced by the cross-fade
+            // and the record is truncated to the repetition period
+            let j = (step - period) % period;
+            if j < n_fade {
+                self.fade(j, &self.sample::<U>(period + j)?, &self.sample::<U>(j)?)
+            } else {
+                self.sample::<U>(j)?
+            }
+        };
+        let channel = self.channels.get_mut(&name)?;
+        channel.step += 1;
+        channel.stitched_psd.push(&loads);
+        Some(Arc::new(Data::new(loads)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dos_actors::prelude::*;
+
+    #[derive(UID)]
+    #[uid(data = "Vec<f64>")]
+    enum Loads {}
+
+    // wind loads client writing a single channel record
+    struct Record {
+        samples: Vec<f64>,
+        step: usize,
+        replay: bool,
+    }
+    impl Update for Record {}
+    impl Write<Vec<f64>, Loads> for Record {
+        fn write(&mut self) -> Option<Arc<Data<Loads>>> {
+            let x = *self.samples.get(self.step)?;
+            self.step += 1;
+            Some(Arc::new(Data::new(vec![x])))
+        }
+    }
+    impl Replay<Loads> for Record {
+        fn replay(&self, step: usize) -> Option<Vec<f64>> {
+            self.replay.then(|| vec![self.samples[step]])
+        }
+    }
+
+    fn stitch(samples: Vec<f64>, n_fade: usize, n_step: usize) -> (Stitched<Record>, Vec<f64>) {
+        stitch_from(samples, n_fade, n_step, false)
+    }
+    fn stitch_from(
+        samples: Vec<f64>,
+        n_fade: usize,
+        n_step: usize,
+        replay: bool,
+    ) -> (Stitched<Record>, Vec<f64>) {
+        let n_record = samples.len();
+        let record = Record {
+            samples,
+            step: 0,
+            replay,
+        };
+        let mut stitched = Stitched::new(record, n_record, n_fade, n_step);
+        let loads = (0..n_step)
+            .map(|_| <Stitched<Record> as Write<Vec<f64>, Loads>>::write(&mut stitched).unwrap()[0])
+            .collect();
+        (stitched, loads)
+    }
+
+    // uniform white noise of unit variance
+    fn noise(n: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..n)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                ((state >> 11) as f64 / (1u64 << 53) as f64 - 0.5) * 12f64.sqrt()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn continuity() {
+        let (n_record, n_fade) = (2000, 200);
+        let record: Vec<f64> = (0..n_record)
+            .map(|i| (2f64 * std::f64::consts::PI * i as f64 / 73.1).sin())
+            .collect();
+        let max_step = |x: &[f64]| {
+            x.windows(2)
+                .map(|x| (x[1] - x[0]).abs())
+                .fold(0f64, f64::max)
+        };
+        // repeating the record without cross-fade
+        assert!((record[n_record - 1] - record[0]).abs() > 4f64 * max_step(&record));
+        let (stitched, loads) = stitch(record.clone(), n_fade, 3 * n_record);
+        assert!(stitched.is_stitched());
+        assert_eq!(stitched.period(), n_record - n_fade);
+        assert_eq!(loads[..stitched.period()], record[..stitched.period()]);
+        assert!(max_step(&loads) < 1.5 * max_step(&record));
+        // the repetitions are identical
+        let period = stitched.period();
+        assert_eq!(
+            loads[n_record..n_record + period],
+            loads[n_record + period..n_record + 2 * period]
+        );
+    }
+
+    #[test]
+    fn power() {
+        let (n_record, n_fade) = (8192, 1024);
+        let record = noise(n_record);
+        let (stitched, loads) = stitch(record.clone(), n_fade, 4 * n_record);
+        let mean_square = |x: &[f64]| x.iter().map(|x| x * x).sum::<f64>() / x.len() as f64;
+        let period = stitched.period();
+        // the cross-fade of 2 uncorrelated parts of the record
+        let fade = mean_square(&loads[period..n_record]);
+        assert!((fade / mean_square(&record) - 1f64).abs() < 0.15);
+        let checks = stitched.spectral_check();
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].name, "Loads");
+        assert!(checks[0].max_error < 1f64);
+    }
+
+    #[test]
+    fn replay() {
+        let record = noise(1000);
+        let (buffered, loads) = stitch_from(record.clone(), 100, 3500, false);
+        let (replayed, replayed_loads) = stitch_from(record, 100, 3500, true);
+        assert_eq!(loads, replayed_loads);
+        assert_eq!(buffered.channels["Loads"].record.len(), 1000);
+        assert!(replayed.channels["Loads"].record.is_empty());
+        assert!(buffered.check_memory(1).is_ok());
+        assert!(buffered.check_memory(1 << 20).is_err());
+    }
+}
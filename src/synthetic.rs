@@ -0,0 +1,463 @@
+//! Synthetic wind loads
+//!
+//! The wind loads are synthesized from their one-sided cross-spectral density matrices, the [Spectra].
+//! The channels of an output are split into [Block]s of correlated channels, e.g. the 3 forces and
+//! the 3 moments of a node, and the blocks are independent from each other.
+//! The spectra are either fitted to a CFD case with [Fit] or given analytically with a von Kármán
+//! turbulence spectrum.
+//!
+//! The [Generator] synthesizes segments of `n_fft` samples in the frequency domain:
+//! at each frequency, the Fourier transforms of the channels of a block are `√(Δf/2) L ξ`
+//! where `LLᴴ` is the cross-spectral density matrix and `ξ` a vector of unit variance complex
+//! Gaussian random variables drawn from a random generator with a fixed seed.
+//! The consecutive segments are joined with the same equal-power cross-fade as the repeated
+//! CFD loads (see [stitch](crate::stitch)), so the loads can be synthesized for any duration.
+
+use crate::{cache::CachedLoads, spectrum::Segments, stitch::Replay, uid_name, GrimError, Result};
+use dos_actors::{
+    io::{Data, Write},
+    UniqueIdentifier, Update,
+};
+use nalgebra as na;
+use rand::{rngs::StdRng, SeedableRng};
+use rand_distr::{Distribution, StandardNormal};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, f64::consts::FRAC_PI_2, fs::File, path::Path, sync::Arc};
+
+/// Cross-spectral density matrices of a block of correlated channels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Block {
+    /// Index of the first channel of the block
+    pub start: usize,
+    /// Number of channels of the block
+    pub size: usize,
+    /// Column-wise `[size x size]` matrices, one per frequency, as `(real,imaginary)` pairs
+    pub csd: Vec<Vec<(f64, f64)>>,
+}
+impl Block {
+    // Returns the factors `L` of the cross-spectral density matrices `LLᴴ`
+    fn factors(&self) -> Vec<na::DMatrix<Complex<f64>>> {
+        self.csd
+            .iter()
+            .map(|csd| {
+                let csd = na::DMatrix::from_iterator(
+                    self.size,
+                    self.size,
+                    csd.iter().map(|(re, im)| Complex::new(*re, *im)),
+                );
+                // hermitian part, robust to the estimation noise
+                let csd = (&csd + csd.adjoint()) * Complex::new(0.5, 0f64);
+                let eigen = csd.symmetric_eigen();
+                let mut factor = eigen.eigenvectors;
+                for (mut column, lambda) in factor.column_iter_mut().zip(eigen.eigenvalues.iter()) {
+                    column *= Complex::new(lambda.max(0f64).sqrt(), 0f64);
+                }
+                factor
+            })
+            .collect()
+    }
+}
+
+/// Spectra of the channels of an output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputSpectra {
+    pub n_channel: usize,
+    pub blocks: Vec<Block>,
+}
+
+/// Wind loads spectra
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spectra {
+    /// Sampling frequency [Hz]
+    pub sampling_frequency: f64,
+    /// Number of samples of the synthesized segments, the spectra have `n_fft/2+1` frequencies
+    pub n_fft: usize,
+    /// Spectra of the outputs
+    pub outputs: BTreeMap<String, OutputSpectra>,
+}
+impl Spectra {
+    /// Creates empty spectra for segments of `n_fft` samples sampled at `sampling_frequency`
+    pub fn new(sampling_frequency: f64, n_fft: usize) -> Self {
+        Self {
+            sampling_frequency,
+            n_fft,
+            outputs: BTreeMap::new(),
+        }
+    }
+    /// Returns the frequencies [Hz]
+    pub fn frequencies(&self) -> Vec<f64> {
+        let df = self.sampling_frequency / self.n_fft as f64;
+        (0..=self.n_fft / 2).map(|i| i as f64 * df).collect()
+    }
+    /// Adds the von Kármán spectra of the output `U` with `n_node` nodes
+    ///
+    /// The forces and the moments of the nodes are independent with the standard deviations
+    /// `force_std` and `moment_std`, respectively, and the spectrum of the wind speed `wind_speed`
+    /// with the turbulence length scale `length_scale`
+    pub fn von_karman<U: UniqueIdentifier>(
+        mut self,
+        n_node: usize,
+        force_std: f64,
+        moment_std: f64,
+        wind_speed: f64,
+        length_scale: f64,
+    ) -> Self {
+        let frequencies = self.frequencies();
+        let blocks = (0..6 * n_node)
+            .map(|start| {
+                let std = if start % 6 < 3 { force_std } else { moment_std };
+                Block {
+                    start,
+                    size: 1,
+                    csd: frequencies
+                        .iter()
+                        .map(|&f| vec![(von_karman(f, std, wind_speed, length_scale), 0f64)])
+                        .collect(),
+                }
+            })
+            .collect();
+        self.outputs.insert(
+            uid_name::<U>(),
+            OutputSpectra {
+                n_channel: 6 * n_node,
+                blocks,
+            },
+        );
+        self
+    }
+    /// Checks that the spectra of the output `U` have `n_channel` channels
+    pub fn check<U: UniqueIdentifier>(&self, n_channel: usize) -> Result<()> {
+        let name = uid_name::<U>();
+        match self.outputs.get(&name) {
+            Some(output) if output.n_channel == n_channel => Ok(()),
+            Some(output) => Err(GrimError::Config(format!(
+                "{name} wind loads spectra have {} channels, expected {n_channel}",
+                output.n_channel
+            ))),
+            None => Err(GrimError::Config(format!("no {name} wind loads spectra"))),
+        }
+    }
+    /// Loads the spectra from the file `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+        Ok(bincode::deserialize_from(file)?)
+    }
+    /// Saves the spectra in the file `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| GrimError::Io(e, path.to_path_buf()))?;
+        bincode::serialize_into(file, self)?;
+        Ok(())
+    }
+}
+
+/// One-sided von Kármán power spectral density at frequency `f` [Hz] of a signal
+/// with the standard deviation `std` and the wind speed `wind_speed` [m/s] with
+/// the turbulence length scale `length_scale` [m]
+pub fn von_karman(f: f64, std: f64, wind_speed: f64, length_scale: f64) -> f64 {
+    let t = length_scale / wind_speed;
+    std * std * 4f64 * t / (1f64 + 70.8 * (f * t).powi(2)).powf(5f64 / 6f64)
+}
+
+/// Fit of the cross-spectral density matrices of an output to a time series
+///
+/// The matrices are estimated with Welch method for blocks of `block_size` consecutive channels
+pub struct Fit {
+    sampling_frequency: f64,
+    block_size: usize,
+    segments: Segments,
+    n_channel: usize,
+    csd: Vec<Vec<Vec<Complex<f64>>>>,
+    n_segment: usize,
+}
+impl Fit {
+    /// Creates a new fit with segments of `n_fft` samples sampled at `sampling_frequency`
+    pub fn new(n_fft: usize, sampling_frequency: f64, block_size: usize) -> Self {
+        Self {
+            sampling_frequency,
+            block_size: block_size.max(1),
+            segments: Segments::new(n_fft),
+            n_channel: 0,
+            csd: vec![],
+            n_segment: 0,
+        }
+    }
+    /// Pushes the sample of all the channels at the next time step
+    pub fn push(&mut self, sample: &[f64]) {
+        let Some(spectra) = self.segments.push(sample) else {
+            return;
+        };
+        let n_freq = self.segments.n_fft() / 2 + 1;
+        if self.csd.is_empty() {
+            self.n_channel = spectra.len();
+            self.csd = spectra
+                .chunks(self.block_size)
+                .map(|block| vec![vec![Complex::default(); block.len().pow(2)]; n_freq])
+                .collect();
+        }
+        for (block, csd) in spectra.chunks(self.block_size).zip(self.csd.iter_mut()) {
+            for (k, csd) in csd.iter_mut().enumerate() {
+                let n = block.len();
+                for j in 0..n {
+                    for i in 0..n {
+                        csd[i + j * n] += block[i][k] * block[j][k].conj();
+                    }
+                }
+            }
+        }
+        self.n_segment += 1;
+    }
+    /// Returns the fitted spectra
+    pub fn spectra(&self) -> OutputSpectra {
+        let n_fft = self.segments.n_fft();
+        let norm =
+            self.sampling_frequency * self.segments.window_power() * self.n_segment.max(1) as f64;
+        let blocks = self
+            .csd
+            .iter()
+            .enumerate()
+            .map(|(b, csd)| {
+                let start = b * self.block_size;
+                let size = self.block_size.min(self.n_channel - start);
+                Block {
+                    start,
+                    size,
+                    csd: csd
+                        .iter()
+                        .enumerate()
+                        .map(|(k, csd)| {
+                            // one-sided density, DC and Nyquist excepted
+                            let k = if k == 0 || k == n_fft / 2 { 1f64 } else { 2f64 };
+                            let scale = k / norm;
+                            csd.iter().map(|x| (x.re * scale, x.im * scale)).collect()
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        OutputSpectra {
+            n_channel: self.n_channel,
+            blocks,
+        }
+    }
+}
+
+// Synthesis state of an output
+struct Synthesis {
+    rng: StdRng,
+    factors: Vec<(usize, Vec<na::DMatrix<Complex<f64>>>)>,
+    n_channel: usize,
+    segment: Vec<Vec<f64>>,
+    tail: Vec<Vec<f64>>,
+    position: usize,
+}
+
+/// Synthetic wind loads client
+pub struct Generator {
+    sampling_frequency: f64,
+    n_fft: usize,
+    ifft: Arc<dyn Fft<f64>>,
+    // cross-fade weights of the end of a segment and of the start of the next one
+    fade: Vec<(f64, f64)>,
+    outputs: BTreeMap<String, Synthesis>,
+}
+impl Generator {
+    /// Creates a new generator of the wind loads with the given `spectra`
+    ///
+    /// The random generator of each output is seeded with `seed` and the output name and
+    /// the consecutive segments are cross-faded over `n_fade` samples
+    pub fn new(spectra: &Spectra, seed: u64, n_fade: usize) -> Self {
+        let n_fade = n_fade.min(spectra.n_fft / 2);
+        let fade = (0..n_fade)
+            .map(|j| {
+                let theta = FRAC_PI_2 * (j as f64 + 0.5) / n_fade as f64;
+                (theta.cos(), theta.sin())
+            })
+            .collect();
+        let outputs = spectra
+            .outputs
+            .iter()
+            .map(|(name, output)| {
+                // the seed of each output does not depend on the order of the outputs
+                let hash = name.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+                    (h ^ b as u64).wrapping_mul(0x100000001b3)
+                });
+                (
+                    name.clone(),
+                    Synthesis {
+                        rng: StdRng::seed_from_u64(seed ^ hash),
+                        factors: output
+                            .blocks
+                            .iter()
+                            .map(|block| (block.start, block.factors()))
+                            .collect(),
+                        n_channel: output.n_channel,
+                        segment: vec![],
+                        tail: vec![],
+                        position: 0,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            sampling_frequency: spectra.sampling_frequency,
+            n_fft: spectra.n_fft,
+            ifft: FftPlanner::new().plan_fft_inverse(spectra.n_fft),
+            fade,
+            outputs,
+        }
+    }
+    /// Returns the sampling frequency [Hz]
+    pub fn sampling_frequency(&self) -> f64 {
+        self.sampling_frequency
+    }
+}
+impl Synthesis {
+    // Synthesizes a new segment of `n_fft` samples
+    fn synthesize(&mut self, n_fft: usize, df: f64, ifft: &Arc<dyn Fft<f64>>) {
+        let mut spectra = vec![vec![Complex::<f64>::default(); n_fft]; self.n_channel];
+        let scale = (df / 2f64).sqrt();
+        for (start, factors) in &self.factors {
+            // DC and Nyquist are left to zero
+            for (k, factor) in factors.iter().enumerate().take(n_fft / 2).skip(1) {
+                let xi = na::DVector::<Complex<f64>>::from_fn(factor.ncols(), |_, _| {
+                    let re: f64 = StandardNormal.sample(&mut self.rng);
+                    let im: f64 = StandardNormal.sample(&mut self.rng);
+                    Complex::new(re, im) * std::f64::consts::FRAC_1_SQRT_2
+                });
+                let x = factor * xi;
+                for (i, x) in x.iter().enumerate() {
+                    let spectrum = &mut spectra[start + i];
+                    spectrum[k] = x * scale;
+                    spectrum[n_fft - k] = (x * scale).conj();
+                }
+            }
+        }
+        for spectrum in spectra.iter_mut() {
+            ifft.process(spectrum);
+        }
+        self.segment = (0..n_fft)
+            .map(|n| spectra.iter().map(|spectrum| spectrum[n].re).collect())
+            .collect();
+        self.position = 0;
+    }
+}
+impl Update for Generator {}
+impl<U: UniqueIdentifier<Data = Vec<f64>>> Write<Vec<f64>, U> for Generator {
+    fn write(&mut self) -> Option<Arc<Data<U>>> {
+        let (n_fft, df) = (self.n_fft, self.sampling_frequency / self.n_fft as f64);
+        let n_fade = self.fade.len();
+        let period = n_fft - n_fade;
+        let output = self.outputs.get_mut(&uid_name::<U>())?;
+        if output.segment.is_empty() {
+            output.synthesize(n_fft, df, &self.ifft);
+        }
+        let j = output.position;
+        let loads: Vec<f64> = if j < n_fade && !output.tail.is_empty() {
+            let (w_end, w_start) = self.fade[j];
+            output.tail[j]
+                .iter()
+                .zip(&output.segment[j])
+                .map(|(x_end, x_start)| w_end * x_end + w_start * x_start)
+                .collect()
+        } else {
+            output.segment[j].clone()
+        };
+        output.position += 1;
+        if output.position == period {
+            output.tail = output.segment.split_off(period);
+            output.synthesize(n_fft, df, &self.ifft);
+        }
+        Some(Arc::new(Data::new(loads)))
+    }
+}
+
+/// Wind loads source, either the CFD loads, the cached CFD loads or the synthetic loads
+pub enum WindSource<C> {
+    Cfd(C),
+    Cached(CachedLoads),
+    Synthetic(Generator),
+}
+impl<C: Update> Update for WindSource<C> {
+    fn update(&mut self) {
+        match self {
+            WindSource::Cfd(client) => client.update(),
+            WindSource::Cached(client) => client.update(),
+            WindSource::Synthetic(client) => client.update(),
+        }
+    }
+}
+impl<U, C> Write<Vec<f64>, U> for WindSource<C>
+where
+    U: UniqueIdentifier<Data = Vec<f64>>,
+    C: Write<Vec<f64>, U>,
+{
+    fn write(&mut self) -> Option<Arc<Data<U>>> {
+        match self {
+            WindSource::Cfd(client) => client.write(),
+            WindSource::Cached(client) => <CachedLoads as Write<Vec<f64>, U>>::write(client),
+            WindSource::Synthetic(client) => <Generator as Write<Vec<f64>, U>>::write(client),
+        }
+    }
+}
+impl<U: UniqueIdentifier, C> Replay<U> for WindSource<C> {
+    fn replay(&self, step: usize) -> Option<Vec<f64>> {
+        match self {
+            WindSource::Cached(client) => <CachedLoads as Replay<U>>::replay(client, step),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::{Psd, SpectralCheck, Welch};
+    use dos_actors::prelude::*;
+
+    #[derive(UID)]
+    #[uid(data = "Vec<f64>")]
+    enum Loads {}
+
+    #[test]
+    fn generator() {
+        let (fs, n_fft, n_node) = (100f64, 512, 2);
+        let (force_std, moment_std, wind_speed, length_scale) = (2f64, 0.5, 20f64, 1f64);
+        let spectra = Spectra::new(fs, n_fft).von_karman::<Loads>(
+            n_node,
+            force_std,
+            moment_std,
+            wind_speed,
+            length_scale,
+        );
+        spectra.check::<Loads>(6 * n_node).unwrap();
+        // the sum of the spectra of all the channels
+        let frequencies = spectra.frequencies();
+        let target = Psd {
+            values: frequencies
+                .iter()
+                .map(|&f| {
+                    3f64 * n_node as f64
+                        * (von_karman(f, force_std, wind_speed, length_scale)
+                            + von_karman(f, moment_std, wind_speed, length_scale))
+                })
+                .collect(),
+            frequencies,
+        };
+        let mut generator = Generator::new(&spectra, 0, n_fft / 8);
+        let mut welch = Welch::new(n_fft, fs);
+        for _ in 0..200 * n_fft {
+            let loads = <Generator as Write<Vec<f64>, Loads>>::write(&mut generator).unwrap();
+            assert_eq!(loads.len(), 6 * n_node);
+            welch.push(&loads);
+        }
+        let check = SpectralCheck::new("Loads", &target, &welch.psd());
+        assert!(check.max_error < 0.5, "{check}");
+        // the power below the frequency resolution is not synthesized
+        let df = fs / n_fft as f64;
+        let rms = target.band_rms(df, f64::INFINITY);
+        assert!((welch.psd().band_rms(df, f64::INFINITY) / rms - 1f64).abs() < 0.05);
+    }
+}
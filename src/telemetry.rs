@@ -0,0 +1,190 @@
+//! Live telemetry
+//!
+//! [Telemetry] gathers the step count of the [probed](crate::probe) actors and the latest
+//! values of the outputs sent to [Monitor] clients.
+//! The telemetry report is served as JSON over HTTP with [Telemetry::spawn_server]:
+//! ```shell
+//! curl http://127.0.0.1:8080/telemetry
+//! ```
+
+use crate::{probe::Probes, uid_name, GrimError, Result};
+use dos_actors::{
+    io::{Data, Read},
+    UniqueIdentifier, Update,
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, ToSocketAddrs},
+    task::JoinHandle,
+};
+
+/// Latest sample of a monitored output
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    /// Number of samples received
+    pub n_sample: usize,
+    pub values: Vec<f64>,
+}
+
+/// Actor step count
+#[derive(Debug, Clone, Serialize)]
+pub struct ActorStep {
+    pub name: String,
+    pub step: usize,
+}
+
+/// Telemetry report
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// Simulation time [s]
+    pub sim_time: f64,
+    /// Simulation duration [s]
+    pub duration: f64,
+    /// Wall clock time since the start of the simulation [s]
+    pub elapsed: f64,
+    /// Estimated wall clock time to the end of the simulation [s]
+    pub eta: Option<f64>,
+    pub actors: Vec<ActorStep>,
+    pub outputs: BTreeMap<String, Sample>,
+}
+
+/// Simulation telemetry
+#[derive(Clone)]
+pub struct Telemetry {
+    probes: Probes,
+    reference: String,
+    sampling_frequency: f64,
+    duration: f64,
+    start: Instant,
+    outputs: Arc<Mutex<BTreeMap<String, Sample>>>,
+}
+impl Telemetry {
+    /// Creates the telemetry of a simulation sampled at `sampling_frequency` and lasting `duration` seconds
+    ///
+    /// The simulation time is derived from the step count of the probe named `reference`
+    /// that must be updated at `sampling_frequency`
+    pub fn new<S: Into<String>>(
+        probes: Probes,
+        reference: S,
+        sampling_frequency: usize,
+        duration: f64,
+    ) -> Self {
+        Self {
+            probes,
+            reference: reference.into(),
+            sampling_frequency: sampling_frequency as f64,
+            duration,
+            start: Instant::now(),
+            outputs: Default::default(),
+        }
+    }
+    /// Returns a new [Monitor] client
+    pub fn monitor(&self) -> Monitor {
+        Monitor {
+            outputs: self.outputs.clone(),
+        }
+    }
+    /// Returns the telemetry report
+    pub fn report(&self) -> Report {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let sim_time = self
+            .probes
+            .get(&self.reference)
+            .map_or(0f64, |stats| stats.step() as f64 / self.sampling_frequency);
+        let eta =
+            (sim_time > 0f64).then(|| elapsed * (self.duration - sim_time).max(0f64) / sim_time);
+        Report {
+            sim_time,
+            duration: self.duration,
+            elapsed,
+            eta,
+            actors: self
+                .probes
+                .stats()
+                .iter()
+                .map(|stats| ActorStep {
+                    name: stats.name().to_string(),
+                    step: stats.step(),
+                })
+                .collect(),
+            outputs: self.outputs.lock().unwrap().clone(),
+        }
+    }
+    /// Serves the telemetry report as JSON on `GET /` and `GET /telemetry`
+    pub async fn serve<A: ToSocketAddrs>(self, addr: A) -> Result<()> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|e| GrimError::Io(e, "telemetry".into()))?;
+        log::info!(
+            "Telemetry served on http://{:?}/telemetry",
+            listener.local_addr().ok()
+        );
+        loop {
+            let (mut socket, _) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    log::warn!("telemetry connection failed: {e}");
+                    continue;
+                }
+            };
+            let telemetry = self.clone();
+            tokio::spawn(async move {
+                let mut buffer = [0u8; 1024];
+                let n = socket.read(&mut buffer).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&buffer[..n]);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body) = match path {
+                    "/" | "/telemetry" => (
+                        "200 OK",
+                        serde_json::to_string(&telemetry.report()).unwrap_or_default(),
+                    ),
+                    _ => ("404 Not Found", String::new()),
+                };
+                let response = format!(
+                    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                if let Err(e) = socket.write_all(response.as_bytes()).await {
+                    log::warn!("telemetry response failed: {e}");
+                }
+            });
+        }
+    }
+    /// Spawns a task serving the telemetry at `addr`
+    pub fn spawn_server(&self, addr: String) -> JoinHandle<()> {
+        let telemetry = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = telemetry.serve(addr).await {
+                log::error!("telemetry server failed: {e}");
+            }
+        })
+    }
+}
+
+/// Telemetry client that records the latest values of its inputs
+pub struct Monitor {
+    outputs: Arc<Mutex<BTreeMap<String, Sample>>>,
+}
+impl Update for Monitor {}
+impl<T, U> Read<Vec<T>, U> for Monitor
+where
+    T: Copy + Into<f64>,
+    U: UniqueIdentifier<Data = Vec<T>>,
+{
+    fn read(&mut self, data: Arc<Data<U>>) {
+        let values: Vec<f64> = data.iter().map(|&x| x.into()).collect();
+        let mut outputs = self.outputs.lock().unwrap();
+        let sample = outputs.entry(uid_name::<U>()).or_insert(Sample {
+            n_sample: 0,
+            values: Vec::new(),
+        });
+        sample.n_sample += 1;
+        sample.values = values;
+    }
+}
@@ -0,0 +1,239 @@
+//! Wind loads scaling
+//!
+//! [ScaledLoads] wraps the CFD wind loads client and scales the loads of each group of nodes.
+//! The loads of a node are the 3 forces followed by the 3 moments applied to the node.
+//! [ScaledLoads] also records the RMS of the resultant force and moment of each group,
+//! i.e. the sum of the forces and of the moments of the nodes of the group.
+
+use crate::uid_name;
+use dos_actors::{
+    io::{Data, Write},
+    UniqueIdentifier, Update,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+/// CFD wind loads groups
+pub const GROUPS: [&str; 11] = [
+    "TopEnd",
+    "M2Baffle",
+    "Trusses",
+    "M1Baffle",
+    "MirrorCovers",
+    "LaserGuideStars",
+    "CRings",
+    "GIR",
+    "Platforms",
+    "M1Segments",
+    "M2Segments",
+];
+
+/// Group of wind loads nodes
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub n_node: usize,
+    pub scale: f64,
+    n_sample: usize,
+    force_sum_squared: f64,
+    moment_sum_squared: f64,
+}
+impl Group {
+    /// Creates a new group of `n_node` nodes with loads scaled by `scale`
+    pub fn new<S: Into<String>>(name: S, n_node: usize, scale: f64) -> Self {
+        Self {
+            name: name.into(),
+            n_node,
+            scale,
+            n_sample: 0,
+            force_sum_squared: 0f64,
+            moment_sum_squared: 0f64,
+        }
+    }
+    // Scales the group loads and updates the statistics
+    fn scale(&mut self, loads: &mut [f64]) {
+        let mut force = [0f64; 3];
+        let mut moment = [0f64; 3];
+        for node in loads.chunks_mut(6) {
+            node.iter_mut().for_each(|x| *x *= self.scale);
+            for i in 0..3 {
+                force[i] += node[i];
+                moment[i] += node[i + 3];
+            }
+        }
+        self.n_sample += 1;
+        self.force_sum_squared += force.iter().map(|x| x * x).sum::<f64>();
+        self.moment_sum_squared += moment.iter().map(|x| x * x).sum::<f64>();
+    }
+}
+
+/// RMS of the resultant force and moment of a group
+#[derive(Debug, Clone)]
+pub struct GroupRms {
+    pub name: String,
+    pub scale: f64,
+    /// Force RMS [N]
+    pub force: f64,
+    /// Moment RMS [N.m]
+    pub moment: f64,
+}
+
+/// Wind loads report
+#[derive(Debug, Clone)]
+pub struct Report(pub Vec<GroupRms>);
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Wind loads:")?;
+        writeln!(
+            f,
+            " {:<16} {:>8} {:>12} {:>12}",
+            "GROUP", "SCALE", "FORCE [N]", "MOMENT [Nm]"
+        )?;
+        for group in &self.0 {
+            writeln!(
+                f,
+                " {:<16} {:>8.3} {:>12.3} {:>12.3}",
+                group.name, group.scale, group.force, group.moment
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Wind loads client with scaled groups of loads
+pub struct ScaledLoads<C> {
+    client: C,
+    outputs: BTreeMap<String, Vec<Group>>,
+}
+impl<C> ScaledLoads<C> {
+    /// Wraps the wind loads `client`
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            outputs: BTreeMap::new(),
+        }
+    }
+    /// Sets the groups of nodes of the output `U` in the order of the nodes
+    pub fn output<U: UniqueIdentifier>(mut self, groups: Vec<Group>) -> Self {
+        self.outputs.insert(uid_name::<U>(), groups);
+        self
+    }
+    /// Returns the RMS of the resultant force and moment of each group
+    pub fn report(&self) -> Report {
+        Report(
+            self.outputs
+                .values()
+                .flatten()
+                .map(|group| {
+                    let n = group.n_sample.max(1) as f64;
+                    GroupRms {
+                        name: group.name.clone(),
+                        scale: group.scale,
+                        force: (group.force_sum_squared / n).sqrt(),
+                        moment: (group.moment_sum_squared / n).sqrt(),
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+impl<C> Deref for ScaledLoads<C> {
+    type Target = C;
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+impl<C> DerefMut for ScaledLoads<C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client
+    }
+}
+impl<C: Update> Update for ScaledLoads<C> {
+    fn update(&mut self) {
+        self.client.update();
+    }
+}
+impl<U, C> Write<Vec<f64>, U> for ScaledLoads<C>
+where
+    U: UniqueIdentifier<Data = Vec<f64>>,
+    C: Write<Vec<f64>, U>,
+{
+    fn write(&mut self) -> Option<Arc<Data<U>>> {
+        let data = self.client.write()?;
+        let Some(groups) = self.outputs.get_mut(&uid_name::<U>()) else {
+            return Some(data);
+        };
+        let mut loads: Vec<f64> = (**data).clone();
+        let mut start = 0;
+        for group in groups.iter_mut() {
+            let end = (start + 6 * group.n_node).min(loads.len());
+            group.scale(&mut loads[start..end]);
+            start = end;
+        }
+        if start != loads.len() {
+            log::warn!(
+                "{}: {} wind loads for {} nodes",
+                uid_name::<U>(),
+                loads.len(),
+                start / 6
+            );
+        }
+        Some(Arc::new(Data::new(loads)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dos_actors::prelude::*;
+
+    #[derive(UID)]
+    #[uid(data = "Vec<f64>")]
+    enum Loads {}
+
+    // wind loads client writing the loads of 3 nodes with an amplitude increasing with the step
+    struct Client {
+        step: usize,
+    }
+    impl Update for Client {}
+    impl Write<Vec<f64>, Loads> for Client {
+        fn write(&mut self) -> Option<Arc<Data<Loads>>> {
+            self.step += 1;
+            let loads = [
+                1., 0., 0., 0., 0., 1., // node #1
+                0., 1., 0., 0., 0., 1., // node #2
+                3., 4., 0., 0., 0., 0., // node #3
+            ];
+            Some(Arc::new(Data::new(
+                loads.iter().map(|x| x * self.step as f64).collect(),
+            )))
+        }
+    }
+
+    #[test]
+    fn scale() {
+        let mut loads = ScaledLoads::new(Client { step: 0 }).output::<Loads>(vec![
+            Group::new("TopEnd", 2, 2.),
+            Group::new("M2Baffle", 1, 0.5),
+        ]);
+        let data = <ScaledLoads<Client> as Write<Vec<f64>, Loads>>::write(&mut loads).unwrap();
+        assert_eq!(
+            **data,
+            vec![2., 0., 0., 0., 0., 2., 0., 2., 0., 0., 0., 2., 1.5, 2., 0., 0., 0., 0.]
+        );
+        <ScaledLoads<Client> as Write<Vec<f64>, Loads>>::write(&mut loads).unwrap();
+        // RMS of the amplitudes 1 and 2
+        let rms = 2.5f64.sqrt();
+        let Report(groups) = loads.report();
+        assert_eq!(groups.len(), 2);
+        assert_eq!((groups[0].name.as_str(), groups[0].scale), ("TopEnd", 2.));
+        assert!((groups[0].force - 8f64.sqrt() * rms).abs() < 1e-12);
+        assert!((groups[0].moment - 4. * rms).abs() < 1e-12);
+        assert!((groups[1].force - 2.5 * rms).abs() < 1e-12);
+        assert_eq!(groups[1].moment, 0.);
+    }
+}